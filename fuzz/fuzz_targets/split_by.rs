@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use split_stream_by::SplitStreamByExt;
+use split_stream_by_fuzz::{is_even, run, Split};
+
+fuzz_target!(|data: &[u8]| {
+    run(data, |source| {
+        let (true_stream, false_stream) = source.split_by(is_even);
+        let handle = true_stream.pause_handle();
+        Split {
+            true_stream: Box::pin(true_stream),
+            false_stream: Box::pin(false_stream),
+            pause: Box::new(move |side, policy| match policy {
+                Some(policy) => handle.pause(side, policy),
+                None => handle.resume(side),
+            }),
+        }
+    })
+});
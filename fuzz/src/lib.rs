@@ -0,0 +1,130 @@
+//! Shared driver for the fuzz targets. The input is read as a list of
+//! operations on a split fed from a channel: sending items, ending the
+//! source, polling, pausing, resuming and dropping either output stream. The
+//! crate is built with `debug-invariants`, so the shared state is checked
+//! after every poll, and when nothing was dropped or diverted the items each
+//! stream yields are checked against what was sent
+//!
+//! Run a target with `cargo +nightly fuzz run split_by_buffered` from the
+//! root of the repository
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{channel::mpsc, stream::BoxStream, task::noop_waker_ref, Stream};
+use split_stream_by::{PausePolicy, Side};
+
+/// The two output streams of a split under test, along with a way of pausing
+/// and resuming them
+pub struct Split {
+    pub true_stream: BoxStream<'static, u8>,
+    pub false_stream: BoxStream<'static, u8>,
+    pub pause: Box<dyn Fn(Side, Option<PausePolicy>)>,
+}
+
+/// The predicate every target splits by
+pub fn is_even(n: &u8) -> bool {
+    n % 2 == 0
+}
+
+struct Output {
+    stream: Option<BoxStream<'static, u8>>,
+    items: Vec<u8>,
+    ended: bool,
+}
+
+impl Output {
+    fn new(stream: BoxStream<'static, u8>) -> Self {
+        Self {
+            stream: Some(stream),
+            items: Vec::new(),
+            ended: false,
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) {
+        if self.ended {
+            return;
+        }
+        if let Some(stream) = self.stream.as_mut() {
+            match Pin::new(stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => self.items.push(item),
+                Poll::Ready(None) => self.ended = true,
+                Poll::Pending => {}
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.ended || self.stream.is_none()
+    }
+}
+
+/// Runs the operations in `data` against the split made by `split`
+pub fn run(data: &[u8], split: impl FnOnce(BoxStream<'static, u8>) -> Split) {
+    let (mut tx, rx) = mpsc::unbounded();
+    let Split {
+        true_stream,
+        false_stream,
+        pause,
+    } = split(Box::pin(rx));
+    let mut outputs = [Output::new(true_stream), Output::new(false_stream)];
+    let mut sent = Vec::new();
+    // Set once items may legitimately go missing or change sides
+    let mut disturbed = false;
+    let mut cx = Context::from_waker(noop_waker_ref());
+    for &byte in data {
+        let side = if byte & 0x10 == 0 {
+            Side::Left
+        } else {
+            Side::Right
+        };
+        match byte & 0x0f {
+            0..=5 => {
+                if tx.unbounded_send(byte).is_ok() {
+                    sent.push(byte);
+                }
+            }
+            6 => tx.close_channel(),
+            7 | 8 => outputs[0].poll(&mut cx),
+            9 | 10 => outputs[1].poll(&mut cx),
+            11 => pause(side, Some(PausePolicy::Buffer)),
+            12 => {
+                disturbed = true;
+                pause(side, Some(PausePolicy::Drop));
+            }
+            13 => {
+                disturbed = true;
+                pause(side, Some(PausePolicy::Divert));
+            }
+            14 => pause(side, None),
+            _ => {
+                disturbed = true;
+                outputs[side as usize].stream = None;
+            }
+        }
+    }
+    // With the source ended and nothing paused, alternating between the two
+    // streams has to run both of them to completion
+    tx.close_channel();
+    pause(Side::Left, None);
+    pause(Side::Right, None);
+    for _ in 0..(sent.len() + 4) * 4 {
+        if outputs.iter().all(Output::is_done) {
+            break;
+        }
+        outputs[0].poll(&mut cx);
+        outputs[1].poll(&mut cx);
+    }
+    assert!(
+        outputs.iter().all(Output::is_done),
+        "the split stalled after its source ended"
+    );
+    if !disturbed {
+        let (even, odd): (Vec<u8>, Vec<u8>) = sent.into_iter().partition(is_even);
+        assert_eq!(even, outputs[0].items);
+        assert_eq!(odd, outputs[1].items);
+    }
+}
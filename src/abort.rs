@@ -0,0 +1,64 @@
+use std::sync::Weak;
+
+use crate::sync::{LockFamily, Mutex, StdMutex};
+
+mod sealed {
+    /// Implemented by the shared state of each kind of split
+    pub trait Abortable {
+        type Parts;
+        fn abort(&mut self) -> Option<Self::Parts>;
+    }
+}
+
+pub(crate) use sealed::Abortable;
+
+/// A control handle for stopping a split immediately, returned by
+/// `abort_handle` on either of its output streams. The handle doesn't keep
+/// the split alive
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::SplitStreamByExt;
+///
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+/// let abort = even_stream.abort_handle();
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(0), even_stream.next().await);
+///     assert!(futures::poll!(even_stream.next()).is_pending());
+///     let mut parts = abort.abort().unwrap();
+///     assert_eq!(Some(1), parts.buffered_false);
+///     assert_eq!(Some(2), parts.stream.next().await);
+///     assert_eq!(None, even_stream.next().await);
+///     assert_eq!(None, odd_stream.next().await);
+/// });
+/// ```
+pub struct AbortHandle<T, M: LockFamily = StdMutex> {
+    state: Weak<Mutex<T, M>>,
+}
+
+impl<T, M: LockFamily> AbortHandle<T, M> {
+    pub(crate) fn new(state: Weak<Mutex<T, M>>) -> Self {
+        Self { state }
+    }
+}
+
+impl<T: Abortable, M: LockFamily> AbortHandle<T, M> {
+    /// Ends both output streams and hands back the underlying stream, which
+    /// won't have been read any further, along with everything that was
+    /// buffered for either side. Returns `None` if the split was already
+    /// aborted or both output streams have been dropped
+    pub fn abort(&self) -> Option<T::Parts> {
+        let state = self.state.upgrade()?;
+        let mut guard = state.lock();
+        guard.abort()
+    }
+}
+
+impl<T, M: LockFamily> Clone for AbortHandle<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Weak::clone(&self.state),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::delay::{Delay, Instant, Timer};
+
+/// An item yielded by a stream wrapped by `at_least_once`, to be
+/// acknowledged with `AckHandle::ack` once it has been handled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delivery<I> {
+    /// Identifies the item for `AckHandle::ack`, and stays the same when the
+    /// item is redelivered
+    pub id: u64,
+    /// How many times the item has been delivered before, 0 the first time
+    pub redeliveries: u32,
+    pub item: I,
+}
+
+struct InFlight<I> {
+    item: I,
+    due: Instant,
+    redeliveries: u32,
+}
+
+struct Acks<I> {
+    in_flight: BTreeMap<u64, InFlight<I>>,
+    /// The task reading the wrapped stream, woken by an ack since it may
+    /// have been waiting on room in the window
+    waker: Option<Waker>,
+}
+
+/// Wraps one side of a split, or any stream, so that every item it yields
+/// has to be acknowledged through the returned `AckHandle`. An item that
+/// hasn't been acknowledged within `timeout` of being yielded is yielded
+/// again, with the same id, until it is. At most `window` items are waiting
+/// to be acknowledged at once, and the underlying stream isn't read while
+/// the window is full, so an unresponsive consumer holds up the split rather
+/// than losing items.
+///
+/// Redelivered items are yielded ahead of new ones. The wrapped stream ends
+/// once the underlying stream has ended and every item has been acknowledged.
+/// To send redeliveries somewhere else, e.g. to a retry lane, acknowledge the
+/// item and hand it to a `RetryHandle` instead
+///
+/// Panics if `window` is zero
+///
+///```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use split_stream_by::at_least_once;
+///
+/// let odd_stream = futures::stream::iter([1, 3]);
+/// let (mut odd_stream, acks) = at_least_once(odd_stream, 8, Duration::from_millis(10));
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let first = odd_stream.next().await.unwrap();
+///     assert_eq!(1, first.item);
+///     let second = odd_stream.next().await.unwrap();
+///     assert_eq!(3, second.item);
+///     assert!(acks.ack(second.id));
+///     // The first item wasn't acknowledged, so it's delivered again
+///     let again = odd_stream.next().await.unwrap();
+///     assert_eq!((first.id, 1), (again.id, again.redeliveries));
+///     assert!(acks.ack(again.id));
+///     assert_eq!(None, odd_stream.next().await);
+/// });
+/// ```
+pub fn at_least_once<S>(
+    stream: S,
+    window: usize,
+    timeout: Duration,
+) -> (AtLeastOnce<S>, AckHandle<S::Item>)
+where
+    S: Stream,
+    S::Item: Clone,
+{
+    assert!(window > 0, "at least one item has to be allowed in flight");
+    let acks = Arc::new(Mutex::new(Acks {
+        in_flight: BTreeMap::new(),
+        waker: None,
+    }));
+    let handle = AckHandle {
+        acks: Arc::downgrade(&acks),
+    };
+    let stream = AtLeastOnce {
+        stream: Box::pin(stream),
+        window,
+        timeout,
+        acks,
+        next_id: 0,
+        stream_ended: false,
+        timer: None,
+    };
+    (stream, handle)
+}
+
+/// A stream wrapped by `at_least_once`, yielding its items as deliveries
+/// that have to be acknowledged
+pub struct AtLeastOnce<S: Stream> {
+    stream: Pin<Box<S>>,
+    window: usize,
+    timeout: Duration,
+    acks: Arc<Mutex<Acks<S::Item>>>,
+    next_id: u64,
+    stream_ended: bool,
+    timer: Option<Timer>,
+}
+
+impl<S> Stream for AtLeastOnce<S>
+where
+    S: Stream,
+    S::Item: Clone,
+{
+    type Item = Delivery<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            let now = Timer::now();
+            let mut acks = this.acks.lock().unwrap_or_else(PoisonError::into_inner);
+            let overdue = acks
+                .in_flight
+                .iter_mut()
+                .min_by_key(|(_, in_flight)| in_flight.due);
+            let next_due = match overdue {
+                Some((&id, in_flight)) if in_flight.due <= now => {
+                    in_flight.due = now + this.timeout;
+                    in_flight.redeliveries += 1;
+                    return Poll::Ready(Some(Delivery {
+                        id,
+                        redeliveries: in_flight.redeliveries,
+                        item: in_flight.item.clone(),
+                    }));
+                }
+                Some((_, in_flight)) => Some(in_flight.due),
+                None => None,
+            };
+            // An ack from now on wakes this stream, since it may make room in the window
+            acks.waker = Some(cx.waker().clone());
+            if !this.stream_ended && acks.in_flight.len() < this.window {
+                match this.stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(item)) => {
+                        let id = this.next_id;
+                        this.next_id += 1;
+                        acks.in_flight.insert(
+                            id,
+                            InFlight {
+                                item: item.clone(),
+                                due: now + this.timeout,
+                                redeliveries: 0,
+                            },
+                        );
+                        return Poll::Ready(Some(Delivery {
+                            id,
+                            redeliveries: 0,
+                            item,
+                        }));
+                    }
+                    Poll::Ready(None) => this.stream_ended = true,
+                    Poll::Pending => {}
+                }
+            }
+            drop(acks);
+            let due = match next_due {
+                Some(due) => due,
+                None if this.stream_ended => return Poll::Ready(None),
+                None => return Poll::Pending,
+            };
+            let wait = due.saturating_duration_since(now);
+            let timer = match &mut this.timer {
+                Some(timer) => {
+                    Delay::reset(timer, wait);
+                    timer
+                }
+                timer => timer.insert(<Timer as Delay>::new(wait)),
+            };
+            if Pin::new(timer).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<S> FusedStream for AtLeastOnce<S>
+where
+    S: Stream,
+    S::Item: Clone,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream_ended
+            && self
+                .acks
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .in_flight
+                .is_empty()
+    }
+}
+
+impl<S: Stream> fmt::Debug for AtLeastOnce<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let acks = self.acks.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("AtLeastOnce")
+            .field("window", &self.window)
+            .field("timeout", &self.timeout)
+            .field("stream_ended", &self.stream_ended)
+            .field("in_flight", &acks.in_flight.len())
+            .finish()
+    }
+}
+
+/// A handle for acknowledging the deliveries of a stream wrapped by
+/// `at_least_once`. It doesn't keep the stream alive
+pub struct AckHandle<I> {
+    acks: Weak<Mutex<Acks<I>>>,
+}
+
+impl<I> AckHandle<I> {
+    /// Acknowledges the item with `id`, so that it's never delivered again.
+    /// Returns false if it had already been acknowledged, or if the wrapped
+    /// stream has been dropped
+    pub fn ack(&self, id: u64) -> bool {
+        let acks = match self.acks.upgrade() {
+            Some(acks) => acks,
+            None => return false,
+        };
+        let mut acks = acks.lock().unwrap_or_else(PoisonError::into_inner);
+        if acks.in_flight.remove(&id).is_none() {
+            return false;
+        }
+        if let Some(waker) = acks.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+
+    /// How many items are waiting to be acknowledged
+    pub fn in_flight(&self) -> usize {
+        self.acks.upgrade().map_or(0, |acks| {
+            acks.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .in_flight
+                .len()
+        })
+    }
+}
+
+impl<I> Clone for AckHandle<I> {
+    fn clone(&self) -> Self {
+        Self {
+            acks: Weak::clone(&self.acks),
+        }
+    }
+}
+
+impl<I> fmt::Debug for AckHandle<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AckHandle")
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::{FutureExt, StreamExt};
+
+    use super::at_least_once;
+
+    #[test]
+    fn test_full_window_holds_back_stream() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut stream, acks) =
+                at_least_once(futures::stream::iter([1, 2, 3]), 2, Duration::from_secs(60));
+            let first = stream.next().await.unwrap();
+            let second = stream.next().await.unwrap();
+            assert_eq!((1, 2), (first.item, second.item));
+            assert!(stream.next().now_or_never().is_none());
+            assert!(acks.ack(first.id));
+            assert!(!acks.ack(first.id));
+            assert_eq!(Some(3), stream.next().await.map(|delivery| delivery.item));
+            assert_eq!(2, acks.in_flight());
+        })
+    }
+}
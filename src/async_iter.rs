@@ -0,0 +1,115 @@
+use std::{
+    async_iter::AsyncIterator,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{
+    sync::LockFamily, Buffer, Cascade, FalseSplitBy, FalseSplitByBufferedWith, FanOut,
+    LeftSplitByMap, LeftSplitByMapBufferedWith, PriorityClass, RightSplitByMap,
+    RightSplitByMapBufferedWith, SplitByDriven, SplitFlat, StrictPriority, Substream, TapSampled,
+    TapSampledSource, TrueSplitBy, TrueSplitByBufferedWith,
+};
+
+/// Implements `AsyncIterator` for output streams by forwarding to their
+/// `Stream` impls, with the same bounds
+macro_rules! async_iterator {
+    ($(impl[$($generics:tt)*] $ty:ty;)*) => {
+        $(
+            impl<$($generics)*> AsyncIterator for $ty
+            where
+                $ty: Stream,
+            {
+                type Item = <$ty as Stream>::Item;
+
+                fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+                    Stream::poll_next(self, cx)
+                }
+
+                fn size_hint(&self) -> (usize, Option<usize>) {
+                    Stream::size_hint(self)
+                }
+            }
+        )*
+    };
+}
+
+async_iterator! {
+    impl[I, S, P, M: LockFamily] TrueSplitBy<I, S, P, M>;
+    impl[I, S, P, M: LockFamily] FalseSplitBy<I, S, P, M>;
+    impl[I, S, P, B: Buffer<I>, M: LockFamily] TrueSplitByBufferedWith<I, S, P, B, M>;
+    impl[I, S, P, B: Buffer<I>, M: LockFamily] FalseSplitByBufferedWith<I, S, P, B, M>;
+    impl[I, L, R, S, P, M: LockFamily] LeftSplitByMap<I, L, R, S, P, M>;
+    impl[I, L, R, S, P, M: LockFamily] RightSplitByMap<I, L, R, S, P, M>;
+    impl[I, L, R, S, P, BL: Buffer<L>, BR: Buffer<R>, M: LockFamily]
+        LeftSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>;
+    impl[I, L, R, S, P, BL: Buffer<L>, BR: Buffer<R>, M: LockFamily]
+        RightSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>;
+    impl[T] SplitByDriven<T>;
+    impl[I, S, const N: usize] TapSampledSource<I, S, N>;
+    impl[I, S, const N: usize] TapSampled<I, S, N>;
+    impl[S: Stream, K: std::hash::Hash + Eq, F, const N: usize] Substream<S, K, F, N>;
+    impl[S: Stream, P, Q, const N: usize] Cascade<S, P, Q, N>;
+    impl[S: Stream<Item: IntoIterator>, P, const N: usize] SplitFlat<S, P, N>;
+    impl[S: Stream, F, const SIDES: usize, const N: usize] FanOut<S, F, SIDES, N>;
+    impl[S: Stream, F, const C: usize, const N: usize] PriorityClass<S, F, C, N>;
+    impl[S: Stream, F, const C: usize, const N: usize] StrictPriority<S, F, C, N>;
+}
+
+#[cfg(feature = "tokio")]
+async_iterator! {
+    impl[T] crate::SplitBySpawned<T>;
+}
+
+#[cfg(feature = "scoped")]
+async_iterator! {
+    impl['a, I, S, P] crate::TrueSplitByScoped<'a, I, S, P>;
+    impl['a, I, S, P] crate::FalseSplitByScoped<'a, I, S, P>;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        async_iter::AsyncIterator,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::task::noop_waker_ref;
+
+    use crate::SplitStreamByExt;
+
+    #[test]
+    fn test_poll_through_async_iterator() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (mut evens, mut odds) = incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            AsyncIterator::poll_next(Pin::new(&mut evens), &mut cx)
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            AsyncIterator::poll_next(Pin::new(&mut odds), &mut cx)
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            AsyncIterator::poll_next(Pin::new(&mut evens), &mut cx)
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            AsyncIterator::poll_next(Pin::new(&mut odds), &mut cx)
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            AsyncIterator::poll_next(Pin::new(&mut evens), &mut cx)
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            AsyncIterator::poll_next(Pin::new(&mut odds), &mut cx)
+        );
+        assert_eq!((0, Some(0)), AsyncIterator::size_hint(&evens));
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use futures::{future::Either, Stream};
+
+use crate::{
+    hooks::RouteHooks, stats::Counters, sync::Mutex, FalseSplitByBuffered, LeftSplitByMapBuffered,
+    RightSplitByMapBuffered, Side, SplitByBuffered, SplitByMapBuffered, TrueSplitByBuffered,
+};
+
+/// How full the buffers of a split were when the predicate was called for an
+/// item, handed to the predicates of `split_by_backlog` and
+/// `split_by_map_backlog`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferState {
+    buffered: [usize; 2],
+    capacity: [usize; 2],
+}
+
+impl BufferState {
+    fn read(stats: &Counters, capacity: usize) -> Self {
+        Self {
+            buffered: [
+                stats.buffered_len(Side::Left),
+                stats.buffered_len(Side::Right),
+            ],
+            capacity: [capacity; 2],
+        }
+    }
+
+    /// How many items are waiting in `side`'s buffer
+    pub fn buffered(&self, side: Side) -> usize {
+        self.buffered[side as usize]
+    }
+
+    /// How many items `side`'s buffer holds when it's full
+    pub fn capacity(&self, side: Side) -> usize {
+        self.capacity[side as usize]
+    }
+
+    /// How many more items fit in `side`'s buffer
+    pub fn remaining(&self, side: Side) -> usize {
+        self.capacity(side).saturating_sub(self.buffered(side))
+    }
+}
+
+/// The predicate `split_by_backlog` splits with, which hands the buffer state
+/// to the one it was given
+pub type BacklogPredicate<I> = Box<dyn Fn(&I) -> bool + Send>;
+
+/// The predicate `split_by_map_backlog` splits with, which hands the buffer
+/// state to the one it was given
+pub type MapBacklogPredicate<I, L, R> = Box<dyn Fn(I) -> Either<L, R> + Send>;
+
+/// The first stream returned by `split_by_backlog`
+pub type BacklogTrue<S, const N: usize> =
+    TrueSplitByBuffered<<S as Stream>::Item, S, BacklogPredicate<<S as Stream>::Item>, N>;
+
+/// The second stream returned by `split_by_backlog`
+pub type BacklogFalse<S, const N: usize> =
+    FalseSplitByBuffered<<S as Stream>::Item, S, BacklogPredicate<<S as Stream>::Item>, N>;
+
+/// The first stream returned by `split_by_map_backlog`
+pub type BacklogLeft<S, L, R, const N: usize> = LeftSplitByMapBuffered<
+    <S as Stream>::Item,
+    L,
+    R,
+    S,
+    MapBacklogPredicate<<S as Stream>::Item, L, R>,
+    N,
+>;
+
+/// The second stream returned by `split_by_map_backlog`
+pub type BacklogRight<S, L, R, const N: usize> = RightSplitByMapBuffered<
+    <S as Stream>::Item,
+    L,
+    R,
+    S,
+    MapBacklogPredicate<<S as Stream>::Item, L, R>,
+    N,
+>;
+
+/// The same as `split_by_buffered`, but the predicate is also handed the
+/// `BufferState` of the split, so that routing can adapt to how far behind
+/// each stream is. The state is read under the split's lock just before the
+/// predicate is called, so it's exact. Unless a stream's `OverflowPolicy`
+/// lets the split read past its full buffer, the predicate never sees that
+/// buffer full, since nothing is read while it is. The predicate is boxed to
+/// give the streams a type that can be named
+///
+///```rust
+/// use futures::{FutureExt, StreamExt};
+/// use split_stream_by::{split_by_backlog, Side};
+///
+/// let incoming_stream = futures::stream::iter(0..8);
+/// // Items go to the analytics stream while its buffer is under half full, and stay
+/// // on the main stream otherwise
+/// let (mut main, analytics) = split_by_backlog::<_, _, 4>(incoming_stream, |_, state| {
+///     state.buffered(Side::Right) >= state.capacity(Side::Right) / 2
+/// });
+/// // Polling only the main stream buffers items for the analytics stream until its
+/// // buffer is half full
+/// let mut main_items = Vec::new();
+/// while main_items.len() < 2 {
+///     if let Some(item) = main.next().now_or_never() {
+///         main_items.extend(item);
+///     }
+/// }
+/// assert_eq!(vec![2, 3], main_items);
+/// futures::executor::block_on(async {
+///     assert_eq!(vec![0, 1, 4, 5, 6, 7], analytics.collect::<Vec<_>>().await);
+/// });
+/// ```
+pub fn split_by_backlog<S, F, const N: usize>(
+    stream: S,
+    predicate: F,
+) -> (BacklogTrue<S, N>, BacklogFalse<S, N>)
+where
+    S: Stream,
+    F: Fn(&S::Item, BufferState) -> bool + Send + 'static,
+{
+    let stats = Arc::new(Counters::default());
+    let state = Arc::clone(&stats);
+    let predicate: BacklogPredicate<S::Item> =
+        Box::new(move |item| predicate(item, BufferState::read(&state, N)));
+    let stream = Arc::new(Mutex::new(SplitByBuffered::with_stats(
+        stream,
+        predicate,
+        RouteHooks::new(),
+        stats,
+    )));
+    (
+        TrueSplitByBuffered::new(Arc::clone(&stream)),
+        FalseSplitByBuffered::new(stream),
+    )
+}
+
+/// The same as `split_by_map_buffered`, but the predicate is also handed the
+/// `BufferState` of the split, as with `split_by_backlog`
+pub fn split_by_map_backlog<S, F, L, R, const N: usize>(
+    stream: S,
+    predicate: F,
+) -> (BacklogLeft<S, L, R, N>, BacklogRight<S, L, R, N>)
+where
+    S: Stream,
+    F: Fn(S::Item, BufferState) -> Either<L, R> + Send + 'static,
+{
+    let stats = Arc::new(Counters::default());
+    let state = Arc::clone(&stats);
+    let predicate: MapBacklogPredicate<S::Item, L, R> =
+        Box::new(move |item| predicate(item, BufferState::read(&state, N)));
+    let stream = Arc::new(Mutex::new(SplitByMapBuffered::with_stats(
+        stream,
+        predicate,
+        RouteHooks::new(),
+        stats,
+    )));
+    (
+        LeftSplitByMapBuffered::new(Arc::clone(&stream)),
+        RightSplitByMapBuffered::new(stream),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{FutureExt, StreamExt};
+
+    use super::split_by_map_backlog;
+    use crate::{Either, Side};
+
+    #[test]
+    fn test_map_backlog_sees_remaining() {
+        let incoming_stream = futures::stream::iter(0..4);
+        let (mut left, mut right) =
+            split_by_map_backlog::<_, _, _, _, 2>(incoming_stream, |n, state| {
+                if state.remaining(Side::Left) > 1 {
+                    Either::Left(n)
+                } else {
+                    Either::Right((n, state.buffered(Side::Left)))
+                }
+            });
+        // Reading only the right stream buffers items for the left until only one more
+        // would fit, after which the items go right along with how much was buffered for
+        // the left
+        let mut right_items = Vec::new();
+        while right_items.len() < 3 {
+            if let Some(item) = right.next().now_or_never() {
+                right_items.extend(item);
+            }
+        }
+        assert_eq!(vec![(1, 1), (2, 1), (3, 1)], right_items);
+        assert_eq!(Some(Some(0)), left.next().now_or_never());
+        assert_eq!(Some(None), left.next().now_or_never());
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future::Either,
+    stream::{FusedStream, Stream},
+};
+
+use crate::{
+    offload::{route_verdict, RouteVerdict},
+    Left, Right, SplitStreamByMapExt, Verdict,
+};
+
+/// The first stream returned by `split_by_batched`
+pub type BatchedLeft<S, F> = Left<
+    Batched<S, F>,
+    <S as Stream>::Item,
+    <S as Stream>::Item,
+    RouteVerdict<<S as Stream>::Item, <S as Stream>::Item>,
+>;
+
+/// The second stream returned by `split_by_batched`
+pub type BatchedRight<S, F> = Right<
+    Batched<S, F>,
+    <S as Stream>::Item,
+    <S as Stream>::Item,
+    RouteVerdict<<S as Stream>::Item, <S as Stream>::Item>,
+>;
+
+/// Splits `stream` into two streams by a `predicate` that's evaluated on a
+/// slice of the items that are ready at once, up to `max_batch` of them,
+/// rather than on one item at a time. This suits predicates that can be
+/// vectorized, and the batch is evaluated on the task polling the streams,
+/// unlike `split_by_offloaded`.
+///
+/// `predicate` has to return one `bool` per item, `true` for the first
+/// stream. A batch is evaluated as soon as `stream` has nothing more ready,
+/// so batches only fill up under load. A panic in the predicate ends both
+/// streams, and its payload can be taken with `take_panic` on either of them
+///
+/// Panics if `max_batch` is zero
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_batched;
+///
+/// let incoming_stream = futures::stream::iter([0.5, 2.0, 1.5, 0.25]);
+/// let (small, large) = split_by_batched(incoming_stream, 64, |batch: &[f64]| {
+///     batch.iter().map(|&x| x < 1.0).collect()
+/// });
+/// futures::executor::block_on(async {
+///     let (small, large) = futures::join!(small.collect::<Vec<_>>(), large.collect::<Vec<_>>());
+///     assert_eq!(vec![0.5, 0.25], small);
+///     assert_eq!(vec![2.0, 1.5], large);
+/// });
+/// ```
+pub fn split_by_batched<S, F>(
+    stream: S,
+    max_batch: usize,
+    predicate: F,
+) -> (BatchedLeft<S, F>, BatchedRight<S, F>)
+where
+    S: Stream,
+    F: Fn(&[S::Item]) -> Vec<bool>,
+{
+    assert!(max_batch > 0, "a batch has to hold at least one item");
+    let stream = Batched {
+        stream: Some(Box::pin(stream)),
+        max_batch,
+        predicate,
+        batch: Vec::new(),
+        ready: VecDeque::new(),
+        failed: false,
+    };
+    stream.split_by_map(route_verdict as RouteVerdict<S::Item, S::Item>)
+}
+
+/// The underlying stream of `split_by_batched`, which evaluates the predicate
+/// on batches of items and yields each item with the side picked for it, in
+/// the order they were read
+pub struct Batched<S: Stream, F> {
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    max_batch: usize,
+    predicate: F,
+    /// The batch being read, kept to reuse its allocation
+    batch: Vec<S::Item>,
+    ready: VecDeque<Either<S::Item, S::Item>>,
+    /// Set once a panic has been yielded
+    failed: bool,
+}
+
+// Everything that might be pinned in place is boxed
+impl<S: Stream, F> Unpin for Batched<S, F> {}
+
+impl<S, F> Stream for Batched<S, F>
+where
+    S: Stream,
+    F: Fn(&[S::Item]) -> Vec<bool>,
+{
+    type Item = Verdict<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(item) = this.ready.pop_front() {
+            return Poll::Ready(Some(Ok(item)));
+        }
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        while this.batch.len() < this.max_batch {
+            let stream = match &mut this.stream {
+                Some(stream) => stream,
+                None => break,
+            };
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => this.batch.push(item),
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+        if this.batch.is_empty() {
+            return if this.stream.is_none() {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        }
+        let predicate = &this.predicate;
+        let batch = &this.batch;
+        let verdicts = match panic::catch_unwind(AssertUnwindSafe(|| predicate(batch))) {
+            Ok(verdicts) => verdicts,
+            Err(payload) => {
+                this.failed = true;
+                this.batch.clear();
+                return Poll::Ready(Some(Err(payload)));
+            }
+        };
+        assert_eq!(
+            this.batch.len(),
+            verdicts.len(),
+            "the batched predicate has to return one verdict per item"
+        );
+        this.ready
+            .extend(this.batch.drain(..).zip(verdicts).map(|(item, is_left)| {
+                if is_left {
+                    Either::Left(item)
+                } else {
+                    Either::Right(item)
+                }
+            }));
+        Poll::Ready(this.ready.pop_front().map(Ok))
+    }
+}
+
+impl<S, F> FusedStream for Batched<S, F>
+where
+    S: Stream,
+    F: Fn(&[S::Item]) -> Vec<bool>,
+{
+    fn is_terminated(&self) -> bool {
+        self.ready.is_empty() && (self.failed || self.stream.is_none())
+    }
+}
+
+impl<S: Stream, F> fmt::Debug for Batched<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batched")
+            .field("max_batch", &self.max_batch)
+            .field("stream_ended", &self.stream.is_none())
+            .field("ready", &self.ready.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use futures::StreamExt;
+
+    use super::split_by_batched;
+
+    #[test]
+    fn test_batched_predicate_batches_ready_items() {
+        let batch_sizes = RefCell::new(Vec::new());
+        let (small, large) = split_by_batched(futures::stream::iter(0..7), 3, |batch: &[i32]| {
+            batch_sizes.borrow_mut().push(batch.len());
+            batch.iter().map(|&n| n < 4).collect()
+        });
+        futures::executor::block_on(async {
+            let (small, large) =
+                futures::join!(small.collect::<Vec<_>>(), large.collect::<Vec<_>>());
+            assert_eq!(vec![0, 1, 2, 3], small);
+            assert_eq!(vec![4, 5, 6], large);
+        });
+        assert_eq!(vec![3, 3, 1], *batch_sizes.borrow());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::Side;
+
+/// Determines which output stream of a buffered split is served first when
+/// both are being polled, set with `set_bias` on either of them. The side
+/// that isn't favored only reads from the underlying stream once everything
+/// buffered for the favored side has been taken, so items for the favored
+/// side are never left waiting behind a run of items for the other. Without a
+/// bias, whichever side happens to be polled reads ahead until the other
+/// side's buffer is full.
+///
+/// The unbuffered splits always behave as if they had the `Alternate` bias,
+/// since an item waiting for one side already blocks the other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PollBias {
+    /// The left stream is served first
+    LeftFirst,
+    /// The right stream is served first
+    RightFirst,
+    /// Each stream waits for the other one to take what's buffered for it
+    /// before reading ahead, so they take turns
+    Alternate,
+    /// While both streams are busy, each takes up to its weight in items per
+    /// round before it has to wait for the other one to take what's buffered
+    /// for it, so weights of 3 and 1 give 3 left items for every right item.
+    /// A round ends once both sides have had their share. A weight of 0 is
+    /// treated as 1
+    Weighted {
+        /// Items the left stream takes per round
+        left: u32,
+        /// Items the right stream takes per round
+        right: u32,
+    },
+}
+
+/// Applies a `PollBias`, keeping count of what each side has taken in the
+/// current round for `PollBias::Weighted`
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    bias: Option<PollBias>,
+    taken: [u32; 2],
+}
+
+impl Scheduler {
+    pub(crate) fn set(&mut self, bias: Option<PollBias>) {
+        self.bias = bias;
+        self.taken = [0, 0];
+    }
+
+    /// Whether `side` has to wait for the other side to take everything
+    /// buffered for it before reading from the underlying stream
+    pub(crate) fn defers(&self, side: Side) -> bool {
+        match self.bias {
+            None => false,
+            Some(PollBias::LeftFirst) => side == Side::Right,
+            Some(PollBias::RightFirst) => side == Side::Left,
+            Some(PollBias::Alternate) => true,
+            Some(PollBias::Weighted { left, right }) => {
+                let weight = if side == Side::Left { left } else { right };
+                self.taken[side as usize] >= weight.max(1)
+            }
+        }
+    }
+
+    /// Records that `side` yielded an item, returning `true` if that ended
+    /// the round so the other side may no longer have to wait
+    pub(crate) fn yielded(&mut self, side: Side) -> bool {
+        if let Some(PollBias::Weighted { left, right }) = self.bias {
+            let taken = &mut self.taken[side as usize];
+            *taken = taken.saturating_add(1);
+            if self.taken[Side::Left as usize] >= left.max(1)
+                && self.taken[Side::Right as usize] >= right.max(1)
+            {
+                self.taken = [0, 0];
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Caps how many items in a row one side of a split may yield before it has
+/// to give the other side a chance to run, set with `set_max_consecutive`
+#[derive(Default)]
+pub(crate) struct Streaks {
+    max: Option<u32>,
+    /// The side that yielded last and how many items in a row it has yielded
+    run: Option<(Side, u32)>,
+}
+
+impl Streaks {
+    pub(crate) fn set(&mut self, max: Option<u32>) {
+        self.max = max.map(|max| max.max(1));
+        self.run = None;
+    }
+
+    /// Whether `side` has used up its run and has to give way before yielding
+    /// again. Giving way starts a new run
+    pub(crate) fn give_way(&mut self, side: Side) -> bool {
+        match (self.max, self.run) {
+            (Some(max), Some((last, count))) if last == side && count >= max => {
+                self.run = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn yielded(&mut self, side: Side) {
+        self.run = match self.run {
+            Some((last, count)) if last == side => Some((side, count.saturating_add(1))),
+            _ => Some((side, 1)),
+        };
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{GrowableRingBuf, RingBuf};
+
+/// The queue a buffered split keeps the items waiting for each side in. It's
+/// implemented by `RingBuf`, whose capacity is fixed at compile time, and by
+/// `GrowableRingBuf`, whose capacity is picked at runtime
+pub trait Buffer<T> {
+    /// Iterates over the items taken out of the buffer by `drain`
+    type Drain<'a>: Iterator<Item = T>
+    where
+        Self: 'a;
+
+    /// How many items are in the buffer
+    fn len(&self) -> usize;
+
+    /// How many more items fit in the buffer
+    fn remaining(&self) -> usize;
+
+    /// Adds `item` to the back of the buffer, handing it back if the buffer is
+    /// full
+    fn push_back(&mut self, item: T) -> Option<T>;
+
+    /// Takes the oldest item out of the buffer
+    fn pop_front(&mut self) -> Option<T>;
+
+    /// Returns the oldest item in the buffer without taking it out
+    fn peek_front(&self) -> Option<&T>;
+
+    /// Takes every item out of the buffer, oldest first. Anything the iterator
+    /// hasn't yielded when it's dropped is dropped with it
+    fn drain(&mut self) -> Self::Drain<'_>;
+
+    /// Moves the items into a new buffer, leaving an empty one with the same
+    /// capacity in its place
+    fn take(&mut self) -> Self;
+
+    /// Whether the buffer holds no items
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Buffer<T> for RingBuf<T, N> {
+    type Drain<'a>
+        = crate::Drain<'a, T, N>
+    where
+        T: 'a;
+
+    fn len(&self) -> usize {
+        RingBuf::len(self)
+    }
+
+    fn remaining(&self) -> usize {
+        RingBuf::remaining(self)
+    }
+
+    fn push_back(&mut self, item: T) -> Option<T> {
+        RingBuf::push_back(self, item)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        RingBuf::pop_front(self)
+    }
+
+    fn peek_front(&self) -> Option<&T> {
+        RingBuf::peek_front(self)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        RingBuf::drain(self)
+    }
+
+    fn take(&mut self) -> Self {
+        std::mem::take(self)
+    }
+}
+
+impl<T> Buffer<T> for GrowableRingBuf<T> {
+    type Drain<'a>
+        = std::collections::vec_deque::Drain<'a, T>
+    where
+        T: 'a;
+
+    fn len(&self) -> usize {
+        GrowableRingBuf::len(self)
+    }
+
+    fn remaining(&self) -> usize {
+        GrowableRingBuf::remaining(self)
+    }
+
+    fn push_back(&mut self, item: T) -> Option<T> {
+        GrowableRingBuf::push_back(self, item)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        GrowableRingBuf::pop_front(self)
+    }
+
+    fn peek_front(&self) -> Option<&T> {
+        GrowableRingBuf::peek_front(self)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        GrowableRingBuf::drain(self)
+    }
+
+    fn take(&mut self) -> Self {
+        let empty = GrowableRingBuf::new(self.initial(), self.max());
+        std::mem::replace(self, empty)
+    }
+}
@@ -0,0 +1,802 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use futures::{
+    future::{BoxFuture, Either},
+    FutureExt, Stream, StreamExt,
+};
+
+use crate::{
+    fan_out::{fan_out_with_lock, FanOut},
+    source::Source,
+    sync::{LockFamily, Mutex, StdMutex},
+    FalseSplitBy, FalseSplitByBufferedWith, LeftSplitByMap, LeftSplitByMapBufferedWith,
+    OverflowPolicy, PollBias, RightSplitByMap, RightSplitByMapBufferedWith, RouteHooks, SplitBy,
+    SplitByBufferedWith, SplitByMap, SplitByMapBufferedWith, TrueSplitBy, TrueSplitByBufferedWith,
+    WakeStrategy,
+};
+
+/// Marks a `SplitBuilder` that will build a split without buffers beyond the
+/// single item held for each side
+pub struct Unbuffered;
+
+/// Marks a `SplitBuilder` that will buffer up to `N` items for each side
+pub struct Buffered<const N: usize>;
+
+/// Marks a `SplitBuilder` that will buffer items for each side in a
+/// `GrowableRingBuf`, whose capacity is picked at runtime
+pub struct Growable {
+    initial: usize,
+    max: usize,
+}
+
+mod sealed {
+    use crate::{Buffer, GrowableRingBuf, RingBuf, RouteHooks};
+
+    use super::{Buffered, Growable};
+
+    /// Either `Buffered` or `Growable`, making the buffers of a buffered split
+    pub trait BuilderBuffers {
+        type Buffer<T>: Buffer<T>;
+
+        fn make<T>(&self) -> Self::Buffer<T>;
+    }
+
+    impl<const N: usize> BuilderBuffers for Buffered<N> {
+        type Buffer<T> = RingBuf<T, N>;
+
+        fn make<T>(&self) -> RingBuf<T, N> {
+            RingBuf::new()
+        }
+    }
+
+    impl BuilderBuffers for Growable {
+        type Buffer<T> = GrowableRingBuf<T>;
+
+        fn make<T>(&self) -> GrowableRingBuf<T> {
+            GrowableRingBuf::new(self.initial, self.max)
+        }
+    }
+
+    /// Either `()` for no hooks, or the `RouteHooks` passed to
+    /// `SplitBuilder::hooks`
+    pub trait BuilderHooks<L, R> {
+        fn into_hooks(self) -> RouteHooks<L, R>;
+    }
+
+    impl<L, R> BuilderHooks<L, R> for () {
+        fn into_hooks(self) -> RouteHooks<L, R> {
+            RouteHooks::new()
+        }
+    }
+
+    impl<L, R> BuilderHooks<L, R> for RouteHooks<L, R> {
+        fn into_hooks(self) -> RouteHooks<L, R> {
+            self
+        }
+    }
+}
+
+use sealed::{BuilderBuffers, BuilderHooks};
+
+/// Takes the underlying stream of a split built with `drain_on_drop` once
+/// both of its sides have been dropped: the function takes the stream out of
+/// its `Source` and turns it into a future that reads it to the end, which is
+/// handed to the boxed `spawn`. A function pointer doesn't tie the split's
+/// drop to `S` the way a boxed closure taking the stream would, which
+/// `split_by_scoped` relies on, and it's only made for an `Unpin` stream, so
+/// taking the stream out of the split's state is fine
+pub(crate) struct DrainSource<S> {
+    spawn: Box<dyn FnOnce(BoxFuture<'static, ()>) + Send>,
+    drain: fn(&mut Source<S>) -> Option<BoxFuture<'static, ()>>,
+}
+
+impl<S> DrainSource<S> {
+    pub(crate) fn drain(self, source: &mut Source<S>) {
+        if let Some(read) = (self.drain)(source) {
+            (self.spawn)(read);
+        }
+    }
+}
+
+fn read_to_end<S>(source: &mut Source<S>) -> Option<BoxFuture<'static, ()>>
+where
+    S: Stream + Unpin + Send + 'static,
+{
+    let stream = source.take()?;
+    Some(stream.for_each(|_| async {}).boxed())
+}
+
+/// Collects the options for splitting a stream before picking the kind of
+/// split with one of the `build_*` methods. Each option is optional, and
+/// leaving them all out gives the same result as the methods of
+/// `SplitStreamByExt` and `SplitStreamByMapExt`. The buffer size is a const
+/// generic, so it's set with `buffer::<N>()` rather than taking a value,
+/// unless it's only known at runtime, which `growable_buffer` is for
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{RouteHooks, Side, SplitBuilder};
+///
+/// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+/// let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+///     .buffer::<3>()
+///     .hooks(RouteHooks::new().on_route(|side, n| {
+///         assert_eq!(side == Side::Left, n % 2 == 0);
+///     }))
+///     .build_by(|&n| n % 2 == 0);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(
+///         even_stream.collect::<Vec<_>>(),
+///         odd_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0,2,4], evens);
+///     assert_eq!(vec![1,3,5], odds);
+/// });
+/// ```
+pub struct SplitBuilder<S, B = Unbuffered, H = (), M = StdMutex> {
+    stream: S,
+    buffering: B,
+    lock: PhantomData<M>,
+    hooks: H,
+    drain: Option<DrainSource<S>>,
+    wake: Option<[Box<dyn WakeStrategy>; 2]>,
+    bias: Option<PollBias>,
+    overflow: Option<OverflowPolicy>,
+}
+
+impl<S> SplitBuilder<S>
+where
+    S: Stream,
+{
+    /// Starts building a split of `stream` with the default options
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffering: Unbuffered,
+            lock: PhantomData,
+            hooks: (),
+            drain: None,
+            wake: None,
+            bias: None,
+            overflow: None,
+        }
+    }
+}
+
+impl<S, B, H, M> SplitBuilder<S, B, H, M> {
+    /// Buffers up to N items for each side instead of just one. As with
+    /// `split_by_buffered`, N must be at least 1
+    pub fn buffer<const N: usize>(self) -> SplitBuilder<S, Buffered<N>, H, M> {
+        self.buffering(Buffered)
+    }
+
+    /// Buffers items for each side in a `GrowableRingBuf` that starts out
+    /// with room for `initial` of them and grows to hold up to `max`, for
+    /// when the buffer size is only known at runtime. Panics if `max` is 0 or
+    /// smaller than `initial`
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitBuilder;
+    ///
+    /// let max = 3;
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+    ///     .growable_buffer(1, max)
+    ///     .build_by(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     let (evens, odds) = futures::join!(
+    ///         even_stream.collect::<Vec<_>>(),
+    ///         odd_stream.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0,2,4], evens);
+    ///     assert_eq!(vec![1,3,5], odds);
+    /// });
+    /// ```
+    pub fn growable_buffer(self, initial: usize, max: usize) -> SplitBuilder<S, Growable, H, M> {
+        assert!(max > 0, "a buffer needs room for at least one item");
+        assert!(
+            initial <= max,
+            "the initial capacity can't exceed the maximum"
+        );
+        self.buffering(Growable { initial, max })
+    }
+
+    fn buffering<C>(self, buffering: C) -> SplitBuilder<S, C, H, M> {
+        SplitBuilder {
+            stream: self.stream,
+            buffering,
+            lock: PhantomData,
+            hooks: self.hooks,
+            drain: self.drain,
+            wake: self.wake,
+            bias: self.bias,
+            overflow: self.overflow,
+        }
+    }
+
+    /// Calls `hooks` from the splitter as items are routed and buffered
+    pub fn hooks<L, R>(self, hooks: RouteHooks<L, R>) -> SplitBuilder<S, B, RouteHooks<L, R>, M> {
+        SplitBuilder {
+            stream: self.stream,
+            buffering: self.buffering,
+            lock: PhantomData,
+            hooks,
+            drain: self.drain,
+            wake: self.wake,
+            bias: self.bias,
+            overflow: self.overflow,
+        }
+    }
+
+    /// Guards the shared state of the split with the lock picked by `L`
+    /// rather than `std::sync::Mutex`. See `LockFamily` for the choices
+    pub fn lock<L: LockFamily>(self) -> SplitBuilder<S, B, H, L> {
+        SplitBuilder {
+            stream: self.stream,
+            buffering: self.buffering,
+            lock: PhantomData,
+            hooks: self.hooks,
+            drain: self.drain,
+            wake: self.wake,
+            bias: self.bias,
+            overflow: self.overflow,
+        }
+    }
+
+    /// Reads the underlying stream to the end, discarding its items, once
+    /// both output streams have been dropped, rather than dropping it part
+    /// way through. This is for sources that only release resources held
+    /// elsewhere once they've been read to the end, such as database cursors.
+    /// The future that does the reading is handed to `spawn`, e.g.
+    /// `|drain| { tokio::spawn(drain); }`. Nothing is drained if the stream
+    /// has already ended, the predicate panicked, or the stream was taken
+    /// back with `into_parts` or `AbortHandle::abort`
+    ///
+    ///```rust
+    /// use std::sync::{
+    ///     atomic::{AtomicUsize, Ordering},
+    ///     Arc,
+    /// };
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitBuilder;
+    ///
+    /// let read = Arc::new(AtomicUsize::new(0));
+    /// let incoming_stream = futures::stream::iter(0..10).inspect({
+    ///     let read = Arc::clone(&read);
+    ///     move |_| {
+    ///         read.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// });
+    /// let (mut even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+    ///     .drain_on_drop(futures::executor::block_on)
+    ///     .build_by(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     assert_eq!(Some(0), even_stream.next().await);
+    /// });
+    /// drop((even_stream, odd_stream));
+    /// assert_eq!(10, read.load(Ordering::Relaxed));
+    /// ```
+    pub fn drain_on_drop(self, spawn: impl FnOnce(BoxFuture<'static, ()>) + Send + 'static) -> Self
+    where
+        S: Stream + Unpin + Send + 'static,
+    {
+        let drain = DrainSource {
+            spawn: Box::new(spawn),
+            drain: read_to_end::<S>,
+        };
+        Self {
+            drain: Some(drain),
+            ..self
+        }
+    }
+}
+
+impl<S, H, M: LockFamily> SplitBuilder<S, Unbuffered, H, M>
+where
+    S: Stream,
+{
+    /// Splits the stream by a predicate, like `split_by`
+    pub fn build_by<P>(
+        self,
+        predicate: P,
+    ) -> (
+        TrueSplitBy<S::Item, S, P, M>,
+        FalseSplitBy<S::Item, S, P, M>,
+    )
+    where
+        P: Fn(&S::Item) -> bool,
+        H: BuilderHooks<S::Item, S::Item>,
+    {
+        let stream = SplitBy::new_shared(self.stream, predicate, self.hooks.into_hooks());
+        let (first, second) = (TrueSplitBy::new(stream.clone()), FalseSplitBy::new(stream));
+        if let Some(drain) = self.drain {
+            first.set_drain_on_drop(drain);
+        }
+        (first, second)
+    }
+
+    /// Splits the stream by a mapping predicate, like `split_by_map`
+    pub fn build_by_map<P, L, R>(
+        self,
+        predicate: P,
+    ) -> (
+        LeftSplitByMap<S::Item, L, R, S, P, M>,
+        RightSplitByMap<S::Item, L, R, S, P, M>,
+    )
+    where
+        P: Fn(S::Item) -> Either<L, R>,
+        H: BuilderHooks<L, R>,
+    {
+        let stream = SplitByMap::new_shared(self.stream, predicate, self.hooks.into_hooks());
+        let (first, second) = (
+            LeftSplitByMap::new(stream.clone()),
+            RightSplitByMap::new(stream),
+        );
+        if let Some(drain) = self.drain {
+            first.set_drain_on_drop(drain);
+        }
+        (first, second)
+    }
+}
+
+impl<S, B: BuilderBuffers, H, M: LockFamily> SplitBuilder<S, B, H, M>
+where
+    S: Stream,
+{
+    /// Decides when each output stream is woken for items buffered for it,
+    /// instead of for every item. See `WakeStrategy` for how it works
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{SplitBuilder, Threshold};
+    ///
+    /// let incoming_stream = futures::stream::iter(0..10);
+    /// let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+    ///     .buffer::<4>()
+    ///     .wake_strategy(Threshold(4))
+    ///     .build_by(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     let (evens, odds) = futures::join!(
+    ///         even_stream.collect::<Vec<_>>(),
+    ///         odd_stream.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 2, 4, 6, 8], evens);
+    ///     assert_eq!(vec![1, 3, 5, 7, 9], odds);
+    /// });
+    /// ```
+    pub fn wake_strategy(self, strategy: impl WakeStrategy + Clone + 'static) -> Self {
+        Self {
+            wake: Some([Box::new(strategy.clone()), Box::new(strategy)]),
+            ..self
+        }
+    }
+
+    /// Sets which output stream is served first when both are being polled.
+    /// See `PollBias` for how it works
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{OverflowPolicy, PollBias, SplitBuilder};
+    ///
+    /// let incoming_stream = futures::stream::iter(0..10);
+    /// let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+    ///     .buffer::<2>()
+    ///     .overflow_policy(OverflowPolicy::DropOldest)
+    ///     .bias(PollBias::LeftFirst)
+    ///     .build_by(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     let (evens, odds) = futures::join!(
+    ///         even_stream.collect::<Vec<_>>(),
+    ///         odd_stream.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 2, 4, 6, 8], evens);
+    ///     assert_eq!(vec![1, 3, 5, 7, 9], odds);
+    /// });
+    /// ```
+    pub fn bias(self, bias: PollBias) -> Self {
+        Self {
+            bias: Some(bias),
+            ..self
+        }
+    }
+
+    /// Sets what happens to items routed to either output stream while its
+    /// buffer is full. Each stream's policy can be changed afterwards with
+    /// its own `set_overflow_policy`. See `OverflowPolicy` for the choices
+    pub fn overflow_policy(self, policy: OverflowPolicy) -> Self {
+        Self {
+            overflow: Some(policy),
+            ..self
+        }
+    }
+
+    /// Splits the stream by a predicate, like `split_by_buffered`
+    pub fn build_by<P>(
+        self,
+        predicate: P,
+    ) -> (
+        TrueSplitByBufferedWith<S::Item, S, P, B::Buffer<S::Item>, M>,
+        FalseSplitByBufferedWith<S::Item, S, P, B::Buffer<S::Item>, M>,
+    )
+    where
+        P: Fn(&S::Item) -> bool,
+        H: BuilderHooks<S::Item, S::Item>,
+    {
+        let state = SplitByBufferedWith::with_buffers(
+            self.stream,
+            predicate,
+            self.hooks.into_hooks(),
+            self.buffering.make(),
+            self.buffering.make(),
+        );
+        let stream = Arc::new(Mutex::new(state));
+        let (first, second) = (
+            TrueSplitByBufferedWith::new(stream.clone()),
+            FalseSplitByBufferedWith::new(stream),
+        );
+        if let Some(drain) = self.drain {
+            first.set_drain_on_drop(drain);
+        }
+        if let Some(strategy) = self.wake {
+            first.set_wake_strategy(strategy);
+        }
+        if let Some(bias) = self.bias {
+            first.set_bias(Some(bias));
+        }
+        if let Some(policy) = self.overflow {
+            first.set_overflow_policy(policy);
+            second.set_overflow_policy(policy);
+        }
+        (first, second)
+    }
+
+    /// Splits the stream by a mapping predicate, like `split_by_map_buffered`
+    pub fn build_by_map<P, L, R>(
+        self,
+        predicate: P,
+    ) -> (
+        LeftSplitByMapBufferedWith<S::Item, L, R, S, P, B::Buffer<L>, B::Buffer<R>, M>,
+        RightSplitByMapBufferedWith<S::Item, L, R, S, P, B::Buffer<L>, B::Buffer<R>, M>,
+    )
+    where
+        P: Fn(S::Item) -> Either<L, R>,
+        H: BuilderHooks<L, R>,
+    {
+        let state = SplitByMapBufferedWith::with_buffers(
+            self.stream,
+            predicate,
+            self.hooks.into_hooks(),
+            self.buffering.make(),
+            self.buffering.make(),
+        );
+        let stream = Arc::new(Mutex::new(state));
+        let (first, second) = (
+            LeftSplitByMapBufferedWith::new(stream.clone()),
+            RightSplitByMapBufferedWith::new(stream),
+        );
+        if let Some(drain) = self.drain {
+            first.set_drain_on_drop(drain);
+        }
+        if let Some(strategy) = self.wake {
+            first.set_wake_strategy(strategy);
+        }
+        if let Some(bias) = self.bias {
+            first.set_bias(Some(bias));
+        }
+        if let Some(policy) = self.overflow {
+            first.set_overflow_policy(policy);
+            second.set_overflow_policy(policy);
+        }
+        (first, second)
+    }
+}
+
+impl<S, M: LockFamily> SplitBuilder<S, Unbuffered, (), M>
+where
+    S: Stream,
+{
+    /// Splits the stream into SIDES outputs by `route`, like `fan_out`. Of
+    /// the other options, only the lock applies
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Local, SplitBuilder};
+    ///
+    /// let incoming_stream = futures::stream::iter(0..6);
+    /// let [zeros, ones, twos] = SplitBuilder::new(incoming_stream)
+    ///     .lock::<Local>()
+    ///     .build_fan_out(|&n| n % 3);
+    /// futures::executor::block_on(async {
+    ///     let (zeros, ones, twos) = futures::join!(
+    ///         zeros.collect::<Vec<_>>(),
+    ///         ones.collect::<Vec<_>>(),
+    ///         twos.collect::<Vec<_>>(),
+    ///     );
+    ///     assert_eq!(vec![0, 3], zeros);
+    ///     assert_eq!(vec![1, 4], ones);
+    ///     assert_eq!(vec![2, 5], twos);
+    /// });
+    /// ```
+    pub fn build_fan_out<F, const SIDES: usize>(
+        self,
+        route: F,
+    ) -> [FanOut<S, F, SIDES, 1, M>; SIDES]
+    where
+        S: Unpin,
+        F: Fn(&S::Item) -> usize,
+    {
+        fan_out_with_lock(self.stream, route)
+    }
+}
+
+impl<S, M: LockFamily, const N: usize> SplitBuilder<S, Buffered<N>, (), M>
+where
+    S: Stream,
+{
+    /// Splits the stream into SIDES outputs by `route`, like
+    /// `fan_out_buffered`. Of the other options, only the buffer size and the
+    /// lock apply
+    pub fn build_fan_out<F, const SIDES: usize>(
+        self,
+        route: F,
+    ) -> [FanOut<S, F, SIDES, N, M>; SIDES]
+    where
+        S: Unpin,
+        F: Fn(&S::Item) -> usize,
+    {
+        fan_out_with_lock(self.stream, route)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::SplitBuilder;
+    use crate::{Either, OverflowPolicy, PollBias, RouteHooks};
+
+    #[test]
+    fn test_unbuffered_by_default() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 3, 0]);
+        let (mut evens, mut odds) = SplitBuilder::new(incoming_stream).build_by(|&n| n % 2 == 0);
+        // 1 is held for the odds, so 3 can't be read
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let (_sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (evens, _odds) = SplitBuilder::new(receiver)
+            .buffer::<4>()
+            .build_by(|&n| n % 2 == 0);
+        let inject = evens.inject_handle();
+        for n in 0..4 {
+            assert_eq!(Ok(()), inject.inject_left(n));
+        }
+        assert_eq!(Err(4), inject.inject_left(4));
+    }
+
+    #[test]
+    fn test_growable_buffer_size() {
+        let max = 5;
+        let (_sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (evens, _odds) = SplitBuilder::new(receiver)
+            .growable_buffer(1, max)
+            .build_by(|&n| n % 2 == 0);
+        let inject = evens.inject_handle();
+        for n in 0..5 {
+            assert_eq!(Ok(()), inject.inject_left(n));
+        }
+        assert_eq!(Err(5), inject.inject_left(5));
+    }
+
+    #[test]
+    fn test_growable_buffer_by_map() {
+        let incoming_stream = futures::stream::iter(0..16);
+        let (evens, odds) = SplitBuilder::new(incoming_stream)
+            .growable_buffer(1, 8)
+            .build_by_map(|n: u32| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n.to_string())
+                }
+            });
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+            assert_eq!((0..16).step_by(2).collect::<Vec<_>>(), evens);
+            assert_eq!(
+                (1..16)
+                    .step_by(2)
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>(),
+                odds
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "a buffer needs room for at least one item")]
+    fn test_growable_buffer_of_nothing_panics() {
+        let incoming_stream = futures::stream::iter(0..2);
+        let _ = SplitBuilder::new(incoming_stream).growable_buffer(0, 0);
+    }
+
+    #[test]
+    fn test_hooks_see_discards() {
+        let discarded = Arc::new(AtomicUsize::new(0));
+        let incoming_stream = futures::stream::iter(0..6);
+        let (evens, odds) = SplitBuilder::new(incoming_stream)
+            .buffer::<2>()
+            .hooks(RouteHooks::new().on_discard_right({
+                let discarded = Arc::clone(&discarded);
+                move |_: String| {
+                    discarded.fetch_add(1, Ordering::SeqCst);
+                }
+            }))
+            .build_by_map(|n: u32| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n.to_string())
+                }
+            });
+        drop(odds);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 2, 4], evens.collect::<Vec<_>>().await);
+        });
+        assert_eq!(3, discarded.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_bias() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 0, 3]);
+        let (mut evens, mut odds) = SplitBuilder::new(incoming_stream)
+            .buffer::<2>()
+            .bias(PollBias::LeftFirst)
+            .build_by(|&n| n % 2 == 0);
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        // The 0 was buffered for the evens, which go first, so the odds can't
+        // read on to the 3
+        assert_eq!(Poll::Pending, odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odds.poll_next_unpin(&mut cx));
+    }
+
+    /// Polls `stream` to the end without ever waiting, as a side that
+    /// doesn't have to wait on its sibling can be
+    fn poll_to_end<T>(stream: &mut (impl futures::Stream<Item = T> + Unpin)) -> Vec<T> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut items = Vec::new();
+        loop {
+            match stream.poll_next_unpin(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return items,
+                Poll::Pending => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow_policy_for_both_sides() {
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..10))
+            .buffer::<2>()
+            .overflow_policy(OverflowPolicy::DropNewest)
+            .build_by_map(|n: u32| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n.to_string())
+                }
+            });
+        assert_eq!(vec![0, 2, 4, 6, 8], poll_to_end(&mut evens));
+        assert_eq!(vec!["1", "3"], poll_to_end(&mut odds));
+
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..10))
+            .buffer::<2>()
+            .overflow_policy(OverflowPolicy::DropOldest)
+            .build_by(|&n| n % 2 == 0);
+        assert_eq!(vec![1, 3, 5, 7, 9], poll_to_end(&mut odds));
+        assert_eq!(vec![6, 8], poll_to_end(&mut evens));
+    }
+
+    /// A source of 0..10 counting how many items have been read from it, and
+    /// a `spawn` for `drain_on_drop` counting how many drains it was handed
+    /// before running them
+    fn counted() -> (
+        impl futures::Stream<Item = u32> + Unpin + Send + 'static,
+        Arc<AtomicUsize>,
+        impl FnOnce(futures::future::BoxFuture<'static, ()>) + Send + 'static,
+        Arc<AtomicUsize>,
+    ) {
+        let read = Arc::new(AtomicUsize::new(0));
+        let stream = futures::stream::iter(0..10).inspect({
+            let read = Arc::clone(&read);
+            move |_| {
+                read.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let drains = Arc::new(AtomicUsize::new(0));
+        let spawn = {
+            let drains = Arc::clone(&drains);
+            move |drain| {
+                drains.fetch_add(1, Ordering::SeqCst);
+                futures::executor::block_on(drain);
+            }
+        };
+        (stream, read, spawn, drains)
+    }
+
+    #[test]
+    fn test_drain_waits_for_both_sides() {
+        let (stream, read, spawn, drains) = counted();
+        let (mut evens, mut odds) = SplitBuilder::new(stream)
+            .buffer::<4>()
+            .drain_on_drop(spawn)
+            .build_by(|&n| n % 2 == 0);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        drop(evens);
+        assert_eq!(0, drains.load(Ordering::SeqCst));
+        // The odds go on reading, and discard the evens they come across
+        assert_eq!(Poll::Ready(Some(3)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(4, read.load(Ordering::SeqCst));
+        drop(odds);
+        assert_eq!(1, drains.load(Ordering::SeqCst));
+        assert_eq!(10, read.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_drain_after_mid_buffer_drop() {
+        let (stream, read, spawn, drains) = counted();
+        let (mut evens, odds) = SplitBuilder::new(stream)
+            .buffer::<4>()
+            .drain_on_drop(spawn)
+            .build_by(|&n| n < 5);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+        drop(evens);
+        // The items still buffered for nobody are dropped and the rest is read
+        drop(odds);
+        assert_eq!(1, drains.load(Ordering::SeqCst));
+        assert_eq!(10, read.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_no_drain_after_end() {
+        let (stream, read, spawn, drains) = counted();
+        let (evens, odds) =
+            SplitBuilder::new(stream)
+                .drain_on_drop(spawn)
+                .build_by_map(|n: u32| {
+                    if n % 2 == 0 {
+                        Either::Left(n)
+                    } else {
+                        Either::Right(n)
+                    }
+                });
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+            assert_eq!((5, 5), (evens.len(), odds.len()));
+        });
+        assert_eq!(10, read.load(Ordering::SeqCst));
+        assert_eq!(0, drains.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+/// Wraps one side of a split, or any stream, so that when it has nothing
+/// ready it's polled again up to `spins` times, with a spin loop hint in
+/// between, before `Pending` is returned. An item that turns up within the
+/// spin is yielded straight away instead of going through a wake and a trip
+/// through the executor, which cuts the tail latency of a side fed by a
+/// source that produces often, such as a market data feed, at the cost of
+/// burning CPU on the polling thread while it's quiet.
+///
+/// The spin only helps when the stream can make progress while it's being
+/// polled, i.e. when its source is filled from another thread. Once the spin
+/// runs out the stream is left waiting on its waker as usual, so a quiet
+/// side costs at most `spins` extra polls each time it's polled. The spin
+/// stops early if a poll wakes the task itself, as a side does when it finds
+/// the split's lock taken, since the task is due to be polled again anyway.
+///
+/// A side that's waiting for the other side to make room in its buffer
+/// wakes the other side on each poll, so spinning on it wakes the other side
+/// again each time around. That's harmless, since the other side is already
+/// due to be polled, but it's wasted work, so a side that's often held up
+/// that way is better off with a larger buffer than with a longer spin
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{busy_poll, SplitStreamByExt};
+///
+/// let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+/// let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<4>(|&n| n % 2 == 0);
+/// let even_stream = busy_poll(even_stream, 64);
+/// let odd_stream = busy_poll(odd_stream, 64);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(
+///         even_stream.collect::<Vec<_>>(),
+///         odd_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0, 2], evens);
+///     assert_eq!(vec![1, 3], odds);
+/// });
+/// ```
+pub fn busy_poll<S: Stream + Unpin>(stream: S, spins: u32) -> BusyPoll<S> {
+    BusyPoll {
+        stream,
+        spins,
+        waker: None,
+    }
+}
+
+/// A stream wrapped by `busy_poll`, which spins on the underlying stream for
+/// a while before returning `Pending`
+pub struct BusyPoll<S> {
+    stream: S,
+    spins: u32,
+    /// The waker handed to the underlying stream while spinning, reused for
+    /// as long as the task polling doesn't change
+    waker: Option<(Arc<SpinWake>, Waker)>,
+}
+
+/// Forwards wakes to the task polling a `BusyPoll`, noting that it was woken
+struct SpinWake {
+    woken: AtomicBool,
+    waker: Waker,
+}
+
+impl Wake for SpinWake {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.waker.wake_by_ref();
+    }
+}
+
+impl<S> BusyPoll<S> {
+    /// Changes how many more times the underlying stream is polled before
+    /// returning `Pending`, 0 turning the spin off
+    pub fn set_spins(&mut self, spins: u32) {
+        self.spins = spins;
+    }
+
+    fn spin_waker(&mut self, waker: &Waker) -> (Arc<SpinWake>, Waker) {
+        match &self.waker {
+            Some((spin, _)) if spin.waker.will_wake(waker) => {}
+            _ => {
+                let spin = Arc::new(SpinWake {
+                    woken: AtomicBool::new(false),
+                    waker: waker.clone(),
+                });
+                self.waker = Some((Arc::clone(&spin), Waker::from(spin)));
+            }
+        }
+        self.waker.clone().expect("the spin waker was just set")
+    }
+}
+
+impl<S: Stream + Unpin> Stream for BusyPoll<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        if this.spins == 0 {
+            return Pin::new(&mut this.stream).poll_next(cx);
+        }
+        let (spin, waker) = this.spin_waker(cx.waker());
+        let mut spin_cx = Context::from_waker(&waker);
+        let mut spins = this.spins;
+        loop {
+            spin.woken.store(false, Ordering::Relaxed);
+            match Pin::new(&mut this.stream).poll_next(&mut spin_cx) {
+                // A wake during the poll means the task is due to be polled again, which
+                // spinning would only get ahead of
+                Poll::Pending if spins > 0 && !spin.woken.load(Ordering::Acquire) => {
+                    spins -= 1;
+                    std::hint::spin_loop();
+                }
+                // The last poll has registered the waker, so returning Pending after it
+                // is the same as for the unwrapped stream
+                poll => return poll,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S: FusedStream + Unpin> FusedStream for BusyPoll<S> {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl<S> fmt::Debug for BusyPoll<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BusyPoll")
+            .field("spins", &self.spins)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, FutureExt, StreamExt};
+
+    use super::busy_poll;
+    use crate::SplitStreamByExt;
+
+    #[test]
+    fn test_item_within_spin() {
+        // The source only has an item on its fourth poll, and never wakes the task
+        let source = |polls: u32| {
+            let mut polled = 0;
+            futures::stream::poll_fn(move |_| {
+                polled += 1;
+                if polled == polls {
+                    Poll::Ready(Some(polled))
+                } else {
+                    Poll::Pending
+                }
+            })
+        };
+        let mut spinning = busy_poll(source(4), 3);
+        assert_eq!(Some(Some(4)), spinning.next().now_or_never());
+        let mut too_short = busy_poll(source(4), 2);
+        assert_eq!(None, too_short.next().now_or_never());
+        too_short.set_spins(0);
+        assert_eq!(Some(Some(4)), too_short.next().now_or_never());
+    }
+
+    #[test]
+    fn test_self_wake_stops_spin() {
+        let mut polled = 0;
+        let source = futures::stream::poll_fn(|cx| {
+            polled += 1;
+            cx.waker().wake_by_ref();
+            Poll::<Option<u32>>::Pending
+        });
+        let mut spinning = busy_poll(source, 8);
+        assert_eq!(None, spinning.next().now_or_never());
+        drop(spinning);
+        assert_eq!(1, polled);
+    }
+
+    #[test]
+    fn test_spin_on_full_sibling() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 3, 0]);
+        let (even_stream, mut odd_stream) = incoming_stream.split_by_buffered::<1>(|&n| n % 2 == 0);
+        let mut even_stream = busy_poll(even_stream, 8);
+        // 3 can't be read while 1 fills the odd buffer, however long the even side spins
+        assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), even_stream.poll_next_unpin(&mut cx));
+    }
+}
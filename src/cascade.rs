@@ -0,0 +1,237 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{fan_out, fanout_core::FanoutCore, sync::Mutex};
+
+struct CascadeState<S: Stream, P, Q, const N: usize> {
+    core: FanoutCore<S::Item, S, 3, N>,
+    outer: P,
+    inner: Q,
+}
+
+/// Splits a stream three ways by two predicates applied in turn: items for
+/// which `outer` returns `true` go to the first stream, the rest go to the
+/// second stream if `inner` returns `true` for them and to the third
+/// otherwise. This gives the same streams as splitting the `false` stream of
+/// one split again,
+///
+/// ```text
+/// let (first, rest) = stream.split_by_buffered::<N>(outer);
+/// let (second, third) = rest.split_by_buffered::<N>(inner);
+/// ```
+///
+/// but with one lock and one set of wakers shared by all three streams, so
+/// routing an item costs one round of locking and waking rather than one for
+/// each level. Up to N items are buffered for each stream, and as with the
+/// buffered splits nothing is read while a buffer is full. Dropping a stream
+/// discards what's buffered for it and anything routed to it from then on
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_cascade;
+///
+/// let incoming_stream = futures::stream::iter(0..10);
+/// let (small, medium, large) = split_by_cascade::<_, _, _, 4>(incoming_stream, |&n| n < 3, |&n| n < 7);
+/// futures::executor::block_on(async {
+///     let (small, medium, large) = futures::join!(
+///         small.collect::<Vec<_>>(),
+///         medium.collect::<Vec<_>>(),
+///         large.collect::<Vec<_>>(),
+///     );
+///     assert_eq!(vec![0, 1, 2], small);
+///     assert_eq!(vec![3, 4, 5, 6], medium);
+///     assert_eq!(vec![7, 8, 9], large);
+/// });
+/// ```
+pub fn split_by_cascade<S, P, Q, const N: usize>(
+    stream: S,
+    outer: P,
+    inner: Q,
+) -> (
+    Cascade<S, P, Q, N>,
+    Cascade<S, P, Q, N>,
+    Cascade<S, P, Q, N>,
+)
+where
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+    Q: Fn(&S::Item) -> bool,
+{
+    let state = Arc::new(Mutex::new(CascadeState {
+        core: FanoutCore::new(stream),
+        outer,
+        inner,
+    }));
+    let side = |side| Cascade {
+        state: Arc::clone(&state),
+        side,
+    };
+    (side(0), side(1), side(2))
+}
+
+/// One of the three streams returned by `split_by_cascade`
+pub struct Cascade<S: Stream, P, Q, const N: usize> {
+    state: Arc<Mutex<CascadeState<S, P, Q, N>>>,
+    side: usize,
+}
+
+impl<S, P, Q, const N: usize> Stream for Cascade<S, P, Q, N>
+where
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+    Q: Fn(&S::Item) -> bool,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut guard = self.state.lock();
+        let CascadeState { core, outer, inner } = &mut *guard;
+        core.poll_side(self.side, cx, |item| {
+            if outer(item) {
+                0
+            } else if inner(item) {
+                1
+            } else {
+                2
+            }
+        })
+        .map(fan_out::routed)
+    }
+}
+
+impl<S, P, Q, const N: usize> FusedStream for Cascade<S, P, Q, N>
+where
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+    Q: Fn(&S::Item) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.state.try_lock() {
+            Some(guard) => guard.core.is_terminated(self.side).unwrap_or(true),
+            // Another output is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<S: Stream, P, Q, const N: usize> Drop for Cascade<S, P, Q, N> {
+    fn drop(&mut self) {
+        self.state
+            .lock_or_defer(|state, side| drop(state.core.close(side)), self.side);
+    }
+}
+
+impl<S: Stream, P, Q, const N: usize> fmt::Debug for Cascade<S, P, Q, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Cascade");
+        debug.field("side", &self.side);
+        match self.state.try_lock() {
+            Some(guard) => debug.field("core", &guard.core),
+            // Another output is in the middle of being polled
+            None => debug.field("core", &format_args!("<locked>")),
+        };
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::split_by_cascade;
+    use crate::SplitStreamByExt;
+
+    #[test]
+    fn test_outer_checked_first() {
+        let inner_calls = Arc::new(AtomicUsize::new(0));
+        let incoming_stream = futures::stream::iter(0..10);
+        let (small, medium, large) = split_by_cascade::<_, _, _, 4>(incoming_stream, |&n| n < 3, {
+            let inner_calls = Arc::clone(&inner_calls);
+            // Also true for everything the outer predicate takes
+            move |&n| {
+                inner_calls.fetch_add(1, Ordering::SeqCst);
+                n < 7
+            }
+        });
+        futures::executor::block_on(async {
+            let (small, medium, large) = futures::join!(
+                small.collect::<Vec<_>>(),
+                medium.collect::<Vec<_>>(),
+                large.collect::<Vec<_>>(),
+            );
+            assert_eq!(vec![0, 1, 2], small);
+            assert_eq!(vec![3, 4, 5, 6], medium);
+            assert_eq!(vec![7, 8, 9], large);
+        });
+        // The inner predicate only sees what the outer one passed on
+        assert_eq!(7, inner_calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_matches_nested_split() {
+        let outer = |n: &u32| n % 3 == 0;
+        let inner = |n: &u32| n % 2 == 0;
+        let (first, second, third) =
+            split_by_cascade::<_, _, _, 2>(futures::stream::iter(0..30), outer, inner);
+        let (nested_first, rest) = futures::stream::iter(0..30).split_by_buffered::<2>(outer);
+        let (nested_second, nested_third) = rest.split_by_buffered::<2>(inner);
+        futures::executor::block_on(async {
+            let cascade = futures::join!(
+                first.collect::<Vec<_>>(),
+                second.collect::<Vec<_>>(),
+                third.collect::<Vec<_>>(),
+            );
+            let nested = futures::join!(
+                nested_first.collect::<Vec<_>>(),
+                nested_second.collect::<Vec<_>>(),
+                nested_third.collect::<Vec<_>>(),
+            );
+            assert_eq!(nested, cascade);
+        });
+    }
+
+    #[test]
+    fn test_full_buffer_holds_up_every_output() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([5, 6, 0, 9]);
+        let (mut small, mut medium, mut large) =
+            split_by_cascade::<_, _, _, 1>(incoming_stream, |&n| n < 3, |&n| n < 7);
+        // 6 can't be routed while 5 fills the medium buffer, so neither the small nor
+        // the large output gets anywhere
+        assert_eq!(Poll::Pending, small.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, large.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(5)), medium.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(6)), medium.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), small.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(9)), large.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), small.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_dropped_output_discards() {
+        let incoming_stream = futures::stream::iter(0..10);
+        let (small, medium, large) =
+            split_by_cascade::<_, _, _, 1>(incoming_stream, |&n| n < 3, |&n| n < 7);
+        drop(medium);
+        futures::executor::block_on(async {
+            let (small, large) =
+                futures::join!(small.collect::<Vec<_>>(), large.collect::<Vec<_>>());
+            assert_eq!(vec![0, 1, 2], small);
+            assert_eq!(vec![7, 8, 9], large);
+        });
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(debug_assertions)]
+use std::task::Waker;
+use std::task::{Context, Poll};
+
+/// Debug-only bookkeeping that notices a handle to an output stream being
+/// polled by a different task than the one it last left waiting. Only a
+/// single waker is kept per handle, so if both tasks go on waiting on the same
+/// handle the first one is never woken. Moving a stream to another task (e.g.
+/// handing it off after a `select!`, or polling it from inside a
+/// `FuturesUnordered`) looks the same from here and is perfectly fine, so this
+/// only counts the polls rather than asserting. With the `tracing` feature the
+/// first one for each handle is reported as a warning
+#[derive(Default)]
+pub(crate) struct ConsumerCheck {
+    #[cfg(debug_assertions)]
+    waiting: Option<Waker>,
+    #[cfg(debug_assertions)]
+    contended: usize,
+}
+
+impl ConsumerCheck {
+    /// Notes if a different task is polling the stream than the one it last
+    /// returned `Poll::Pending` to
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    pub(crate) fn check(&mut self, name: &str, cx: &Context<'_>) {
+        #[cfg(debug_assertions)]
+        if let Some(waker) = &self.waiting {
+            if !waker.will_wake(cx.waker()) {
+                self.contended += 1;
+                #[cfg(feature = "tracing")]
+                if self.contended == 1 {
+                    tracing::warn!(
+                        "{} was polled by a different task than the one it left waiting. That's \
+                         fine if the stream was handed off, but if both tasks go on polling it, \
+                         clone the stream instead",
+                        name
+                    );
+                }
+                #[cfg(not(feature = "tracing"))]
+                let _ = name;
+            }
+        }
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    pub(crate) fn record<T>(&mut self, cx: &Context<'_>, poll: &Poll<T>) {
+        #[cfg(debug_assertions)]
+        {
+            self.waiting = if poll.is_pending() {
+                Some(cx.waker().clone())
+            } else {
+                None
+            };
+        }
+    }
+
+    /// How many times the stream was polled by a different task than the one
+    /// it left waiting
+    #[cfg(all(test, debug_assertions))]
+    pub(crate) fn contended(&self) -> usize {
+        self.contended
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod test {
+    use std::task::{Context, Poll};
+
+    use super::ConsumerCheck;
+    use crate::test_util::CountingWaker;
+
+    /// Polls through `check` the way an output stream does, returning
+    /// `Poll::Pending` as it would while waiting on the other side
+    fn poll(check: &mut ConsumerCheck, task: &CountingWaker) {
+        let waker = task.waker();
+        let cx = Context::from_waker(&waker);
+        check.check("test", &cx);
+        check.record(&cx, &Poll::<()>::Pending);
+    }
+
+    #[test]
+    fn test_same_task_not_counted() {
+        let task = CountingWaker::new();
+        let mut check = ConsumerCheck::default();
+        poll(&mut check, &task);
+        poll(&mut check, &task);
+        assert_eq!(0, check.contended());
+    }
+
+    #[test]
+    fn test_two_tasks_counted() {
+        let (task_a, task_b) = (CountingWaker::new(), CountingWaker::new());
+        let mut check = ConsumerCheck::default();
+        poll(&mut check, &task_a);
+        poll(&mut check, &task_b);
+        poll(&mut check, &task_a);
+        assert_eq!(2, check.contended());
+    }
+
+    #[test]
+    fn test_not_counted_after_ready() {
+        let (task_a, task_b) = (CountingWaker::new(), CountingWaker::new());
+        let mut check = ConsumerCheck::default();
+        poll(&mut check, &task_a);
+        let waker = task_a.waker();
+        check.record(&Context::from_waker(&waker), &Poll::Ready(()));
+        // Nobody was left waiting, so another task taking over is fine
+        poll(&mut check, &task_b);
+        assert_eq!(0, check.contended());
+    }
+}
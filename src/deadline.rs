@@ -0,0 +1,288 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    delay::{Delay, Instant, Timer},
+    Side,
+};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    stream::{FusedStream, Stream},
+};
+
+/// Determines what happens to an item that has waited in an output stream's
+/// buffer for longer than the deadline set with `set_deadline`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadlinePolicy {
+    /// Yield the item from the other stream instead, the next time that one
+    /// is polled
+    Reroute,
+    /// Drop the item
+    Drop,
+    /// Send the item to the stream returned by `dead_letters`, or drop it if
+    /// there isn't one
+    DeadLetter,
+}
+
+/// Tracks when each buffered item was buffered so that items can be taken out
+/// of the buffers once they've waited too long. The timestamps are kept in
+/// the same order as the buffers themselves. The timer is a parameter so that
+/// the tests can fire it when they like
+pub(crate) struct Deadlines<I, D = Timer> {
+    deadline: Option<(Duration, DeadlinePolicy)>,
+    enqueued: [VecDeque<Instant>; 2],
+    /// The timer for when the oldest item buffered for each side expires,
+    /// along with when that is. It's polled by the other side
+    timers: [Option<(Instant, D)>; 2],
+    dead_letters: Option<UnboundedSender<I>>,
+}
+
+impl<I, D> Default for Deadlines<I, D> {
+    fn default() -> Self {
+        Self {
+            deadline: None,
+            enqueued: Default::default(),
+            timers: [None, None],
+            dead_letters: None,
+        }
+    }
+}
+
+impl<I, D: Delay> Deadlines<I, D> {
+    pub(crate) fn set(&mut self, deadline: Duration, policy: DeadlinePolicy) {
+        self.deadline = Some((deadline, policy));
+        self.timers = [None, None];
+    }
+
+    /// Brings the timestamps for `side` in line with the buffer now holding
+    /// `len` items. Growing means items were just buffered, while shrinking
+    /// means they were dropped
+    pub(crate) fn buffered(&mut self, side: Side, len: usize) {
+        let enqueued = &mut self.enqueued[side as usize];
+        if enqueued.len() < len {
+            let now = D::now();
+            enqueued.resize(len, now);
+        } else {
+            enqueued.truncate(len);
+        }
+    }
+
+    /// Swaps the timestamps of the two sides along with their buffers. The
+    /// timers are dropped since each is polled by the side that no longer
+    /// deals with it
+    pub(crate) fn swap(&mut self) {
+        self.enqueued.swap(0, 1);
+        self.timers = [None, None];
+    }
+
+    /// Forgets the oldest item buffered for `side`, which was just taken out
+    /// of the buffer
+    pub(crate) fn unbuffered(&mut self, side: Side) {
+        self.enqueued[side as usize].pop_front();
+    }
+
+    /// Returns the policy to apply to the oldest item buffered for `side` if
+    /// it has waited for longer than the deadline
+    pub(crate) fn expired(&self, side: Side) -> Option<DeadlinePolicy> {
+        let (deadline, policy) = self.deadline?;
+        let enqueued = self.enqueued[side as usize].front()?;
+        (enqueued.elapsed() >= deadline).then_some(policy)
+    }
+
+    /// Hands an expired item that isn't being rerouted to the dead letter
+    /// stream, handing it back if it's to be discarded instead
+    pub(crate) fn discard(&mut self, item: I) -> Option<I> {
+        if self.deadline.map(|(_, policy)| policy) != Some(DeadlinePolicy::DeadLetter) {
+            return Some(item);
+        }
+        if let Some(sender) = &self.dead_letters {
+            match sender.unbounded_send(item) {
+                Ok(()) => return None,
+                Err(err) => {
+                    // The dead letter stream was dropped
+                    self.dead_letters = None;
+                    return Some(err.into_inner());
+                }
+            }
+        }
+        Some(item)
+    }
+
+    /// Makes sure the task in `cx` is woken once the oldest item buffered for
+    /// `side` expires. This is polled by the other side, since it's the one
+    /// that deals with it
+    pub(crate) fn poll_expiry(&mut self, side: Side, cx: &mut Context<'_>) {
+        let expires_at = match (self.deadline, self.enqueued[side as usize].front()) {
+            (Some((deadline, _)), Some(&enqueued)) => enqueued + deadline,
+            _ => {
+                self.timers[side as usize] = None;
+                return;
+            }
+        };
+        let timer = &mut self.timers[side as usize];
+        match timer {
+            Some((at, delay)) if *at == expires_at => {
+                if Pin::new(delay).poll(cx).is_ready() {
+                    // It expired after it was checked, so have it dealt with right away
+                    *timer = None;
+                    cx.waker().wake_by_ref();
+                }
+            }
+            _ => {
+                let mut delay = D::new(expires_at.saturating_duration_since(D::now()));
+                if Pin::new(&mut delay).poll(cx).is_ready() {
+                    cx.waker().wake_by_ref();
+                } else {
+                    *timer = Some((expires_at, delay));
+                }
+            }
+        }
+    }
+
+    /// Returns a new dead letter stream, ending the previous one if there was
+    /// one
+    pub(crate) fn dead_letters(&mut self) -> DeadLetters<I> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.dead_letters = Some(sender);
+        DeadLetters { receiver }
+    }
+}
+
+/// A stream of the items that expired with `DeadlinePolicy::DeadLetter`,
+/// created with `dead_letters` on either output stream. Items are queued
+/// without bound until they're read, and the stream ends once the splitter is
+/// dropped or a newer dead letter stream replaces it
+pub struct DeadLetters<I> {
+    receiver: UnboundedReceiver<I>,
+}
+
+impl<I> Stream for DeadLetters<I> {
+    type Item = I;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.receiver.size_hint()
+    }
+}
+
+impl<I> FusedStream for DeadLetters<I> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::{Cell, RefCell},
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures::StreamExt;
+
+    use super::{DeadlinePolicy, Deadlines};
+    use crate::{delay::Delay, test_util::CountingWaker, Side};
+
+    thread_local! {
+        /// Whether the mock timers made on this thread have fired
+        static FIRED: Cell<bool> = const { Cell::new(false) };
+        /// How long each mock timer made on this thread was set for
+        static SET_FOR: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// A timer that fires when the test says so rather than when its time is up
+    struct MockDelay;
+
+    impl MockDelay {
+        /// Clears what earlier tests run on this thread left behind
+        fn reset_all() {
+            FIRED.with(|fired| fired.set(false));
+            SET_FOR.with(|set_for| set_for.take());
+        }
+    }
+
+    impl Future for MockDelay {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if FIRED.with(Cell::get) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Delay for MockDelay {
+        fn new(after: Duration) -> Self {
+            SET_FOR.with(|set_for| set_for.borrow_mut().push(after));
+            MockDelay
+        }
+
+        fn reset(&mut self, after: Duration) {
+            SET_FOR.with(|set_for| set_for.borrow_mut().push(after));
+        }
+    }
+
+    #[test]
+    fn test_deadline_not_reached() {
+        MockDelay::reset_all();
+        let wakes = CountingWaker::new();
+        let waker = wakes.waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut deadlines = Deadlines::<u32, MockDelay>::default();
+        deadlines.set(Duration::from_secs(60), DeadlinePolicy::Drop);
+        deadlines.buffered(Side::Left, 1);
+        deadlines.poll_expiry(Side::Left, &mut cx);
+        deadlines.poll_expiry(Side::Left, &mut cx);
+        assert_eq!(0, wakes.wakes());
+        assert_eq!(None, deadlines.expired(Side::Left));
+        // One timer is set for the time left until the item expires, and kept
+        let set_for = SET_FOR.with(|set_for| set_for.take());
+        assert_eq!(1, set_for.len());
+        assert!(set_for[0] > Duration::from_secs(59) && set_for[0] <= Duration::from_secs(60));
+        // Once nothing is buffered, there's nothing to wait for
+        deadlines.unbuffered(Side::Left);
+        deadlines.poll_expiry(Side::Left, &mut cx);
+        assert!(deadlines.timers[Side::Left as usize].is_none());
+    }
+
+    #[test]
+    fn test_deadline_fires() {
+        MockDelay::reset_all();
+        let wakes = CountingWaker::new();
+        let waker = wakes.waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut deadlines = Deadlines::<u32, MockDelay>::default();
+        let dead_letters = deadlines.dead_letters();
+        deadlines.set(Duration::ZERO, DeadlinePolicy::DeadLetter);
+        deadlines.buffered(Side::Right, 1);
+        deadlines.poll_expiry(Side::Right, &mut cx);
+        assert_eq!(0, wakes.wakes());
+        // The timer firing wakes the task to deal with the expired item
+        FIRED.with(|fired| fired.set(true));
+        deadlines.poll_expiry(Side::Right, &mut cx);
+        assert_eq!(1, wakes.wakes());
+        assert!(deadlines.timers[Side::Right as usize].is_none());
+        assert_eq!(
+            Some(DeadlinePolicy::DeadLetter),
+            deadlines.expired(Side::Right)
+        );
+        deadlines.unbuffered(Side::Right);
+        assert_eq!(None, deadlines.discard(7));
+        drop(deadlines);
+        futures::executor::block_on(async {
+            assert_eq!(vec![7], dead_letters.collect::<Vec<_>>().await);
+        });
+    }
+}
@@ -0,0 +1,159 @@
+#[cfg(feature = "time")]
+use std::time::Duration;
+
+#[cfg(feature = "time")]
+use crate::stall;
+use crate::{trace::trace_event, Side};
+
+/// When an output stream that isn't keeping up is switched to dropping the
+/// items routed to it, set with `set_auto_degrade` on either stream. While a
+/// stream is degraded the other one keeps flowing instead of waiting on it,
+/// and the dropped items are counted in `Stats`. It recovers as soon as it's
+/// polled again, keeping whatever was already buffered for it. This turns
+/// forgetting to poll a best-effort stream into lost items rather than a hang
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoDegrade {
+    /// Degrade a stream once this many items are waiting in its buffer. The
+    /// unbuffered splits only ever buffer one item, so anything above 1
+    /// never triggers for them, and 0 is treated as 1
+    Buffered(usize),
+    /// Degrade a stream with items waiting in its buffer once it hasn't been
+    /// polled for this long
+    #[cfg(feature = "time")]
+    Idle(Duration),
+}
+
+/// Tracks which sides of a split have been degraded under an `AutoDegrade`
+#[derive(Default)]
+pub(crate) struct Degrader {
+    policy: Option<AutoDegrade>,
+    /// When the policy was set, as returned by `stall::now_nanos`, which
+    /// counts as the last poll of a side that hasn't been polled since
+    #[cfg(feature = "time")]
+    since: u64,
+    /// When each side was last polled, only kept under `AutoDegrade::Idle`
+    #[cfg(feature = "time")]
+    last_poll: [u64; 2],
+    degraded: [bool; 2],
+}
+
+impl Degrader {
+    pub(crate) fn set(&mut self, policy: Option<AutoDegrade>) {
+        self.policy = policy;
+        #[cfg(feature = "time")]
+        {
+            self.since = stall::now_nanos();
+        }
+        self.degraded = [false, false];
+    }
+
+    /// Degrades `side` if it has `buffered` items waiting and isn't keeping
+    /// up, returning whether it's degraded
+    pub(crate) fn check(&mut self, side: Side, buffered: usize) -> bool {
+        if self.degraded[side as usize] || buffered == 0 {
+            return self.degraded[side as usize];
+        }
+        let degrade = match self.policy {
+            None => false,
+            Some(AutoDegrade::Buffered(limit)) => buffered >= limit.max(1),
+            #[cfg(feature = "time")]
+            Some(AutoDegrade::Idle(idle)) => {
+                let last_poll = self.last_poll[side as usize].max(self.since);
+                Duration::from_nanos(stall::now_nanos().saturating_sub(last_poll)) >= idle
+            }
+        };
+        if degrade {
+            trace_event!(?side, "stream degraded");
+            self.degraded[side as usize] = true;
+        }
+        degrade
+    }
+
+    pub(crate) fn is_degraded(&self, side: Side) -> bool {
+        self.degraded[side as usize]
+    }
+
+    /// Recovers `side` now that it's being polled
+    pub(crate) fn polled(&mut self, side: Side) {
+        self.degraded[side as usize] = false;
+        #[cfg(feature = "time")]
+        if let Some(AutoDegrade::Idle(_)) = self.policy {
+            self.last_poll[side as usize] = stall::now_nanos();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{AutoDegrade, Degrader};
+    use crate::{Side, SplitStreamByExt};
+
+    #[test]
+    fn test_buffered_limit() {
+        let mut degrader = Degrader::default();
+        // Nothing degrades without a policy
+        assert!(!degrader.check(Side::Left, 100));
+        degrader.set(Some(AutoDegrade::Buffered(3)));
+        assert!(!degrader.check(Side::Left, 2));
+        assert!(degrader.check(Side::Left, 3));
+        // It stays degraded, whatever is buffered, until it's polled
+        assert!(degrader.check(Side::Left, 0));
+        assert!(!degrader.is_degraded(Side::Right));
+        degrader.polled(Side::Left);
+        assert!(!degrader.check(Side::Left, 2));
+    }
+
+    #[test]
+    fn test_zero_limit_needs_buffered_item() {
+        let mut degrader = Degrader::default();
+        degrader.set(Some(AutoDegrade::Buffered(0)));
+        assert!(!degrader.check(Side::Right, 0));
+        assert!(degrader.check(Side::Right, 1));
+        // Setting a policy starts afresh
+        degrader.set(Some(AutoDegrade::Buffered(0)));
+        assert!(!degrader.is_degraded(Side::Right));
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_idle() {
+        let idle = std::time::Duration::from_millis(10);
+        let mut degrader = Degrader::default();
+        degrader.set(Some(AutoDegrade::Idle(idle)));
+        assert!(!degrader.check(Side::Left, 1));
+        std::thread::sleep(idle * 2);
+        // An empty buffer never degrades, however long the side has been idle
+        assert!(!degrader.check(Side::Right, 0));
+        assert!(degrader.check(Side::Left, 1));
+        degrader.polled(Side::Left);
+        assert!(!degrader.check(Side::Left, 1));
+    }
+
+    #[test]
+    fn test_recovery_keeps_buffered_items() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 3, 5, 0, 7]);
+        let (mut even_stream, mut odd_stream) =
+            incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        even_stream.set_auto_degrade(Some(AutoDegrade::Buffered(2)));
+        let mut poll_even = || loop {
+            if let Poll::Ready(n) = even_stream.poll_next_unpin(&mut cx) {
+                break n;
+            }
+        };
+        // 1 and 3 degrade the odd stream, so 5 is dropped
+        assert_eq!(Some(0), poll_even());
+        // Polling the odd stream recovers it, so the 7 is buffered for it again
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(None, poll_even());
+        assert_eq!(1, odd_stream.stats_handle().snapshot().dropped_right);
+        assert_eq!(Poll::Ready(Some(3)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(7)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), odd_stream.poll_next_unpin(&mut cx));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{future::Future, time::Duration};
+
+#[cfg(not(any(feature = "timer", feature = "tokio-timer")))]
+compile_error!(
+    "the `time` feature needs a timer to wait on, so enable `timer`, `tokio-timer` or \
+     `wasm-timer` instead"
+);
+
+/// The point in time the time based features measure from: the browser's
+/// clock with the `wasm-timer` feature, where `std::time::Instant` isn't
+/// available, and `std::time::Instant` otherwise
+#[cfg(feature = "wasm-timer")]
+pub type Instant = web_time::Instant;
+#[cfg(not(feature = "wasm-timer"))]
+pub type Instant = std::time::Instant;
+
+/// A timer that the time based features (deadlines, rate limits, stall
+/// watching and `AutoDegrade::Idle`) wait on, and the clock they read, so
+/// that they aren't tied to one runtime. Which implementation they use is
+/// picked by feature, see `Timer`, and it can be used directly to wait on
+/// the same timer
+///
+///```rust
+/// use std::time::Duration;
+/// use split_stream_by::{Delay, Timer};
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let start = Timer::now();
+/// Timer::new(Duration::from_millis(10)).await;
+/// assert!(Timer::now() - start >= Duration::from_millis(10));
+/// # });
+/// ```
+pub trait Delay: Future<Output = ()> + Unpin + Send {
+    /// Creates a timer that fires once `after` has passed
+    fn new(after: Duration) -> Self;
+
+    /// Restarts the timer so that it fires once `after` has passed from now
+    fn reset(&mut self, after: Duration);
+
+    /// The current time on the clock this timer runs on
+    fn now() -> Instant {
+        Instant::now()
+    }
+}
+
+/// The timer used by the time based features: tokio's with the `tokio-timer`
+/// feature, which needs a tokio runtime with its time driver enabled, and
+/// otherwise `futures-timer`'s, which runs its own timer thread and works
+/// with any executor, or waits on the browser's `setTimeout` with the
+/// `wasm-timer` feature when built for `wasm32`, where tokio's timer isn't
+/// available
+#[cfg(all(
+    feature = "tokio-timer",
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+))]
+pub type Timer = tokio_delay::TokioDelay;
+#[cfg(not(all(
+    feature = "tokio-timer",
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+)))]
+pub type Timer = futures_timer::Delay;
+
+#[cfg(feature = "timer")]
+impl Delay for futures_timer::Delay {
+    fn new(after: Duration) -> Self {
+        futures_timer::Delay::new(after)
+    }
+
+    fn reset(&mut self, after: Duration) {
+        futures_timer::Delay::reset(self, after)
+    }
+}
+
+#[cfg(all(
+    feature = "tokio-timer",
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+))]
+pub(crate) mod tokio_delay {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use super::{Delay, Instant};
+
+    /// A `tokio::time::Sleep`, boxed so that it can be polled without pinning
+    /// whatever holds it
+    pub struct TokioDelay(Pin<Box<tokio::time::Sleep>>);
+
+    impl Future for TokioDelay {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.0.as_mut().poll(cx)
+        }
+    }
+
+    impl Delay for TokioDelay {
+        fn new(after: Duration) -> Self {
+            Self(Box::pin(tokio::time::sleep(after)))
+        }
+
+        fn reset(&mut self, after: Duration) {
+            self.0.as_mut().reset(tokio::time::Instant::now() + after)
+        }
+
+        /// Reads tokio's clock, so that a runtime whose time is paused is
+        /// followed here too
+        fn now() -> Instant {
+            tokio::time::Instant::now().into_std()
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+#[cfg(feature = "time")]
+use std::{future::Future, time::Duration};
+
+use futures::{stream::FusedStream, Stream};
+
+#[cfg(feature = "time")]
+use crate::delay::{Delay, Instant, Timer};
+use crate::ring_buf::RingBuf;
+
+/// Splits a stream into one substream per key, where `key` picks each item's
+/// key. The returned stream yields a `(key, Substream)` pair the first time
+/// each key is seen, and the substream then yields every item with that key,
+/// starting with the one that announced it. This fits spawning a task per
+/// key, e.g. per connection or session, with `for_each_concurrent`.
+///
+/// Each key buffers up to N items. As with `split_by_buffered`, nothing more
+/// is read from the underlying stream while any key's buffer is full, so a
+/// substream that isn't being polled holds up the rest. Dropping a substream
+/// discards the rest of its key's items, and dropping the returned stream
+/// discards the items of keys that haven't been announced yet. Everything
+/// ends once the underlying stream has ended and been drained.
+///
+/// `Demux::set_max_buffered` holds each key to fewer than N items, and with
+/// the `timer` feature `Demux::set_idle_timeout` evicts keys that have gone
+/// quiet, so that a long running router neither waits forever on a stuck
+/// key nor keeps every key it has ever seen
+///
+///```rust
+/// use std::sync::Mutex;
+/// use futures::StreamExt;
+/// use split_stream_by::demux_by_key;
+///
+/// let incoming_stream = futures::stream::iter([("a", 1), ("b", 2), ("a", 3), ("b", 4)]);
+/// futures::executor::block_on(async {
+///     let sessions = Mutex::new(Vec::new());
+///     demux_by_key::<_, _, _, 2>(incoming_stream, |&(session, _)| session)
+///         .for_each_concurrent(None, |(session, items)| {
+///             let sessions = &sessions;
+///             async move {
+///                 let values = items.map(|(_, n)| n).collect::<Vec<_>>().await;
+///                 sessions.lock().unwrap().push((session, values));
+///             }
+///         })
+///         .await;
+///     let mut sessions = sessions.into_inner().unwrap();
+///     sessions.sort();
+///     assert_eq!(vec![("a", vec![1, 3]), ("b", vec![2, 4])], sessions);
+/// });
+/// ```
+pub fn demux_by_key<S, K, F, const N: usize>(stream: S, key: F) -> Demux<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    Demux {
+        state: Arc::new(Mutex::new(DemuxState {
+            stream: Some(Box::pin(stream)),
+            key,
+            keys: HashMap::new(),
+            announced: VecDeque::new(),
+            full: 0,
+            max_buffered: N,
+            next_id: 0,
+            waiting: Vec::new(),
+            waker: None,
+            dropped: false,
+            #[cfg(feature = "time")]
+            idle: None,
+        })),
+    }
+}
+
+/// What's kept for each key that has been seen
+struct KeyState<I, const N: usize> {
+    buf: RingBuf<I, N>,
+    waker: Option<Waker>,
+    /// Tells this key's substream apart from those of keys with the same
+    /// value that were evicted before it
+    id: u64,
+    /// Set once the key's substream has been yielded by `Demux`, after which
+    /// the key can be evicted
+    yielded: bool,
+    /// Set once the key's substream has been dropped, after which its items
+    /// are discarded
+    dropped: bool,
+    /// When an item was last routed to or taken from the key
+    #[cfg(feature = "time")]
+    active: Instant,
+}
+
+struct DemuxState<S: Stream, K, F, const N: usize> {
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    key: F,
+    keys: HashMap<K, KeyState<S::Item, N>>,
+    /// Keys whose substreams are waiting to be yielded by `Demux`
+    announced: VecDeque<K>,
+    /// How many keys have a full buffer, holding up the underlying stream
+    full: usize,
+    /// How many items a key's buffer holds before it counts as full
+    max_buffered: usize,
+    /// The id given to the next key seen
+    next_id: u64,
+    /// The tasks waiting for a full buffer to make room
+    waiting: Vec<Waker>,
+    /// The waker of the task polling `Demux`
+    waker: Option<Waker>,
+    /// Set once `Demux` has been dropped, after which new keys' items are
+    /// discarded
+    dropped: bool,
+    /// How long a key can go without an item before it's evicted, along with
+    /// the timer for the next key due to be, started on the next read
+    #[cfg(feature = "time")]
+    idle: Option<(Duration, Option<Timer>)>,
+}
+
+impl<S, K, F, const N: usize> DemuxState<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    /// Reads one item from the underlying stream and buffers it for its key,
+    /// announcing the key if it's new. Returns `Ready(false)` once the stream
+    /// has ended
+    fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<bool> {
+        #[cfg(feature = "time")]
+        self.poll_evict(cx);
+        if self.full > 0 {
+            // Wait for the full buffer to make room before reading ahead
+            if !self.waiting.iter().any(|waker| waker.will_wake(cx.waker())) {
+                self.waiting.push(cx.waker().clone());
+            }
+            return Poll::Pending;
+        }
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return Poll::Ready(false),
+        };
+        let item = match stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => {
+                self.stream = None;
+                // Everyone has to find out that their key won't get anything else
+                for key_state in self.keys.values_mut() {
+                    if let Some(waker) = key_state.waker.take() {
+                        waker.wake();
+                    }
+                }
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+                return Poll::Ready(false);
+            }
+            Poll::Pending => return Poll::Pending,
+        };
+        let key = (self.key)(&item);
+        let max_buffered = self.max_buffered;
+        match self.keys.get_mut(&key) {
+            Some(key_state) if key_state.dropped =>
+            {
+                #[cfg(feature = "time")]
+                if self.idle.is_some() {
+                    key_state.active = Timer::now();
+                }
+            }
+            Some(key_state) => {
+                let rejected = key_state.buf.push_back(item);
+                debug_assert!(rejected.is_none(), "nothing is read while a buffer is full");
+                if key_state.buf.len() == max_buffered {
+                    self.full += 1;
+                }
+                #[cfg(feature = "time")]
+                if self.idle.is_some() {
+                    key_state.active = Timer::now();
+                }
+                if let Some(waker) = key_state.waker.take() {
+                    waker.wake();
+                }
+            }
+            None if self.dropped => {}
+            None => {
+                let mut buf = RingBuf::new();
+                let _ = buf.push_back(item);
+                if buf.len() == max_buffered {
+                    self.full += 1;
+                }
+                self.keys.insert(
+                    key.clone(),
+                    KeyState {
+                        buf,
+                        waker: None,
+                        id: self.next_id,
+                        yielded: false,
+                        dropped: false,
+                        #[cfg(feature = "time")]
+                        active: Timer::now(),
+                    },
+                );
+                self.next_id += 1;
+                self.announced.push_back(key);
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        Poll::Ready(true)
+    }
+
+    /// Evicts the keys that have been idle for the timeout once the timer for
+    /// the next one due fires, then starts the timer for the one after
+    #[cfg(feature = "time")]
+    fn poll_evict(&mut self, cx: &mut Context<'_>) {
+        let (idle, timer) = match &mut self.idle {
+            Some((idle, timer)) => (*idle, timer),
+            None => return,
+        };
+        let timer = timer.get_or_insert_with(|| <Timer as Delay>::new(idle));
+        while Pin::new(&mut *timer).poll(cx).is_ready() {
+            let now = Timer::now();
+            let max_buffered = self.max_buffered;
+            let mut next = idle;
+            let mut made_room = 0;
+            self.keys.retain(|_, key_state| {
+                let quiet = now.saturating_duration_since(key_state.active);
+                // A key waiting to be announced is left to `Demux`
+                if !key_state.yielded && !key_state.dropped {
+                    return true;
+                }
+                if quiet < idle {
+                    next = next.min(idle - quiet);
+                    return true;
+                }
+                if key_state.buf.len() >= max_buffered {
+                    made_room += 1;
+                }
+                if let Some(waker) = key_state.waker.take() {
+                    waker.wake();
+                }
+                false
+            });
+            if made_room > 0 {
+                self.full -= made_room;
+                for waker in self.waiting.drain(..) {
+                    waker.wake();
+                }
+            }
+            Delay::reset(timer, next);
+        }
+    }
+
+    /// Takes the next item buffered for `key`, waking the tasks waiting on
+    /// room if its buffer was full
+    fn pop(&mut self, key: &K, id: u64) -> Option<S::Item> {
+        let max_buffered = self.max_buffered;
+        let key_state = self
+            .keys
+            .get_mut(key)
+            .filter(|key_state| key_state.id == id)?;
+        let was_full = key_state.buf.len() >= max_buffered;
+        let item = key_state.buf.pop_front()?;
+        #[cfg(feature = "time")]
+        if self.idle.is_some() {
+            key_state.active = Timer::now();
+        }
+        if was_full && key_state.buf.len() < max_buffered {
+            self.made_room();
+        }
+        Some(item)
+    }
+}
+
+impl<S: Stream, K, F, const N: usize> DemuxState<S, K, F, N> {
+    /// Records that a full buffer has made room, waking the tasks waiting to
+    /// read on
+    fn made_room(&mut self) {
+        self.full -= 1;
+        for waker in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The stream returned by `demux_by_key`, which yields each key along with
+/// its substream the first time the key is seen
+pub struct Demux<S: Stream, K: Hash + Eq, F, const N: usize> {
+    state: Arc<Mutex<DemuxState<S, K, F, N>>>,
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> Demux<S, K, F, N> {
+    /// Holds each key to `max` buffered items rather than N, so that a key
+    /// whose substream falls behind holds up the rest sooner. `max` is capped
+    /// to between 1 and N
+    pub fn set_max_buffered(&self, max: usize) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let max = max.clamp(1, N);
+        state.max_buffered = max;
+        let full = state
+            .keys
+            .values()
+            .filter(|key_state| key_state.buf.len() >= max)
+            .count();
+        let made_room = full < state.full;
+        state.full = full;
+        if made_room {
+            for waker in state.waiting.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Evicts each key that goes `idle` without an item being routed to it
+    /// or taken from it, discarding what's buffered for it and ending its
+    /// substream. A key that turns up again after being evicted is announced
+    /// afresh, with a new substream. This keeps a long running router from
+    /// holding on to every key it has seen, and from being held up for good
+    /// by a substream that stops being polled with a full buffer.
+    ///
+    /// The timer is polled along with the underlying stream, by whichever of
+    /// `Demux` and the substreams is being polled, and wakes the task that
+    /// polled it last
+    #[cfg(feature = "time")]
+    pub fn set_idle_timeout(&self, idle: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Timer::now();
+        for key_state in state.keys.values_mut() {
+            key_state.active = now;
+        }
+        state.idle = Some((idle, None));
+    }
+}
+
+impl<S, K, F, const N: usize> Stream for Demux<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    type Item = (K, Substream<S, K, F, N>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(key) = state.announced.pop_front() {
+                let key_state = match state.keys.get_mut(&key) {
+                    Some(key_state) => key_state,
+                    None => continue,
+                };
+                key_state.yielded = true;
+                let substream = Substream {
+                    state: Arc::clone(&self.state),
+                    id: key_state.id,
+                    key: key.clone(),
+                };
+                return Poll::Ready(Some((key, substream)));
+            }
+            state.waker = Some(cx.waker().clone());
+            match state.poll_read(cx) {
+                Poll::Ready(true) => {}
+                Poll::Ready(false) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S, K, F, const N: usize> FusedStream for Demux<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    fn is_terminated(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.stream.is_none() && state.announced.is_empty()
+    }
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> Drop for Demux<S, K, F, N> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.dropped = true;
+        state.waker = None;
+        // Nobody will take the substreams that were waiting to be yielded, so their
+        // items are dropped and stop holding up the others
+        while let Some(key) = state.announced.pop_front() {
+            let max_buffered = state.max_buffered;
+            if let Some(key_state) = state.keys.get_mut(&key) {
+                key_state.dropped = true;
+                let was_full = key_state.buf.len() >= max_buffered;
+                key_state.buf.clear();
+                if was_full {
+                    state.made_room();
+                }
+            }
+        }
+    }
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> fmt::Debug for Demux<S, K, F, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Demux")
+            .field("keys", &state.keys.len())
+            .field("announced", &state.announced.len())
+            .field("max_buffered", &state.max_buffered)
+            .field("stream_ended", &state.stream.is_none())
+            .finish()
+    }
+}
+
+/// A stream of the items with one key, yielded by `Demux`
+pub struct Substream<S: Stream, K: Hash + Eq, F, const N: usize> {
+    state: Arc<Mutex<DemuxState<S, K, F, N>>>,
+    key: K,
+    id: u64,
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> Substream<S, K, F, N> {
+    /// This substream's key's state, unless the key has been evicted
+    fn key_state<'a>(
+        &self,
+        state: &'a mut DemuxState<S, K, F, N>,
+    ) -> Option<&'a mut KeyState<S::Item, N>> {
+        state
+            .keys
+            .get_mut(&self.key)
+            .filter(|key_state| key_state.id == self.id)
+    }
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> Substream<S, K, F, N> {
+    /// The key of this substream's items
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<S, K, F, const N: usize> Stream for Substream<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(item) = state.pop(&self.key, self.id) {
+                return Poll::Ready(Some(item));
+            }
+            match self.key_state(&mut state) {
+                Some(key_state) => key_state.waker = Some(cx.waker().clone()),
+                // The key has been evicted
+                None => return Poll::Ready(None),
+            }
+            match state.poll_read(cx) {
+                Poll::Ready(true) => {}
+                Poll::Ready(false) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S, K, F, const N: usize> FusedStream for Substream<S, K, F, N>
+where
+    S: Stream,
+    K: Hash + Eq + Clone,
+    F: Fn(&S::Item) -> K,
+{
+    fn is_terminated(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let ended = state.stream.is_none();
+        self.key_state(&mut state)
+            .is_none_or(|key_state| ended && key_state.buf.is_empty())
+    }
+}
+
+impl<S: Stream, K: Hash + Eq, F, const N: usize> Drop for Substream<S, K, F, N> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let max_buffered = state.max_buffered;
+        if let Some(key_state) = self.key_state(&mut state) {
+            key_state.dropped = true;
+            key_state.waker = None;
+            let was_full = key_state.buf.len() >= max_buffered;
+            key_state.buf.clear();
+            if was_full {
+                state.made_room();
+            }
+        }
+    }
+}
+
+impl<S: Stream, K: Hash + Eq + fmt::Debug, F, const N: usize> fmt::Debug for Substream<S, K, F, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Substream").field("key", &self.key).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::demux_by_key;
+
+    #[test]
+    fn test_demux_dropped_substream() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 2, 11, 3, 12, 13]);
+            let mut keys = demux_by_key::<_, _, _, 1>(incoming_stream, |&n| n / 10);
+            let (key, mut ones) = keys.next().await.unwrap();
+            assert_eq!(0, key);
+            assert_eq!(Some(1), ones.next().await);
+            assert_eq!(Some(2), ones.next().await);
+            // 11 announces the tens, whose substream is dropped straight away so the
+            // rest of them are discarded rather than holding up the ones
+            let (key, tens) = keys.next().await.unwrap();
+            assert_eq!(1, key);
+            drop(tens);
+            assert_eq!(vec![3], ones.collect::<Vec<_>>().await);
+            assert!(keys.next().await.is_none());
+        })
+    }
+
+    #[test]
+    fn test_demux_max_buffered() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 2, 11]);
+        let mut keys = demux_by_key::<_, _, _, 4>(incoming_stream, |&n| n / 10);
+        keys.set_max_buffered(1);
+        let (_, mut ones) = futures::executor::block_on(keys.next()).unwrap();
+        // 1 fills the ones' buffer, so 2 isn't read and the tens aren't announced
+        assert!(keys.poll_next_unpin(&mut cx).is_pending());
+        assert_eq!(Poll::Ready(Some(1)), ones.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(2)), ones.poll_next_unpin(&mut cx));
+        // Raising the limit back up lets the tens through while 2 waits
+        keys.set_max_buffered(usize::MAX);
+        match keys.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some((key, _))) => assert_eq!(1, key),
+            _ => panic!("expected the tens"),
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_demux_evicts_idle_key() {
+        use std::time::Duration;
+
+        use futures::stream::FusedStream;
+
+        let incoming_stream =
+            futures::stream::iter([("a", 1), ("a", 2), ("b", 3)]).chain(futures::stream::pending());
+        let mut keys = demux_by_key::<_, _, _, 1>(incoming_stream, |&(key, _)| key);
+        keys.set_idle_timeout(Duration::from_millis(20));
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (key, mut stuck) = keys.next().await.unwrap();
+            assert_eq!("a", key);
+            // Nothing takes 1, so reading is held up until "a" is evicted, after which
+            // 2 announces it again
+            let (key, mut again) = keys.next().await.unwrap();
+            assert_eq!("a", key);
+            assert_eq!(None, stuck.next().await);
+            assert!(stuck.is_terminated());
+            assert_eq!(Some(("a", 2)), again.next().await);
+            let (key, mut b) = keys.next().await.unwrap();
+            assert_eq!("b", key);
+            assert_eq!(Some(("b", 3)), b.next().await);
+            // With nothing more coming, both go idle and end
+            assert_eq!(None, again.next().await);
+            assert_eq!(None, b.next().await);
+        })
+    }
+}
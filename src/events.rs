@@ -0,0 +1,186 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    stream::{FusedStream, Stream},
+};
+
+use crate::Side;
+
+/// Something that happened inside a splitter, as yielded by the stream
+/// returned from `StatsHandle::events`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SplitEvent {
+    /// The predicate routed an item to `side`
+    Routed {
+        /// The side the item was routed to
+        side: Side,
+    },
+    /// Reading from the underlying stream is blocked until the `side` stream
+    /// makes room in its buffer
+    BufferFull {
+        /// The side whose buffer is full
+        side: Side,
+    },
+    /// An item for `side` was dropped without being yielded, e.g. because that
+    /// stream had been closed
+    Dropped {
+        /// The side the item was meant for
+        side: Side,
+    },
+    /// The underlying stream ended or the predicate panicked. This is always
+    /// the last event
+    Terminated,
+}
+
+/// A change in how much room a splitter has for items, as yielded by the
+/// stream returned from `StatsHandle::backpressure`. A producer feeding the
+/// underlying stream can use these to slow down while a side is full and
+/// speed back up once it has drained
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BackpressureEvent {
+    /// The buffer for `side` filled up, so the underlying stream won't be read
+    /// until that stream catches up. This is only sent once until the side
+    /// drains
+    SideFull {
+        /// The side whose buffer is full
+        side: Side,
+    },
+    /// The buffer for `side`, which had been full, is now empty
+    SideDrained {
+        /// The side whose buffer drained
+        side: Side,
+    },
+    /// The underlying stream had nothing ready when it was polled, so the
+    /// splitter is waiting on the producer. This is only sent once until the
+    /// next item arrives
+    SourceStalled,
+}
+
+/// A channel that events are sent to while something is listening. The flag
+/// is checked first so that nothing is locked unless someone is
+pub(crate) struct Monitor<E> {
+    monitored: AtomicBool,
+    sender: Mutex<Option<UnboundedSender<E>>>,
+}
+
+impl<E> Default for Monitor<E> {
+    fn default() -> Self {
+        Self {
+            monitored: AtomicBool::new(false),
+            sender: Mutex::new(None),
+        }
+    }
+}
+
+impl<E> Monitor<E> {
+    /// Sends `event` to the listener if there is one
+    pub(crate) fn emit(&self, event: E) {
+        if !self.monitored.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut sender = self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(inner) = &*sender {
+            if inner.unbounded_send(event).is_err() {
+                // The listener was dropped
+                *sender = None;
+                self.monitored.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns a new receiver for the events, replacing the previous one. The
+    /// receiver is empty if `ended` is set, which is checked with the lock
+    /// held so that it can't miss `close` being called in between
+    pub(crate) fn subscribe(&self, ended: &AtomicBool) -> UnboundedReceiver<E> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut current = self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        if !ended.load(Ordering::Relaxed) {
+            *current = Some(sender);
+            self.monitored.store(true, Ordering::Relaxed);
+        }
+        receiver
+    }
+
+    /// Ends the listener's stream now that nothing else can happen
+    pub(crate) fn close(&self) {
+        self.monitored.store(false, Ordering::Relaxed);
+        self.sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+}
+
+/// A stream of the `SplitEvent`s of a splitter, created with
+/// `StatsHandle::events`. Events are queued without bound until they're read,
+/// and the stream ends after `SplitEvent::Terminated`, when the splitter is
+/// dropped or when a newer event stream replaces it
+pub struct SplitEvents {
+    receiver: UnboundedReceiver<SplitEvent>,
+}
+
+impl SplitEvents {
+    pub(crate) fn new(receiver: UnboundedReceiver<SplitEvent>) -> Self {
+        Self { receiver }
+    }
+}
+
+impl Stream for SplitEvents {
+    type Item = SplitEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.receiver.size_hint()
+    }
+}
+
+impl FusedStream for SplitEvents {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+/// A stream of the `BackpressureEvent`s of a splitter, created with
+/// `StatsHandle::backpressure`. Events are queued without bound until they're
+/// read, and the stream ends once the underlying stream has ended, when the
+/// splitter is dropped or when a newer backpressure stream replaces it
+pub struct BackpressureEvents {
+    receiver: UnboundedReceiver<BackpressureEvent>,
+}
+
+impl BackpressureEvents {
+    pub(crate) fn new(receiver: UnboundedReceiver<BackpressureEvent>) -> Self {
+        Self { receiver }
+    }
+}
+
+impl Stream for BackpressureEvents {
+    type Item = BackpressureEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.receiver.size_hint()
+    }
+}
+
+impl FusedStream for BackpressureEvents {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
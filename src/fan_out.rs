@@ -0,0 +1,270 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{
+    fanout_core::{FanoutCore, NoSuchOutput},
+    sync::{self, LockFamily, Mutex, StdMutex},
+};
+
+pub(crate) struct FanOutState<S: Stream, F, const SIDES: usize, const N: usize> {
+    pub(crate) core: FanoutCore<S::Item, S, SIDES, N>,
+    pub(crate) route: F,
+}
+
+pub(crate) type SharedState<S, F, const SIDES: usize, const N: usize, M = StdMutex> =
+    Arc<Mutex<FanOutState<S, F, SIDES, N>, M>>;
+
+/// Splits a stream into SIDES outputs by `route`, which returns the index of
+/// the output each item goes to. As with `split_by`, a single item is held
+/// for each output, so reading stops as soon as an item is routed to an
+/// output that hasn't taken the last one routed to it yet. See
+/// `fan_out_buffered` for buffering more than one. Dropping an output
+/// discards what's held for it and anything routed to it from then on. The
+/// outputs share their state behind a `std::sync::Mutex`, and
+/// `SplitBuilder::build_fan_out` picks another lock
+///
+/// Polling an output panics if `route` returns an index that isn't below
+/// SIDES
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::fan_out;
+///
+/// let incoming_stream = futures::stream::iter(0..9);
+/// let [zeros, ones, twos] = fan_out(incoming_stream, |n| n % 3);
+/// futures::executor::block_on(async {
+///     let (zeros, ones, twos) = futures::join!(
+///         zeros.collect::<Vec<_>>(),
+///         ones.collect::<Vec<_>>(),
+///         twos.collect::<Vec<_>>(),
+///     );
+///     assert_eq!(vec![0, 3, 6], zeros);
+///     assert_eq!(vec![1, 4, 7], ones);
+///     assert_eq!(vec![2, 5, 8], twos);
+/// });
+/// ```
+pub fn fan_out<S, F, const SIDES: usize>(stream: S, route: F) -> [FanOut<S, F, SIDES, 1>; SIDES]
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fan_out_buffered::<S, F, SIDES, 1>(stream, route)
+}
+
+/// The same as `fan_out`, but buffers up to N items for each output, as
+/// `split_by_buffered` does for its two. The more outputs there are, the
+/// likelier it is that one of them is momentarily slow, holding up the rest
+/// until it takes what's buffered for it
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::fan_out_buffered;
+///
+/// let incoming_stream = futures::stream::iter(0..12);
+/// let [low, mid, high] = fan_out_buffered::<_, _, 3, 4>(incoming_stream, |&n| n / 4);
+/// futures::executor::block_on(async {
+///     let (low, mid, high) = futures::join!(
+///         low.collect::<Vec<_>>(),
+///         mid.collect::<Vec<_>>(),
+///         high.collect::<Vec<_>>(),
+///     );
+///     assert_eq!(vec![0, 1, 2, 3], low);
+///     assert_eq!(vec![4, 5, 6, 7], mid);
+///     assert_eq!(vec![8, 9, 10, 11], high);
+/// });
+/// ```
+pub fn fan_out_buffered<S, F, const SIDES: usize, const N: usize>(
+    stream: S,
+    route: F,
+) -> [FanOut<S, F, SIDES, N>; SIDES]
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fan_out_with_lock(stream, route)
+}
+
+/// `fan_out_buffered` with the state guarded by the lock picked by `M`, for
+/// `SplitBuilder::build_fan_out`
+pub(crate) fn fan_out_with_lock<S, F, const SIDES: usize, const N: usize, M: LockFamily>(
+    stream: S,
+    route: F,
+) -> [FanOut<S, F, SIDES, N, M>; SIDES]
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    let state = Arc::new(Mutex::new(FanOutState {
+        core: FanoutCore::new(stream),
+        route,
+    }));
+    std::array::from_fn(|index| FanOut {
+        state: Arc::clone(&state),
+        index,
+    })
+}
+
+/// Locks the state for polling, or if another output is in the middle of being
+/// polled has the task polled again and returns `None`, as the two-way splits do
+pub(crate) fn try_lock<'a, S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily>(
+    state: &'a SharedState<S, F, SIDES, N, M>,
+    cx: &Context<'_>,
+) -> Option<sync::MutexGuard<'a, FanOutState<S, F, SIDES, N>, M>> {
+    match state.try_lock() {
+        Some(guard) => Some(guard),
+        None => {
+            sync::lock_contended();
+            cx.waker().wake_by_ref();
+            None
+        }
+    }
+}
+
+/// Unwraps what `FanoutCore::poll_side` returns for the splits built on it,
+/// which only ever poll their own outputs, panicking if `route` returned an
+/// output that doesn't exist
+pub(crate) fn routed<I>(polled: Result<Option<I>, NoSuchOutput<I>>) -> Option<I> {
+    match polled {
+        Ok(item) => item,
+        Err(error) => panic!("an item was routed to output {}", error.index()),
+    }
+}
+
+pub(crate) fn debug_core<S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily>(
+    state: &SharedState<S, F, SIDES, N, M>,
+    debug: &mut fmt::DebugStruct<'_, '_>,
+) {
+    match state.try_lock() {
+        Some(guard) => debug.field("core", &guard.core),
+        // Another output is in the middle of being polled
+        None => debug.field("core", &format_args!("<locked>")),
+    };
+}
+
+/// One of the outputs returned by `fan_out` or `fan_out_buffered`. As with
+/// the two-way splits, `M` picks the lock guarding the state they share,
+/// which can be changed with `SplitBuilder::build_fan_out`
+pub struct FanOut<S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily = StdMutex> {
+    pub(crate) state: SharedState<S, F, SIDES, N, M>,
+    index: usize,
+}
+
+impl<S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily> FanOut<S, F, SIDES, N, M> {
+    /// The index `route` returns for the items this stream yields
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.state.lock();
+        guard
+            .core
+            .buffered(self.index)
+            .is_ok_and(|buffered| buffered > 0)
+    }
+}
+
+impl<S, F, const SIDES: usize, const N: usize, M: LockFamily> Stream for FanOut<S, F, SIDES, N, M>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut guard = match try_lock(&self.state, cx) {
+            Some(guard) => guard,
+            None => return Poll::Pending,
+        };
+        let FanOutState { core, route } = &mut *guard;
+        core.poll_side(self.index, cx, route).map(routed)
+    }
+}
+
+impl<S, F, const SIDES: usize, const N: usize, M: LockFamily> FusedStream
+    for FanOut<S, F, SIDES, N, M>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fn is_terminated(&self) -> bool {
+        match self.state.try_lock() {
+            Some(guard) => guard.core.is_terminated(self.index).unwrap_or(true),
+            // Another output is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily> Drop
+    for FanOut<S, F, SIDES, N, M>
+{
+    fn drop(&mut self) {
+        self.state
+            .lock_or_defer(|state, side| drop(state.core.close(side)), self.index);
+    }
+}
+
+impl<S: Stream, F, const SIDES: usize, const N: usize, M: LockFamily> fmt::Debug
+    for FanOut<S, F, SIDES, N, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FanOut");
+        debug.field("index", &self.index);
+        debug_core(&self.state, &mut debug);
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{fan_out, fan_out_buffered};
+
+    #[test]
+    fn test_unbuffered_holds_one_item() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 1, 0]);
+        let [mut first, mut second] = fan_out(incoming_stream, |&n| n);
+        // The second 1 can't be read until the first has been taken
+        assert_eq!(Poll::Pending, first.poll_next_unpin(&mut cx));
+        assert!(second.has_buffered());
+        assert_eq!(Poll::Ready(Some(1)), second.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, first.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), second.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), first.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_dropped_output_discards() {
+        let incoming_stream = futures::stream::iter(0..10);
+        let [evens, odds] = fan_out_buffered::<_, _, 2, 2>(incoming_stream, |&n| n % 2);
+        drop(odds);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 2, 4, 6, 8], evens.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_end_leaves_buffered_items() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 1]);
+        let [mut first, mut second] = fan_out_buffered::<_, _, 2, 4>(incoming_stream, |&n| n);
+        assert_eq!(Poll::Ready(None), first.poll_next_unpin(&mut cx));
+        assert!(second.has_buffered());
+        assert_eq!(Poll::Ready(Some(1)), second.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), second.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), second.poll_next_unpin(&mut cx));
+    }
+}
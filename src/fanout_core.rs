@@ -0,0 +1,387 @@
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+use crate::{side_waker::SideWaker, source::Source, RingBuf};
+
+/// The driver shared by the outputs of a split into `SIDES` outputs, which
+/// `fan_out`, `split_by_priority`, `cascade` and `split_flat_by` are built
+/// on, and which custom splitters can be built on too. It holds the
+/// underlying stream, a buffer of up to `N` items and a waker for each
+/// output, and tracks which outputs have been closed and whether the stream
+/// has ended. The two way splits keep their own state, since they track much
+/// more per side.
+///
+/// Polling an output with `poll_side` yields what's buffered for it, and
+/// otherwise reads the underlying stream, buffering items that are routed to
+/// other outputs and waking them, until it reads one for itself. Nothing is
+/// read while the buffer of an open output is full, since the next item might
+/// be for it. That output is woken instead, and the one polled is woken once
+/// it makes room. As with the two way splits, waking an output that hasn't
+/// been polled yet is remembered until it is. Each output should only be
+/// polled by one task at a time.
+///
+/// The outputs are numbered from 0, and passing or routing an item to one
+/// that isn't below `SIDES` returns a `NoSuchOutput` error. The stream is held
+/// inline and polled in place, so it has to be `Unpin`
+///
+///```rust
+/// use std::sync::{Arc, Mutex};
+///
+/// use futures::StreamExt;
+/// use split_stream_by::FanoutCore;
+///
+/// let core = FanoutCore::<_, _, 3, 4>::new(futures::stream::iter(0..9));
+/// let core = Arc::new(Mutex::new(core));
+/// let output = |side: usize| {
+///     let core = Arc::clone(&core);
+///     futures::stream::poll_fn(move |cx| {
+///         core.lock()
+///             .unwrap()
+///             .poll_side(side, cx, |n| n % 3)
+///             .map(Result::unwrap)
+///     })
+/// };
+/// futures::executor::block_on(async {
+///     let (zeros, ones, twos) = futures::join!(
+///         output(0).collect::<Vec<_>>(),
+///         output(1).collect::<Vec<_>>(),
+///         output(2).collect::<Vec<_>>(),
+///     );
+///     assert_eq!(vec![0, 3, 6], zeros);
+///     assert_eq!(vec![1, 4, 7], ones);
+///     assert_eq!(vec![2, 5, 8], twos);
+/// });
+/// ```
+pub struct FanoutCore<I, S, const SIDES: usize, const N: usize> {
+    buffers: [RingBuf<I, N>; SIDES],
+    wakers: [SideWaker; SIDES],
+    closed: [bool; SIDES],
+    terminated: bool,
+    stream: Source<S>,
+}
+
+impl<I, S, const SIDES: usize, const N: usize> FanoutCore<I, S, SIDES, N>
+where
+    S: Stream<Item = I> + Unpin,
+{
+    /// Creates the driver for splitting `stream` into `SIDES` outputs
+    pub fn new(stream: S) -> Self {
+        Self {
+            buffers: std::array::from_fn(|_| RingBuf::new()),
+            wakers: std::array::from_fn(|_| SideWaker::new("fanout")),
+            closed: [false; SIDES],
+            terminated: false,
+            stream: Source::new(stream),
+        }
+    }
+
+    /// Polls for the next item for output `side`, routing each item read from
+    /// the underlying stream to the output `route` returns for it. Items
+    /// routed to a closed output are dropped. An item routed to an output that
+    /// doesn't exist is handed back in the error, and the next poll carries on
+    /// reading after it
+    pub fn poll_side(
+        &mut self,
+        side: usize,
+        cx: &mut Context<'_>,
+        mut route: impl FnMut(&I) -> usize,
+    ) -> Poll<Result<Option<I>, NoSuchOutput<I>>> {
+        if side >= SIDES {
+            return Poll::Ready(Err(NoSuchOutput::new(side, None)));
+        }
+        if self.closed[side] {
+            return Poll::Ready(Ok(None));
+        }
+        self.wakers[side].register(0, cx.waker());
+        if let Some(item) = self.buffers[side].pop_front() {
+            // Another output may be waiting on room in this buffer
+            if self.buffers[side].remaining() == 1 {
+                self.wake_others(side);
+            }
+            return Poll::Ready(Ok(Some(item)));
+        }
+        if self.terminated {
+            return Poll::Ready(Ok(None));
+        }
+        loop {
+            if let Some(full) = (0..SIDES).find(|&other| {
+                !self.closed[other] && other != side && self.buffers[other].is_full()
+            }) {
+                // The next item might be for the full output, so nothing can be read until it
+                // makes room, which wakes this one
+                self.wake(full);
+                return Poll::Pending;
+            }
+            let item = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    // The other outputs end once they've taken what's buffered for them
+                    self.terminated = true;
+                    self.wake_others(side);
+                    return Poll::Ready(Ok(None));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            let target = route(&item);
+            if target >= SIDES {
+                return Poll::Ready(Err(NoSuchOutput::new(target, Some(item))));
+            }
+            if target == side {
+                return Poll::Ready(Ok(Some(item)));
+            }
+            if self.closed[target] {
+                continue;
+            }
+            let rejected = self.buffers[target].push_back(item);
+            debug_assert!(rejected.is_none());
+            self.wake(target);
+        }
+    }
+}
+
+impl<I, S, const SIDES: usize, const N: usize> FanoutCore<I, S, SIDES, N> {
+    /// Closes output `side`, dropping what's buffered for it and anything
+    /// routed to it from now on, and waking the other outputs in case they
+    /// were waiting on room in its buffer
+    pub fn close(&mut self, side: usize) -> Result<(), NoSuchOutput> {
+        Self::check(side)?;
+        self.closed[side] = true;
+        self.buffers[side].clear();
+        self.wakers[side].unregister(0);
+        self.wake_others(side);
+        Ok(())
+    }
+
+    /// Whether output `side` won't yield anything else
+    pub fn is_terminated(&self, side: usize) -> Result<bool, NoSuchOutput> {
+        Self::check(side)?;
+        Ok(self.closed[side] || (self.terminated && self.buffers[side].is_empty()))
+    }
+
+    /// How many items are waiting in the buffer for output `side`
+    pub fn buffered(&self, side: usize) -> Result<usize, NoSuchOutput> {
+        Self::check(side)?;
+        Ok(self.buffers[side].len())
+    }
+
+    fn check(side: usize) -> Result<(), NoSuchOutput> {
+        if side < SIDES {
+            Ok(())
+        } else {
+            Err(NoSuchOutput::new(side, None))
+        }
+    }
+
+    fn wake(&mut self, side: usize) {
+        if !self.closed[side] {
+            self.wakers[side].wake();
+        }
+    }
+
+    fn wake_others(&mut self, side: usize) {
+        for other in (0..SIDES).filter(|&other| other != side) {
+            self.wake(other);
+        }
+    }
+}
+
+impl<I, S, const SIDES: usize, const N: usize> fmt::Debug for FanoutCore<I, S, SIDES, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buffered: Vec<usize> = self.buffers.iter().map(RingBuf::len).collect();
+        f.debug_struct("FanoutCore")
+            .field("buffered", &buffered)
+            .field("closed", &self.closed)
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+/// Error returned by `FanoutCore` when it's given, or an item is routed to,
+/// an output that isn't below `SIDES`. An item routed to it is handed back
+/// with `into_item`
+#[derive(Debug, PartialEq, Eq)]
+pub struct NoSuchOutput<I = ()> {
+    index: usize,
+    item: Option<I>,
+}
+
+impl<I> NoSuchOutput<I> {
+    fn new(index: usize, item: Option<I>) -> Self {
+        Self { index, item }
+    }
+
+    /// The index of the output that doesn't exist
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The item that was routed to the output, if the error came from routing
+    /// one
+    pub fn into_item(self) -> Option<I> {
+        self.item
+    }
+}
+
+impl<I> fmt::Display for NoSuchOutput<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "there is no output {}", self.index)
+    }
+}
+
+impl<I: fmt::Debug> std::error::Error for NoSuchOutput<I> {}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use crate::{test_util::CountingWaker, FanoutCore, NoSuchOutput};
+
+    #[test]
+    fn test_three_way_split() {
+        let core = Arc::new(Mutex::new(FanoutCore::<_, _, 3, 4>::new(
+            futures::stream::iter(0..9),
+        )));
+        let side = |index: usize| {
+            let core = Arc::clone(&core);
+            futures::stream::poll_fn(move |cx| {
+                core.lock()
+                    .unwrap()
+                    .poll_side(index, cx, |n| n % 3)
+                    .map(Result::unwrap)
+            })
+        };
+        let (zeros, ones, twos) = (side(0), side(1), side(2));
+        futures::executor::block_on(async {
+            let (zeros, ones, twos) = futures::join!(
+                zeros.collect::<Vec<_>>(),
+                ones.collect::<Vec<_>>(),
+                twos.collect::<Vec<_>>(),
+            );
+            assert_eq!(vec![0, 3, 6], zeros);
+            assert_eq!(vec![1, 4, 7], ones);
+            assert_eq!(vec![2, 5, 8], twos);
+        });
+    }
+
+    #[test]
+    fn test_full_buffer_blocks_until_closed() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut core = FanoutCore::<_, _, 3, 1>::new(futures::stream::iter(0..6));
+        let route = |n: &i32| (*n % 3) as usize;
+        // 0 fills the buffer for output 0, which holds up output 2 until it's closed,
+        // and then 1 does the same for output 1
+        assert_eq!(Poll::Pending, core.poll_side(2, &mut cx, route));
+        assert_eq!(Ok(1), core.buffered(0));
+        assert_eq!(Ok(()), core.close(0));
+        assert_eq!(Poll::Pending, core.poll_side(2, &mut cx, route));
+        assert_eq!(Ok(()), core.close(1));
+        assert_eq!(Poll::Ready(Ok(Some(2))), core.poll_side(2, &mut cx, route));
+        assert_eq!(Poll::Ready(Ok(Some(5))), core.poll_side(2, &mut cx, route));
+        assert_eq!(Poll::Ready(Ok(None)), core.poll_side(2, &mut cx, route));
+        assert_eq!(Ok(true), core.is_terminated(0));
+        assert_eq!(Ok(true), core.is_terminated(2));
+    }
+
+    #[test]
+    fn test_wake_before_first_poll() {
+        let mut core = FanoutCore::<_, _, 2, 2>::new(futures::stream::iter([1, 0]));
+        let route = |n: &usize| *n;
+        // Output 1 is woken for its item before it has a waker, which is made up for
+        // when it first polls
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Ok(Some(0))), core.poll_side(0, &mut cx, route));
+        let task = CountingWaker::new();
+        let waker = task.waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Ready(Ok(Some(1))), core.poll_side(1, &mut cx, route));
+        assert_eq!(1, task.wakes());
+    }
+
+    #[test]
+    fn test_room_made_wakes_waiting_output() {
+        let mut core = FanoutCore::<_, _, 2, 1>::new(futures::stream::iter([1, 1, 0]));
+        let route = |n: &usize| *n;
+        let task = CountingWaker::new();
+        let waker = task.waker();
+        let mut waiting = Context::from_waker(&waker);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Ok(Some(1))), core.poll_side(1, &mut cx, route));
+        // The second 1 fills output 1's buffer, so output 0 waits on it
+        assert_eq!(Poll::Pending, core.poll_side(0, &mut waiting, route));
+        let woken = task.wakes();
+        assert_eq!(Poll::Ready(Ok(Some(1))), core.poll_side(1, &mut cx, route));
+        assert!(task.wakes() > woken);
+        assert_eq!(
+            Poll::Ready(Ok(Some(0))),
+            core.poll_side(0, &mut waiting, route)
+        );
+    }
+
+    #[test]
+    fn test_end_leaves_buffered_items() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut core = FanoutCore::<_, _, 2, 4>::new(futures::stream::iter([1, 1, 1]));
+        let route = |n: &usize| *n;
+        assert_eq!(Poll::Ready(Ok(None)), core.poll_side(0, &mut cx, route));
+        assert_eq!(Ok(true), core.is_terminated(0));
+        assert_eq!(Ok(false), core.is_terminated(1));
+        for _ in 0..3 {
+            assert_eq!(Poll::Ready(Ok(Some(1))), core.poll_side(1, &mut cx, route));
+        }
+        assert_eq!(Poll::Ready(Ok(None)), core.poll_side(1, &mut cx, route));
+        assert_eq!(Ok(true), core.is_terminated(1));
+    }
+
+    #[test]
+    fn test_no_such_side() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut core = FanoutCore::<_, _, 2, 1>::new(futures::stream::iter([0]));
+        let route = |n: &usize| *n;
+        let error = match core.poll_side(2, &mut cx, route) {
+            Poll::Ready(Err(error)) => error,
+            polled => panic!("polled {:?}", polled),
+        };
+        assert_eq!(2, error.index());
+        assert_eq!(None, error.into_item());
+        assert_eq!(2, core.close(2).unwrap_err().index());
+        assert!(core.is_terminated(3).is_err());
+        assert!(core.buffered(3).is_err());
+        // Nothing was read or closed along the way
+        assert_eq!(Poll::Ready(Ok(Some(0))), core.poll_side(0, &mut cx, route));
+    }
+
+    #[test]
+    fn test_routed_to_no_such_side() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut core = FanoutCore::<_, _, 2, 1>::new(futures::stream::iter([0, 5, 1]));
+        let route = |n: &usize| *n;
+        assert_eq!(Poll::Ready(Ok(Some(0))), core.poll_side(0, &mut cx, route));
+        // 5 is handed back rather than dropped, and the next poll reads on after it
+        let error = match core.poll_side(0, &mut cx, route) {
+            Poll::Ready(Err(error)) => error,
+            polled => panic!("polled {:?}", polled),
+        };
+        assert_eq!("there is no output 5", error.to_string());
+        assert_eq!(Some(5), error.into_item());
+        assert_eq!(Poll::Ready(Ok(Some(1))), core.poll_side(1, &mut cx, route));
+        assert_eq!(Poll::Ready(Ok(None)), core.poll_side(0, &mut cx, route));
+    }
+
+    #[test]
+    fn test_error_without_item() {
+        let error: NoSuchOutput = FanoutCore::<i32, _, 1, 1>::new(futures::stream::empty())
+            .buffered(1)
+            .unwrap_err();
+        assert_eq!(1, error.index());
+    }
+}
@@ -0,0 +1,217 @@
+//! Fault injection for testing code built on the splits under the worst
+//! interleavings a splitter can produce, enabled with the `test-fault`
+//! feature. A `FaultSchedule` installed on a thread makes the splits polled
+//! on that thread misbehave in ways they're allowed to: locks that look
+//! contended, polls that return `Poll::Pending` for no reason, wakes that
+//! arrive late and wakes of the handles to a side in a shuffled order. The
+//! schedule is seeded, so a failure can be reproduced by installing the same
+//! schedule again
+//!
+//!```rust
+//! use std::time::Duration;
+//! use futures::StreamExt;
+//! use split_stream_by::{fault::FaultSchedule, SplitStreamByExt};
+//!
+//! let _faults = FaultSchedule::new(42)
+//!     .lock_contention(0.2)
+//!     .forced_pending(0.2)
+//!     .delayed_wakes(0.2, Duration::from_millis(1))
+//!     .reordered_wakes()
+//!     .install();
+//! let incoming_stream = futures::stream::iter(0..100);
+//! let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<4>(|&n| n % 2 == 0);
+//! futures::executor::block_on(async {
+//!     let (evens, odds) = futures::join!(
+//!         even_stream.collect::<Vec<_>>(),
+//!         odd_stream.collect::<Vec<_>>()
+//!     );
+//!     assert_eq!((0..100).step_by(2).collect::<Vec<_>>(), evens);
+//!     assert_eq!((1..100).step_by(2).collect::<Vec<_>>(), odds);
+//! });
+//! ```
+
+use std::{
+    cell::RefCell,
+    fmt,
+    task::{Context, Waker},
+    thread,
+    time::Duration,
+};
+
+thread_local! {
+    static SCHEDULE: RefCell<Option<FaultSchedule>> = const { RefCell::new(None) };
+}
+
+/// Which faults to inject and how often, as the chance of each happening
+/// whenever it could. Every fault is off until it's given a rate
+#[derive(Clone)]
+pub struct FaultSchedule {
+    /// The state of the xorshift generator the faults are drawn from
+    state: u64,
+    contention: f64,
+    pending: f64,
+    delay: f64,
+    max_delay: Duration,
+    reorder: bool,
+}
+
+impl FaultSchedule {
+    /// Creates a schedule that injects nothing, drawing the faults from a
+    /// generator seeded with `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            // Xorshift gets stuck at zero
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+            contention: 0.0,
+            pending: 0.0,
+            delay: 0.0,
+            max_delay: Duration::ZERO,
+            reorder: false,
+        }
+    }
+
+    /// Makes an output stream find the lock on its split taken, as if the
+    /// other side were being polled, at `rate` of its polls
+    pub fn lock_contention(self, rate: f64) -> Self {
+        Self {
+            contention: rate,
+            ..self
+        }
+    }
+
+    /// Makes an output stream return `Poll::Pending` and wake itself, without
+    /// doing anything else, at `rate` of its polls
+    pub fn forced_pending(self, rate: f64) -> Self {
+        Self {
+            pending: rate,
+            ..self
+        }
+    }
+
+    /// Holds back `rate` of the wakes for up to `max_delay`, delivering them
+    /// from another thread
+    pub fn delayed_wakes(self, rate: f64, max_delay: Duration) -> Self {
+        Self {
+            delay: rate,
+            max_delay,
+            ..self
+        }
+    }
+
+    /// Wakes the handles to a side in a random order, rather than in the
+    /// order they first polled it
+    pub fn reordered_wakes(self) -> Self {
+        Self {
+            reorder: true,
+            ..self
+        }
+    }
+
+    /// Injects faults into the splits polled on this thread until the
+    /// returned guard is dropped, replacing any schedule already installed
+    pub fn install(self) -> FaultGuard {
+        let previous = SCHEDULE.with(|schedule| schedule.borrow_mut().replace(self));
+        FaultGuard { previous }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn roll(&mut self, rate: f64) -> bool {
+        rate > 0.0 && (self.next() >> 11) as f64 / (1u64 << 53) as f64 <= rate
+    }
+}
+
+impl fmt::Debug for FaultSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultSchedule")
+            .field("lock_contention", &self.contention)
+            .field("forced_pending", &self.pending)
+            .field("delayed_wakes", &self.delay)
+            .field("max_delay", &self.max_delay)
+            .field("reordered_wakes", &self.reorder)
+            .finish()
+    }
+}
+
+/// Keeps a `FaultSchedule` installed on its thread, putting back whichever
+/// schedule was installed before when dropped
+#[derive(Debug)]
+pub struct FaultGuard {
+    previous: Option<FaultSchedule>,
+}
+
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCHEDULE.with(|schedule| *schedule.borrow_mut() = previous);
+    }
+}
+
+fn with_schedule<R>(f: impl FnOnce(&mut FaultSchedule) -> R) -> Option<R> {
+    SCHEDULE.with(|schedule| schedule.borrow_mut().as_mut().map(f))
+}
+
+/// Whether a lock should be reported as taken
+pub(crate) fn contend() -> bool {
+    with_schedule(|schedule| schedule.roll(schedule.contention)).unwrap_or(false)
+}
+
+/// Whether an output stream should return `Poll::Pending` straight away, in
+/// which case it has been woken to be polled again
+pub(crate) fn force_pending(cx: &Context<'_>) -> bool {
+    let pending = with_schedule(|schedule| schedule.roll(schedule.pending)).unwrap_or(false);
+    if pending {
+        cx.waker().wake_by_ref();
+    }
+    pending
+}
+
+/// Wakes `wakers` according to the schedule, returning false if there's no
+/// schedule installed and they haven't been woken
+pub(crate) fn wake(wakers: &[(usize, Waker)]) -> bool {
+    let plan = with_schedule(|schedule| {
+        let mut order: Vec<usize> = (0..wakers.len()).collect();
+        if schedule.reorder {
+            for i in (1..order.len()).rev() {
+                order.swap(i, (schedule.next() % (i as u64 + 1)) as usize);
+            }
+        }
+        order
+            .into_iter()
+            .map(|index| {
+                let delay = if schedule.roll(schedule.delay) {
+                    schedule
+                        .max_delay
+                        .mul_f64((schedule.next() >> 11) as f64 / (1u64 << 53) as f64)
+                } else {
+                    Duration::ZERO
+                };
+                (index, delay)
+            })
+            .collect::<Vec<_>>()
+    });
+    // The wakes happen once the schedule is no longer borrowed, in case one
+    // of them polls a split on this thread
+    let plan = match plan {
+        Some(plan) => plan,
+        None => return false,
+    };
+    for (index, delay) in plan {
+        let waker = &wakers[index].1;
+        if delay.is_zero() {
+            waker.wake_by_ref();
+        } else {
+            let waker = waker.clone();
+            thread::spawn(move || {
+                thread::sleep(delay);
+                waker.wake();
+            });
+        }
+    }
+    true
+}
@@ -0,0 +1,236 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{fan_out, fanout_core::FanoutCore, sync::Mutex};
+
+/// The element type of a stream of batches
+type Element<S> = <<S as Stream>::Item as IntoIterator>::Item;
+
+/// Splits a stream of batches, such as `Vec`s or `Bytes` chunks, into two
+/// streams of their elements by a predicate, `true` for the first stream.
+/// Routing one element of a batch goes straight on to the next, buffering
+/// elements for the other stream, until it finds one for the stream being
+/// polled, so the batches are routed element by element without flattening
+/// them into a stream of their own first. Up to N elements are buffered for
+/// each stream, and as with the buffered splits nothing is read while a
+/// buffer is full. Dropping a stream discards what's buffered for it and
+/// anything routed to it from then on
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_flat_by;
+///
+/// let incoming_stream = futures::stream::iter([vec![0, 1, 2], vec![], vec![3, 4]]);
+/// let (even_stream, odd_stream) = split_flat_by::<_, _, 4>(incoming_stream, |&n| n % 2 == 0);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(
+///         even_stream.collect::<Vec<_>>(),
+///         odd_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3], odds);
+/// });
+/// ```
+pub fn split_flat_by<S, P, const N: usize>(
+    stream: S,
+    predicate: P,
+) -> (SplitFlat<S, P, N>, SplitFlat<S, P, N>)
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    P: Fn(&Element<S>) -> bool,
+{
+    let batches = Batches {
+        stream: Box::pin(stream),
+        batch: None,
+    };
+    let state = Arc::new(Mutex::new(FlatState {
+        core: FanoutCore::new(batches),
+        predicate,
+    }));
+    let first = SplitFlat {
+        state: Arc::clone(&state),
+        side: 0,
+    };
+    (first, SplitFlat { state, side: 1 })
+}
+
+/// The elements of a stream of batches, taken from each batch in turn
+struct Batches<S: Stream>
+where
+    S::Item: IntoIterator,
+{
+    stream: Pin<Box<S>>,
+    batch: Option<<S::Item as IntoIterator>::IntoIter>,
+}
+
+// The stream is boxed so that nothing is pinned in place
+impl<S: Stream> Unpin for Batches<S> where S::Item: IntoIterator {}
+
+impl<S: Stream> Stream for Batches<S>
+where
+    S::Item: IntoIterator,
+{
+    type Item = Element<S>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Element<S>>> {
+        loop {
+            if let Some(element) = self.batch.as_mut().and_then(Iterator::next) {
+                return Poll::Ready(Some(element));
+            }
+            match self.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(batch)) => self.batch = Some(batch.into_iter()),
+                Poll::Ready(None) => {
+                    self.batch = None;
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+struct FlatState<S: Stream, P, const N: usize>
+where
+    S::Item: IntoIterator,
+{
+    core: FanoutCore<Element<S>, Batches<S>, 2, N>,
+    predicate: P,
+}
+
+/// One of the two streams returned by `split_flat_by`
+pub struct SplitFlat<S: Stream, P, const N: usize>
+where
+    S::Item: IntoIterator,
+{
+    state: Arc<Mutex<FlatState<S, P, N>>>,
+    side: usize,
+}
+
+impl<S, P, const N: usize> Stream for SplitFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    P: Fn(&Element<S>) -> bool,
+{
+    type Item = Element<S>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Element<S>>> {
+        let mut guard = self.state.lock();
+        let FlatState { core, predicate } = &mut *guard;
+        core.poll_side(self.side, cx, |element| usize::from(!predicate(element)))
+            .map(fan_out::routed)
+    }
+}
+
+impl<S, P, const N: usize> FusedStream for SplitFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    P: Fn(&Element<S>) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.state.try_lock() {
+            Some(guard) => guard.core.is_terminated(self.side).unwrap_or(true),
+            // Another output is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<S: Stream, P, const N: usize> Drop for SplitFlat<S, P, N>
+where
+    S::Item: IntoIterator,
+{
+    fn drop(&mut self) {
+        self.state
+            .lock_or_defer(|state, side| drop(state.core.close(side)), self.side);
+    }
+}
+
+impl<S: Stream, P, const N: usize> fmt::Debug for SplitFlat<S, P, N>
+where
+    S::Item: IntoIterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("SplitFlat");
+        debug.field("side", &self.side);
+        match self.state.try_lock() {
+            Some(guard) => debug.field("core", &guard.core),
+            // Another output is in the middle of being polled
+            None => debug.field("core", &format_args!("<locked>")),
+        };
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::split_flat_by;
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn test_any_batch_type() {
+        // Arrays, and a predicate on the elements rather than the batches
+        let incoming_stream = futures::stream::iter([[1, 2], [3, 4], [6, 8]]);
+        let (evens, odds) = split_flat_by::<_, _, 2>(incoming_stream, |&n: &u8| n % 2 == 0);
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+            assert_eq!(vec![2, 4, 6, 8], evens);
+            assert_eq!(vec![1, 3], odds);
+        });
+    }
+
+    #[test]
+    fn test_full_buffer_stops_mid_batch() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([vec![1, 3, 0]]);
+        let (mut evens, mut odds) = split_flat_by::<_, _, 1>(incoming_stream, |&n| n % 2 == 0);
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        // The rest of the batch is picked up where it was left
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), evens.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), odds.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_empty_batch_yields_nothing() {
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Vec<u32>>();
+        let (mut evens, _odds) = split_flat_by::<_, _, 2>(rx, |&n| n % 2 == 0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        // An empty batch wakes the side but gives it nothing, and isn't taken as the end
+        tx.unbounded_send(vec![]).unwrap();
+        assert_eq!(1, counter.wakes());
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        tx.unbounded_send(vec![2]).unwrap();
+        assert_eq!(2, counter.wakes());
+        assert_eq!(Poll::Ready(Some(2)), evens.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_dropped_side_discards() {
+        // More odd elements than fit in a buffer, which go nowhere once the odds are dropped
+        let incoming_stream = futures::stream::iter([vec![1, 3, 5, 7, 0], vec![9, 2]]);
+        let (evens, odds) = split_flat_by::<_, _, 1>(incoming_stream, |&n| n % 2 == 0);
+        drop(odds);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 2], evens.collect::<Vec<_>>().await);
+        });
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Sink, Stream};
+
+use crate::Side;
+
+/// Forwards the items of `stream` into two sinks without a predicate, sending
+/// each item to whichever sink is ready for it. When both are ready they take
+/// turns, and when neither is, the item is held until one of them becomes
+/// ready, without reading any more of `stream`. This balances load across
+/// e.g. two workers or connections by their demand, rather than by anything
+/// about the items.
+///
+/// The future completes once `stream` has ended and both sinks have been
+/// closed, or with the first error either sink returns
+///
+///```rust
+/// use futures::{channel::mpsc, StreamExt};
+/// use split_stream_by::forward_balanced;
+///
+/// let (left_sender, left_receiver) = mpsc::unbounded();
+/// let (right_sender, right_receiver) = mpsc::unbounded();
+/// let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+/// futures::executor::block_on(async {
+///     forward_balanced(incoming_stream, left_sender, right_sender).await.unwrap();
+///     assert_eq!(vec![0, 2], left_receiver.collect::<Vec<_>>().await);
+///     assert_eq!(vec![1, 3], right_receiver.collect::<Vec<_>>().await);
+/// });
+/// ```
+pub fn forward_balanced<S, A, B>(stream: S, left: A, right: B) -> ForwardBalanced<S, A, B>
+where
+    S: Stream,
+    A: Sink<S::Item>,
+    B: Sink<S::Item, Error = A::Error>,
+{
+    ForwardBalanced {
+        stream: Some(Box::pin(stream)),
+        left: Box::pin(left),
+        right: Box::pin(right),
+        item: None,
+        next: Side::Left,
+        sent: [0, 0],
+        closed: [false, false],
+    }
+}
+
+/// A future that forwards the items of a stream into two sinks by their
+/// readiness, returned by `forward_balanced`
+#[must_use = "nothing is forwarded unless the `ForwardBalanced` future is polled"]
+pub struct ForwardBalanced<S, A, B>
+where
+    S: Stream,
+{
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    left: Pin<Box<A>>,
+    right: Pin<Box<B>>,
+    /// An item read from the stream that neither sink was ready for
+    item: Option<S::Item>,
+    /// The sink offered the next item first, so that two ready sinks take
+    /// turns
+    next: Side,
+    sent: [u64; 2],
+    closed: [bool; 2],
+}
+
+// Everything is boxed so that nothing is pinned in place
+impl<S, A, B> Unpin for ForwardBalanced<S, A, B> where S: Stream {}
+
+impl<S, A, B> ForwardBalanced<S, A, B>
+where
+    S: Stream,
+    A: Sink<S::Item>,
+    B: Sink<S::Item, Error = A::Error>,
+{
+    /// How many items have been sent to the `side` sink so far
+    pub fn sent(&self, side: Side) -> u64 {
+        self.sent[side as usize]
+    }
+
+    /// Offers the held item to `side`'s sink, returning whether it took it
+    fn try_send(&mut self, side: Side, cx: &mut Context<'_>) -> Result<bool, A::Error> {
+        let ready = match side {
+            Side::Left => self.left.as_mut().poll_ready(cx),
+            Side::Right => self.right.as_mut().poll_ready(cx),
+        };
+        match ready {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(err)) => return Err(err),
+            Poll::Pending => return Ok(false),
+        }
+        let item = self.item.take().expect("there's an item to send");
+        match side {
+            Side::Left => self.left.as_mut().start_send(item)?,
+            Side::Right => self.right.as_mut().start_send(item)?,
+        }
+        self.sent[side as usize] += 1;
+        self.next = match side {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        };
+        Ok(true)
+    }
+}
+
+impl<S, A, B> Future for ForwardBalanced<S, A, B>
+where
+    S: Stream,
+    A: Sink<S::Item>,
+    B: Sink<S::Item, Error = A::Error>,
+{
+    type Output = Result<(), A::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if this.item.is_some() {
+                let first = this.next;
+                let second = match first {
+                    Side::Left => Side::Right,
+                    Side::Right => Side::Left,
+                };
+                // Both sinks are polled when neither is ready, so that either one
+                // becoming ready wakes this future
+                if !this.try_send(first, cx)? && !this.try_send(second, cx)? {
+                    return Poll::Pending;
+                }
+                continue;
+            }
+            let stream = match &mut this.stream {
+                Some(stream) => stream,
+                None => break,
+            };
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => this.item = Some(item),
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => {
+                    // Nothing more to send for now, so push out what the sinks have
+                    // buffered
+                    let _ = this.left.as_mut().poll_flush(cx)?;
+                    let _ = this.right.as_mut().poll_flush(cx)?;
+                    return Poll::Pending;
+                }
+            }
+        }
+        // A sink isn't closed again once it has finished closing
+        if !this.closed[0] {
+            this.closed[0] = this.left.as_mut().poll_close(cx)?.is_ready();
+        }
+        if !this.closed[1] {
+            this.closed[1] = this.right.as_mut().poll_close(cx)?.is_ready();
+        }
+        if this.closed == [true, true] {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, A, B> fmt::Debug for ForwardBalanced<S, A, B>
+where
+    S: Stream,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForwardBalanced")
+            .field("stream_ended", &self.stream.is_none())
+            .field("holding_item", &self.item.is_some())
+            .field("sent_left", &self.sent[0])
+            .field("sent_right", &self.sent[1])
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{channel::mpsc, StreamExt};
+
+    use super::forward_balanced;
+    use crate::Side;
+
+    #[test]
+    fn test_forward_balanced_by_readiness() {
+        futures::executor::block_on(async {
+            // A channel with no buffer takes one item per sender until it's read
+            let (left_sender, left_receiver) = mpsc::channel(0);
+            let (right_sender, right_receiver) = mpsc::unbounded();
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let mut forward = forward_balanced(incoming_stream, left_sender, right_sender);
+            (&mut forward).await.unwrap();
+            assert_eq!(1, forward.sent(Side::Left));
+            assert_eq!(3, forward.sent(Side::Right));
+            assert_eq!(vec![0], left_receiver.collect::<Vec<_>>().await);
+            assert_eq!(vec![1, 2, 3], right_receiver.collect::<Vec<_>>().await);
+        })
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    stream::{ReuniteError, SplitSink, SplitStream},
+    Sink, StreamExt,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use crate::{FalseSplitBy, SplitStreamByExt, TrueSplitBy};
+
+/// What a `Framed` yields for each frame it reads
+type FrameResult<U> = Result<<U as Decoder>::Item, <U as Decoder>::Error>;
+
+/// Splits the read side of `framed` into two streams per `predicate`, as with
+/// `split_by`, and hands back the write side as a `SinkHandle` alongside them.
+/// The predicate sees each frame as the `Framed` yields it, so it decides
+/// where decoding errors go too. `Framed` yields `None` right after an error,
+/// so both streams end once the error has been yielded
+///
+///```rust
+/// use futures::{SinkExt, StreamExt};
+/// use split_stream_by::split_framed_by;
+/// use tokio_util::codec::{Framed, LinesCodec};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let (client, server) = tokio::io::duplex(64);
+///     let mut client = Framed::new(client, LinesCodec::new());
+///     let (mut commands, mut chatter, mut sink) =
+///         split_framed_by(Framed::new(server, LinesCodec::new()), |line| {
+///             matches!(line, Ok(line) if line.starts_with('/'))
+///         });
+///     client.send("hello").await.unwrap();
+///     client.send("/quit").await.unwrap();
+///     assert_eq!("hello", chatter.next().await.unwrap().unwrap());
+///     assert_eq!("/quit", commands.next().await.unwrap().unwrap());
+///     sink.send("bye").await.unwrap();
+///     assert_eq!("bye", client.next().await.unwrap().unwrap());
+/// });
+/// ```
+pub fn split_framed_by<T, U, Item, P>(
+    framed: Framed<T, U>,
+    predicate: P,
+) -> (
+    TrueSplitBy<FrameResult<U>, SplitStream<Framed<T, U>>, P>,
+    FalseSplitBy<FrameResult<U>, SplitStream<Framed<T, U>>, P>,
+    SinkHandle<T, U, Item>,
+)
+where
+    T: AsyncRead + AsyncWrite,
+    U: Decoder + Encoder<Item>,
+    <U as Encoder<Item>>::Error: From<io::Error>,
+    P: Fn(&FrameResult<U>) -> bool,
+{
+    let (sink, stream) = framed.split();
+    let (true_stream, false_stream) = stream.split_by(predicate);
+    (true_stream, false_stream, SinkHandle { sink })
+}
+
+/// The write side of a `Framed` whose read side was split by
+/// `split_framed_by`. It implements `Sink` for the frames the codec encodes
+pub struct SinkHandle<T, U, Item> {
+    sink: SplitSink<Framed<T, U>, Item>,
+}
+
+impl<T, U, Item> SinkHandle<T, U, Item> {
+    /// Puts the `Framed` back together from this and the underlying stream
+    /// handed back by `into_parts` on the split's streams. Fails if `stream`
+    /// came from a different `Framed`
+    pub fn reunite(
+        self,
+        stream: SplitStream<Framed<T, U>>,
+    ) -> Result<Framed<T, U>, ReuniteError<Framed<T, U>, Item>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+        U: Decoder + Encoder<Item>,
+    {
+        self.sink.reunite(stream)
+    }
+}
+
+impl<T, U, Item> Sink<Item> for SinkHandle<T, U, Item>
+where
+    T: AsyncWrite,
+    U: Encoder<Item>,
+    U::Error: From<io::Error>,
+{
+    type Error = U::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        Pin::new(&mut self.sink).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_close(cx)
+    }
+}
+
+impl<T, U, Item> fmt::Debug for SinkHandle<T, U, Item> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SinkHandle").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+
+    use super::{split_framed_by, SinkHandle};
+
+    #[test]
+    fn test_partial_frames() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut client, server) = tokio::io::duplex(64);
+            let (mut errors, mut lines, _sink): (_, _, SinkHandle<_, _, String>) =
+                split_framed_by(Framed::new(server, LinesCodec::new()), |line| line.is_err());
+            client.write_all(b"hel").await.unwrap();
+            // Half a line isn't a frame yet
+            assert!(futures::poll!(lines.next()).is_pending());
+            client.write_all(b"lo\nwor").await.unwrap();
+            assert_eq!("hello", lines.next().await.unwrap().unwrap());
+            assert!(futures::poll!(lines.next()).is_pending());
+            client.write_all(b"ld\nbye").await.unwrap();
+            assert_eq!("world", lines.next().await.unwrap().unwrap());
+            // What's left when the connection closes is the last frame
+            drop(client);
+            assert_eq!("bye", lines.next().await.unwrap().unwrap());
+            assert!(lines.next().await.is_none());
+            assert!(errors.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_decoder_error() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut client, server) = tokio::io::duplex(64);
+            let (mut errors, mut lines, _sink): (_, _, SinkHandle<_, _, String>) = split_framed_by(
+                Framed::new(server, LinesCodec::new_with_max_length(4)),
+                |line| line.is_err(),
+            );
+            client.write_all(b"ok\ntoo long\nfine\n").await.unwrap();
+            assert_eq!("ok", lines.next().await.unwrap().unwrap());
+            assert!(matches!(
+                errors.next().await,
+                Some(Err(LinesCodecError::MaxLineLengthExceeded))
+            ));
+            // `Framed` yields `None` after an error, which ends the split
+            assert!(lines.next().await.is_none());
+            assert!(errors.next().await.is_none());
+        });
+    }
+}
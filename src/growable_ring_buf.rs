@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+
+/// A FIFO queue like `RingBuf`, but heap allocated, starting out small and
+/// doubling its capacity as needed until it holds `max` items, and halving it
+/// again as it empties out. This suits buffers whose size is picked at
+/// runtime, or that are usually close to empty but have to absorb the
+/// occasional burst. It can back the buffered splits in place of `RingBuf`,
+/// see `SplitBuilder::growable_buffer`
+///
+///```rust
+/// use split_stream_by::GrowableRingBuf;
+///
+/// let mut buf = GrowableRingBuf::new(1, 3);
+/// assert_eq!(None, buf.push_back(1));
+/// assert_eq!(None, buf.push_back(2));
+/// assert_eq!(None, buf.push_back(3));
+/// assert_eq!(Some(4), buf.push_back(4));
+/// assert_eq!(Some(1), buf.pop_front());
+/// ```
+pub struct GrowableRingBuf<T> {
+    items: VecDeque<T>,
+    /// The capacity the buffer grows into next, which doubles each time up to
+    /// `max`, and halves back down to `initial` as the buffer empties
+    capacity: usize,
+    initial: usize,
+    max: usize,
+}
+
+impl<T> GrowableRingBuf<T> {
+    /// Creates an empty buffer with room for `initial` items, which grows to
+    /// hold up to `max`. Panics if `max` is 0 or smaller than `initial`
+    pub fn new(initial: usize, max: usize) -> Self {
+        assert!(max > 0, "a buffer needs room for at least one item");
+        assert!(
+            initial <= max,
+            "the initial capacity can't exceed the maximum"
+        );
+        let capacity = initial.max(1);
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            initial: capacity,
+            max,
+        }
+    }
+
+    /// The capacity the buffer starts out with and shrinks back to
+    pub fn initial(&self) -> usize {
+        self.initial
+    }
+
+    /// How many items the buffer can hold before it has to grow again
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The most items the buffer will ever hold
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// How many more items fit in the buffer, counting the room it can grow
+    /// into
+    pub fn remaining(&self) -> usize {
+        self.max - self.items.len()
+    }
+
+    /// How many items are in the buffer
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether the buffer holds no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Adds `item` to the back of the buffer, doubling its capacity if it's
+    /// out of room. The item is handed back if the buffer already holds `max`
+    /// items
+    pub fn push_back(&mut self, item: T) -> Option<T> {
+        if self.items.len() == self.max {
+            return Some(item);
+        }
+        if self.items.len() == self.capacity {
+            self.capacity = self.capacity.saturating_mul(2).min(self.max);
+            self.items.reserve_exact(self.capacity - self.items.len());
+        }
+        self.items.push_back(item);
+        None
+    }
+
+    /// Takes the oldest item out of the buffer, halving its capacity once it's
+    /// down to a quarter full
+    pub fn pop_front(&mut self) -> Option<T> {
+        let item = self.items.pop_front();
+        if self.capacity > self.initial && self.items.len() <= self.capacity / 4 {
+            self.capacity = (self.capacity / 2).max(self.initial);
+            self.items.shrink_to(self.capacity);
+        }
+        item
+    }
+
+    /// Returns the oldest item in the buffer without taking it out
+    pub fn peek_front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    /// Takes every item out of the buffer, oldest first, keeping its capacity.
+    /// Anything the iterator hasn't yielded when it's dropped is dropped with it
+    pub fn drain(&mut self) -> std::collections::vec_deque::Drain<'_, T> {
+        self.items.drain(..)
+    }
+
+    /// Drops every item in the buffer, keeping its capacity
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Returns the items in the buffer, oldest first, as two slices
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.items.as_slices()
+    }
+
+    /// Iterates over the items in the buffer, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GrowableRingBuf;
+
+    #[test]
+    fn test_grows_by_doubling_up_to_max() {
+        let mut buf = GrowableRingBuf::new(2, 5);
+        assert_eq!(2, buf.capacity());
+        for i in 0..3 {
+            assert_eq!(None, buf.push_back(i));
+        }
+        assert_eq!(4, buf.capacity());
+        for i in 3..5 {
+            assert_eq!(None, buf.push_back(i));
+        }
+        // Doubling again would overshoot, so it stops at `max`
+        assert_eq!(5, buf.capacity());
+        assert_eq!(0, buf.remaining());
+        assert_eq!(Some(5), buf.push_back(5));
+        assert_eq!(vec![0, 1, 2, 3, 4], buf.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_wraps_around() {
+        let mut buf = GrowableRingBuf::new(4, 4);
+        for i in 0..4 {
+            assert_eq!(None, buf.push_back(i));
+        }
+        // Keep the buffer full while the front moves all the way around it
+        for i in 4..10 {
+            assert_eq!(Some(i - 4), buf.pop_front());
+            assert_eq!(None, buf.push_back(i));
+            assert_eq!(Some(&(i - 3)), buf.peek_front());
+        }
+        assert_eq!(4, buf.capacity());
+        let (front, back) = buf.as_slices();
+        assert_eq!(vec![6, 7, 8, 9], [front, back].concat());
+        assert_eq!(vec![6, 7, 8, 9], buf.drain().collect::<Vec<_>>());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_shrinks_as_it_empties() {
+        let mut buf = GrowableRingBuf::new(1, 16);
+        for i in 0..16 {
+            assert_eq!(None, buf.push_back(i));
+        }
+        assert_eq!(16, buf.capacity());
+        for _ in 0..12 {
+            buf.pop_front();
+        }
+        assert_eq!(8, buf.capacity());
+        for _ in 0..2 {
+            buf.pop_front();
+        }
+        assert_eq!(4, buf.capacity());
+        for _ in 0..2 {
+            buf.pop_front();
+        }
+        // Never shrinks below where it started
+        assert_eq!(1, buf.capacity());
+        assert_eq!(None, buf.pop_front());
+        assert_eq!(1, buf.capacity());
+        // And grows again when the next burst comes
+        for i in 0..3 {
+            assert_eq!(None, buf.push_back(i));
+        }
+        assert_eq!(4, buf.capacity());
+    }
+
+    #[test]
+    #[should_panic(expected = "the initial capacity can't exceed the maximum")]
+    fn test_initial_above_max_panics() {
+        GrowableRingBuf::<u8>::new(4, 2);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future::Either,
+    stream::{FusedStream, Stream},
+};
+
+use crate::{
+    offload::{route_verdict, RouteVerdict},
+    Left, Right, SplitStreamByMapExt, Verdict,
+};
+
+/// The first stream returned by `split_by_handshake`
+pub type HandshakeLeft<S, F> = Left<
+    Handshake<S, F>,
+    Handshaken<<S as Stream>::Item>,
+    Handshaken<<S as Stream>::Item>,
+    RouteVerdict<Handshaken<<S as Stream>::Item>, Handshaken<<S as Stream>::Item>>,
+>;
+
+/// The second stream returned by `split_by_handshake`
+pub type HandshakeRight<S, F> = Right<
+    Handshake<S, F>,
+    Handshaken<<S as Stream>::Item>,
+    Handshaken<<S as Stream>::Item>,
+    RouteVerdict<Handshaken<<S as Stream>::Item>, Handshaken<<S as Stream>::Item>>,
+>;
+
+/// Splits a stream of streams, such as incoming connections, into two
+/// streams by the first item of each inner stream, e.g. routing connections
+/// by their first frame. `predicate` is called on the first item, `true` for
+/// the first stream, and the inner stream is yielded by the side it picked as
+/// a `Handshaken`, which yields the first item again before the rest.
+///
+/// Up to `max_pending` inner streams are polled for their first item at once,
+/// and each is routed as soon as it has one, so a slow handshake doesn't hold
+/// up the ones behind it. Inner streams that end without an item are dropped.
+/// A panic in the predicate ends both streams, and its payload can be taken
+/// with `take_panic` on either of them
+///
+/// Panics if `max_pending` is zero
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_handshake;
+///
+/// let connections = futures::stream::iter([
+///     futures::stream::iter(vec!["GET /", "Host: a"]),
+///     futures::stream::iter(vec!["SSH-2.0", "key"]),
+///     futures::stream::iter(vec!["POST /", "Host: b"]),
+/// ]);
+/// let (http, other) = split_by_handshake(connections, 16, |first: &&str| {
+///     first.starts_with("GET") || first.starts_with("POST")
+/// });
+/// futures::executor::block_on(async {
+///     let (http, other) = futures::join!(
+///         http.flat_map(|frames| frames).collect::<Vec<_>>(),
+///         other.flat_map(|frames| frames).collect::<Vec<_>>(),
+///     );
+///     assert_eq!(vec!["GET /", "Host: a", "POST /", "Host: b"], http);
+///     assert_eq!(vec!["SSH-2.0", "key"], other);
+/// });
+/// ```
+pub fn split_by_handshake<S, F>(
+    stream: S,
+    max_pending: usize,
+    predicate: F,
+) -> (HandshakeLeft<S, F>, HandshakeRight<S, F>)
+where
+    S: Stream,
+    S::Item: Stream,
+    F: Fn(&<S::Item as Stream>::Item) -> bool,
+{
+    assert!(max_pending > 0, "at least one handshake has to be pending");
+    let stream = Handshake {
+        stream: Some(Box::pin(stream)),
+        max_pending,
+        predicate,
+        pending: Vec::new(),
+        failed: false,
+    };
+    stream.split_by_map(route_verdict as RouteVerdict<Handshaken<S::Item>, Handshaken<S::Item>>)
+}
+
+/// An inner stream routed by `split_by_handshake`, which yields the item it
+/// was routed by and then the rest of the inner stream
+pub struct Handshaken<T: Stream> {
+    first: Option<T::Item>,
+    stream: Pin<Box<T>>,
+}
+
+// The inner stream is boxed so that nothing is pinned in place
+impl<T: Stream> Unpin for Handshaken<T> {}
+
+impl<T: Stream> Handshaken<T> {
+    /// The item the inner stream was routed by, if it hasn't been yielded yet
+    pub fn first(&self) -> Option<&T::Item> {
+        self.first.as_ref()
+    }
+}
+
+impl<T: Stream> Stream for Handshaken<T> {
+    type Item = T::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(item) = self.first.take() {
+            return Poll::Ready(Some(item));
+        }
+        self.stream.as_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let first = usize::from(self.first.is_some());
+        let (lower, upper) = self.stream.size_hint();
+        (
+            lower.saturating_add(first),
+            upper.and_then(|upper| upper.checked_add(first)),
+        )
+    }
+}
+
+impl<T: Stream> fmt::Debug for Handshaken<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handshaken")
+            .field("first_pending", &self.first.is_some())
+            .finish()
+    }
+}
+
+/// The underlying stream of `split_by_handshake`, which reads the first item
+/// of each inner stream and yields the inner stream with the side picked for
+/// it, in the order their first items arrive
+pub struct Handshake<S: Stream, F>
+where
+    S::Item: Stream,
+{
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    max_pending: usize,
+    predicate: F,
+    /// The inner streams waiting for their first item
+    pending: Vec<Pin<Box<S::Item>>>,
+    /// Set once a panic has been yielded
+    failed: bool,
+}
+
+// Everything that might be pinned in place is boxed
+impl<S: Stream, F> Unpin for Handshake<S, F> where S::Item: Stream {}
+
+impl<S, F> Stream for Handshake<S, F>
+where
+    S: Stream,
+    S::Item: Stream,
+    F: Fn(&<S::Item as Stream>::Item) -> bool,
+{
+    type Item = Verdict<Handshaken<S::Item>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        loop {
+            while this.pending.len() < this.max_pending {
+                let stream = match &mut this.stream {
+                    Some(stream) => stream,
+                    None => break,
+                };
+                match stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(inner)) => this.pending.push(Box::pin(inner)),
+                    Poll::Ready(None) => this.stream = None,
+                    Poll::Pending => break,
+                }
+            }
+            let was_full = this.pending.len() == this.max_pending;
+            let mut index = 0;
+            while index < this.pending.len() {
+                let first = match this.pending[index].as_mut().poll_next(cx) {
+                    Poll::Ready(Some(first)) => first,
+                    Poll::Ready(None) => {
+                        this.pending.remove(index);
+                        continue;
+                    }
+                    Poll::Pending => {
+                        index += 1;
+                        continue;
+                    }
+                };
+                let stream = this.pending.remove(index);
+                let predicate = &this.predicate;
+                let is_left = match panic::catch_unwind(AssertUnwindSafe(|| predicate(&first))) {
+                    Ok(is_left) => is_left,
+                    Err(payload) => {
+                        this.failed = true;
+                        this.pending.clear();
+                        return Poll::Ready(Some(Err(payload)));
+                    }
+                };
+                let substream = Handshaken {
+                    first: Some(first),
+                    stream,
+                };
+                return Poll::Ready(Some(Ok(if is_left {
+                    Either::Left(substream)
+                } else {
+                    Either::Right(substream)
+                })));
+            }
+            // Inner streams that ended empty make room for more, and the outer stream
+            // wasn't polled while there was none
+            if !(was_full && this.pending.len() < this.max_pending && this.stream.is_some()) {
+                break;
+            }
+        }
+        if this.stream.is_none() && this.pending.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, F> FusedStream for Handshake<S, F>
+where
+    S: Stream,
+    S::Item: Stream,
+    F: Fn(&<S::Item as Stream>::Item) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.failed || (self.stream.is_none() && self.pending.is_empty())
+    }
+}
+
+impl<S: Stream, F> fmt::Debug for Handshake<S, F>
+where
+    S::Item: Stream,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handshake")
+            .field("max_pending", &self.max_pending)
+            .field("stream_ended", &self.stream.is_none())
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{
+        channel::mpsc,
+        stream::{self, BoxStream},
+        StreamExt,
+    };
+
+    use super::split_by_handshake;
+
+    #[test]
+    fn test_slow_handshake_does_not_block() {
+        let (slow_tx, slow_rx) = mpsc::unbounded();
+        let connections: Vec<BoxStream<'static, u8>> = vec![
+            slow_rx.boxed(),
+            stream::iter(vec![1, 2]).boxed(),
+            stream::empty().boxed(),
+        ];
+        let (mut ones, mut twos) =
+            split_by_handshake(stream::iter(connections), 4, |&first| first == 1);
+        futures::executor::block_on(async {
+            // The first connection hasn't sent anything yet, which doesn't hold up the second
+            let fast = ones.next().await.unwrap();
+            assert_eq!(Some(&1), fast.first());
+            slow_tx.unbounded_send(2).unwrap();
+            drop(slow_tx);
+            let slow = twos.next().await.unwrap();
+            assert_eq!(vec![2], slow.collect::<Vec<_>>().await);
+            assert_eq!(vec![1, 2], fast.collect::<Vec<_>>().await);
+            assert!(ones.next().await.is_none());
+            assert!(twos.next().await.is_none());
+        });
+    }
+}
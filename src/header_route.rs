@@ -0,0 +1,127 @@
+use bytes::{Bytes, BytesMut};
+
+mod sealed {
+    /// Implemented by the frame types `header_predicate` can inspect
+    pub trait Frame {
+        fn bytes(&self) -> &[u8];
+    }
+}
+
+use sealed::Frame;
+
+impl Frame for Bytes {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Frame for BytesMut {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Which frames `header_predicate` sends to the first stream: those with a
+/// header field, read as a big endian integer of up to 8 bytes starting at
+/// `offset`, that equals one of the given values once masked. Frames too
+/// short to hold the field go to the second stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderMatch {
+    offset: usize,
+    len: usize,
+    mask: u64,
+    values: Vec<u64>,
+}
+
+impl HeaderMatch {
+    /// Matches on the `len` bytes starting at `offset`, which no value matches
+    /// until some are added. Panics if `len` isn't between 1 and 8
+    pub fn new(offset: usize, len: usize) -> Self {
+        assert!(
+            (1..=8).contains(&len),
+            "a header field is between 1 and 8 bytes long"
+        );
+        Self {
+            offset,
+            len,
+            mask: u64::MAX >> (64 - 8 * len),
+            values: Vec::new(),
+        }
+    }
+
+    /// Only compares the bits of the field that are set in `mask`
+    pub fn mask(mut self, mask: u64) -> Self {
+        self.mask &= mask;
+        self
+    }
+
+    /// Adds a value the masked field can match
+    pub fn value(self, value: u64) -> Self {
+        self.values([value])
+    }
+
+    /// Adds values the masked field can match
+    pub fn values(mut self, values: impl IntoIterator<Item = u64>) -> Self {
+        self.values.extend(values);
+        self.values.sort_unstable();
+        self.values.dedup();
+        self
+    }
+
+    /// Whether `frame` has a header field matching one of the values
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        let field = match frame.get(self.offset..self.offset + self.len) {
+            Some(field) => field,
+            None => return false,
+        };
+        let field = field
+            .iter()
+            .fold(0u64, |field, &byte| field << 8 | u64::from(byte));
+        self.values.binary_search(&(field & self.mask)).is_ok()
+    }
+}
+
+/// Builds a predicate for `split_by` and `split_by_buffered` that routes
+/// `Bytes` or `BytesMut` frames by a field in their header, as described by
+/// `header`, instead of calling a closure that parses each frame. Matching
+/// frames go to the first stream and the rest to the second
+///
+///```rust
+/// use bytes::Bytes;
+/// use futures::StreamExt;
+/// use split_stream_by::{header_predicate, HeaderMatch, SplitStreamByExt};
+///
+/// // The message type is the low nibble of the second byte, and types 1 and 2
+/// // are control messages
+/// let control = HeaderMatch::new(1, 1).mask(0x0f).values([1, 2]);
+/// let incoming_stream = futures::stream::iter([
+///     Bytes::from_static(&[0, 0x31, 7]),
+///     Bytes::from_static(&[0, 0x05, 8]),
+///     Bytes::from_static(&[0]),
+/// ]);
+/// let (control_stream, data_stream) =
+///     incoming_stream.split_by_buffered::<2>(header_predicate(control));
+/// futures::executor::block_on(async {
+///     let (control, data) = futures::join!(control_stream.collect::<Vec<_>>(), data_stream.collect::<Vec<_>>());
+///     assert_eq!(vec![Bytes::from_static(&[0, 0x31, 7])], control);
+///     assert_eq!(2, data.len());
+/// });
+/// ```
+pub fn header_predicate<B: Frame>(header: HeaderMatch) -> impl Fn(&B) -> bool + Clone {
+    move |frame| header.matches(frame.bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header_match() {
+        let header = HeaderMatch::new(2, 2).mask(0xfff0).value(0x1230);
+        assert!(header.matches(&[0, 0, 0x12, 0x34]));
+        assert!(!header.matches(&[0, 0, 0x13, 0x34]));
+        assert!(!header.matches(&[0, 0, 0x12]));
+        let wide = HeaderMatch::new(0, 8).value(u64::MAX);
+        assert!(wide.matches(&[0xff; 8]));
+    }
+}
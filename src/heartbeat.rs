@@ -0,0 +1,189 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::delay::{Delay, Timer};
+
+/// Wraps one side of a split, or any stream, so that it yields an item made
+/// by `heartbeat` whenever it has yielded nothing for `interval`, letting the
+/// consumer tell a quiet side from a dead one. The interval starts from when
+/// the stream is first polled and again after each item, heartbeats
+/// included, and heartbeats stop once the stream has ended
+///
+///```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use split_stream_by::{with_heartbeat, SplitStreamByExt};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Event {
+///     Reading(u32),
+///     Heartbeat,
+/// }
+///
+/// let incoming_stream = futures::stream::iter([1, 2, 3]).map(Event::Reading).chain(futures::stream::pending());
+/// let (even_stream, odd_stream) = incoming_stream.split_by(|event| *event == Event::Reading(2));
+/// let mut even_stream = with_heartbeat(even_stream, Duration::from_millis(10), || Event::Heartbeat);
+/// let mut odd_stream = with_heartbeat(odd_stream, Duration::from_millis(10), || Event::Heartbeat);
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     assert_eq!(Some(Event::Reading(1)), odd_stream.next().await);
+///     assert_eq!(Some(Event::Reading(2)), even_stream.next().await);
+///     assert_eq!(Some(Event::Reading(3)), odd_stream.next().await);
+///     assert_eq!(Some(Event::Heartbeat), odd_stream.next().await);
+///     assert_eq!(Some(Event::Heartbeat), even_stream.next().await);
+/// });
+/// ```
+pub fn with_heartbeat<S, F>(stream: S, interval: Duration, heartbeat: F) -> Heartbeat<S, F>
+where
+    S: Stream,
+    F: FnMut() -> S::Item,
+{
+    Heartbeat {
+        stream: Box::pin(stream),
+        interval,
+        heartbeat,
+        timer: None,
+        ended: false,
+    }
+}
+
+/// A stream wrapped by `with_heartbeat`, yielding its items along with a
+/// heartbeat whenever it has been quiet for the interval
+pub struct Heartbeat<S, F> {
+    stream: Pin<Box<S>>,
+    interval: Duration,
+    heartbeat: F,
+    /// Started on the first poll
+    timer: Option<Timer>,
+    ended: bool,
+}
+
+// The underlying stream is boxed so that nothing is pinned in place
+impl<S, F> Unpin for Heartbeat<S, F> {}
+
+impl<S, F> Stream for Heartbeat<S, F>
+where
+    S: Stream,
+    F: FnMut() -> S::Item,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        if this.ended {
+            return Poll::Ready(None);
+        }
+        let interval = this.interval;
+        match this.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let Some(timer) = &mut this.timer {
+                    Delay::reset(timer, interval);
+                }
+                return Poll::Ready(Some(item));
+            }
+            Poll::Ready(None) => {
+                this.ended = true;
+                this.timer = None;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {}
+        }
+        let timer = this
+            .timer
+            .get_or_insert_with(|| <Timer as Delay>::new(interval));
+        match Pin::new(&mut *timer).poll(cx) {
+            Poll::Ready(()) => {
+                Delay::reset(timer, interval);
+                Poll::Ready(Some((this.heartbeat)()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.ended {
+            (0, Some(0))
+        } else {
+            (self.stream.size_hint().0, None)
+        }
+    }
+}
+
+impl<S, F> FusedStream for Heartbeat<S, F>
+where
+    S: Stream,
+    F: FnMut() -> S::Item,
+{
+    fn is_terminated(&self) -> bool {
+        self.ended
+    }
+}
+
+impl<S, F> fmt::Debug for Heartbeat<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("interval", &self.interval)
+            .field("ended", &self.ended)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures::{stream::FusedStream, Stream, StreamExt};
+
+    use super::with_heartbeat;
+
+    const INTERVAL: Duration = Duration::from_millis(40);
+
+    #[test]
+    fn test_items_reset_interval() {
+        // Items every quarter interval keep the heartbeat away until they stop
+        let incoming_stream = futures::stream::unfold(0, |n| async move {
+            tokio::time::sleep(INTERVAL / 4).await;
+            Some((Some(n), n + 1))
+        })
+        .take(4)
+        .chain(futures::stream::pending());
+        let heartbeats = with_heartbeat(incoming_stream, INTERVAL, || None);
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let received = heartbeats.take(5).collect::<Vec<_>>().await;
+            assert_eq!(vec![Some(0), Some(1), Some(2), Some(3), None], received);
+        });
+    }
+
+    #[test]
+    fn test_heartbeat_each_interval() {
+        let mut beats = 0;
+        let heartbeats = with_heartbeat(futures::stream::pending(), INTERVAL, || {
+            beats += 1;
+            beats
+        });
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let start = Instant::now();
+            assert_eq!(vec![1, 2, 3], heartbeats.take(3).collect::<Vec<_>>().await);
+            assert!(start.elapsed() >= INTERVAL * 3);
+        });
+    }
+
+    #[test]
+    fn test_no_heartbeat_after_end() {
+        let mut heartbeats = with_heartbeat(futures::stream::iter([Some(1)]), INTERVAL, || None);
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            assert_eq!(Some(Some(1)), heartbeats.next().await);
+            assert_eq!(None, heartbeats.next().await);
+            assert!(heartbeats.is_terminated());
+            assert_eq!((0, Some(0)), heartbeats.size_hint());
+            tokio::time::sleep(INTERVAL * 2).await;
+            assert_eq!(None, heartbeats.next().await);
+        });
+    }
+}
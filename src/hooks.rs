@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use crate::Side;
+
+/// Callbacks run by a splitter as it routes and buffers items, for lightweight
+/// observability without the `tracing` or `metrics` features. `L` and `R` are
+/// the item types of the left and right streams, which are the same for
+/// `split_by` and `split_by_buffered`.
+///
+/// The callbacks run while the splitter's lock is held, so they should be
+/// quick and mustn't poll either of its streams
+///
+///```rust
+/// use std::sync::{
+///     atomic::{AtomicUsize, Ordering},
+///     Arc,
+/// };
+/// use futures::StreamExt;
+/// use split_stream_by::{RouteHooks, SplitStreamByExt};
+///
+/// let largest_buffer = Arc::new(AtomicUsize::new(0));
+/// let hooks = RouteHooks::new().on_buffer({
+///     let largest_buffer = Arc::clone(&largest_buffer);
+///     move |_side, len| {
+///         largest_buffer.fetch_max(len, Ordering::Relaxed);
+///     }
+/// });
+/// let incoming_stream = futures::stream::iter([1, 3, 0, 2]);
+/// let (even_stream, odd_stream) =
+///     incoming_stream.split_by_buffered_with_hooks::<2>(|&n| n % 2 == 0, hooks);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(
+///         even_stream.collect::<Vec<_>>(),
+///         odd_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0, 2], evens);
+///     assert_eq!(vec![1, 3], odds);
+/// });
+/// assert!(largest_buffer.load(Ordering::Relaxed) <= 2);
+/// ```
+pub struct RouteHooks<L, R = L> {
+    on_route_left: Option<Box<dyn Fn(&L) + Send>>,
+    on_route_right: Option<Box<dyn Fn(&R) + Send>>,
+    on_buffer: Option<Box<dyn Fn(Side, usize) + Send>>,
+    on_discard_left: Option<Box<dyn Fn(L) + Send>>,
+    on_discard_right: Option<Box<dyn Fn(R) + Send>>,
+}
+
+impl<L, R> RouteHooks<L, R> {
+    /// Creates a set of hooks that don't do anything
+    pub fn new() -> Self {
+        Self {
+            on_route_left: None,
+            on_route_right: None,
+            on_buffer: None,
+            on_discard_left: None,
+            on_discard_right: None,
+        }
+    }
+
+    /// Calls `f` with each item the predicate routes to the left stream,
+    /// including any that are discarded because that stream was closed
+    pub fn on_route_left(mut self, f: impl Fn(&L) + Send + 'static) -> Self {
+        self.on_route_left = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with each item the predicate routes to the right stream,
+    /// including any that are discarded because that stream was closed
+    pub fn on_route_right(mut self, f: impl Fn(&R) + Send + 'static) -> Self {
+        self.on_route_right = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the new number of buffered items whenever the buffer for
+    /// a side grows or shrinks
+    pub fn on_buffer(mut self, f: impl Fn(Side, usize) + Send + 'static) -> Self {
+        self.on_buffer = Some(Box::new(f));
+        self
+    }
+
+    /// Hands `f` each item meant for the left stream that the splitter
+    /// discards rather than yields, instead of dropping it. This covers items
+    /// dropped because the stream was closed, paused with
+    /// `PausePolicy::Drop`, degraded, over its quota or past its deadline, and
+    /// anything buffered for it when it's closed. It's meant for returning
+    /// the items' allocations to a pool, e.g. `BytesMut` frames. Dead letters
+    /// only reach it when there's no `DeadLetters` stream to take them, since
+    /// otherwise they belong to whoever consumes that stream
+    pub fn on_discard_left(mut self, f: impl Fn(L) + Send + 'static) -> Self {
+        self.on_discard_left = Some(Box::new(f));
+        self
+    }
+
+    /// Hands `f` each item meant for the right stream that the splitter
+    /// discards rather than yields, instead of dropping it. See
+    /// `on_discard_left` for when that happens
+    pub fn on_discard_right(mut self, f: impl Fn(R) + Send + 'static) -> Self {
+        self.on_discard_right = Some(Box::new(f));
+        self
+    }
+
+    pub(crate) fn routed_left(&self, item: &L) {
+        if let Some(f) = &self.on_route_left {
+            f(item);
+        }
+    }
+
+    pub(crate) fn routed_right(&self, item: &R) {
+        if let Some(f) = &self.on_route_right {
+            f(item);
+        }
+    }
+
+    pub(crate) fn buffered(&self, side: Side, len: usize) {
+        if let Some(f) = &self.on_buffer {
+            f(side, len);
+        }
+    }
+
+    /// Hands discarded items meant for the left stream to the hook, or drops
+    /// them if there isn't one
+    pub(crate) fn discarded_left(&self, items: impl IntoIterator<Item = L>) {
+        match &self.on_discard_left {
+            Some(f) => items.into_iter().for_each(f),
+            None => drop(items),
+        }
+    }
+
+    /// Hands discarded items meant for the right stream to the hook, or drops
+    /// them if there isn't one
+    pub(crate) fn discarded_right(&self, items: impl IntoIterator<Item = R>) {
+        match &self.on_discard_right {
+            Some(f) => items.into_iter().for_each(f),
+            None => drop(items),
+        }
+    }
+}
+
+impl<T> RouteHooks<T, T> {
+    /// Calls `f` with each item the predicate routes and the side it was
+    /// routed to, for splits where both streams have the same item type
+    pub fn on_route(self, f: impl Fn(Side, &T) + Send + Sync + 'static) -> Self {
+        let f = Arc::new(f);
+        let left = Arc::clone(&f);
+        self.on_route_left(move |item| left(Side::Left, item))
+            .on_route_right(move |item| f(Side::Right, item))
+    }
+
+    /// Hands `f` each item the splitter discards rather than yields, along
+    /// with the side it was meant for, for splits where both streams have the
+    /// same item type. See `on_discard_left` for when that happens
+    ///
+    ///```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use futures::StreamExt;
+    /// use split_stream_by::{ClosePolicy, RouteHooks, SplitStreamByExt};
+    ///
+    /// let pool = Arc::new(Mutex::new(Vec::new()));
+    /// let hooks = RouteHooks::new().on_discard({
+    ///     let pool = Arc::clone(&pool);
+    ///     move |_side, mut buf: Vec<u8>| {
+    ///         buf.clear();
+    ///         pool.lock().unwrap().push(buf);
+    ///     }
+    /// });
+    /// let incoming_stream = futures::stream::iter([vec![1], vec![2, 2], vec![3]]);
+    /// let (short, mut long) = incoming_stream.split_by_with_hooks(|buf| buf.len() < 2, hooks);
+    /// long.close(ClosePolicy::Discard);
+    /// futures::executor::block_on(async {
+    ///     assert_eq!(vec![vec![1], vec![3]], short.collect::<Vec<_>>().await);
+    /// });
+    /// assert_eq!(1, pool.lock().unwrap().len());
+    /// assert!(pool.lock().unwrap()[0].capacity() >= 2);
+    /// ```
+    pub fn on_discard(self, f: impl Fn(Side, T) + Send + Sync + 'static) -> Self {
+        let f = Arc::new(f);
+        let left = Arc::clone(&f);
+        self.on_discard_left(move |item| left(Side::Left, item))
+            .on_discard_right(move |item| f(Side::Right, item))
+    }
+
+    /// Hands discarded items meant for `side` to the hook, or drops them if
+    /// there isn't one
+    pub(crate) fn discarded(&self, side: Side, items: impl IntoIterator<Item = T>) {
+        match side {
+            Side::Left => self.discarded_left(items),
+            Side::Right => self.discarded_right(items),
+        }
+    }
+}
+
+impl<L, R> Default for RouteHooks<L, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
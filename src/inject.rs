@@ -0,0 +1,154 @@
+use std::sync::Weak;
+
+use crate::sync::{LockFamily, Mutex, StdMutex};
+
+mod sealed {
+    /// Implemented by the shared state of each kind of split
+    pub trait Injectable {
+        type Left;
+        type Right;
+        fn inject_left(&mut self, item: Self::Left) -> Result<(), Self::Left>;
+        fn inject_right(&mut self, item: Self::Right) -> Result<(), Self::Right>;
+    }
+}
+
+pub(crate) use sealed::Injectable;
+
+/// A handle for pushing items from outside the split into either output
+/// stream, returned by `inject_handle` on either of them. Injected items go
+/// into the stream's buffer behind anything already waiting there, so they're
+/// merged with the routed items in the order they arrive. The left stream is
+/// the `true` stream for `split_by`. The handle doesn't keep the split alive
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::SplitStreamByExt;
+///
+/// let incoming_stream = futures::stream::iter([0,2]);
+/// let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+/// let inject = odd_stream.inject_handle();
+/// assert_eq!(Ok(()), inject.inject_right(101));
+/// // There's only room for one item in the unbuffered splits
+/// assert_eq!(Err(103), inject.inject_right(103));
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(101), odd_stream.next().await);
+///     assert_eq!(Some(0), even_stream.next().await);
+/// });
+/// ```
+pub struct InjectHandle<T, M: LockFamily = StdMutex> {
+    state: Weak<Mutex<T, M>>,
+}
+
+impl<T, M: LockFamily> InjectHandle<T, M> {
+    pub(crate) fn new(state: Weak<Mutex<T, M>>) -> Self {
+        Self { state }
+    }
+}
+
+impl<T: Injectable, M: LockFamily> InjectHandle<T, M> {
+    /// Buffers `item` for the left stream, waking it. The item is handed back
+    /// if the buffer is full, if the left stream has been closed, if the
+    /// underlying stream has ended or if both streams have been dropped
+    pub fn inject_left(&self, item: T::Left) -> Result<(), T::Left> {
+        match self.state.upgrade() {
+            Some(state) => {
+                let mut guard = state.lock();
+                guard.inject_left(item)
+            }
+            None => Err(item),
+        }
+    }
+
+    /// Buffers `item` for the right stream, waking it. The item is handed
+    /// back if the buffer is full, if the right stream has been closed, if
+    /// the underlying stream has ended or if both streams have been dropped
+    pub fn inject_right(&self, item: T::Right) -> Result<(), T::Right> {
+        match self.state.upgrade() {
+            Some(state) => {
+                let mut guard = state.lock();
+                guard.inject_right(item)
+            }
+            None => Err(item),
+        }
+    }
+}
+
+impl<T, M: LockFamily> Clone for InjectHandle<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Weak::clone(&self.state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use crate::{test_util::CountingWaker, SplitStreamByExt};
+
+    #[test]
+    fn test_inject_wakes_waiting_side() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        let (_sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (_evens, mut odds) = receiver.split_by_buffered::<2>(|&n| n % 2 == 0);
+        let inject = odds.inject_handle();
+        assert_eq!(Poll::Pending, odds.poll_next_unpin(&mut cx));
+        let woken = counter.wakes();
+        assert_eq!(Ok(()), inject.inject_right(101));
+        assert!(counter.wakes() > woken);
+        assert_eq!(Poll::Ready(Some(101)), odds.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_inject_behind_routed_items() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 0]);
+        let (mut evens, mut odds) = incoming_stream.split_by_buffered::<4>(|&n| n % 2 == 0);
+        let inject = evens.inject_handle();
+        let first_even = loop {
+            if let Poll::Ready(even) = evens.poll_next_unpin(&mut cx) {
+                break even;
+            }
+        };
+        assert_eq!(Some(0), first_even);
+        // 101 goes in behind the 1 already waiting for the odds
+        assert_eq!(Ok(()), inject.inject_right(101));
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(101)), odds.poll_next_unpin(&mut cx));
+        // Once the underlying stream has ended nothing more is taken
+        assert_eq!(Poll::Ready(None), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Err(103), inject.inject_right(103));
+    }
+
+    #[test]
+    fn test_full_buffer_hands_item_back() {
+        let (_sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (mut evens, _odds) = receiver.split_by_buffered::<2>(|&n| n % 2 == 0);
+        let inject = evens.inject_handle();
+        assert_eq!(Ok(()), inject.inject_left(100));
+        assert_eq!(Ok(()), inject.inject_left(102));
+        assert_eq!(Err(104), inject.inject_left(104));
+        // Taking an item makes room for another
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Ready(Some(100)), evens.poll_next_unpin(&mut cx));
+        assert_eq!(Ok(()), inject.inject_left(104));
+    }
+
+    #[test]
+    fn test_handle_refused_once_closed() {
+        let incoming_stream = futures::stream::iter([0, 1]);
+        let (evens, odds) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let inject = evens.inject_handle();
+        drop(odds);
+        assert_eq!(Err(101), inject.inject_right(101));
+        assert_eq!(Ok(()), inject.inject_left(100));
+        // The handle doesn't keep the split alive
+        drop(evens);
+        assert_eq!(Err(102), inject.clone().inject_left(102));
+    }
+}
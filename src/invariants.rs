@@ -0,0 +1,183 @@
+use std::task::Poll;
+
+use crate::{side_waker::SideWaker, stats::StatsSlot, Side};
+
+/// What `check` needs to know about one side of a split
+pub(crate) struct SideState<'a> {
+    pub(crate) buffered: usize,
+    pub(crate) closed: bool,
+    pub(crate) waker: &'a SideWaker,
+}
+
+/// Panics if the shared state of a split is in a state it should never reach,
+/// given that polling `side` just returned `poll`. Only compiled in with the
+/// `debug-invariants` feature, which calls this after every poll of an output
+/// stream
+pub(crate) fn check<T>(
+    side: Side,
+    poll: &Poll<Option<T>>,
+    sides: [SideState<'_>; 2],
+    terminated: bool,
+    has_stream: bool,
+    stats: &StatsSlot,
+) {
+    for (index, state) in sides.iter().enumerate() {
+        let side = if index == 0 { Side::Left } else { Side::Right };
+        assert_eq!(
+            state.buffered,
+            stats.buffered_len(side),
+            "the buffered gauge for {:?} is out of step with its buffer",
+            side
+        );
+        assert!(
+            !state.closed || state.buffered == 0,
+            "{:?} is closed but still has items buffered",
+            side
+        );
+    }
+    assert!(
+        has_stream || terminated,
+        "the underlying stream was handed back without ending the split"
+    );
+    let state = &sides[side as usize];
+    match poll {
+        Poll::Pending => assert!(
+            state.waker.is_registered(),
+            "{:?} returned pending without registering a waker",
+            side
+        ),
+        Poll::Ready(None) => assert_eq!(
+            0, state.buffered,
+            "{:?} ended with items still buffered for it",
+            side
+        ),
+        Poll::Ready(Some(_)) => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::task::noop_waker_ref;
+
+    use super::{check, SideState};
+    use crate::{side_waker::SideWaker, stats::StatsSlot, Side};
+
+    /// Checks a split with one item buffered for the left side, after the
+    /// right side was polled with `poll`
+    fn check_right(poll: Poll<Option<u32>>, right_closed: bool, waker: &SideWaker) {
+        let mut stats = StatsSlot::default();
+        stats.buffered(Side::Left, 1);
+        let left_waker = SideWaker::new("left");
+        check(
+            Side::Right,
+            &poll,
+            [
+                SideState {
+                    buffered: 1,
+                    closed: false,
+                    waker: &left_waker,
+                },
+                SideState {
+                    buffered: 0,
+                    closed: right_closed,
+                    waker,
+                },
+            ],
+            false,
+            true,
+            &stats,
+        );
+    }
+
+    #[test]
+    fn test_consistent_state() {
+        let mut waker = SideWaker::new("right");
+        check_right(Poll::Ready(Some(1)), false, &waker);
+        check_right(Poll::Ready(None), true, &waker);
+        waker.register(0, noop_waker_ref());
+        check_right(Poll::Pending, false, &waker);
+    }
+
+    #[test]
+    #[should_panic(expected = "returned pending without registering a waker")]
+    fn test_pending_without_waker() {
+        check_right(Poll::Pending, false, &SideWaker::new("right"));
+    }
+
+    #[test]
+    #[should_panic(expected = "is closed but still has items buffered")]
+    fn test_closed_with_items_buffered() {
+        let mut stats = StatsSlot::default();
+        stats.buffered(Side::Left, 1);
+        let waker = SideWaker::new("left");
+        let sides = [
+            SideState {
+                buffered: 1,
+                closed: true,
+                waker: &waker,
+            },
+            SideState {
+                buffered: 0,
+                closed: false,
+                waker: &waker,
+            },
+        ];
+        check(
+            Side::Right,
+            &Poll::<Option<u32>>::Ready(Some(1)),
+            sides,
+            false,
+            true,
+            &stats,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "out of step with its buffer")]
+    fn test_gauge_out_of_step() {
+        let stats = StatsSlot::default();
+        let waker = SideWaker::new("left");
+        let sides = [
+            SideState {
+                buffered: 1,
+                closed: false,
+                waker: &waker,
+            },
+            SideState {
+                buffered: 0,
+                closed: false,
+                waker: &waker,
+            },
+        ];
+        check(
+            Side::Right,
+            &Poll::<Option<u32>>::Ready(Some(1)),
+            sides,
+            false,
+            true,
+            &stats,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "handed back without ending the split")]
+    fn test_stream_taken_before_end() {
+        let stats = StatsSlot::default();
+        let waker = SideWaker::new("left");
+        let side = || SideState {
+            buffered: 0,
+            closed: false,
+            waker: &waker,
+        };
+        check(
+            Side::Left,
+            &Poll::<Option<u32>>::Ready(None),
+            [side(), side()],
+            false,
+            false,
+            &stats,
+        );
+    }
+}
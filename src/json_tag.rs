@@ -0,0 +1,173 @@
+use std::{fmt, sync::Arc};
+
+use futures::future::Either;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+mod sealed {
+    /// Implemented by the item types `json_tag_predicate` can parse
+    pub trait Json {
+        fn into_value(self) -> Result<serde_json::Value, serde_json::Error>;
+    }
+}
+
+use sealed::Json;
+
+impl Json for Value {
+    fn into_value(self) -> Result<Value, serde_json::Error> {
+        Ok(self)
+    }
+}
+
+impl Json for String {
+    fn into_value(self) -> Result<Value, serde_json::Error> {
+        serde_json::from_str(&self)
+    }
+}
+
+impl Json for &str {
+    fn into_value(self) -> Result<Value, serde_json::Error> {
+        serde_json::from_str(self)
+    }
+}
+
+/// Why `json_tag_predicate` couldn't turn an item into one of the two types
+#[derive(Debug)]
+pub enum JsonTagError {
+    /// The item isn't valid JSON, or doesn't deserialize into the type its tag
+    /// calls for
+    Json(serde_json::Error),
+    /// The item isn't an object with the tag field set to either of the
+    /// expected values. This holds the item as it was parsed
+    UnknownTag(Value),
+}
+
+impl fmt::Display for JsonTagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonTagError::Json(err) => write!(f, "{}", err),
+            JsonTagError::UnknownTag(_) => f.write_str("the item has no known tag"),
+        }
+    }
+}
+
+impl std::error::Error for JsonTagError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonTagError::Json(err) => Some(err),
+            JsonTagError::UnknownTag(_) => None,
+        }
+    }
+}
+
+/// What `json_tag_predicate` yields for each side
+pub type Tagged<T> = Result<T, JsonTagError>;
+
+/// Builds a predicate for `split_by_map` that splits JSON values, or lines of
+/// newline delimited JSON, by the string in their `tag` field and
+/// deserializes each into the type for its side: `A` for items tagged `left`
+/// and `B` for those tagged `right`. An item that doesn't deserialize into
+/// the type its tag calls for goes to that side as an error, while items that
+/// aren't valid JSON or don't have either tag go to the second stream as
+/// errors
+///
+///```rust
+/// use futures::StreamExt;
+/// use serde::Deserialize;
+/// use split_stream_by::{json_tag_predicate, SplitStreamByMapExt};
+///
+/// #[derive(Deserialize)]
+/// struct Metric {
+///     name: String,
+///     value: f64,
+/// }
+/// #[derive(Deserialize)]
+/// struct Log {
+///     message: String,
+/// }
+///
+/// let lines = futures::stream::iter([
+///     r#"{"type": "metric", "name": "cpu", "value": 0.5}"#,
+///     r#"{"type": "log", "message": "started"}"#,
+///     r#"{"type": "trace"}"#,
+/// ]);
+/// let (metrics, logs) = lines.split_by_map(json_tag_predicate::<_, Metric, Log>("type", "metric", "log"));
+/// futures::executor::block_on(async {
+///     let (metrics, logs) = futures::join!(metrics.collect::<Vec<_>>(), logs.collect::<Vec<_>>());
+///     assert_eq!("cpu", metrics[0].as_ref().unwrap().name);
+///     assert_eq!("started", logs[0].as_ref().unwrap().message);
+///     assert!(logs[1].is_err());
+/// });
+/// ```
+pub fn json_tag_predicate<T, A, B>(
+    tag: &str,
+    left: &str,
+    right: &str,
+) -> impl Fn(T) -> Either<Tagged<A>, Tagged<B>> + Clone
+where
+    T: Json,
+    A: DeserializeOwned,
+    B: DeserializeOwned,
+{
+    let tags: Arc<[String; 3]> = Arc::new([tag.to_owned(), left.to_owned(), right.to_owned()]);
+    move |item| {
+        let [tag, left, right] = &*tags;
+        let value = match item.into_value() {
+            Ok(value) => value,
+            Err(err) => return Either::Right(Err(JsonTagError::Json(err))),
+        };
+        let is_left = match value.get(tag).and_then(Value::as_str) {
+            Some(found) if found == left => true,
+            Some(found) if found == right => false,
+            _ => return Either::Right(Err(JsonTagError::UnknownTag(value))),
+        };
+        if is_left {
+            Either::Left(serde_json::from_value(value).map_err(JsonTagError::Json))
+        } else {
+            Either::Right(serde_json::from_value(value).map_err(JsonTagError::Json))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Count {
+        value: u32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Name {
+        value: String,
+    }
+
+    #[test]
+    fn test_json_tag_predicate() {
+        let predicate = json_tag_predicate::<Value, Count, Name>("kind", "count", "name");
+        let route = |json: &str| predicate(serde_json::from_str(json).unwrap());
+        assert!(matches!(
+            route(r#"{"kind": "count", "value": 3}"#),
+            Either::Left(Ok(Count { value: 3 }))
+        ));
+        assert!(matches!(
+            route(r#"{"kind": "name", "value": "a"}"#),
+            Either::Right(Ok(Name { value })) if value == "a"
+        ));
+        assert!(matches!(
+            route(r#"{"kind": "count", "value": "a"}"#),
+            Either::Left(Err(JsonTagError::Json(_)))
+        ));
+        assert!(matches!(
+            route(r#"{"kind": "other"}"#),
+            Either::Right(Err(JsonTagError::UnknownTag(_)))
+        ));
+        assert!(matches!(
+            route("3"),
+            Either::Right(Err(JsonTagError::UnknownTag(_)))
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+use std::{collections::VecDeque, convert::TryFrom, time::Instant};
+
+use hdrhistogram::Histogram;
+
+use crate::Side;
+
+/// Tracks when each buffered item was buffered so that the time it spends in
+/// the buffer can be recorded once it's yielded. The timestamps are kept in
+/// the same order as the buffers themselves
+pub(crate) struct Latency {
+    enqueued: [VecDeque<Instant>; 2],
+    histograms: [Histogram<u64>; 2],
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        let histogram = || Histogram::new(3).expect("3 significant figures is valid");
+        Self {
+            enqueued: Default::default(),
+            histograms: [histogram(), histogram()],
+        }
+    }
+}
+
+impl Latency {
+    /// Brings the timestamps for `side` in line with the buffer now holding
+    /// `len` items. Growing means items were just buffered, while shrinking
+    /// means they were dropped without being yielded, so nothing is recorded
+    pub(crate) fn buffered(&mut self, side: Side, len: usize) {
+        let enqueued = &mut self.enqueued[side as usize];
+        if enqueued.len() < len {
+            let now = Instant::now();
+            enqueued.resize(len, now);
+        } else {
+            enqueued.truncate(len);
+        }
+    }
+
+    /// Records the time the oldest item buffered for `side` spent waiting
+    pub(crate) fn unbuffered(&mut self, side: Side) {
+        if let Some(enqueued) = self.enqueued[side as usize].pop_front() {
+            let nanos = u64::try_from(enqueued.elapsed().as_nanos()).unwrap_or(u64::MAX);
+            self.histograms[side as usize].saturating_record(nanos);
+        }
+    }
+
+    pub(crate) fn histogram(&self, side: Side) -> Histogram<u64> {
+        self.histograms[side as usize].clone()
+    }
+}
@@ -75,24 +75,364 @@
 //!     assert_eq!(vec![Response,Response], responses.unwrap());
 //! })
 //! ```
+//!
+//! When both halves are consumed within the same scope, `split_by_scoped`
+//! (with the `scoped` feature) keeps the shared state in the returned future instead of behind an
+//! `Arc<Mutex<..>>`, handing out halves that borrow it for the duration of
+//! an async closure
+//!
+//! The halves reach the underlying stream through their shared state, so
+//! they need it to be `Unpin`. One that isn't can be pinned with `Box::pin`
+//! first, or polled in place by `split_by_driven`, whose `Drive` future holds
+//! it, or by owning one of the states like `SplitBy` directly and pinning it
+//!
+//! # Cancellation safety
+//!
+//! `StreamExt::next` on any of the output streams is cancellation safe. An
+//! item read from the underlying stream is either yielded by the poll that
+//! read it or stored in the other side's buffer before that poll returns, so
+//! dropping a `next()` future (e.g. a losing `select!` branch) never loses an
+//! item. The only items that are dropped are those routed to a side that has
+//! been closed or dropped
+//!
+//! # Features
+//!
+//! - `tracing`: emits trace level events for routing decisions (item routed,
+//!   buffered, discarded, buffer full, side woken, stream terminated) inside a
+//!   span per splitter, to help work out why one side of a split has stalled.
+//!   The driver of a spawned or driven split runs in a span of its own, within
+//!   the span current when the split was made
+//! - `metrics`: adds `StatsHandle::register_metrics` for publishing a
+//!   splitter's counters and gauges through the `metrics` facade
+//! - `latency`: adds `StatsHandle::buffer_latency`, an HDR histogram per side
+//!   of how long items spent buffered before being yielded
+//! - `tokio`: adds `split_by_spawned` and `split_by_map_spawned`, which route
+//!   items from a spawned task so that neither stream waits on the other,
+//!   along with `_named` variants that name the task for tokio-console,
+//!   `permit_predicate`, which routes by whether a semaphore permit is
+//!   available, and `split_by_map_blocking`, which runs a CPU heavy mapping
+//!   function with `spawn_blocking`
+//! - `timer`: adds `StatsHandle::watch_stalls`, which reports a side that has
+//!   items waiting for it but isn't being polled, and `set_deadline` on the
+//!   `split_by` and `split_by_buffered` streams, which reroutes, drops or
+//!   dead letters items that have waited too long for their stream,
+//!   `set_rate_limit` on every output stream, `AutoDegrade::Idle`,
+//!   `retryable`, which lets items be fed back into a stream after a delay,
+//!   `at_least_once`, which redelivers items that aren't acknowledged in
+//!   time, `with_heartbeat`, which yields a heartbeat on a side that has
+//!   been quiet for a while, and `Demux::set_idle_timeout`, which evicts the
+//!   keys of `demux_by_key` that have gone quiet. They wait on
+//!   `futures-timer`'s timer, and the `Delay` trait describes the timer and
+//!   clock they use
+//! - `tokio-timer`: the `timer` features, waiting on tokio's timer and reading
+//!   tokio's clock instead, without depending on `futures-timer`. The output
+//!   streams then have to be polled within a tokio runtime with its time
+//!   driver enabled
+//! - `wasm-timer`: the `timer` features for `wasm32-unknown-unknown`, waiting
+//!   on the browser's `setTimeout` and reading its clock, since
+//!   `std::time::Instant` panics there
+//! - `bytes`: adds `header_predicate`, which routes `Bytes` frames by a
+//!   masked field in their header without a closure parsing each one
+//! - `codec`: adds `split_framed_by`, which splits the frames read from a
+//!   `tokio_util::codec::Framed` into two streams while keeping its write
+//!   side usable as a `SinkHandle`
+//! - `lines`: adds `split_lines_by`, which reads an `AsyncBufRead` such as a
+//!   child process's stdout line by line and splits the lines into two
+//!   streams, and `prefix_predicate` for telling lines apart by their prefix
+//! - `rumqttc`: adds `split_publishes` and `split_event_loop`, which split
+//!   the events of a rumqttc event loop into the publishes received from the
+//!   broker and everything else
+//! - `rayon`: adds `split_by_rayon`, which evaluates an expensive predicate
+//!   on batches of items on rayon's thread pool rather than on the task
+//!   polling the streams. `split_by_offloaded` does the same on any executor
+//! - `serde_json`: adds `json_tag_predicate`, which splits JSON values or
+//!   lines by a tag field and deserializes each side into its own type
+//! - `debug-invariants`: checks the shared state of a split after every poll
+//!   of an output stream, panicking if the buffers, the buffered counts, the
+//!   registered wakers or the termination flags have got out of step. It's
+//!   meant for tests and fuzzing, not production
+//! - `test-util`: adds the `test_util` module, with a source stream that can
+//!   be told when to yield, pend and end, a waker counting its wakes, and
+//!   assertions on what each side of a split received
+//! - `test-fault`: adds the `fault` module, for injecting lock contention,
+//!   spurious `Poll::Pending`s and late or reordered wakes into the splits to
+//!   test their consumers under the worst interleavings. Like
+//!   `debug-invariants`, it's not meant for production
+//! - `scoped`: adds `split_by_scoped`, whose halves borrow their shared state
+//!   for the duration of an async closure rather than sharing it behind a
+//!   lock. It takes an `AsyncFnOnce`, so it needs Rust 1.85 rather than the
+//!   1.82 the rest of the crate does
+//! - `safe`: builds `RingBuf` on a `VecDeque` instead of an inline array of
+//!   `MaybeUninit`, at the cost of allocating each buffer once, which leaves
+//!   the crate free of unsafe code and marks it `forbid(unsafe_code)`
+//! - `parking_lot`: adds `ParkingLot`, for picking `parking_lot::Mutex` to
+//!   guard the shared state of a split with `SplitBuilder::lock`
+//! - `spin`: adds `Spin`, for picking a spin lock from the `spin` crate. See
+//!   `LockFamily` for the locks available
+//! - `unstable-async-iterator`: implements the nightly only
+//!   `std::async_iter::AsyncIterator` for the output streams, alongside
+//!   `Stream`. It needs a nightly compiler, so `--all-features` fails on
+//!   stable and the other features have to be listed instead
+//!
+//! # Testing under loom
+//!
+//! Building with `RUSTFLAGS="--cfg loom"` swaps `StdMutex`, the default lock,
+//! for [loom](https://docs.rs/loom)'s model of it, and `cargo test
+//! --release --test loom` then checks that two tasks polling the two sides
+//! of a split never lose a wakeup, whatever order they run in
+#![allow(clippy::type_complexity)]
+// The examples carried over from the first release indent with tabs
+#![allow(clippy::tabs_in_doc_comments)]
+#![cfg_attr(feature = "safe", forbid(unsafe_code))]
+#![cfg_attr(feature = "unstable-async-iterator", feature(async_iterator))]
+
+mod abort;
+#[cfg(feature = "time")]
+mod ack;
+#[cfg(feature = "unstable-async-iterator")]
+mod async_iter;
+mod backlog;
+mod batched;
+mod bias;
+mod buffer;
+mod builder;
+mod busy_poll;
+mod cascade;
+mod consumer_check;
+#[cfg(feature = "time")]
+mod deadline;
+mod degrade;
+#[cfg(feature = "time")]
+mod delay;
+mod demux;
+mod events;
+mod fan_out;
+mod fanout_core;
+#[cfg(feature = "test-fault")]
+pub mod fault;
+mod flat;
+mod forward_balanced;
+#[cfg(feature = "codec")]
+mod framed;
+mod growable_ring_buf;
+mod handshake;
+#[cfg(feature = "bytes")]
+mod header_route;
+#[cfg(feature = "time")]
+mod heartbeat;
+mod hooks;
+mod inject;
+#[cfg(feature = "debug-invariants")]
+mod invariants;
+#[cfg(feature = "serde_json")]
+mod json_tag;
+#[cfg(feature = "latency")]
+mod latency;
+#[cfg(feature = "lines")]
+mod lines;
+mod load_shed;
+#[cfg(feature = "tokio")]
+mod map_blocking;
+mod map_sides;
+#[cfg(feature = "metrics")]
+mod metrics_export;
+#[cfg(feature = "rumqttc")]
+mod mqtt;
+#[cfg(not(feature = "time"))]
+mod no_timer;
+mod offload;
+mod overflow;
+mod pause;
+mod peek;
+mod predicate_handle;
+mod priority;
+mod push;
+mod quota;
+#[cfg(feature = "time")]
+mod rate_limit;
+#[cfg(feature = "time")]
+mod retry;
+#[cfg(not(feature = "safe"))]
+mod ring_buf;
+#[cfg(feature = "safe")]
+#[path = "safe_ring_buf.rs"]
 mod ring_buf;
+mod side_waker;
+mod slow_lane;
+mod source;
 mod split_by;
 mod split_by_buffered;
+mod split_by_driven;
 mod split_by_map;
 mod split_by_map_buffered;
+#[cfg(feature = "scoped")]
+mod split_by_scoped;
+#[cfg(feature = "tokio")]
+mod split_by_spawned;
+mod split_handle;
+mod split_pair;
+#[cfg(feature = "time")]
+mod stall;
+mod stats;
+mod sticky;
+mod sync;
+mod tap_sampled;
+// The crate's own tests use it whether or not the feature is on
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod trace;
+mod try_from;
+mod variants;
+mod wake_strategy;
 
-pub(crate) use split_by::SplitBy;
-pub use split_by::{FalseSplitBy, TrueSplitBy};
-pub(crate) use split_by_buffered::SplitByBuffered;
-pub use split_by_buffered::{FalseSplitByBuffered, TrueSplitByBuffered};
-pub(crate) use split_by_map::SplitByMap;
-pub use split_by_map::{LeftSplitByMap, RightSplitByMap};
-pub(crate) use split_by_map_buffered::SplitByMapBuffered;
-pub use split_by_map_buffered::{LeftSplitByMapBuffered, RightSplitByMapBuffered};
+pub use abort::AbortHandle;
+#[cfg(feature = "time")]
+pub use ack::{at_least_once, AckHandle, AtLeastOnce, Delivery};
+pub use backlog::{
+    split_by_backlog, split_by_map_backlog, BacklogFalse, BacklogLeft, BacklogPredicate,
+    BacklogRight, BacklogTrue, BufferState, MapBacklogPredicate,
+};
+pub use batched::{split_by_batched, Batched, BatchedLeft, BatchedRight};
+pub use bias::PollBias;
+pub use buffer::Buffer;
+pub use builder::{Buffered, Growable, SplitBuilder, Unbuffered};
+pub use busy_poll::{busy_poll, BusyPoll};
+pub use cascade::{split_by_cascade, Cascade};
+#[cfg(feature = "time")]
+pub use deadline::{DeadLetters, DeadlinePolicy};
+pub use degrade::AutoDegrade;
+#[cfg(all(
+    feature = "tokio-timer",
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+))]
+pub use delay::tokio_delay::TokioDelay;
+#[cfg(feature = "time")]
+pub use delay::{Delay, Instant, Timer};
+pub use demux::{demux_by_key, Demux, Substream};
+pub use events::{BackpressureEvent, BackpressureEvents, SplitEvent, SplitEvents};
+pub use fan_out::{fan_out, fan_out_buffered, FanOut};
+pub use fanout_core::{FanoutCore, NoSuchOutput};
+pub use flat::{split_flat_by, SplitFlat};
+pub use forward_balanced::{forward_balanced, ForwardBalanced};
+#[cfg(feature = "codec")]
+pub use framed::{split_framed_by, SinkHandle};
+pub use growable_ring_buf::GrowableRingBuf;
+pub use handshake::{split_by_handshake, Handshake, HandshakeLeft, HandshakeRight, Handshaken};
+#[cfg(feature = "bytes")]
+pub use header_route::{header_predicate, HeaderMatch};
+#[cfg(feature = "time")]
+pub use heartbeat::{with_heartbeat, Heartbeat};
+pub use hooks::RouteHooks;
+pub use inject::InjectHandle;
+#[cfg(feature = "serde_json")]
+pub use json_tag::{json_tag_predicate, JsonTagError, Tagged};
+#[cfg(feature = "lines")]
+pub use lines::{lines, prefix_predicate, split_lines_by, Line, LinesStream};
+pub use load_shed::capacity_predicate;
+#[cfg(feature = "tokio")]
+pub use load_shed::permit_predicate;
+#[cfg(feature = "tokio")]
+pub use map_blocking::{BlockingLeft, BlockingRight, MapBlocking};
+pub use map_sides::map_sides;
+#[cfg(feature = "rumqttc")]
+pub use mqtt::{split_event_loop, split_publishes, MqttEvent, ProtocolEvents, Publishes};
+#[cfg(feature = "rayon")]
+pub use offload::split_by_rayon;
+pub use offload::{
+    offload_predicate, split_by_offloaded, Job, Offloaded, OffloadedLeft, OffloadedRight, Verdict,
+};
+pub use overflow::OverflowPolicy;
+pub use pause::{PauseHandle, PausePolicy};
+pub use peek::Peeked;
+pub use predicate_handle::{MapPredicateHandle, PredicateHandle};
+pub use priority::{split_by_priority, strict_priority, PriorityClass, StrictPriority};
+pub use push::{pushable, PushClosed, PushHandle, Pushable};
+pub use quota::{Quota, QuotaPolicy};
+#[cfg(feature = "time")]
+pub use rate_limit::RateLimit;
+#[cfg(feature = "time")]
+pub use retry::{retryable, RetryHandle, RetryPolicy, Retryable};
+pub use ring_buf::{Drain, RingBuf};
+pub use slow_lane::SlowLane;
+pub use split_by::{False, FalseSplitBy, SplitBy, SplitByParts, True, TrueSplitBy};
+pub use split_by_buffered::{
+    FalseBuffered, FalseSplitByBuffered, FalseSplitByBufferedWith, SplitByBuffered,
+    SplitByBufferedParts, SplitByBufferedWith, TrueBuffered, TrueSplitByBuffered,
+    TrueSplitByBufferedWith,
+};
+pub use split_by_driven::{Drive, SplitByDriven};
+pub use split_by_map::{Left, LeftSplitByMap, Right, RightSplitByMap, SplitByMap, SplitByMapParts};
+pub use split_by_map_buffered::{
+    LeftBuffered, LeftSplitByMapBuffered, LeftSplitByMapBufferedWith, RightBuffered,
+    RightSplitByMapBuffered, RightSplitByMapBufferedWith, SplitByMapBuffered,
+    SplitByMapBufferedParts, SplitByMapBufferedWith,
+};
+#[cfg(feature = "scoped")]
+pub use split_by_scoped::{split_by_scoped, FalseSplitByScoped, TrueSplitByScoped};
+#[cfg(feature = "tokio")]
+pub use split_by_spawned::SplitBySpawned;
+pub use split_handle::SplitHandle;
+pub use split_pair::{ReuniteByKey, SplitPair};
+#[cfg(feature = "time")]
+pub use stall::Stall;
+pub use stats::{OnComplete, Stats, StatsHandle};
+pub use sticky::sticky_predicate;
+#[cfg(feature = "parking_lot")]
+pub use sync::ParkingLot;
+#[cfg(feature = "spin")]
+pub use sync::Spin;
+pub use sync::{Local, LockFamily, SharedLock, StdMutex};
+pub use tap_sampled::{tap_sampled, TapSampled, TapSampledSource};
+pub use try_from::{SplitStreamByTryFromExt, TryFromErrors, TryFromLeft, TryFromRight};
+pub use wake_strategy::{Coalesced, Immediate, Threshold, WakeStrategy};
+
+use std::fmt;
 
 pub use futures::future::Either;
 use futures::Stream;
 
+/// Determines what happens to items that get routed to an output stream after
+/// it has been closed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClosePolicy {
+    /// Drop the item and keep polling the underlying stream on behalf of the
+    /// other output stream
+    Discard,
+    /// End the other output stream as well, since it would otherwise skip over
+    /// items that nobody will consume
+    EndSibling,
+}
+
+/// Identifies one of the two output streams of a split. `Left` is the first of
+/// the pair (the `true` stream for `split_by`) and `Right` is the second
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// The first output stream
+    Left,
+    /// The second output stream
+    Right,
+}
+
+/// Error returned by `into_parts` when the two output streams passed to it
+/// didn't come from the same split, when other clones of them still exist, or
+/// when the underlying stream was already taken with `AbortHandle::abort`.
+/// Both streams are handed back unchanged
+pub struct ReuniteError<T, F>(pub T, pub F);
+
+impl<T, F> fmt::Debug for ReuniteError<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReuniteError").finish()
+    }
+}
+
+impl<T, F> fmt::Display for ReuniteError<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tried to reunite output streams that don't form a pair")
+    }
+}
+
+impl<T, F> std::error::Error for ReuniteError<T, F> {}
+
 /// This extension trait provides the functionality for splitting a
 /// stream by a predicate of type `Fn(&Self::Item) -> bool`. The two resulting
 /// streams will both yield `Self::Item`
@@ -119,7 +459,46 @@ pub trait SplitStreamByExt<P>: Stream {
         P: Fn(&Self::Item) -> bool,
         Self: Sized,
     {
-        let stream = SplitBy::new(self, predicate);
+        self.split_by_with_hooks(predicate, RouteHooks::new())
+    }
+
+    /// The same as `split_by`, but returns the two streams as a `SplitPair`
+    ///
+    ///```rust
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3]);
+    /// let pair = incoming_stream.split_by_pair(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     assert_eq!((vec![0, 2], vec![1, 3]), pair.collect_both().await);
+    /// });
+    /// ```
+    fn split_by_pair(
+        self,
+        predicate: P,
+    ) -> SplitPair<TrueSplitBy<Self::Item, Self, P>, FalseSplitBy<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        SplitPair::from(self.split_by(predicate))
+    }
+
+    /// The same as `split_by`, but calls `hooks` from the splitter as items
+    /// are routed and buffered
+    fn split_by_with_hooks(
+        self,
+        predicate: P,
+        hooks: RouteHooks<Self::Item>,
+    ) -> (
+        TrueSplitBy<Self::Item, Self, P>,
+        FalseSplitBy<Self::Item, Self, P>,
+    )
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        let stream = SplitBy::new_shared(self, predicate, hooks);
         let true_stream = TrueSplitBy::new(stream.clone());
         let false_stream = FalseSplitBy::new(stream);
         (true_stream, false_stream)
@@ -138,6 +517,20 @@ pub trait SplitStreamByExt<P>: Stream {
     /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
     /// let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<3>(|&n| n % 2 == 0);
     /// ```
+    ///
+    /// N must be at least 1, which is checked at compile time
+    ///
+    ///```compile_fail
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<0>(|&n| n % 2 == 0);
+    /// ```
+    ///
+    /// Both buffers are stored inline in the shared allocation, taking up
+    /// `2 * N * size_of::<Self::Item>()` bytes, and are built on the stack
+    /// before being moved there. For large N with large items, box the items
+    /// (e.g. with `map(Box::new)`) so that only pointers are buffered
     fn split_by_buffered<const N: usize>(
         self,
         predicate: P,
@@ -149,11 +542,166 @@ pub trait SplitStreamByExt<P>: Stream {
         P: Fn(&Self::Item) -> bool,
         Self: Sized,
     {
-        let stream = SplitByBuffered::new(self, predicate);
+        self.split_by_buffered_with_hooks::<N>(predicate, RouteHooks::new())
+    }
+
+    /// The same as `split_by_buffered`, but returns the two streams as a
+    /// `SplitPair`
+    fn split_by_buffered_pair<const N: usize>(
+        self,
+        predicate: P,
+    ) -> SplitPair<
+        TrueSplitByBuffered<Self::Item, Self, P, N>,
+        FalseSplitByBuffered<Self::Item, Self, P, N>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        SplitPair::from(self.split_by_buffered::<N>(predicate))
+    }
+
+    /// The same as `split_by_buffered`, but calls `hooks` from the splitter as items
+    /// are routed and buffered
+    fn split_by_buffered_with_hooks<const N: usize>(
+        self,
+        predicate: P,
+        hooks: RouteHooks<Self::Item>,
+    ) -> (
+        TrueSplitByBuffered<Self::Item, Self, P, N>,
+        FalseSplitByBuffered<Self::Item, Self, P, N>,
+    )
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        let stream = SplitByBuffered::new_shared(self, predicate, hooks);
         let true_stream = TrueSplitByBuffered::new(stream.clone());
         let false_stream = FalseSplitByBuffered::new(stream);
         (true_stream, false_stream)
     }
+
+    /// This takes ownership of a stream and returns two streams based on a
+    /// predicate, as with `split_by`, along with a `Drive` future that does the
+    /// routing. Each side has its own unbounded queue, so as long as `Drive` is
+    /// polled (e.g. joined with the consumers, or in the caller's select loop),
+    /// neither stream depends on the other one being polled. This works on any
+    /// executor, unlike `split_by_spawned`
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let (even_stream, odd_stream, drive) = incoming_stream.split_by_driven(|&n| n % 2 == 0);
+    /// futures::executor::block_on(async {
+    ///     // The odd stream can be ignored without holding up the even one
+    ///     let (evens, ()) = futures::join!(even_stream.collect::<Vec<_>>(), drive);
+    ///     assert_eq!(vec![0,2,4], evens);
+    /// });
+    /// # drop(odd_stream);
+    /// ```
+    fn split_by_driven(
+        self,
+        predicate: P,
+    ) -> (
+        SplitByDriven<Self::Item>,
+        SplitByDriven<Self::Item>,
+        Drive<Self, P, Self::Item, Self::Item>,
+    )
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        split_by_driven::new(self, predicate, |predicate, item| {
+            if predicate(&item) {
+                Either::Left(item)
+            } else {
+                Either::Right(item)
+            }
+        })
+    }
+
+    /// This spawns a task onto the current tokio runtime that reads the
+    /// stream and routes each item by a predicate, as with `split_by`. Each
+    /// side has its own unbounded queue, so unlike the other kinds of split,
+    /// neither stream depends on the other one being polled. Dropping both
+    /// streams stops the task, and a panic in the predicate ends both streams
+    ///
+    /// Panics if called outside of a tokio runtime
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let (even_stream, odd_stream) = incoming_stream.split_by_spawned(|&n| n % 2 == 0);
+    /// // The odd stream can be ignored without holding up the even one
+    /// assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
+    /// # drop(odd_stream);
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    fn split_by_spawned(
+        self,
+        predicate: P,
+    ) -> (SplitBySpawned<Self::Item>, SplitBySpawned<Self::Item>)
+    where
+        P: Fn(&Self::Item) -> bool + Send + 'static,
+        Self: Sized + Send + 'static,
+        Self::Item: Send + 'static,
+    {
+        split_by_spawned::spawn(self, None, move |item| {
+            if predicate(&item) {
+                Either::Left(item)
+            } else {
+                Either::Right(item)
+            }
+        })
+    }
+
+    /// As with `split_by_spawned`, but the spawned task is named after `name`,
+    /// e.g. "split-stream-by driver (orders)", so that it can be told apart
+    /// from the other splits' tasks in tokio-console. With the `tracing`
+    /// feature the task runs in a span that records the name, within the span
+    /// current at the call, and the name is also given to tokio when it's
+    /// built with `--cfg tokio_unstable`, as tokio-console requires
+    ///
+    /// Panics if called outside of a tokio runtime
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let (even_stream, odd_stream) = incoming_stream.split_by_spawned_named("orders", |&n| n % 2 == 0);
+    /// assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
+    /// # drop(odd_stream);
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    fn split_by_spawned_named(
+        self,
+        name: &str,
+        predicate: P,
+    ) -> (SplitBySpawned<Self::Item>, SplitBySpawned<Self::Item>)
+    where
+        P: Fn(&Self::Item) -> bool + Send + 'static,
+        Self: Sized + Send + 'static,
+        Self::Item: Send + 'static,
+    {
+        split_by_spawned::spawn(self, Some(name), move |item| {
+            if predicate(&item) {
+                Either::Left(item)
+            } else {
+                Either::Right(item)
+            }
+        })
+    }
 }
 
 impl<T, P> SplitStreamByExt<P> for T where T: Stream + ?Sized {}
@@ -190,7 +738,6 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
     /// 	Message::Response(res) => Either::Right(res),
     /// });
     /// ```
-
     fn split_by_map(
         self,
         predicate: P,
@@ -202,7 +749,40 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
         P: Fn(Self::Item) -> Either<L, R>,
         Self: Sized,
     {
-        let stream = SplitByMap::new(self, predicate);
+        self.split_by_map_with_hooks(predicate, RouteHooks::new())
+    }
+
+    /// The same as `split_by_map`, but returns the two streams as a
+    /// `SplitPair`
+    fn split_by_map_pair(
+        self,
+        predicate: P,
+    ) -> SplitPair<
+        LeftSplitByMap<Self::Item, L, R, Self, P>,
+        RightSplitByMap<Self::Item, L, R, Self, P>,
+    >
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        SplitPair::from(self.split_by_map(predicate))
+    }
+
+    /// The same as `split_by_map`, but calls `hooks` from the splitter as items
+    /// are routed and buffered
+    fn split_by_map_with_hooks(
+        self,
+        predicate: P,
+        hooks: RouteHooks<L, R>,
+    ) -> (
+        LeftSplitByMap<Self::Item, L, R, Self, P>,
+        RightSplitByMap<Self::Item, L, R, Self, P>,
+    )
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        let stream = SplitByMap::new_shared(self, predicate, hooks);
         let true_stream = LeftSplitByMap::new(stream.clone());
         let false_stream = RightSplitByMap::new(stream);
         (true_stream, false_stream)
@@ -213,7 +793,9 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
     /// `Either::Left(..)` or `Either::Right(..)` where the inner
     /// values of `Left` and `Right` become the items of the two respective
     /// streams. This will buffer up to N items of the inactive stream before
-    /// returning Pending and notifying that stream
+    /// returning Pending and notifying that stream. As with
+    /// `split_by_buffered`, N must be at least 1 and the buffers are stored
+    /// inline, so box large items when using a large N
     ///
     /// ```
     /// use split_stream_by::{Either,SplitStreamByMapExt};
@@ -237,7 +819,6 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
     /// 	Message::Response(res) => Either::Right(res),
     /// });
     /// ```
-
     fn split_by_map_buffered<const N: usize>(
         self,
         predicate: P,
@@ -249,11 +830,137 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
         P: Fn(Self::Item) -> Either<L, R>,
         Self: Sized,
     {
-        let stream = SplitByMapBuffered::new(self, predicate);
+        self.split_by_map_buffered_with_hooks::<N>(predicate, RouteHooks::new())
+    }
+
+    /// The same as `split_by_map_buffered`, but returns the two streams as a
+    /// `SplitPair`
+    fn split_by_map_buffered_pair<const N: usize>(
+        self,
+        predicate: P,
+    ) -> SplitPair<
+        LeftSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
+        RightSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
+    >
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        SplitPair::from(self.split_by_map_buffered::<N>(predicate))
+    }
+
+    /// The same as `split_by_map_buffered`, but calls `hooks` from the splitter as items
+    /// are routed and buffered
+    fn split_by_map_buffered_with_hooks<const N: usize>(
+        self,
+        predicate: P,
+        hooks: RouteHooks<L, R>,
+    ) -> (
+        LeftSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
+        RightSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
+    )
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        let stream = SplitByMapBuffered::new_shared(self, predicate, hooks);
         let true_stream = LeftSplitByMapBuffered::new(stream.clone());
         let false_stream = RightSplitByMapBuffered::new(stream);
         (true_stream, false_stream)
     }
+
+    /// This takes ownership of a stream and returns two streams based on a
+    /// predicate, as with `split_by_map`, along with a `Drive` future that does
+    /// the routing. See `split_by_driven` for how this differs from the other
+    /// kinds of split
+    fn split_by_map_driven(
+        self,
+        predicate: P,
+    ) -> (SplitByDriven<L>, SplitByDriven<R>, Drive<Self, P, L, R>)
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        split_by_driven::new(self, predicate, |predicate, item| predicate(item))
+    }
+
+    /// This spawns a task onto the current tokio runtime that reads the
+    /// stream and routes each item by a predicate, as with `split_by_map`.
+    /// See `split_by_spawned` for how this differs from the other kinds of
+    /// split
+    ///
+    /// Panics if called outside of a tokio runtime
+    #[cfg(feature = "tokio")]
+    fn split_by_map_spawned(self, predicate: P) -> (SplitBySpawned<L>, SplitBySpawned<R>)
+    where
+        P: Fn(Self::Item) -> Either<L, R> + Send + 'static,
+        Self: Sized + Send + 'static,
+        L: Send + 'static,
+        R: Send + 'static,
+    {
+        split_by_spawned::spawn(self, None, predicate)
+    }
+
+    /// As with `split_by_map_spawned`, but the spawned task is named after
+    /// `name`. See `split_by_spawned_named` for where the name shows up
+    ///
+    /// Panics if called outside of a tokio runtime
+    #[cfg(feature = "tokio")]
+    fn split_by_map_spawned_named(
+        self,
+        name: &str,
+        predicate: P,
+    ) -> (SplitBySpawned<L>, SplitBySpawned<R>)
+    where
+        P: Fn(Self::Item) -> Either<L, R> + Send + 'static,
+        Self: Sized + Send + 'static,
+        L: Send + 'static,
+        R: Send + 'static,
+    {
+        split_by_spawned::spawn(self, Some(name), predicate)
+    }
+
+    /// This takes ownership of a stream and returns two streams based on a
+    /// predicate, as with `split_by_map`, but runs the predicate on each item
+    /// with tokio's `spawn_blocking`, for mapping functions that are CPU heavy
+    /// or block. Up to `max_in_flight` items are mapped at once, and each
+    /// stream yields its items in the order they were read. A panic in the
+    /// predicate ends both streams, and its payload can be taken with
+    /// `take_panic` on either of them
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Either, SplitStreamByMapExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let incoming_stream = futures::stream::iter(["1", "x", "3"]);
+    ///     let (numbers, rejected) = incoming_stream.split_by_map_blocking(2, |s| match s.parse::<u32>() {
+    ///         Ok(n) => Either::Left(n),
+    ///         Err(_) => Either::Right(s),
+    ///     });
+    ///     let (numbers, rejected) = futures::join!(numbers.collect::<Vec<_>>(), rejected.collect::<Vec<_>>());
+    ///     assert_eq!(vec![1, 3], numbers);
+    ///     assert_eq!(vec!["x"], rejected);
+    /// })
+    /// ```
+    ///
+    /// Panics if `max_in_flight` is zero. The streams have to be polled within
+    /// a tokio runtime
+    #[cfg(feature = "tokio")]
+    fn split_by_map_blocking(
+        self,
+        max_in_flight: usize,
+        predicate: P,
+    ) -> (BlockingLeft<Self, P, L, R>, BlockingRight<Self, P, L, R>)
+    where
+        P: Fn(Self::Item) -> Either<L, R> + Send + Sync + 'static,
+        Self: Sized,
+        Self::Item: Send + 'static,
+        L: Send + 'static,
+        R: Send + 'static,
+    {
+        map_blocking::split(self, max_in_flight, predicate)
+    }
 }
 
 impl<T, P, L, R> SplitStreamByMapExt<P, L, R> for T where T: Stream + ?Sized {}
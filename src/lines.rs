@@ -0,0 +1,142 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+
+use crate::{FalseSplitBy, SplitStreamByExt, TrueSplitBy};
+
+/// What a `LinesStream` yields for each line it reads
+pub type Line = io::Result<String>;
+
+/// A stream of the lines read from an `AsyncBufRead`, without their line
+/// endings, returned by `lines`
+pub struct LinesStream<R> {
+    lines: Lines<R>,
+    done: bool,
+}
+
+/// Reads `reader` line by line as a stream, ending at the end of the input
+pub fn lines<R: AsyncBufRead + Unpin>(reader: R) -> LinesStream<R> {
+    LinesStream {
+        lines: reader.lines(),
+        done: false,
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> LinesStream<R> {
+    /// Hands back the reader. Part of a line that was read from it but hasn't
+    /// been yielded yet is lost
+    pub fn into_inner(self) -> R {
+        self.lines.into_inner()
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for LinesStream<R> {
+    type Item = Line;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Line>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let line = futures::ready!(Pin::new(&mut self.lines).poll_next_line(cx)).transpose();
+        self.done = line.is_none();
+        Poll::Ready(line)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> FusedStream for LinesStream<R> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+impl<R> fmt::Debug for LinesStream<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinesStream")
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads `reader` line by line, e.g. a child process's stdout, and splits the
+/// lines into two streams per `predicate`, as with `split_by`. The predicate
+/// sees each line as it was read, so it decides where read errors go too.
+/// `prefix_predicate` builds one for the common case of telling lines apart
+/// by how they start
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{prefix_predicate, split_lines_by};
+///
+/// let output: &[u8] = b"progress: 10%\nfirst\nprogress: 60%\nsecond\n";
+/// let (progress, payload) = split_lines_by(output, prefix_predicate("progress: "));
+/// futures::executor::block_on(async {
+///     let (progress, payload) = futures::join!(progress.collect::<Vec<_>>(), payload.collect::<Vec<_>>());
+///     assert_eq!(2, progress.len());
+///     assert_eq!("first", payload[0].as_ref().unwrap());
+/// });
+/// ```
+pub fn split_lines_by<R, P>(
+    reader: R,
+    predicate: P,
+) -> (
+    TrueSplitBy<Line, LinesStream<R>, P>,
+    FalseSplitBy<Line, LinesStream<R>, P>,
+)
+where
+    R: AsyncBufRead + Unpin,
+    P: Fn(&Line) -> bool,
+{
+    lines(reader).split_by(predicate)
+}
+
+/// Builds a predicate for `split_lines_by` that sends the lines starting with
+/// `prefix` to the first stream, and the other lines and any read errors to
+/// the second
+pub fn prefix_predicate(prefix: impl Into<String>) -> impl Fn(&Line) -> bool + Clone {
+    let prefix = prefix.into();
+    move |line| matches!(line, Ok(line) if line.starts_with(&prefix))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use futures::StreamExt;
+
+    use super::{lines, prefix_predicate, split_lines_by};
+
+    #[test]
+    fn test_trailing_line_without_newline() {
+        let output: &[u8] = b"first\r\nsecond\nlast";
+        futures::executor::block_on(async {
+            let lines: Vec<_> = lines(output).map(Result::unwrap).collect().await;
+            assert_eq!(vec!["first", "second", "last"], lines);
+        });
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let output: &[u8] = b"> ok\n> \xff\xfe\nafter\n";
+        let (prompts, rest) = split_lines_by(output, prefix_predicate("> "));
+        futures::executor::block_on(async {
+            let (prompts, rest) =
+                futures::join!(prompts.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+            // The line that isn't valid UTF-8 can't be matched by its prefix, so
+            // the error goes to the second stream
+            assert_eq!(
+                vec!["> ok"],
+                prompts.into_iter().map(Result::unwrap).collect::<Vec<_>>()
+            );
+            let error = rest[0].as_ref().unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+            // The bad line is skipped, and reading carries on after it
+            assert_eq!(2, rest.len());
+            assert_eq!("after", rest[1].as_ref().unwrap());
+        });
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
+#[cfg(feature = "tokio")]
+use futures::future::Either;
+#[cfg(feature = "tokio")]
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Builds a predicate for `split_by` and its variants that sheds load at the
+/// routing point. `has_capacity` is called for every item, and the item goes
+/// to the first (primary) stream if it returns `true` and to the second
+/// (overflow) stream otherwise
+///
+///```rust
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use futures::StreamExt;
+/// use split_stream_by::{capacity_predicate, SplitStreamByExt};
+///
+/// let in_flight = AtomicUsize::new(0);
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let (primary, overflow) = incoming_stream.split_by(capacity_predicate(|| {
+///     in_flight.fetch_add(1, Ordering::Relaxed) < 2
+/// }));
+/// futures::executor::block_on(async {
+///     let (primary, overflow) = futures::join!(primary.collect::<Vec<_>>(), overflow.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 1], primary);
+///     assert_eq!(vec![2, 3], overflow);
+/// });
+/// ```
+pub fn capacity_predicate<I>(has_capacity: impl Fn() -> bool) -> impl Fn(&I) -> bool {
+    move |_| has_capacity()
+}
+
+/// Builds a predicate for `split_by_map` and its variants that routes an item
+/// to the first (primary) stream along with a permit from `semaphore` if one
+/// is available right away, and to the second (overflow) stream otherwise.
+/// The permit is released once it's dropped, so holding on to it while the
+/// item is processed bounds how many primary items are in flight
+///
+///```rust
+/// use std::sync::Arc;
+/// use futures::StreamExt;
+/// use split_stream_by::{permit_predicate, SplitStreamByMapExt};
+/// use tokio::sync::Semaphore;
+///
+/// let semaphore = Arc::new(Semaphore::new(1));
+/// let incoming_stream = futures::stream::iter([0,1,2]);
+/// let (mut primary, mut overflow) =
+///     incoming_stream.split_by_map(permit_predicate(Arc::clone(&semaphore)));
+/// futures::executor::block_on(async {
+///     let (item, permit) = primary.next().await.unwrap();
+///     assert_eq!(0, item);
+///     assert_eq!(Some(1), overflow.next().await);
+///     drop(permit);
+///     assert_eq!(Some(2), primary.next().await.map(|(item, _)| item));
+/// });
+/// ```
+#[cfg(feature = "tokio")]
+pub fn permit_predicate<I>(
+    semaphore: Arc<Semaphore>,
+) -> impl Fn(I) -> Either<(I, OwnedSemaphorePermit), I> {
+    move |item| match Arc::clone(&semaphore).try_acquire_owned() {
+        Ok(permit) => Either::Left((item, permit)),
+        Err(_) => Either::Right(item),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::capacity_predicate;
+    use crate::SplitStreamByExt;
+
+    /// Takes a slot out of `in_flight` if fewer than `limit` are taken
+    fn acquire(in_flight: &AtomicUsize, limit: usize) -> bool {
+        in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < limit).then_some(n + 1)
+            })
+            .is_ok()
+    }
+
+    #[test]
+    fn test_sheds_while_full() {
+        let in_flight = AtomicUsize::new(0);
+        let incoming_stream = futures::stream::iter(0..6);
+        let (primary, overflow) =
+            incoming_stream.split_by_buffered::<2>(capacity_predicate(|| acquire(&in_flight, 2)));
+        futures::executor::block_on(async {
+            let (primary, overflow) =
+                futures::join!(primary.collect::<Vec<_>>(), overflow.collect::<Vec<_>>());
+            // Nothing was released, so everything after the first two is shed
+            assert_eq!(vec![0, 1], primary);
+            assert_eq!(vec![2, 3, 4, 5], overflow);
+        });
+    }
+
+    #[test]
+    fn test_recovers_once_capacity_returns() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let in_flight = AtomicUsize::new(0);
+        let incoming_stream = futures::stream::iter(0..5);
+        let (mut primary, mut overflow) =
+            incoming_stream.split_by(capacity_predicate(|| acquire(&in_flight, 1)));
+        assert_eq!(Poll::Ready(Some(0)), primary.poll_next_unpin(&mut cx));
+        // Still holding the only slot, so 1 is shed
+        assert_eq!(Poll::Pending, primary.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(1)), overflow.poll_next_unpin(&mut cx));
+        // Once 0 is done with, the next item goes to the primary stream again
+        in_flight.fetch_sub(1, Ordering::Relaxed);
+        assert_eq!(Poll::Ready(Some(2)), primary.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, primary.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), overflow.poll_next_unpin(&mut cx));
+        in_flight.fetch_sub(1, Ordering::Relaxed);
+        assert_eq!(Poll::Ready(Some(4)), primary.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), primary.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), overflow.poll_next_unpin(&mut cx));
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    any::Any,
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future::Either,
+    stream::{FusedStream, FuturesOrdered, Stream, StreamExt},
+};
+use tokio::task::{self, JoinHandle};
+
+use crate::{
+    offload::{route_verdict, RouteVerdict},
+    Left, Right, SplitStreamByMapExt,
+};
+
+/// The first stream returned by `split_by_map_blocking`
+pub type BlockingLeft<S, P, L, R> = Left<MapBlocking<S, P, L, R>, L, R, RouteVerdict<L, R>>;
+
+/// The second stream returned by `split_by_map_blocking`
+pub type BlockingRight<S, P, L, R> = Right<MapBlocking<S, P, L, R>, L, R, RouteVerdict<L, R>>;
+
+pub(crate) fn split<S, P, L, R>(
+    stream: S,
+    max_in_flight: usize,
+    predicate: P,
+) -> (BlockingLeft<S, P, L, R>, BlockingRight<S, P, L, R>)
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    P: Fn(S::Item) -> Either<L, R> + Send + Sync + 'static,
+    L: Send + 'static,
+    R: Send + 'static,
+{
+    assert!(
+        max_in_flight > 0,
+        "at least one item has to be mapped at a time"
+    );
+    let stream = MapBlocking {
+        stream: Some(Box::pin(stream)),
+        max_in_flight,
+        predicate: Arc::new(predicate),
+        in_flight: FuturesOrdered::new(),
+        failed: false,
+    };
+    stream.split_by_map(route_verdict as RouteVerdict<L, R>)
+}
+
+/// The underlying stream of `split_by_map_blocking`, which runs the predicate
+/// on each item with `spawn_blocking` and yields the results in the order the
+/// items were read
+pub struct MapBlocking<S: Stream, P, L, R> {
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    max_in_flight: usize,
+    predicate: Arc<P>,
+    in_flight: FuturesOrdered<JoinHandle<Either<L, R>>>,
+    /// Set once a panic or a cancelled map has been yielded
+    failed: bool,
+}
+
+// Everything that might be pinned in place is boxed
+impl<S: Stream, P, L, R> Unpin for MapBlocking<S, P, L, R> {}
+
+impl<S, P, L, R> Stream for MapBlocking<S, P, L, R>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    P: Fn(S::Item) -> Either<L, R> + Send + Sync + 'static,
+    L: Send + 'static,
+    R: Send + 'static,
+{
+    type Item = Result<Either<L, R>, Box<dyn Any + Send>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        // Start mapping whatever the stream has ready while there's room, so the
+        // maps run while earlier results wait to be taken
+        while this.in_flight.len() < this.max_in_flight {
+            let stream = match &mut this.stream {
+                Some(stream) => stream,
+                None => break,
+            };
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let predicate = Arc::clone(&this.predicate);
+                    this.in_flight
+                        .push_back(task::spawn_blocking(move || predicate(item)));
+                }
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+        match this.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(item))) => Poll::Ready(Some(Ok(item))),
+            Poll::Ready(Some(Err(err))) => {
+                this.failed = true;
+                let payload: Box<dyn Any + Send> = match err.try_into_panic() {
+                    Ok(payload) => payload,
+                    Err(_) => Box::new("the blocking map was cancelled"),
+                };
+                Poll::Ready(Some(Err(payload)))
+            }
+            Poll::Ready(None) if this.stream.is_none() => Poll::Ready(None),
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S, P, L, R> FusedStream for MapBlocking<S, P, L, R>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    P: Fn(S::Item) -> Either<L, R> + Send + Sync + 'static,
+    L: Send + 'static,
+    R: Send + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        self.failed || (self.stream.is_none() && self.in_flight.is_empty())
+    }
+}
+
+impl<S: Stream, P, L, R> fmt::Debug for MapBlocking<S, P, L, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapBlocking")
+            .field("max_in_flight", &self.max_in_flight)
+            .field("in_flight", &self.in_flight.len())
+            .field("stream_ended", &self.stream.is_none())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use futures::StreamExt;
+
+    use crate::{Either, SplitStreamByMapExt};
+
+    #[tokio::test]
+    async fn test_map_blocking_keeps_order() {
+        let incoming_stream = futures::stream::iter(0..8u64);
+        // Earlier items take longer to map, so they'd finish last if the results
+        // weren't put back in order
+        let (evens, odds) = incoming_stream.split_by_map_blocking(4, |n| {
+            thread::sleep(Duration::from_millis(8 - n));
+            if n % 2 == 0 {
+                Either::Left(n * 10)
+            } else {
+                Either::Right(n.to_string())
+            }
+        });
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!(vec![0, 20, 40, 60], evens);
+        assert_eq!(vec!["1", "3", "5", "7"], odds);
+    }
+}
@@ -0,0 +1,135 @@
+use futures::future::Either;
+
+/// Builds a predicate for `split_by_map` and its variants out of a `split_by`
+/// style `predicate` and a transform for each side. Each item is routed by
+/// `predicate` and then passed through `map_left` or `map_right` inside the
+/// splitter, before it's buffered, so the buffers only ever hold the final
+/// types. This matters when one side immediately shrinks its items, since
+/// the large intermediate items are never buffered
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{map_sides, SplitStreamByMapExt};
+///
+/// let incoming_stream = futures::stream::iter([vec![0; 100], vec![1; 3], vec![2; 50]]);
+/// let (lengths, small) = incoming_stream.split_by_map_buffered::<4>(map_sides(
+///     |chunk: &Vec<i32>| chunk.len() > 10,
+///     |chunk| chunk.len(),
+///     |chunk| chunk,
+/// ));
+/// futures::executor::block_on(async {
+///     let (lengths, small) = futures::join!(lengths.collect::<Vec<_>>(), small.collect::<Vec<_>>());
+///     assert_eq!(vec![100, 50], lengths);
+///     assert_eq!(vec![vec![1; 3]], small);
+/// });
+/// ```
+pub fn map_sides<I, L, R>(
+    predicate: impl Fn(&I) -> bool,
+    map_left: impl Fn(I) -> L,
+    map_right: impl Fn(I) -> R,
+) -> impl Fn(I) -> Either<L, R> {
+    move |item| {
+        if predicate(&item) {
+            Either::Left(map_left(item))
+        } else {
+            Either::Right(map_right(item))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::map_sides;
+    use crate::SplitStreamByMapExt;
+
+    /// A closure counting its calls into `count` before calling `f`
+    fn counted<A, B>(count: &Arc<AtomicUsize>, f: impl Fn(A) -> B) -> impl Fn(A) -> B {
+        let count = Arc::clone(count);
+        move |a| {
+            count.fetch_add(1, Ordering::SeqCst);
+            f(a)
+        }
+    }
+
+    #[test]
+    fn test_mapped_before_buffering() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mapped = Arc::new(AtomicUsize::new(0));
+        let incoming_stream = futures::stream::iter([1, 3, 0]);
+        let (mut evens, mut odds) = incoming_stream.split_by_map_buffered::<4>(map_sides(
+            |n: &u32| n % 2 == 0,
+            |n| n,
+            counted(&mapped, |n: u32| n.to_string()),
+        ));
+        // The evens give the odds a turn once something is buffered for them
+        let mut next_even = || loop {
+            if let Poll::Ready(even) = evens.poll_next_unpin(&mut cx) {
+                return even;
+            }
+        };
+        assert_eq!(Some(0), next_even());
+        // The odds were mapped as they were buffered, before anything polled for them
+        assert_eq!(2, mapped.load(Ordering::SeqCst));
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(
+            Poll::Ready(Some("1".to_string())),
+            odds.poll_next_unpin(&mut cx)
+        );
+        assert_eq!(2, mapped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_each_item_routed_and_mapped_once() {
+        let routed = Arc::new(AtomicUsize::new(0));
+        let left = Arc::new(AtomicUsize::new(0));
+        let right = Arc::new(AtomicUsize::new(0));
+        let incoming_stream = futures::stream::iter(0..10);
+        let (evens, odds) = incoming_stream.split_by_map(map_sides(
+            {
+                let routed = Arc::clone(&routed);
+                move |n: &u32| {
+                    routed.fetch_add(1, Ordering::SeqCst);
+                    n % 2 == 0
+                }
+            },
+            counted(&left, |n: u32| n / 2),
+            counted(&right, |n: u32| -(n as i64)),
+        ));
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+            assert_eq!(vec![0, 1, 2, 3, 4], evens);
+            assert_eq!(vec![-1, -3, -5, -7, -9], odds);
+        });
+        assert_eq!(10, routed.load(Ordering::SeqCst));
+        assert_eq!(5, left.load(Ordering::SeqCst));
+        assert_eq!(5, right.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_dropped_side_still_mapped() {
+        let mapped = Arc::new(AtomicUsize::new(0));
+        let incoming_stream = futures::stream::iter(0..6);
+        let (evens, odds) = incoming_stream.split_by_map(map_sides(
+            |n: &u32| n % 2 == 0,
+            |n| n,
+            counted(&mapped, |n: u32| n),
+        ));
+        drop(odds);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 2, 4], evens.collect::<Vec<_>>().await);
+        });
+        // Routing and mapping are one step, so items for the dropped side are still
+        // mapped before being discarded
+        assert_eq!(3, mapped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,185 @@
+use metrics::{Counter, Gauge, IntoLabels, Label};
+
+use crate::{Side, Stats};
+
+/// Handles to the `metrics` facade for a splitter that has been registered
+/// with `StatsHandle::register_metrics`
+pub(crate) struct MetricsHandles {
+    routed: [Counter; 2],
+    buffered: [Gauge; 2],
+    wakes: Counter,
+    source_polls: Counter,
+    terminated: Gauge,
+}
+
+impl MetricsHandles {
+    /// Registers the splitter's metrics with the installed recorder, seeding
+    /// them from `current` so nothing that happened before registering is lost
+    pub(crate) fn register(labels: impl IntoLabels, current: &Stats) -> Self {
+        let labels = labels.into_labels();
+        let with_side = |side: &'static str| {
+            let mut labels = labels.clone();
+            labels.push(Label::new("side", side));
+            labels
+        };
+        let handles = Self {
+            routed: [
+                metrics::counter!("split_stream_by_routed_total", with_side("left")),
+                metrics::counter!("split_stream_by_routed_total", with_side("right")),
+            ],
+            buffered: [
+                metrics::gauge!("split_stream_by_buffered", with_side("left")),
+                metrics::gauge!("split_stream_by_buffered", with_side("right")),
+            ],
+            wakes: metrics::counter!("split_stream_by_wakes_total", labels.clone()),
+            source_polls: metrics::counter!("split_stream_by_source_polls_total", labels.clone()),
+            terminated: metrics::gauge!("split_stream_by_terminated", labels),
+        };
+        handles.routed[0].absolute(current.routed_left);
+        handles.routed[1].absolute(current.routed_right);
+        handles.buffered[0].set(current.buffered_left as f64);
+        handles.buffered[1].set(current.buffered_right as f64);
+        handles.wakes.absolute(current.wakes);
+        handles.source_polls.absolute(current.source_polls);
+        handles
+            .terminated
+            .set(f64::from(u8::from(current.terminated)));
+        handles
+    }
+
+    pub(crate) fn routed(&self, side: Side) {
+        self.routed[side as usize].increment(1);
+    }
+
+    pub(crate) fn buffered(&self, side: Side, len: usize) {
+        self.buffered[side as usize].set(len as f64);
+    }
+
+    pub(crate) fn woke(&self) {
+        self.wakes.increment(1);
+    }
+
+    pub(crate) fn source_polled(&self) {
+        self.source_polls.increment(1);
+    }
+
+    pub(crate) fn terminated(&self) {
+        self.terminated.set(1.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+    use metrics::{
+        Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use crate::SplitStreamByExt;
+
+    /// Keeps the value of each counter and gauge registered with it, keyed by
+    /// the metric's name and labels
+    #[derive(Default)]
+    struct Recorded {
+        counters: Mutex<HashMap<String, Arc<AtomicU64>>>,
+        gauges: Mutex<HashMap<String, Arc<AtomicU64>>>,
+    }
+
+    fn name(key: &Key) -> String {
+        key.labels().fold(key.name().to_string(), |name, label| {
+            format!("{},{}={}", name, label.key(), label.value())
+        })
+    }
+
+    impl Recorded {
+        fn counter(&self, name: &str) -> u64 {
+            self.counters.lock().unwrap()[name].load(Ordering::Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.gauges.lock().unwrap()[name].load(Ordering::Relaxed))
+        }
+    }
+
+    impl Recorder for Recorded {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let mut counters = self.counters.lock().unwrap();
+            Counter::from_arc(Arc::clone(counters.entry(name(key)).or_default()))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            let mut gauges = self.gauges.lock().unwrap();
+            Gauge::from_arc(Arc::clone(gauges.entry(name(key)).or_default()))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_register_metrics() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let recorded = Recorded::default();
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (mut even_stream, mut odd_stream) =
+            incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+        let stats = even_stream.stats_handle();
+        assert!(metrics::with_local_recorder(&recorded, || {
+            stats.register_metrics(&[("splitter", "test")])
+        }));
+        assert!(!metrics::with_local_recorder(&recorded, || {
+            stats.register_metrics(&[("splitter", "test")])
+        }));
+        // What happened before registering is carried over
+        assert_eq!(
+            1,
+            recorded.counter("split_stream_by_routed_total,splitter=test,side=left")
+        );
+        assert_eq!(
+            0,
+            recorded.counter("split_stream_by_routed_total,splitter=test,side=right")
+        );
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(2)), even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(
+            0.0,
+            recorded.gauge("split_stream_by_terminated,splitter=test")
+        );
+        assert_eq!(Poll::Ready(None), even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(
+            2,
+            recorded.counter("split_stream_by_routed_total,splitter=test,side=left")
+        );
+        assert_eq!(
+            2,
+            recorded.counter("split_stream_by_routed_total,splitter=test,side=right")
+        );
+        assert_eq!(
+            0.0,
+            recorded.gauge("split_stream_by_buffered,splitter=test,side=right")
+        );
+        assert_eq!(
+            1.0,
+            recorded.gauge("split_stream_by_terminated,splitter=test")
+        );
+        assert!(recorded.counter("split_stream_by_source_polls_total,splitter=test") >= 5);
+    }
+}
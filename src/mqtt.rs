@@ -0,0 +1,133 @@
+use futures::{future::Either, stream::BoxStream, Stream, StreamExt};
+use rumqttc::{ConnectionError, Event, EventLoop, Packet, Publish};
+
+use crate::{Left, Right, SplitStreamByMapExt};
+
+/// What a rumqttc `EventLoop` returns each time it's polled
+pub type MqttEvent = Result<Event, ConnectionError>;
+
+type RoutePublish = fn(MqttEvent) -> Either<Publish, MqttEvent>;
+
+/// The stream of publishes received from the broker, returned by
+/// `split_publishes`
+pub type Publishes<S> = Left<S, Publish, MqttEvent, RoutePublish>;
+
+/// The stream of everything else the event loop returns, returned by
+/// `split_publishes`
+pub type ProtocolEvents<S> = Right<S, Publish, MqttEvent, RoutePublish>;
+
+fn route_publish(event: MqttEvent) -> Either<Publish, MqttEvent> {
+    match event {
+        Ok(Event::Incoming(Packet::Publish(publish))) => Either::Left(publish),
+        event => Either::Right(event),
+    }
+}
+
+/// Splits the events of a rumqttc event loop into the publishes received
+/// from the broker, which are what the application wants, and everything
+/// else: acks, pings, outgoing packets and connection errors, which are
+/// mostly of interest for logging and reconnect handling. `events` is the
+/// event loop wrapped up as a stream, as `split_event_loop` does
+///
+///```rust
+/// use futures::StreamExt;
+/// use rumqttc::{Event, Outgoing, Packet, Publish, QoS};
+/// use split_stream_by::split_publishes;
+///
+/// let events = futures::stream::iter([
+///     Ok(Event::Outgoing(Outgoing::PingReq)),
+///     Ok(Event::Incoming(Packet::Publish(Publish::new("sensors/1", QoS::AtMostOnce, "21.5")))),
+/// ]);
+/// let (publishes, protocol_events) = split_publishes(events);
+/// futures::executor::block_on(async {
+///     let (publishes, protocol_events) = futures::join!(publishes.collect::<Vec<_>>(), protocol_events.collect::<Vec<_>>());
+///     assert_eq!("sensors/1", publishes[0].topic);
+///     assert_eq!(1, protocol_events.len());
+/// });
+/// ```
+pub fn split_publishes<S>(events: S) -> (Publishes<S>, ProtocolEvents<S>)
+where
+    S: Stream<Item = MqttEvent>,
+{
+    events.split_by_map(route_publish as RoutePublish)
+}
+
+/// Polls `event_loop` as a stream and splits its events as with
+/// `split_publishes`. The event loop only makes progress while one of the
+/// streams is polled, and as with polling it directly, it reconnects on the
+/// next poll after returning a connection error, so the streams never end
+pub fn split_event_loop(
+    event_loop: EventLoop,
+) -> (
+    Publishes<BoxStream<'static, MqttEvent>>,
+    ProtocolEvents<BoxStream<'static, MqttEvent>>,
+) {
+    let events = futures::stream::unfold(event_loop, |mut event_loop| async move {
+        let event = event_loop.poll().await;
+        Some((event, event_loop))
+    });
+    split_publishes(events.boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{future::Either, StreamExt};
+    use rumqttc::{ConnectionError, Event, Outgoing, Packet, PubAck, Publish, QoS};
+
+    use super::{route_publish, split_publishes};
+
+    fn publish(topic: &str) -> Publish {
+        Publish::new(topic, QoS::AtLeastOnce, "payload")
+    }
+
+    #[test]
+    fn test_routes_incoming_publishes() {
+        let event = Ok(Event::Incoming(Packet::Publish(publish("a"))));
+        assert!(matches!(route_publish(event), Either::Left(publish) if publish.topic == "a"));
+    }
+
+    #[test]
+    fn test_routes_everything_else() {
+        let events = [
+            Ok(Event::Incoming(Packet::PubAck(PubAck::new(1)))),
+            Ok(Event::Incoming(Packet::PingResp)),
+            // A publish going out to the broker isn't one received from it
+            Ok(Event::Outgoing(Outgoing::Publish(1))),
+            Err(ConnectionError::RequestsDone),
+        ];
+        for event in events {
+            assert!(matches!(route_publish(event), Either::Right(_)));
+        }
+    }
+
+    #[test]
+    fn test_split_publishes_keeps_order() {
+        let events = futures::stream::iter([
+            Ok(Event::Outgoing(Outgoing::PingReq)),
+            Ok(Event::Incoming(Packet::Publish(publish("a")))),
+            Ok(Event::Incoming(Packet::PingResp)),
+            Err(ConnectionError::NetworkTimeout),
+            Ok(Event::Incoming(Packet::Publish(publish("b")))),
+        ]);
+        let (publishes, protocol_events) = split_publishes(events);
+        futures::executor::block_on(async {
+            let (publishes, protocol_events) = futures::join!(
+                publishes.collect::<Vec<_>>(),
+                protocol_events.collect::<Vec<_>>()
+            );
+            let topics: Vec<_> = publishes
+                .iter()
+                .map(|publish| publish.topic.as_str())
+                .collect();
+            assert_eq!(vec!["a", "b"], topics);
+            assert!(matches!(
+                protocol_events.as_slice(),
+                [
+                    Ok(Event::Outgoing(Outgoing::PingReq)),
+                    Ok(Event::Incoming(Packet::PingResp)),
+                    Err(ConnectionError::NetworkTimeout),
+                ]
+            ));
+        });
+    }
+}
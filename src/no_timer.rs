@@ -0,0 +1,9 @@
+//! Stand-ins for the timer state each split holds, so that without the `timer`
+//! feature its fields are still there, just empty. `pin_project!` can't gate a
+//! field on a feature
+
+use std::marker::PhantomData;
+
+pub(crate) type Deadlines<I> = PhantomData<I>;
+
+pub(crate) type RateLimits = ();
@@ -0,0 +1,341 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::oneshot,
+    future::Either,
+    stream::{FusedStream, Stream},
+};
+
+use crate::{Left, Right, SplitStreamByMapExt};
+
+/// What `Offloaded` yields for each item: the item along with the side the
+/// predicate picked for it, or the payload of a panic raised by the
+/// predicate, after which it ends
+pub type Verdict<I> = Result<Either<I, I>, Box<dyn Any + Send>>;
+
+/// A boxed job that evaluates the predicate on one batch, handed to the
+/// executor passed to `offload_predicate`
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Routes an item whose side was picked off the polling task, re-raising a
+/// panic from picking it inside the splitter
+pub(crate) type RouteVerdict<L, R> = fn(Result<Either<L, R>, Box<dyn Any + Send>>) -> Either<L, R>;
+
+/// The first stream returned by `split_by_offloaded`
+pub type OffloadedLeft<S, F, E> = Left<
+    Offloaded<S, F, E>,
+    <S as Stream>::Item,
+    <S as Stream>::Item,
+    RouteVerdict<<S as Stream>::Item, <S as Stream>::Item>,
+>;
+
+/// The second stream returned by `split_by_offloaded`
+pub type OffloadedRight<S, F, E> = Right<
+    Offloaded<S, F, E>,
+    <S as Stream>::Item,
+    <S as Stream>::Item,
+    RouteVerdict<<S as Stream>::Item, <S as Stream>::Item>,
+>;
+
+type Batch<I> = (Vec<I>, Result<Vec<bool>, Box<dyn Any + Send>>);
+
+/// Wraps `stream` so that its items are collected into batches of up to
+/// `max_batch` and `predicate` is evaluated on each batch by a job handed to
+/// `spawn`, rather than on the task polling the stream. The wrapped stream
+/// yields each item with the side the predicate picked for it, in the order
+/// they were read. A batch is sent off as soon as `stream` has nothing more
+/// ready, so batches only fill up under load, and only one batch is
+/// evaluated at a time.
+///
+/// `predicate` has to return one `bool` per item, `true` for the first
+/// stream. A panic in it, or an executor that drops the job without running
+/// it, is yielded as the last `Verdict`. Most of the time this is used
+/// through `split_by_offloaded` or, with the `rayon` feature,
+/// `split_by_rayon`
+///
+/// Panics if `max_batch` is zero
+pub fn offload_predicate<S, F, E>(
+    stream: S,
+    max_batch: usize,
+    predicate: F,
+    spawn: E,
+) -> Offloaded<S, F, E>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+    E: Fn(Job),
+{
+    assert!(max_batch > 0, "a batch has to hold at least one item");
+    Offloaded {
+        stream: Some(Box::pin(stream)),
+        max_batch,
+        predicate: Arc::new(predicate),
+        spawn,
+        in_flight: None,
+        ready: VecDeque::new(),
+        failed: false,
+    }
+}
+
+/// Splits `stream` into two streams by a `predicate` that's evaluated on
+/// batches of items by jobs handed to `spawn`, e.g. a thread pool, so that
+/// an expensive predicate doesn't block the task polling the streams. See
+/// `offload_predicate` for how items are batched. A panic in the predicate
+/// ends both streams, and its payload can be taken with `take_panic` on
+/// either of them
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_offloaded;
+///
+/// let incoming_stream = futures::stream::iter(0..6);
+/// let spawn = |job: split_stream_by::Job| {
+///     std::thread::spawn(job);
+/// };
+/// let (evens, odds) = split_by_offloaded(incoming_stream, 4, |batch: &[i32]| {
+///     batch.iter().map(|n| n % 2 == 0).collect()
+/// }, spawn);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3, 5], odds);
+/// });
+/// ```
+pub fn split_by_offloaded<S, F, E>(
+    stream: S,
+    max_batch: usize,
+    predicate: F,
+    spawn: E,
+) -> (OffloadedLeft<S, F, E>, OffloadedRight<S, F, E>)
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+    E: Fn(Job),
+{
+    offload_predicate(stream, max_batch, predicate, spawn)
+        .split_by_map(route_verdict as RouteVerdict<S::Item, S::Item>)
+}
+
+/// The same as `split_by_offloaded`, evaluating the predicate on rayon's
+/// global thread pool
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_rayon;
+///
+/// let incoming_stream = futures::stream::iter(0..6);
+/// let (small, large) = split_by_rayon(incoming_stream, 64, |batch: &[u64]| {
+///     batch.iter().map(|&n| n < 3).collect()
+/// });
+/// futures::executor::block_on(async {
+///     let (small, large) = futures::join!(small.collect::<Vec<_>>(), large.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 1, 2], small);
+///     assert_eq!(vec![3, 4, 5], large);
+/// });
+/// ```
+#[cfg(feature = "rayon")]
+pub fn split_by_rayon<S, F>(
+    stream: S,
+    max_batch: usize,
+    predicate: F,
+) -> (OffloadedLeft<S, F, fn(Job)>, OffloadedRight<S, F, fn(Job)>)
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+{
+    split_by_offloaded(stream, max_batch, predicate, rayon::spawn as fn(Job))
+}
+
+/// Hands the item to the side picked for it, or raises the predicate's panic
+/// again inside the splitter, which ends both sides and keeps the payload for
+/// `take_panic`
+pub(crate) fn route_verdict<L, R>(
+    verdict: Result<Either<L, R>, Box<dyn Any + Send>>,
+) -> Either<L, R> {
+    match verdict {
+        Ok(item) => item,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// A stream wrapped by `offload_predicate`, yielding its items along with the
+/// side the predicate picked for each
+pub struct Offloaded<S: Stream, F, E> {
+    /// Taken once the stream has ended
+    stream: Option<Pin<Box<S>>>,
+    max_batch: usize,
+    predicate: Arc<F>,
+    spawn: E,
+    in_flight: Option<oneshot::Receiver<Batch<S::Item>>>,
+    ready: VecDeque<Either<S::Item, S::Item>>,
+    /// Set once a panic or a dropped job has been yielded
+    failed: bool,
+}
+
+// Everything that might be pinned in place is boxed
+impl<S: Stream, F, E> Unpin for Offloaded<S, F, E> {}
+
+impl<S, F, E> Offloaded<S, F, E>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+    E: Fn(Job),
+{
+    /// Reads what the stream has ready, up to a batch, and hands it to the
+    /// executor. Returns whether anything was read
+    fn start_batch(&mut self, cx: &mut Context<'_>) -> bool {
+        let mut items = Vec::new();
+        while items.len() < self.max_batch {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => break,
+            };
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => self.stream = None,
+                Poll::Pending => break,
+            }
+        }
+        if items.is_empty() {
+            return false;
+        }
+        let (sender, receiver) = oneshot::channel();
+        let predicate = Arc::clone(&self.predicate);
+        (self.spawn)(Box::new(move || {
+            let verdicts = panic::catch_unwind(AssertUnwindSafe(|| predicate(&items)));
+            let _ = sender.send((items, verdicts));
+        }));
+        self.in_flight = Some(receiver);
+        true
+    }
+}
+
+impl<S, F, E> Stream for Offloaded<S, F, E>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+    E: Fn(Job),
+{
+    type Item = Verdict<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(item) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if this.failed {
+                return Poll::Ready(None);
+            }
+            if let Some(in_flight) = &mut this.in_flight {
+                let batch = match Pin::new(in_flight).poll(cx) {
+                    Poll::Ready(batch) => batch,
+                    Poll::Pending => return Poll::Pending,
+                };
+                this.in_flight = None;
+                let (items, verdicts) = match batch {
+                    Ok((items, Ok(verdicts))) => (items, verdicts),
+                    Ok((_, Err(payload))) => {
+                        this.failed = true;
+                        return Poll::Ready(Some(Err(payload)));
+                    }
+                    Err(oneshot::Canceled) => {
+                        this.failed = true;
+                        let payload: Box<dyn Any + Send> =
+                            Box::new("the offloaded predicate was dropped without running");
+                        return Poll::Ready(Some(Err(payload)));
+                    }
+                };
+                assert_eq!(
+                    items.len(),
+                    verdicts.len(),
+                    "the offloaded predicate has to return one verdict per item"
+                );
+                this.ready
+                    .extend(items.into_iter().zip(verdicts).map(|(item, is_left)| {
+                        if is_left {
+                            Either::Left(item)
+                        } else {
+                            Either::Right(item)
+                        }
+                    }));
+                continue;
+            }
+            if !this.start_batch(cx) {
+                return if this.stream.is_none() {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            }
+        }
+    }
+}
+
+impl<S, F, E> FusedStream for Offloaded<S, F, E>
+where
+    S: Stream,
+    S::Item: Send + 'static,
+    F: Fn(&[S::Item]) -> Vec<bool> + Send + Sync + 'static,
+    E: Fn(Job),
+{
+    fn is_terminated(&self) -> bool {
+        self.ready.is_empty() && self.in_flight.is_none() && (self.failed || self.stream.is_none())
+    }
+}
+
+impl<S: Stream, F, E> fmt::Debug for Offloaded<S, F, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Offloaded")
+            .field("max_batch", &self.max_batch)
+            .field("stream_ended", &self.stream.is_none())
+            .field("in_flight", &self.in_flight.is_some())
+            .field("ready", &self.ready.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::{split_by_offloaded, Job};
+
+    #[test]
+    fn test_offloaded_predicate_panic() {
+        let spawn = |job: Job| {
+            std::thread::spawn(job);
+        };
+        let (mut small, large) = split_by_offloaded(
+            futures::stream::iter(0..4),
+            2,
+            |batch: &[i32]| {
+                assert!(batch[0] < 2, "too large");
+                batch.iter().map(|&n| n < 1).collect()
+            },
+            spawn,
+        );
+        futures::executor::block_on(async {
+            let (small_items, large_items) =
+                futures::join!((&mut small).collect::<Vec<_>>(), large.collect::<Vec<_>>());
+            assert_eq!(vec![0], small_items);
+            assert_eq!(vec![1], large_items);
+        });
+        let payload = small.take_panic().unwrap();
+        assert_eq!(Some(&"too large"), payload.downcast_ref::<&str>());
+    }
+}
@@ -0,0 +1,17 @@
+/// Determines what happens when an item is routed to an output stream of a
+/// buffered split whose buffer is full, set with `set_overflow_policy` on it.
+/// Each stream has its own policy, so e.g. a control stream can hold up the
+/// split while a data stream sheds load
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading the underlying stream until this stream makes room, so
+    /// the other stream waits on it. This is the default
+    #[default]
+    Block,
+    /// Drop the item that didn't fit, keeping what's already buffered
+    DropNewest,
+    /// Drop the oldest item in the buffer to make room for the one that
+    /// didn't fit, so the stream sees the most recent items once it catches
+    /// up
+    DropOldest,
+}
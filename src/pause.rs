@@ -0,0 +1,187 @@
+use std::sync::Weak;
+
+use crate::sync::{LockFamily, Mutex, StdMutex};
+
+use crate::Side;
+
+/// Determines what happens to items routed to an output stream while it's
+/// paused with a `PauseHandle`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// Buffer the items as usual. Once the buffer is full, the other stream
+    /// waits for this one to be resumed
+    Buffer,
+    /// Drop the items, so the other stream keeps flowing
+    Drop,
+    /// Yield the items from the other stream instead. This is only possible
+    /// when both streams have the same item type, so the `split_by_map`
+    /// variants treat it as `Buffer`
+    Divert,
+}
+
+mod sealed {
+    use crate::{PausePolicy, Side};
+
+    /// Implemented by the shared state of each kind of split
+    pub trait Pausable {
+        fn pause(&mut self, side: Side, policy: PausePolicy);
+        fn resume(&mut self, side: Side);
+    }
+}
+
+pub(crate) use sealed::Pausable;
+
+/// A control handle for pausing and resuming the output streams of a split,
+/// returned by `pause_handle` on either of them. A paused stream returns
+/// `Poll::Pending` until it's resumed, while its items are handled according
+/// to the `PausePolicy` it was paused with. The handle doesn't keep the split
+/// alive, and does nothing once both streams have been dropped
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{PausePolicy, Side, SplitStreamByExt};
+///
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+/// let pause = even_stream.pause_handle();
+/// pause.pause(Side::Right, PausePolicy::Drop);
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(0), even_stream.next().await);
+///     assert_eq!(Some(2), even_stream.next().await);
+///     pause.resume(Side::Right);
+///     assert_eq!(Some(3), odd_stream.next().await);
+/// });
+/// ```
+pub struct PauseHandle<T, M: LockFamily = StdMutex> {
+    state: Weak<Mutex<T, M>>,
+}
+
+impl<T, M: LockFamily> PauseHandle<T, M> {
+    pub(crate) fn new(state: Weak<Mutex<T, M>>) -> Self {
+        Self { state }
+    }
+}
+
+impl<T: Pausable, M: LockFamily> PauseHandle<T, M> {
+    /// Pauses the `side` stream, or changes the policy it's paused with.
+    /// Anything already buffered for it stays buffered
+    pub fn pause(&self, side: Side, policy: PausePolicy) {
+        if let Some(state) = self.state.upgrade() {
+            let mut guard = state.lock();
+            guard.pause(side, policy);
+        }
+    }
+
+    /// Resumes the `side` stream, waking it if it was paused
+    pub fn resume(&self, side: Side) {
+        if let Some(state) = self.state.upgrade() {
+            let mut guard = state.lock();
+            guard.resume(side);
+        }
+    }
+}
+
+impl<T, M: LockFamily> Clone for PauseHandle<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Weak::clone(&self.state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use crate::{
+        test_util::CountingWaker, Either, PausePolicy, Side, SplitStreamByExt, SplitStreamByMapExt,
+    };
+
+    #[test]
+    fn test_resume_wakes_paused_side() {
+        let incoming_stream = futures::stream::iter([0, 1, 2]);
+        let (mut even_stream, _odd_stream) =
+            incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        let pause = even_stream.pause_handle();
+        pause.pause(Side::Left, PausePolicy::Buffer);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(0, counter.wakes());
+        pause.resume(Side::Left);
+        assert_eq!(1, counter.wakes());
+        assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_buffer_holds_up_other_side_once_full() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 3, 0]);
+        let (mut even_stream, mut odd_stream) =
+            incoming_stream.split_by_buffered::<1>(|&n| n % 2 == 0);
+        let pause = odd_stream.pause_handle();
+        pause.pause(Side::Right, PausePolicy::Buffer);
+        // 1 fills the paused side's buffer, so 3 can't be read
+        assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Pending, odd_stream.poll_next_unpin(&mut cx));
+        pause.resume(Side::Right);
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_divert_to_other_side() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let pause = odd_stream.pause_handle();
+        pause.pause(Side::Right, PausePolicy::Divert);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(Poll::Pending, odd_stream.poll_next_unpin(&mut cx));
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 1, 2, 3], even_stream.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_map_divert_buffers() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 0]);
+        let (mut evens, mut odds) = incoming_stream.split_by_map(|n: u32| {
+            if n % 2 == 0 {
+                Either::Left(n)
+            } else {
+                Either::Right(n.to_string())
+            }
+        });
+        let pause = odds.pause_handle();
+        // The odd items can't become evens, so they wait for the odd side instead
+        pause.pause(Side::Right, PausePolicy::Divert);
+        assert_eq!(Poll::Pending, evens.poll_next_unpin(&mut cx));
+        pause.resume(Side::Right);
+        assert_eq!(
+            Poll::Ready(Some("1".to_string())),
+            odds.poll_next_unpin(&mut cx)
+        );
+        assert_eq!(Poll::Ready(Some(0)), evens.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_handle_outlives_split() {
+        let incoming_stream = futures::stream::iter(0..6);
+        let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let pause = odd_stream.pause_handle();
+        drop(odd_stream);
+        // Pausing a dropped side changes nothing for the other
+        pause.pause(Side::Right, PausePolicy::Buffer);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 2, 4], even_stream.collect::<Vec<_>>().await);
+        });
+        pause.pause(Side::Left, PausePolicy::Drop);
+        pause.resume(Side::Left);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    ops::Deref,
+    task::{Context, Poll},
+};
+
+use crate::{
+    consumer_check::ConsumerCheck,
+    sync::{self, LockFamily, Mutex, MutexGuard, StdMutex},
+};
+
+/// The next item of an output stream, borrowed by `peek` or `poll_peek`
+/// without taking it, so that it's still yielded by the next poll. It holds
+/// the lock on the shared state of the split, so neither stream can make
+/// progress until it's dropped
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::SplitStreamByExt;
+///
+/// let incoming_stream = futures::stream::iter([1,2,3,4]);
+/// let (mut even_stream, mut odd_stream) = incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(1), odd_stream.peek().await.map(|n| *n));
+///     assert_eq!(Some(2), even_stream.next().await);
+///     assert_eq!(Some(1), odd_stream.next().await);
+/// });
+/// ```
+pub struct Peeked<'a, T: 'a, I, M: LockFamily = StdMutex> {
+    guard: MutexGuard<'a, T, M>,
+    item: fn(&T) -> Option<&I>,
+}
+
+impl<'a, T: 'a, I, M: LockFamily> Peeked<'a, T, I, M> {
+    /// `item` finds the peeked item in the shared state, which must have one
+    pub(crate) fn new(guard: MutexGuard<'a, T, M>, item: fn(&T) -> Option<&I>) -> Self {
+        Self { guard, item }
+    }
+}
+
+impl<'a, T: 'a, I, M: LockFamily> Deref for Peeked<'a, T, I, M> {
+    type Target = I;
+
+    fn deref(&self) -> &I {
+        (self.item)(&self.guard).expect("the peeked item is buffered while the lock is held")
+    }
+}
+
+impl<'a, T: 'a, I: fmt::Debug, M: LockFamily> fmt::Debug for Peeked<'a, T, I, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Peeked").field(&**self).finish()
+    }
+}
+
+/// Does the work of `poll_peek` on an output stream. `poll` makes sure the
+/// shared state has an item buffered for the stream, returning whether there
+/// is one, and `item` finds it
+pub(crate) fn poll_peek<'a, T, I, M: LockFamily>(
+    stream: &'a Mutex<T, M>,
+    consumer: &mut ConsumerCheck,
+    name: &str,
+    cx: &mut Context<'_>,
+    poll: impl FnOnce(&mut T, &mut Context<'_>) -> Poll<bool>,
+    item: fn(&T) -> Option<&I>,
+) -> Poll<Option<Peeked<'a, T, I, M>>> {
+    consumer.check(name, cx);
+    let mut guard = match stream.try_lock() {
+        Some(guard) => guard,
+        None => {
+            sync::lock_contended();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+    };
+    let found = poll(&mut guard, cx);
+    consumer.record(cx, &found);
+    match found {
+        Poll::Ready(true) => Poll::Ready(Some(Peeked::new(guard, item))),
+        Poll::Ready(false) => Poll::Ready(None),
+        Poll::Pending => Poll::Pending,
+    }
+}
+
+/// Does the work of `peek` on an output stream, as with `poll_peek`
+pub(crate) async fn peek<'a, T, I, M: LockFamily>(
+    stream: &'a Mutex<T, M>,
+    consumer: &mut ConsumerCheck,
+    name: &str,
+    mut poll: impl FnMut(&mut T, &mut Context<'_>) -> Poll<bool>,
+    item: fn(&T) -> Option<&I>,
+) -> Option<Peeked<'a, T, I, M>> {
+    loop {
+        let found = futures::future::poll_fn(|cx| {
+            poll_peek(stream, consumer, name, cx, &mut poll, item).map(|peeked| peeked.is_some())
+        })
+        .await;
+        if !found {
+            return None;
+        }
+        // The lock was let go of in between, so the item may have gone again,
+        // e.g. by its deadline passing, in which case look for the next one
+        let guard = stream.lock();
+        if item(&guard).is_some() {
+            return Some(Peeked::new(guard, item));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use crate::{test_util::CountingWaker, SplitStreamByExt};
+
+    #[test]
+    fn test_peek_leaves_item() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let incoming_stream = futures::stream::iter([1, 3]);
+        let (_even_stream, mut odd_stream) =
+            incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        for _ in 0..2 {
+            match odd_stream.poll_peek(&mut cx) {
+                Poll::Ready(Some(peeked)) => assert_eq!(1, *peeked),
+                poll => panic!("expected a peeked item, got {:?}", poll),
+            }
+        }
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odd_stream.poll_next_unpin(&mut cx));
+        assert!(matches!(odd_stream.poll_peek(&mut cx), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn test_peeked_holds_up_sibling() {
+        let incoming_stream = futures::stream::iter([1, 0]);
+        let (mut even_stream, mut odd_stream) =
+            incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        let peeked = match odd_stream.poll_peek(&mut Context::from_waker(noop_waker_ref())) {
+            Poll::Ready(Some(peeked)) => peeked,
+            poll => panic!("expected a peeked item, got {:?}", poll),
+        };
+        // The sibling finds the lock taken, and has itself polled again
+        assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+        assert_eq!(1, counter.wakes());
+        assert_eq!(1, *peeked);
+        drop(peeked);
+        assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_peek_wakes_after_pending() {
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let (mut even_stream, _odd_stream) = rx.split_by_buffered::<2>(|&n: &u32| n % 2 == 0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(even_stream.poll_peek(&mut cx).is_pending());
+        tx.unbounded_send(2).unwrap();
+        assert_eq!(1, counter.wakes());
+        match even_stream.poll_peek(&mut cx) {
+            Poll::Ready(Some(peeked)) => assert_eq!(2, *peeked),
+            poll => panic!("expected a peeked item, got {:?}", poll),
+        }
+        assert_eq!(Poll::Ready(Some(2)), even_stream.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_async_peek() {
+        let incoming_stream = futures::stream::iter([1, 2]);
+        let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        futures::executor::block_on(async {
+            assert_eq!(Some(1), odd_stream.peek().await.map(|n| *n));
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(Some(2), even_stream.peek().await.map(|n| *n));
+            assert_eq!(Some(2), even_stream.next().await);
+            assert!(odd_stream.peek().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use futures::future::Either;
+
+/// A control handle for replacing the predicate of a running `split_by` or
+/// `split_by_buffered`. `new` returns the handle along with a predicate to
+/// split with, which forwards to whichever predicate was set last. Replacing
+/// it only affects items that haven't been routed yet
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{PredicateHandle, SplitStreamByExt};
+///
+/// let (handle, predicate) = PredicateHandle::new(|&n: &i32| n % 2 == 0);
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let (mut true_stream, mut false_stream) = incoming_stream.split_by(predicate);
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(0), true_stream.next().await);
+///     handle.replace(|_| true);
+///     assert_eq!(Some(1), true_stream.next().await);
+///     assert_eq!(Some(2), true_stream.next().await);
+/// });
+/// ```
+pub struct PredicateHandle<T> {
+    predicate: Arc<RwLock<Arc<dyn Fn(&T) -> bool + Send + Sync>>>,
+}
+
+impl<T> PredicateHandle<T> {
+    /// Creates a handle starting out with `predicate`, and the predicate to
+    /// pass to `split_by` that it controls
+    pub fn new(
+        predicate: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> (Self, impl Fn(&T) -> bool + Send + Sync) {
+        let handle = Self {
+            predicate: Arc::new(RwLock::new(Arc::new(predicate))),
+        };
+        let current = Arc::clone(&handle.predicate);
+        let predicate = move |item: &T| {
+            let predicate = current.read().unwrap_or_else(PoisonError::into_inner);
+            predicate(item)
+        };
+        (handle, predicate)
+    }
+
+    /// Routes items that haven't been routed yet with `predicate`
+    pub fn replace(&self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static) {
+        *self
+            .predicate
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(predicate);
+    }
+}
+
+impl<T> Clone for PredicateHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            predicate: Arc::clone(&self.predicate),
+        }
+    }
+}
+
+/// The same as `PredicateHandle`, but for the predicate of `split_by_map` or
+/// `split_by_map_buffered`
+pub struct MapPredicateHandle<I, L, R> {
+    predicate: Arc<RwLock<Arc<dyn Fn(I) -> Either<L, R> + Send + Sync>>>,
+}
+
+impl<I, L, R> MapPredicateHandle<I, L, R> {
+    /// Creates a handle starting out with `predicate`, and the predicate to
+    /// pass to `split_by_map` that it controls
+    pub fn new(
+        predicate: impl Fn(I) -> Either<L, R> + Send + Sync + 'static,
+    ) -> (Self, impl Fn(I) -> Either<L, R> + Send + Sync) {
+        let handle = Self {
+            predicate: Arc::new(RwLock::new(Arc::new(predicate))),
+        };
+        let current = Arc::clone(&handle.predicate);
+        let predicate = move |item: I| {
+            let predicate = current.read().unwrap_or_else(PoisonError::into_inner);
+            predicate(item)
+        };
+        (handle, predicate)
+    }
+
+    /// Routes items that haven't been routed yet with `predicate`
+    pub fn replace(&self, predicate: impl Fn(I) -> Either<L, R> + Send + Sync + 'static) {
+        *self
+            .predicate
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(predicate);
+    }
+}
+
+impl<I, L, R> Clone for MapPredicateHandle<I, L, R> {
+    fn clone(&self) -> Self {
+        Self {
+            predicate: Arc::clone(&self.predicate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{MapPredicateHandle, PredicateHandle};
+    use crate::{test_util::CountingWaker, Either, SplitStreamByExt, SplitStreamByMapExt};
+
+    #[test]
+    fn test_replace_leaves_buffered_items() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (handle, predicate) = PredicateHandle::new(|&n: &u32| n % 2 == 0);
+        let incoming_stream = futures::stream::iter([1, 3, 0, 5, 7]);
+        let (mut evens, mut odds) = incoming_stream.split_by_buffered::<4>(predicate);
+        // The evens give the odds a turn once 1 is buffered for them, so it can
+        // take more than one poll to reach 0
+        let first_even = loop {
+            if let Poll::Ready(even) = evens.poll_next_unpin(&mut cx) {
+                break even;
+            }
+        };
+        assert_eq!(Some(0), first_even);
+        // 1 and 3 were routed before the replacement, so they stay with the odds
+        handle.replace(|_| true);
+        assert_eq!(Poll::Ready(Some(1)), odds.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), odds.poll_next_unpin(&mut cx));
+        futures::executor::block_on(async {
+            assert_eq!(vec![5, 7], evens.collect::<Vec<_>>().await);
+        });
+        assert_eq!(Poll::Ready(None), odds.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_clones_share_predicate() {
+        let (handle, predicate) = PredicateHandle::new(|&n: &u32| n < 10);
+        let clone = handle.clone();
+        assert!(predicate(&5));
+        clone.replace(|&n| n < 3);
+        assert!(!predicate(&5));
+        // The last replacement wins, whichever handle made it
+        handle.replace(|&n| n > 3);
+        assert!(predicate(&5));
+    }
+
+    #[test]
+    fn test_map_replace_reroutes_waiting_side() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        let (handle, predicate) = MapPredicateHandle::new(|n: u32| Either::<u32, String>::Left(n));
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let (left, mut right) = receiver.split_by_map(predicate);
+        assert_eq!(Poll::Pending, right.poll_next_unpin(&mut cx));
+        // The replacement can build the other side's type, which the original never did
+        handle.replace(|n| Either::Right(n.to_string()));
+        sender.unbounded_send(1).unwrap();
+        assert_eq!(1, counter.wakes());
+        assert_eq!(
+            Poll::Ready(Some("1".to_string())),
+            right.poll_next_unpin(&mut cx)
+        );
+        drop((sender, left));
+        assert_eq!(Poll::Ready(None), right.poll_next_unpin(&mut cx));
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{
+    fan_out::{self, FanOut, FanOutState},
+    fan_out_buffered,
+};
+
+/// Splits a stream into C priority classes by `classify`, which returns the
+/// class of each item, 0 being the highest priority. This is
+/// `fan_out_buffered` with classes for outputs. Each class can be
+/// consumed as a stream of its own, or all of them together with
+/// `strict_priority`, which always yields from the highest priority class
+/// that has an item ready. Up to N items are buffered for each class, and as
+/// with the buffered splits nothing is read while a buffer is full. Dropping
+/// a class discards what's buffered for it and anything classified into it
+/// from then on
+///
+/// Polling a class panics if `classify` returns a class that isn't below C
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{split_by_priority, strict_priority};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Job {
+///     Interactive(u32),
+///     Batch(u32),
+///     Cleanup(u32),
+/// }
+///
+/// let incoming_stream = futures::stream::iter([
+///     Job::Cleanup(0),
+///     Job::Batch(1),
+///     Job::Interactive(2),
+///     Job::Batch(3),
+/// ]);
+/// let classes = split_by_priority::<_, _, 3, 4>(incoming_stream, |job| match job {
+///     Job::Interactive(_) => 0,
+///     Job::Batch(_) => 1,
+///     Job::Cleanup(_) => 2,
+/// });
+/// let jobs = strict_priority(classes);
+/// futures::executor::block_on(async {
+///     assert_eq!(
+///         vec![Job::Interactive(2), Job::Batch(1), Job::Batch(3), Job::Cleanup(0)],
+///         jobs.collect::<Vec<_>>().await
+///     );
+/// });
+/// ```
+pub fn split_by_priority<S, F, const C: usize, const N: usize>(
+    stream: S,
+    classify: F,
+) -> [PriorityClass<S, F, C, N>; C]
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fan_out_buffered(stream, classify).map(|output| PriorityClass { output })
+}
+
+/// Consumes the classes returned by `split_by_priority` as one stream, which
+/// yields from the highest priority class that has an item ready. Items of
+/// a class are only yielded once the underlying stream has nothing ready and
+/// every class above it is empty, so a steady flow of high priority items
+/// starves the lower classes
+///
+/// Panics if `classes` aren't the classes of one split in the order they
+/// were returned
+pub fn strict_priority<S, F, const C: usize, const N: usize>(
+    classes: [PriorityClass<S, F, C, N>; C],
+) -> StrictPriority<S, F, C, N>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    assert!(
+        classes.iter().enumerate().all(|(class, stream)| {
+            stream.class() == class && Arc::ptr_eq(&stream.output.state, &classes[0].output.state)
+        }),
+        "the classes have to come from the same split in order"
+    );
+    StrictPriority { classes }
+}
+
+/// The stream of one priority class returned by `split_by_priority`
+pub struct PriorityClass<S: Stream, F, const C: usize, const N: usize> {
+    output: FanOut<S, F, C, N>,
+}
+
+impl<S: Stream, F, const C: usize, const N: usize> PriorityClass<S, F, C, N> {
+    /// The class of the items this stream yields
+    pub fn class(&self) -> usize {
+        self.output.index()
+    }
+}
+
+impl<S, F, const C: usize, const N: usize> Stream for PriorityClass<S, F, C, N>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        Pin::new(&mut self.output).poll_next(cx)
+    }
+}
+
+impl<S, F, const C: usize, const N: usize> FusedStream for PriorityClass<S, F, C, N>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fn is_terminated(&self) -> bool {
+        self.output.is_terminated()
+    }
+}
+
+impl<S: Stream, F, const C: usize, const N: usize> fmt::Debug for PriorityClass<S, F, C, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PriorityClass");
+        debug.field("class", &self.class());
+        fan_out::debug_core(&self.output.state, &mut debug);
+        debug.finish()
+    }
+}
+
+/// The stream returned by `strict_priority`
+pub struct StrictPriority<S: Stream, F, const C: usize, const N: usize> {
+    classes: [PriorityClass<S, F, C, N>; C],
+}
+
+impl<S, F, const C: usize, const N: usize> Stream for StrictPriority<S, F, C, N>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut guard = match fan_out::try_lock(&self.classes[0].output.state, cx) {
+            Some(guard) => guard,
+            None => return Poll::Pending,
+        };
+        let FanOutState { core, route } = &mut *guard;
+        // Polling the highest class reads everything ready from the underlying stream
+        // until there's an item for it, so by the time a lower class is polled the
+        // classes above it are empty
+        let mut ended = true;
+        for class in 0..C {
+            match core.poll_side(class, cx, &mut *route).map(fan_out::routed) {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => {}
+                Poll::Pending => ended = false,
+            }
+        }
+        if ended {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, F, const C: usize, const N: usize> FusedStream for StrictPriority<S, F, C, N>
+where
+    S: Stream + Unpin,
+    F: Fn(&S::Item) -> usize,
+{
+    fn is_terminated(&self) -> bool {
+        self.classes.iter().all(FusedStream::is_terminated)
+    }
+}
+
+impl<S: Stream, F, const C: usize, const N: usize> fmt::Debug for StrictPriority<S, F, C, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("StrictPriority");
+        fan_out::debug_core(&self.classes[0].output.state, &mut debug);
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{Stream, StreamExt};
+
+    use super::{split_by_priority, strict_priority};
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn test_strict_priority_with_full_buffer() {
+        // The lower class's buffer fills up before the higher class's item is read, which
+        // holds the higher class up until the lower one has been taken from
+        let incoming_stream = futures::stream::iter([1, 1, 0, 1]);
+        let classes = split_by_priority::<_, _, 2, 1>(incoming_stream, |&class| class);
+        let items = strict_priority(classes);
+        futures::executor::block_on(async {
+            assert_eq!(vec![1, 1, 0, 1], items.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_contended_poll_wakes() {
+        let task = CountingWaker::new();
+        let waker = task.waker();
+        let incoming_stream = futures::stream::iter([0, 1]);
+        let [mut high, low] = split_by_priority::<_, _, 2, 1>(incoming_stream, |&class| class);
+        // While the other class holds the lock, polling gives up straight away and has
+        // the task polled again rather than blocking
+        let guard = low.output.state.lock();
+        let poll = Pin::new(&mut high).poll_next(&mut Context::from_waker(&waker));
+        assert!(poll.is_pending());
+        assert_eq!(1, task.wakes());
+        assert!(format!("{:?}", low).contains("<locked>"));
+        drop(guard);
+        let poll = Pin::new(&mut high).poll_next(&mut Context::from_waker(&waker));
+        assert_eq!(Poll::Ready(Some(0)), poll);
+    }
+}
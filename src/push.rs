@@ -0,0 +1,283 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+};
+
+use futures::{stream::FusedStream, Sink, Stream};
+
+struct Pushed<I> {
+    queue: VecDeque<I>,
+    capacity: usize,
+    ended: bool,
+    /// The task reading the wrapped stream, woken when an item is pushed
+    reader: Option<Waker>,
+    /// The tasks waiting on room in the queue
+    writers: Vec<Waker>,
+}
+
+/// Wraps `stream` so that items can also be pushed into it from outside with
+/// a `PushHandle`, to be yielded alongside its own items. Splitting the
+/// wrapped stream gives a split with two inputs, such as items read from a
+/// socket and messages generated locally, which go through the same
+/// predicate and buffers.
+///
+/// Up to `capacity` pushed items wait to be yielded, and they're yielded
+/// ahead of the items of the underlying stream. The wrapped stream ends once
+/// the underlying stream has ended and there are no pushed items waiting,
+/// after which pushing hands the item back
+///
+/// Panics if `capacity` is zero
+///
+///```rust
+/// use futures::{SinkExt, StreamExt};
+/// use split_stream_by::{pushable, SplitStreamByExt};
+///
+/// let (incoming_stream, mut local) = pushable(futures::stream::iter([1, 2, 3]), 8);
+/// let (odd_stream, even_stream) = incoming_stream.split_by(|&n| n % 2 == 1);
+/// futures::executor::block_on(async {
+///     local.send(10).await.unwrap();
+///     assert_eq!(Ok(()), local.try_push(11));
+///     let (odds, evens) = futures::join!(
+///         odd_stream.collect::<Vec<_>>(),
+///         even_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![11, 1, 3], odds);
+///     assert_eq!(vec![10, 2], evens);
+///     assert_eq!(Err(12), local.try_push(12));
+/// });
+/// ```
+pub fn pushable<S: Stream>(stream: S, capacity: usize) -> (Pushable<S>, PushHandle<S::Item>) {
+    assert!(
+        capacity > 0,
+        "there has to be room for at least one pushed item"
+    );
+    let pushed = Arc::new(Mutex::new(Pushed {
+        queue: VecDeque::new(),
+        capacity,
+        ended: false,
+        reader: None,
+        writers: Vec::new(),
+    }));
+    let handle = PushHandle {
+        pushed: Arc::downgrade(&pushed),
+    };
+    let stream = Pushable {
+        stream: Box::pin(stream),
+        pushed,
+        stream_ended: false,
+    };
+    (stream, handle)
+}
+
+/// A stream wrapped by `pushable`, yielding its items along with those
+/// pushed through the `PushHandle`
+pub struct Pushable<S: Stream> {
+    stream: Pin<Box<S>>,
+    pushed: Arc<Mutex<Pushed<S::Item>>>,
+    stream_ended: bool,
+}
+
+impl<S: Stream> Stream for Pushable<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        let mut pushed = this.pushed.lock().unwrap_or_else(PoisonError::into_inner);
+        if pushed.ended {
+            return Poll::Ready(None);
+        }
+        if let Some(item) = pushed.queue.pop_front() {
+            // Whoever is waiting on room can push again
+            for writer in pushed.writers.drain(..) {
+                writer.wake();
+            }
+            return Poll::Ready(Some(item));
+        }
+        // An item pushed from now on wakes this stream
+        match &mut pushed.reader {
+            Some(reader) if reader.will_wake(cx.waker()) => {}
+            reader => *reader = Some(cx.waker().clone()),
+        }
+        if this.stream_ended {
+            pushed.ended = true;
+            // Anyone waiting on room finds out that the stream has ended
+            for writer in pushed.writers.drain(..) {
+                writer.wake();
+            }
+            return Poll::Ready(None);
+        }
+        // The lock isn't held while the underlying stream is polled, since that may
+        // push into this stream
+        drop(pushed);
+        match this.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Some(item)),
+            Poll::Ready(None) => {
+                this.stream_ended = true;
+                // Something may have been pushed while the stream was being polled
+                self.as_mut().poll_next(cx)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: Stream> FusedStream for Pushable<S> {
+    fn is_terminated(&self) -> bool {
+        self.pushed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ended
+    }
+}
+
+impl<S: Stream> fmt::Debug for Pushable<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pushed = self.pushed.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Pushable")
+            .field("stream_ended", &self.stream_ended)
+            .field("pushed", &pushed.queue.len())
+            .field("capacity", &pushed.capacity)
+            .finish()
+    }
+}
+
+/// A handle for pushing items into a stream wrapped by `pushable`. It
+/// implements `Sink`, waiting for room when the wrapped stream has as many
+/// pushed items waiting as it will take, and doesn't keep the stream alive
+pub struct PushHandle<I> {
+    pushed: Weak<Mutex<Pushed<I>>>,
+}
+
+impl<I> PushHandle<I> {
+    /// Pushes `item` into the wrapped stream without waiting. The item is
+    /// handed back if there's no room for it, or if the wrapped stream has
+    /// ended or been dropped
+    pub fn try_push(&self, item: I) -> Result<(), I> {
+        let pushed = match self.pushed.upgrade() {
+            Some(pushed) => pushed,
+            None => return Err(item),
+        };
+        let mut pushed = pushed.lock().unwrap_or_else(PoisonError::into_inner);
+        if pushed.ended || pushed.queue.len() >= pushed.capacity {
+            return Err(item);
+        }
+        pushed.push(item);
+        Ok(())
+    }
+
+    /// How many pushed items are waiting to be yielded
+    pub fn pending(&self) -> usize {
+        self.pushed.upgrade().map_or(0, |pushed| {
+            pushed
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .queue
+                .len()
+        })
+    }
+}
+
+impl<I> Pushed<I> {
+    fn push(&mut self, item: I) {
+        self.queue.push_back(item);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+}
+
+impl<I> Sink<I> for PushHandle<I> {
+    type Error = PushClosed;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PushClosed>> {
+        let pushed = self.pushed.upgrade().ok_or(PushClosed)?;
+        let mut pushed = pushed.lock().unwrap_or_else(PoisonError::into_inner);
+        if pushed.ended {
+            return Poll::Ready(Err(PushClosed));
+        }
+        if pushed.queue.len() < pushed.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        if !pushed
+            .writers
+            .iter()
+            .any(|writer| writer.will_wake(cx.waker()))
+        {
+            pushed.writers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// Pushes `item`, which may go over the capacity if another handle
+    /// pushed since `poll_ready` found room
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), PushClosed> {
+        let pushed = self.pushed.upgrade().ok_or(PushClosed)?;
+        let mut pushed = pushed.lock().unwrap_or_else(PoisonError::into_inner);
+        if pushed.ended {
+            return Err(PushClosed);
+        }
+        pushed.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), PushClosed>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), PushClosed>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I> Clone for PushHandle<I> {
+    fn clone(&self) -> Self {
+        Self {
+            pushed: Weak::clone(&self.pushed),
+        }
+    }
+}
+
+impl<I> fmt::Debug for PushHandle<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PushHandle")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+/// The error from sending into a `PushHandle` once the wrapped stream has
+/// ended or been dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushClosed;
+
+impl fmt::Display for PushClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pushed into a stream that has ended")
+    }
+}
+
+impl std::error::Error for PushClosed {}
+
+#[cfg(test)]
+mod test {
+    use futures::{FutureExt, SinkExt, StreamExt};
+
+    use super::pushable;
+
+    #[test]
+    fn test_push_waits_for_room() {
+        let (mut stream, mut handle) = pushable(futures::stream::pending::<i32>(), 1);
+        assert_eq!(Ok(()), handle.try_push(1));
+        assert_eq!(Err(2), handle.try_push(2));
+        let mut send = handle.send(2);
+        assert!((&mut send).now_or_never().is_none());
+        assert_eq!(Some(Some(1)), stream.next().now_or_never());
+        assert_eq!(Some(Ok(())), send.now_or_never());
+        assert_eq!(Some(Some(2)), stream.next().now_or_never());
+        drop(stream);
+        assert!(handle.send(3).now_or_never().unwrap().is_err());
+    }
+}
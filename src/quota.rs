@@ -0,0 +1,170 @@
+use crate::Side;
+
+/// Determines what happens to items routed to an output stream once it has
+/// received its quota, set with `set_quota` on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Send the items to the other stream instead, where they count towards
+    /// its quota if it has one. This is only possible when both streams have
+    /// the same item type, so the `split_by_map` variants treat it as `Drop`
+    Divert,
+    /// Drop the items
+    Drop,
+    /// End the stream as soon as it has yielded its quota, dropping any items
+    /// routed to it from then on
+    End,
+}
+
+/// A cap on the total number of items an output stream receives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    items: u64,
+    policy: QuotaPolicy,
+}
+
+impl Quota {
+    /// Lets the stream receive `items` before the rest are handled according
+    /// to `policy`
+    pub fn new(items: u64, policy: QuotaPolicy) -> Self {
+        Self { items, policy }
+    }
+}
+
+/// The quotas of both sides of a split
+#[derive(Default)]
+pub(crate) struct Quotas {
+    sides: [Option<Quota>; 2],
+    ended: [bool; 2],
+}
+
+impl Quotas {
+    /// Gives `side` a fresh quota, or removes its quota. A side that has
+    /// already ended stays ended
+    pub(crate) fn set(&mut self, side: Side, quota: Option<Quota>) {
+        if let Some(Quota {
+            items: 0,
+            policy: QuotaPolicy::End,
+        }) = quota
+        {
+            self.ended[side as usize] = true;
+        }
+        self.sides[side as usize] = quota;
+    }
+
+    /// How many more items `side` may receive, or `None` if it has no quota
+    pub(crate) fn remaining(&self, side: Side) -> Option<u64> {
+        self.sides[side as usize].as_ref().map(|quota| quota.items)
+    }
+
+    /// Whether `side` has ended because of its quota
+    pub(crate) fn ended(&self, side: Side) -> bool {
+        self.ended[side as usize]
+    }
+
+    /// Counts an item routed to `side` against its quota, returning the side
+    /// it should go to, which differs once `side` is over its quota, or
+    /// `None` if it should be dropped. `can_divert` is whether the item could
+    /// go to the other side
+    pub(crate) fn route(&mut self, side: Side, can_divert: bool) -> Option<Side> {
+        if self.ended[side as usize] {
+            return None;
+        }
+        let quota = match &mut self.sides[side as usize] {
+            Some(quota) => quota,
+            None => return Some(side),
+        };
+        if quota.items > 0 {
+            quota.items -= 1;
+            // With `End`, the side ends once it has yielded this item rather than
+            // waiting for the next one to find it over its quota
+            if quota.items == 0 && quota.policy == QuotaPolicy::End {
+                self.ended[side as usize] = true;
+            }
+            return Some(side);
+        }
+        match quota.policy {
+            QuotaPolicy::Divert if can_divert => {
+                let other = match side {
+                    Side::Left => Side::Right,
+                    Side::Right => Side::Left,
+                };
+                self.route(other, false)
+            }
+            QuotaPolicy::Divert | QuotaPolicy::Drop | QuotaPolicy::End => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::StreamExt;
+
+    use super::{Quota, QuotaPolicy, Quotas};
+    use crate::{test_util::CountingWaker, Side, SplitStreamByExt};
+
+    #[test]
+    fn test_end_after_last_item() {
+        let mut quotas = Quotas::default();
+        quotas.set(Side::Left, Some(Quota::new(2, QuotaPolicy::End)));
+        assert_eq!(Some(Side::Left), quotas.route(Side::Left, true));
+        assert!(!quotas.ended(Side::Left));
+        // The side ends with the last item it's allowed, not with the next one
+        assert_eq!(Some(Side::Left), quotas.route(Side::Left, true));
+        assert!(quotas.ended(Side::Left));
+        assert_eq!(None, quotas.route(Side::Left, true));
+        // Other sides aren't affected, and a fresh quota doesn't revive an ended side
+        assert_eq!(Some(Side::Right), quotas.route(Side::Right, true));
+        quotas.set(Side::Left, Some(Quota::new(5, QuotaPolicy::Drop)));
+        assert_eq!(None, quotas.route(Side::Left, true));
+    }
+
+    #[test]
+    fn test_zero_end_quota_ends_at_once() {
+        let mut quotas = Quotas::default();
+        quotas.set(Side::Right, Some(Quota::new(0, QuotaPolicy::End)));
+        assert!(quotas.ended(Side::Right));
+        assert_eq!(Some(0), quotas.remaining(Side::Right));
+        assert_eq!(None, quotas.remaining(Side::Left));
+    }
+
+    #[test]
+    fn test_divert_counts_against_other_quota() {
+        let mut quotas = Quotas::default();
+        quotas.set(Side::Left, Some(Quota::new(1, QuotaPolicy::Divert)));
+        quotas.set(Side::Right, Some(Quota::new(1, QuotaPolicy::Divert)));
+        assert_eq!(Some(Side::Left), quotas.route(Side::Left, true));
+        assert_eq!(Some(Side::Right), quotas.route(Side::Left, true));
+        assert_eq!(Some(0), quotas.remaining(Side::Right));
+        // Both are over, and an item isn't diverted back again
+        assert_eq!(None, quotas.route(Side::Left, true));
+        assert_eq!(None, quotas.route(Side::Right, true));
+    }
+
+    #[test]
+    fn test_divert_without_other_side_drops() {
+        let mut quotas = Quotas::default();
+        quotas.set(Side::Left, Some(Quota::new(0, QuotaPolicy::Divert)));
+        // As for the `split_by_map` variants, whose sides hold different types
+        assert_eq!(None, quotas.route(Side::Left, false));
+        assert_eq!(Some(0), quotas.remaining(Side::Left));
+    }
+
+    #[test]
+    fn test_end_quota_ends_stream_without_source() {
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let (_even_stream, mut odd_stream) = rx.split_by_buffered::<2>(|&n: &u32| n % 2 == 0);
+        odd_stream.set_quota(Some(Quota::new(1, QuotaPolicy::End)));
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, odd_stream.poll_next_unpin(&mut cx));
+        tx.unbounded_send(1).unwrap();
+        assert!(counter.wakes() > 0);
+        assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+        // The source is still open, but the stream is done
+        assert_eq!(Poll::Ready(None), odd_stream.poll_next_unpin(&mut cx));
+        assert_eq!(Some(0), odd_stream.quota_remaining());
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    delay::{Delay, Instant, Timer},
+    Side,
+};
+
+/// A token bucket limit on how fast an output stream yields items, set with
+/// `set_rate_limit` on it. Up to `items` can be yielded in a burst, and the
+/// allowance refills evenly over each `per`. While a stream is held back its
+/// items stay buffered, and once its buffer is full the other stream waits as
+/// well, so the limit holds back the underlying stream rather than letting
+/// items pile up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    items: u32,
+    per: Duration,
+}
+
+impl RateLimit {
+    /// Allows `items` to be yielded per `per`. Panics if either is zero
+    pub fn new(items: u32, per: Duration) -> Self {
+        assert!(items > 0, "a rate limit has to allow at least one item");
+        assert!(!per.is_zero(), "a rate limit needs a non-zero period");
+        Self { items, per }
+    }
+
+    /// Allows `items` to be yielded per second
+    pub fn per_second(items: u32) -> Self {
+        Self::new(items, Duration::from_secs(1))
+    }
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+    timer: Option<Timer>,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Timer::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        let rate = f64::from(self.limit.items) / self.limit.per.as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.items));
+        self.refilled = now;
+    }
+}
+
+/// The token buckets of both sides of a split
+#[derive(Default)]
+pub(crate) struct RateLimits {
+    buckets: [Option<Bucket>; 2],
+}
+
+impl RateLimits {
+    /// Limits `side` to `limit`, starting with a full bucket, or removes its
+    /// limit
+    pub(crate) fn set(&mut self, side: Side, limit: Option<RateLimit>) {
+        self.buckets[side as usize] = limit.map(|limit| Bucket {
+            limit,
+            tokens: f64::from(limit.items),
+            refilled: Timer::now(),
+            timer: None,
+        });
+    }
+
+    /// Returns `Poll::Ready` if `side` is allowed to yield an item now.
+    /// Otherwise the task in `cx` is woken once it is
+    pub(crate) fn poll_ready(&mut self, side: Side, cx: &mut Context<'_>) -> Poll<()> {
+        let bucket = match &mut self.buckets[side as usize] {
+            Some(bucket) => bucket,
+            None => return Poll::Ready(()),
+        };
+        bucket.refill();
+        if bucket.tokens >= 1.0 {
+            bucket.timer = None;
+            return Poll::Ready(());
+        }
+        let rate = f64::from(bucket.limit.items) / bucket.limit.per.as_secs_f64();
+        let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / rate);
+        let timer = bucket
+            .timer
+            .get_or_insert_with(|| <Timer as Delay>::new(wait));
+        Delay::reset(timer, wait);
+        if Pin::new(timer).poll(cx).is_ready() {
+            // The wait was too short for the timer, so just try again
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+
+    /// Uses up some of the allowance of `side` for an item it just yielded
+    pub(crate) fn take(&mut self, side: Side) {
+        if let Some(bucket) = &mut self.buckets[side as usize] {
+            bucket.tokens -= 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{RateLimit, RateLimits};
+    use crate::{Side, SplitStreamByExt};
+
+    const PER: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limits = RateLimits::default();
+        limits.set(Side::Left, Some(RateLimit::new(3, PER)));
+        // The timers need a runtime with the tokio backend
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut cx = Context::from_waker(noop_waker_ref());
+            for _ in 0..3 {
+                assert_eq!(Poll::Ready(()), limits.poll_ready(Side::Left, &mut cx));
+                limits.take(Side::Left);
+            }
+            assert_eq!(Poll::Pending, limits.poll_ready(Side::Left, &mut cx));
+            // The other side has no limit
+            assert_eq!(Poll::Ready(()), limits.poll_ready(Side::Right, &mut cx));
+            tokio::time::sleep(PER / 2).await;
+            assert_eq!(Poll::Ready(()), limits.poll_ready(Side::Left, &mut cx));
+            // Removing the limit lets everything through
+            limits.set(Side::Left, None);
+            for _ in 0..10 {
+                limits.take(Side::Left);
+                assert_eq!(Poll::Ready(()), limits.poll_ready(Side::Left, &mut cx));
+            }
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one item")]
+    fn test_zero_items() {
+        RateLimit::new(0, PER);
+    }
+
+    #[test]
+    fn test_timer_wakes_held_back_side() {
+        let incoming_stream = futures::stream::iter([0, 2, 4]);
+        let (mut even_stream, odd_stream) = incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+        drop(odd_stream);
+        even_stream.set_rate_limit(Some(RateLimit::new(1, PER)));
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let start = Instant::now();
+            assert_eq!(Some(0), even_stream.next().await);
+            let mut cx = Context::from_waker(noop_waker_ref());
+            assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+            // The timer set by the last poll is what brings the next item
+            assert_eq!(Some(2), even_stream.next().await);
+            assert!(start.elapsed() >= PER / 2);
+        });
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::delay::{Delay, Instant, Timer};
+
+/// Where retried items go relative to the items of the underlying stream, set
+/// when calling `retryable`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// A retry that's due is yielded before the next item of the underlying
+    /// stream
+    Ahead,
+    /// A retry that's due is only yielded once the underlying stream has
+    /// nothing ready, so retries never hold up live items but can be put off
+    /// for as long as it keeps them coming
+    Behind,
+}
+
+/// A retried item waiting for its delay to pass. Retries are ordered by when
+/// they're due and then by when they were made
+struct Retry<I> {
+    due: Instant,
+    seq: u64,
+    item: I,
+}
+
+impl<I> PartialEq for Retry<I> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl<I> Eq for Retry<I> {}
+
+impl<I> PartialOrd for Retry<I> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I> Ord for Retry<I> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+struct Retries<I> {
+    queue: BinaryHeap<Reverse<Retry<I>>>,
+    seq: u64,
+    ended: bool,
+    waker: Option<Waker>,
+}
+
+/// Wraps `stream` so that items can be fed back into it with a
+/// `RetryHandle`, to be yielded again once a delay has passed. Splitting the
+/// wrapped stream and holding on to the handle on the side that handles
+/// failures gives a retry lane: items that fail are retried through the
+/// predicate as if they'd just arrived, merged with the underlying stream per
+/// `policy`.
+///
+/// The wrapped stream ends once the underlying stream has ended and there are
+/// no retries waiting, after which retrying hands the item back
+///
+///```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use split_stream_by::{retryable, RetryPolicy, SplitStreamByExt};
+///
+/// let (incoming_stream, retry) = retryable(futures::stream::iter([1, 2, 3]), RetryPolicy::Ahead);
+/// let (mut ok_stream, mut failed_stream) = incoming_stream.split_by(|&n| n != 2);
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     assert_eq!(Some(1), ok_stream.next().await);
+///     let failed = failed_stream.next().await.unwrap();
+///     assert_eq!(Ok(()), retry.retry(failed + 10, Duration::from_millis(10)));
+///     assert_eq!(Some(3), ok_stream.next().await);
+///     assert_eq!(Some(12), ok_stream.next().await);
+///     assert_eq!(None, ok_stream.next().await);
+/// });
+/// ```
+pub fn retryable<S: Stream>(
+    stream: S,
+    policy: RetryPolicy,
+) -> (Retryable<S>, RetryHandle<S::Item>) {
+    let retries = Arc::new(Mutex::new(Retries {
+        queue: BinaryHeap::new(),
+        seq: 0,
+        ended: false,
+        waker: None,
+    }));
+    let handle = RetryHandle {
+        retries: Arc::downgrade(&retries),
+    };
+    let stream = Retryable {
+        stream: Box::pin(stream),
+        policy,
+        retries,
+        stream_ended: false,
+        timer: None,
+    };
+    (stream, handle)
+}
+
+/// A stream wrapped by `retryable`, yielding its items along with those fed
+/// back through the `RetryHandle`
+pub struct Retryable<S: Stream> {
+    stream: Pin<Box<S>>,
+    policy: RetryPolicy,
+    retries: Arc<Mutex<Retries<S::Item>>>,
+    stream_ended: bool,
+    timer: Option<Timer>,
+}
+
+impl<S: Stream> Retryable<S> {
+    /// Polls the underlying stream unless it has ended
+    fn poll_stream(&mut self, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        if self.stream_ended {
+            return Poll::Ready(None);
+        }
+        let poll = self.stream.as_mut().poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            self.stream_ended = true;
+        }
+        poll
+    }
+}
+
+impl<S: Stream> Stream for Retryable<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        if this.policy == RetryPolicy::Behind {
+            if let Poll::Ready(Some(item)) = this.poll_stream(cx) {
+                return Poll::Ready(Some(item));
+            }
+        }
+        loop {
+            let mut retries = this.retries.lock().unwrap_or_else(PoisonError::into_inner);
+            if retries.ended {
+                return Poll::Ready(None);
+            }
+            let now = Timer::now();
+            let next_due = retries.queue.peek().map(|Reverse(retry)| retry.due);
+            if next_due.is_some_and(|due| due <= now) {
+                let Reverse(retry) = retries.queue.pop().expect("there's a retry due");
+                return Poll::Ready(Some(retry.item));
+            }
+            // A retry made from now on wakes this stream, since it may be due
+            // before anything else
+            retries.waker = Some(cx.waker().clone());
+            drop(retries);
+            if this.policy == RetryPolicy::Ahead {
+                if let Poll::Ready(Some(item)) = this.poll_stream(cx) {
+                    return Poll::Ready(Some(item));
+                }
+            }
+            let due = match next_due {
+                Some(due) => due,
+                None if this.stream_ended => {
+                    let mut retries = this.retries.lock().unwrap_or_else(PoisonError::into_inner);
+                    if !retries.queue.is_empty() {
+                        // A retry came in since the queue was looked at
+                        continue;
+                    }
+                    retries.ended = true;
+                    return Poll::Ready(None);
+                }
+                None => return Poll::Pending,
+            };
+            let wait = due.saturating_duration_since(now);
+            let timer = match &mut this.timer {
+                Some(timer) => {
+                    Delay::reset(timer, wait);
+                    timer
+                }
+                timer => timer.insert(<Timer as Delay>::new(wait)),
+            };
+            if Pin::new(timer).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<S: Stream> FusedStream for Retryable<S> {
+    fn is_terminated(&self) -> bool {
+        self.retries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ended
+    }
+}
+
+impl<S: Stream> fmt::Debug for Retryable<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let retries = self.retries.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Retryable")
+            .field("policy", &self.policy)
+            .field("stream_ended", &self.stream_ended)
+            .field("retries", &retries.queue.len())
+            .finish()
+    }
+}
+
+/// A handle for feeding items back into a stream wrapped by `retryable`. It
+/// doesn't keep the stream alive
+pub struct RetryHandle<I> {
+    retries: Weak<Mutex<Retries<I>>>,
+}
+
+impl<I> RetryHandle<I> {
+    /// Has the wrapped stream yield `item` again once `after` has passed. The
+    /// item is handed back if the wrapped stream has ended or been dropped
+    pub fn retry(&self, item: I, after: Duration) -> Result<(), I> {
+        let retries = match self.retries.upgrade() {
+            Some(retries) => retries,
+            None => return Err(item),
+        };
+        let mut retries = retries.lock().unwrap_or_else(PoisonError::into_inner);
+        if retries.ended {
+            return Err(item);
+        }
+        let seq = retries.seq;
+        retries.seq += 1;
+        retries.queue.push(Reverse(Retry {
+            due: Timer::now() + after,
+            seq,
+            item,
+        }));
+        if let Some(waker) = retries.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// How many retries are waiting to be yielded
+    pub fn pending(&self) -> usize {
+        self.retries.upgrade().map_or(0, |retries| {
+            retries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .queue
+                .len()
+        })
+    }
+}
+
+impl<I> Clone for RetryHandle<I> {
+    fn clone(&self) -> Self {
+        Self {
+            retries: Weak::clone(&self.retries),
+        }
+    }
+}
+
+impl<I> fmt::Debug for RetryHandle<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryHandle")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::{retryable, RetryPolicy};
+
+    #[test]
+    fn test_retry_policy() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (stream, retry) = retryable(futures::stream::iter([1, 2]), RetryPolicy::Ahead);
+            assert_eq!(Ok(()), retry.retry(0, Duration::ZERO));
+            assert_eq!(vec![0, 1, 2], stream.collect::<Vec<_>>().await);
+            assert_eq!(Err(3), retry.retry(3, Duration::ZERO));
+
+            let (stream, retry) = retryable(futures::stream::iter([1, 2]), RetryPolicy::Behind);
+            assert_eq!(Ok(()), retry.retry(0, Duration::ZERO));
+            assert_eq!(Ok(()), retry.retry(3, Duration::from_millis(20)));
+            assert_eq!(2, retry.pending());
+            assert_eq!(vec![1, 2, 0, 3], stream.collect::<Vec<_>>().await);
+        })
+    }
+}
@@ -1,13 +1,36 @@
-use std::mem::MaybeUninit;
+use std::{fmt, mem::MaybeUninit, ptr};
 
-pub(crate) struct RingBuf<T, const N: usize> {
+/// A fixed capacity FIFO queue holding up to `N` items inline, without
+/// allocating. This is the buffer each side of the buffered splits uses, made
+/// available for building other splitters on the same model
+///
+///```rust
+/// use split_stream_by::RingBuf;
+///
+/// let mut buf = RingBuf::<_, 2>::new();
+/// assert_eq!(None, buf.push_back(1));
+/// assert_eq!(None, buf.push_back(2));
+/// // A full buffer hands the item back
+/// assert_eq!(Some(3), buf.push_back(3));
+/// assert_eq!(vec![&1, &2], buf.iter().collect::<Vec<_>>());
+/// assert_eq!(Some(1), buf.pop_front());
+/// ```
+pub struct RingBuf<T, const N: usize> {
     index: usize,
     count: usize,
     data: [MaybeUninit<T>; N],
 }
 
 impl<T, const N: usize> RingBuf<T, N> {
-    pub(crate) fn new() -> Self {
+    // A zero sized buffer could never hold an item for the other side, so the
+    // buffered splits would stall on the first item routed across. Referencing
+    // this in `new` turns that into a compile time error
+    const NON_ZERO: () = assert!(N > 0, "buffered splits need a buffer size of at least 1");
+
+    /// Creates an empty buffer. A buffer size of 0 is a compile time error
+    pub fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NON_ZERO;
         Self {
             index: 0,
             count: 0,
@@ -18,11 +41,30 @@ impl<T, const N: usize> RingBuf<T, N> {
         }
     }
 
-    pub(crate) fn remaining(&self) -> usize {
+    /// How many more items fit in the buffer
+    pub fn remaining(&self) -> usize {
         N - self.count
     }
 
-    pub(crate) fn push_back(&mut self, item: T) -> Option<T> {
+    /// How many items are in the buffer
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether the buffer holds no items
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Whether the buffer holds `N` items, so that `push_back` would hand
+    /// the next one back
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Adds `item` to the back of the buffer, handing it back if the buffer is
+    /// full
+    pub fn push_back(&mut self, item: T) -> Option<T> {
         if self.remaining() > 0 {
             let ptr = self.data[(self.index + self.count) % N].as_mut_ptr();
             // This is safe because there is space available so self.data[self.tail] points
@@ -35,25 +77,182 @@ impl<T, const N: usize> RingBuf<T, N> {
         }
     }
 
-    pub(crate) fn pop_front(&mut self) -> Option<T> {
+    /// Adds `item` to the back of the buffer, making room for it if the buffer
+    /// is full by evicting the oldest item, which is returned
+    pub fn force_push_back(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            let rejected = self.push_back(item);
+            debug_assert!(rejected.is_none());
+            return None;
+        }
+        // When the buffer is full the back of it is the slot the oldest item is in, so
+        // the new item takes its place and the front moves on by one
+        let slot = &mut self.data[self.index];
+        // This is safe because the buffer is full so the slot holds an item, which is
+        // replaced straight away
+        let evicted = unsafe { slot.as_ptr().read() };
+        slot.write(item);
+        self.index += 1;
+        if self.index == N {
+            self.index = 0;
+        }
+        Some(evicted)
+    }
+
+    /// Takes the oldest item out of the buffer
+    pub fn pop_front(&mut self) -> Option<T> {
         if self.count > 0 {
             let ptr = self.data[self.index].as_mut_ptr();
             // This is safe because there are items in the buffer so self.data[self.head]
             // points to a value
             let item = unsafe { ptr.read() };
-            self.index = (self.index + 1) % N;
-            self.count -= 1;
+            self.advance(1);
             Some(item)
         } else {
             None
         }
     }
+
+    /// Returns the oldest item without taking it out of the buffer
+    pub fn peek_front(&self) -> Option<&T> {
+        // This is safe because there are items in the buffer so self.data[self.index]
+        // points to a value
+        (self.count > 0).then(|| unsafe { &*self.data[self.index].as_ptr() })
+    }
+
+    /// Returns the newest item without taking it out of the buffer
+    pub fn peek_back(&self) -> Option<&T> {
+        // This is safe because there are items in the buffer so the slot before the
+        // back points to a value
+        (self.count > 0).then(|| unsafe { &*self.data[(self.index + self.count - 1) % N].as_ptr() })
+    }
+
+    /// Forgets the `n` oldest items, which must already have been moved out
+    fn advance(&mut self, n: usize) {
+        debug_assert!(n <= self.count);
+        self.index += n;
+        if self.index >= N {
+            self.index -= N;
+        }
+        self.count -= n;
+    }
+
+    /// Pushes items from `iter` until it runs out or the buffer is full,
+    /// returning how many were pushed. Whatever didn't fit is left in `iter`
+    pub fn extend_from_iter(&mut self, iter: &mut impl Iterator<Item = T>) -> usize {
+        let mut pushed = 0;
+        while self.remaining() > 0 {
+            match iter.next() {
+                Some(item) => {
+                    let rejected = self.push_back(item);
+                    debug_assert!(rejected.is_none());
+                    pushed += 1;
+                }
+                None => break,
+            }
+        }
+        pushed
+    }
+
+    /// Moves up to `n` of the oldest items to the end of `out`, returning how
+    /// many were moved. The items are copied a contiguous run at a time rather
+    /// than popped one by one
+    pub fn pop_many(&mut self, out: &mut Vec<T>, n: usize) -> usize {
+        let n = n.min(self.count);
+        out.reserve(n);
+        let first = n.min(N - self.index);
+        // This is safe because the first `n` items from `index` are initialized, and
+        // are forgotten by `advance` straight after being moved into `out`, which has
+        // room for them. Both source pointers are derived from the whole array, since
+        // a pointer to a single element may only be used to read that element
+        unsafe {
+            let dst = out.as_mut_ptr().add(out.len());
+            let src = self.data.as_ptr().cast::<T>();
+            ptr::copy_nonoverlapping(src.add(self.index), dst, first);
+            ptr::copy_nonoverlapping(src, dst.add(first), n - first);
+            out.set_len(out.len() + n);
+        }
+        self.advance(n);
+        n
+    }
+
+    /// Returns an iterator that takes every item out of the buffer, oldest
+    /// first. Any items the iterator doesn't get to are dropped along with it
+    pub fn drain(&mut self) -> Drain<'_, T, N> {
+        Drain { buf: self }
+    }
+
+    /// Drops every item in the buffer
+    pub fn clear(&mut self) {
+        // pop_front reads values from MaybeUninit which will then run its drop code
+        while self.pop_front().is_some() {}
+    }
+
+    /// Returns the items in the buffer, oldest first, as two slices. The
+    /// second one is only non-empty when the items wrap around the end of the
+    /// storage
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let end = self.index + self.count;
+        let front = &self.data[self.index..end.min(N)];
+        let back = &self.data[..end.saturating_sub(N)];
+        // This is safe because the slices only cover the items in the buffer, which are
+        // initialized, and `MaybeUninit<T>` has the same layout as `T`
+        unsafe {
+            (
+                &*(front as *const [MaybeUninit<T>] as *const [T]),
+                &*(back as *const [MaybeUninit<T>] as *const [T]),
+            )
+        }
+    }
+
+    /// Iterates over the items in the buffer, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        let (front, back) = self.as_slices();
+        front.iter().chain(back)
+    }
+}
+
+/// An iterator taking every item out of a `RingBuf`, returned by
+/// `RingBuf::drain`
+pub struct Drain<'a, T, const N: usize> {
+    buf: &'a mut RingBuf<T, N>,
+}
+
+impl<T, const N: usize> Iterator for Drain<'_, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.buf.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buf.len(), Some(self.buf.len()))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for Drain<'_, T, N> {}
+
+impl<T, const N: usize> Drop for Drain<'_, T, N> {
+    fn drop(&mut self) {
+        self.buf.clear();
+    }
+}
+
+impl<T, const N: usize> Default for RingBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for RingBuf<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
 }
 
 impl<T, const N: usize> Drop for RingBuf<T, N> {
     fn drop(&mut self) {
-        // pop_front reads values from MaybeUninit which will then run its drop code
-        while let Some(_) = self.pop_front() {}
+        self.clear()
     }
 }
 
@@ -81,4 +280,54 @@ mod test {
         assert_eq!(buf.pop_front(), Some(3));
         assert_eq!(buf.pop_front(), None);
     }
+
+    #[test]
+    fn test_as_slices_wrapped() {
+        let mut buf = RingBuf::<_, 3>::new();
+        buf.push_back(1);
+        buf.push_back(2);
+        buf.pop_front();
+        buf.push_back(3);
+        buf.push_back(4);
+        assert_eq!((&[2, 3][..], &[4][..]), buf.as_slices());
+        assert_eq!(vec![&2, &3, &4], buf.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut buf = RingBuf::<_, 2>::new();
+        assert_eq!((None, None), (buf.peek_front(), buf.peek_back()));
+        buf.push_back(1);
+        buf.push_back(2);
+        buf.pop_front();
+        buf.push_back(3);
+        assert!(buf.is_full());
+        assert_eq!((Some(&2), Some(&3)), (buf.peek_front(), buf.peek_back()));
+    }
+
+    #[test]
+    fn test_force_push_back() {
+        let mut buf = RingBuf::<_, 2>::new();
+        assert_eq!(None, buf.force_push_back(1));
+        assert_eq!(None, buf.force_push_back(2));
+        assert_eq!(Some(1), buf.force_push_back(3));
+        assert_eq!(Some(2), buf.force_push_back(4));
+        assert_eq!(vec![&3, &4], buf.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bulk() {
+        let mut buf = RingBuf::<_, 3>::new();
+        let mut items = (0..5).map(|n| n.to_string());
+        assert_eq!(3, buf.extend_from_iter(&mut items));
+        let mut out = Vec::new();
+        assert_eq!(2, buf.pop_many(&mut out, 2));
+        // The next items wrap around the end of the storage
+        assert_eq!(2, buf.extend_from_iter(&mut items));
+        assert_eq!(3, buf.pop_many(&mut out, 5));
+        assert_eq!(vec!["0", "1", "2", "3", "4"], out);
+        buf.extend_from_iter(&mut (5..7).map(|n| n.to_string()));
+        assert_eq!(vec!["5", "6"], buf.drain().collect::<Vec<_>>());
+        assert!(buf.is_empty());
+    }
 }
@@ -0,0 +1,202 @@
+use std::{collections::VecDeque, fmt};
+
+/// A fixed capacity FIFO queue holding up to `N` items. This is the buffer
+/// each side of the buffered splits uses, made available for building other
+/// splitters on the same model
+///
+/// With the `safe` feature this is built on a `VecDeque` allocated once with
+/// room for `N` items, so the crate has no unsafe code at all
+///
+///```rust
+/// use split_stream_by::RingBuf;
+///
+/// let mut buf = RingBuf::<_, 2>::new();
+/// assert_eq!(None, buf.push_back(1));
+/// assert_eq!(None, buf.push_back(2));
+/// // A full buffer hands the item back
+/// assert_eq!(Some(3), buf.push_back(3));
+/// assert_eq!(vec![&1, &2], buf.iter().collect::<Vec<_>>());
+/// assert_eq!(Some(1), buf.pop_front());
+/// ```
+pub struct RingBuf<T, const N: usize> {
+    items: VecDeque<T>,
+}
+
+impl<T, const N: usize> RingBuf<T, N> {
+    // A zero sized buffer could never hold an item for the other side, so the
+    // buffered splits would stall on the first item routed across. Referencing
+    // this in `new` turns that into a compile time error
+    const NON_ZERO: () = assert!(N > 0, "buffered splits need a buffer size of at least 1");
+
+    /// Creates an empty buffer. A buffer size of 0 is a compile time error
+    pub fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NON_ZERO;
+        Self {
+            items: VecDeque::with_capacity(N),
+        }
+    }
+
+    /// How many more items fit in the buffer
+    pub fn remaining(&self) -> usize {
+        N - self.items.len()
+    }
+
+    /// How many items are in the buffer
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether the buffer holds no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Whether the buffer holds `N` items, so that `push_back` would hand
+    /// the next one back
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Adds `item` to the back of the buffer, handing it back if the buffer is
+    /// full
+    pub fn push_back(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            self.items.push_back(item);
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Adds `item` to the back of the buffer, making room for it if the buffer
+    /// is full by evicting the oldest item, which is returned
+    pub fn force_push_back(&mut self, item: T) -> Option<T> {
+        let evicted = if self.remaining() > 0 {
+            None
+        } else {
+            self.items.pop_front()
+        };
+        self.items.push_back(item);
+        evicted
+    }
+
+    /// Takes the oldest item out of the buffer
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    /// Returns the oldest item without taking it out of the buffer
+    pub fn peek_front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    /// Returns the newest item without taking it out of the buffer
+    pub fn peek_back(&self) -> Option<&T> {
+        self.items.back()
+    }
+
+    /// Pushes items from `iter` until it runs out or the buffer is full,
+    /// returning how many were pushed. Whatever didn't fit is left in `iter`
+    pub fn extend_from_iter(&mut self, iter: &mut impl Iterator<Item = T>) -> usize {
+        let pushed = self.remaining();
+        self.items.extend(iter.take(pushed));
+        pushed - self.remaining()
+    }
+
+    /// Moves up to `n` of the oldest items to the end of `out`, returning how
+    /// many were moved
+    pub fn pop_many(&mut self, out: &mut Vec<T>, n: usize) -> usize {
+        let n = n.min(self.items.len());
+        out.extend(self.items.drain(..n));
+        n
+    }
+
+    /// Returns an iterator that takes every item out of the buffer, oldest
+    /// first. Any items the iterator doesn't get to are dropped along with it
+    pub fn drain(&mut self) -> Drain<'_, T, N> {
+        Drain { buf: self }
+    }
+
+    /// Drops every item in the buffer
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Returns the items in the buffer, oldest first, as two slices. The
+    /// second one is only non-empty when the items wrap around the end of the
+    /// storage
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.items.as_slices()
+    }
+
+    /// Iterates over the items in the buffer, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.items.iter()
+    }
+}
+
+/// An iterator taking every item out of a `RingBuf`, returned by
+/// `RingBuf::drain`
+pub struct Drain<'a, T, const N: usize> {
+    buf: &'a mut RingBuf<T, N>,
+}
+
+impl<T, const N: usize> Iterator for Drain<'_, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.buf.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buf.len(), Some(self.buf.len()))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for Drain<'_, T, N> {}
+
+impl<T, const N: usize> Drop for Drain<'_, T, N> {
+    fn drop(&mut self) {
+        self.buf.clear();
+    }
+}
+
+impl<T, const N: usize> Default for RingBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for RingBuf<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_force_push_back() {
+        let mut buf = RingBuf::<_, 2>::new();
+        assert_eq!(None, buf.force_push_back(1));
+        assert_eq!(None, buf.force_push_back(2));
+        assert_eq!(Some(1), buf.force_push_back(3));
+        assert_eq!(vec![&2, &3], buf.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bulk() {
+        let mut buf = RingBuf::<_, 3>::new();
+        let mut items = (0..5).map(|n| n.to_string());
+        assert_eq!(3, buf.extend_from_iter(&mut items));
+        let mut out = Vec::new();
+        assert_eq!(2, buf.pop_many(&mut out, 2));
+        assert_eq!(2, buf.extend_from_iter(&mut items));
+        assert_eq!(3, buf.pop_many(&mut out, 5));
+        assert_eq!(vec!["0", "1", "2", "3", "4"], out);
+        assert!(buf.is_empty());
+    }
+}
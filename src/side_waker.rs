@@ -0,0 +1,177 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::Waker,
+};
+
+use crate::{stats::Counters, trace::trace_event, WakeStrategy};
+
+/// Returns an id for a new handle to an output stream, used to tell apart
+/// the wakers of clones of the same stream. 0 is never returned, and is left
+/// for polling the shared state directly
+pub(crate) fn next_consumer_id() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The wakers for one side of a split, one for each handle to its output
+/// stream that has polled it. Waking a side that hasn't registered a waker yet
+/// is remembered, so a wake can't be lost in the window before the side is
+/// first polled
+pub(crate) struct SideWaker {
+    wakers: Vec<(usize, Waker)>,
+    wake_pending: bool,
+    /// Decides whether to wake for a buffered item straight away, which
+    /// always happens without one
+    strategy: Option<Box<dyn WakeStrategy>>,
+    /// Set when a wake for a buffered item has been held back by `strategy`
+    held_back: bool,
+    /// Where wakes are counted, once stats have been asked for
+    stats: Option<Arc<Counters>>,
+    #[cfg(feature = "tracing")]
+    side: &'static str,
+}
+
+impl SideWaker {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn new(side: &'static str) -> Self {
+        Self {
+            wakers: Vec::new(),
+            wake_pending: false,
+            strategy: None,
+            held_back: false,
+            stats: None,
+            #[cfg(feature = "tracing")]
+            side,
+        }
+    }
+
+    /// Counts wakes in `stats` from now on
+    pub(crate) fn count_wakes(&mut self, stats: Arc<Counters>) {
+        self.stats = Some(stats);
+    }
+
+    /// Whether a task has registered to be woken for this side
+    pub(crate) fn is_registered(&self) -> bool {
+        !self.wakers.is_empty()
+    }
+
+    /// Stores the waker of the task currently polling this side through
+    /// `consumer`, replacing the previous one for `consumer` if it belonged to a
+    /// different task so that wakes never go to a stale task
+    pub(crate) fn register(&mut self, consumer: usize, waker: &Waker) {
+        match self.wakers.iter_mut().find(|(id, _)| *id == consumer) {
+            Some((_, current)) if current.will_wake(waker) => {}
+            Some((_, current)) => *current = waker.clone(),
+            None => self.wakers.push((consumer, waker.clone())),
+        }
+        if self.wake_pending {
+            // Someone tried to wake this side before it had a waker. The poll that's
+            // registering will most likely see whatever prompted the wake, but wake
+            // again so the task is polled once more in case it doesn't
+            self.wake_pending = false;
+            waker.wake_by_ref();
+        }
+    }
+
+    pub(crate) fn set_strategy(&mut self, strategy: Box<dyn WakeStrategy>) {
+        self.strategy = Some(strategy);
+    }
+
+    /// Wakes this side for an item that has just been buffered for it, with
+    /// `buffered` items now waiting, unless the wake strategy holds the wake
+    /// back. Returns whether it woke, and otherwise `flush` has to be called
+    /// once the splitter stops reading
+    pub(crate) fn wake_buffered(&mut self, buffered: usize) -> bool {
+        let now = self
+            .strategy
+            .as_mut()
+            .is_none_or(|strategy| strategy.wake_now(buffered));
+        if now {
+            self.wake();
+        } else {
+            self.held_back = true;
+        }
+        now
+    }
+
+    /// Wakes this side if a wake was held back by `wake_buffered`
+    pub(crate) fn flush(&mut self) {
+        if self.held_back {
+            self.wake();
+        }
+    }
+
+    /// Forgets the waker of `consumer`, which has been dropped
+    pub(crate) fn unregister(&mut self, consumer: usize) {
+        self.wakers.retain(|(id, _)| *id != consumer);
+    }
+
+    /// Wakes every task that has polled this side, since with several handles
+    /// to its stream there's no telling which of them will take the next item
+    pub(crate) fn wake(&mut self) {
+        self.held_back = false;
+        if let Some(stats) = &self.stats {
+            stats.woke();
+        }
+        if self.wakers.is_empty() {
+            trace_event!(side = self.side, "side woken before registering a waker");
+            self.wake_pending = true;
+        } else {
+            trace_event!(side = self.side, "side woken");
+            #[cfg(feature = "test-fault")]
+            if crate::fault::wake(&self.wakers) {
+                return;
+            }
+            for (_, waker) in &self.wakers {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn test_wake_before_register() {
+        let count = CountingWaker::new();
+        let waker = count.waker();
+        let mut side = SideWaker::new("test");
+        side.wake();
+        side.register(0, &waker);
+        assert_eq!(1, count.wakes());
+        side.register(0, &waker);
+        assert_eq!(1, count.wakes());
+    }
+
+    #[test]
+    fn test_register_replaces_stale_waker() {
+        let stale = CountingWaker::new();
+        let fresh = CountingWaker::new();
+        let mut side = SideWaker::new("test");
+        side.register(0, &stale.waker());
+        side.register(0, &fresh.waker());
+        side.wake();
+        assert_eq!(0, stale.wakes());
+        assert_eq!(1, fresh.wakes());
+    }
+
+    #[test]
+    fn test_wake_all_consumers() {
+        let first = CountingWaker::new();
+        let second = CountingWaker::new();
+        let mut side = SideWaker::new("test");
+        side.register(1, &first.waker());
+        side.register(2, &second.waker());
+        side.wake();
+        side.unregister(1);
+        side.wake();
+        assert_eq!(1, first.wakes());
+        assert_eq!(2, second.wakes());
+    }
+}
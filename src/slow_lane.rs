@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How far one key has got through its allowance
+struct KeyRate {
+    tokens: f64,
+    refilled: Instant,
+}
+
+struct Lanes<K> {
+    items: u32,
+    per: Duration,
+    keys: HashMap<K, KeyRate>,
+    /// How many keys were tracked after idle ones were last let go of
+    swept: usize,
+}
+
+impl<K: Hash + Eq> Lanes<K> {
+    fn rate(&self) -> f64 {
+        f64::from(self.items) / self.per.as_secs_f64()
+    }
+
+    /// Refills `key`'s allowance for the time since it last sent an item
+    fn refill(rate: f64, items: u32, key_rate: &mut KeyRate, now: Instant) {
+        let elapsed = now.duration_since(key_rate.refilled).as_secs_f64();
+        key_rate.tokens = (key_rate.tokens + elapsed * rate).min(f64::from(items));
+        key_rate.refilled = now;
+    }
+
+    /// Takes one item from `key`'s allowance, returning whether it had any
+    /// left
+    fn take(&mut self, key: K) -> bool {
+        let now = Instant::now();
+        if self.keys.len() >= 2 * self.swept.max(16) {
+            self.sweep(now);
+        }
+        let (rate, items) = (self.rate(), self.items);
+        let key_rate = self.keys.entry(key).or_insert(KeyRate {
+            tokens: f64::from(items),
+            refilled: now,
+        });
+        Self::refill(rate, items, key_rate, now);
+        if key_rate.tokens >= 1.0 {
+            key_rate.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Lets go of the keys that have been quiet long enough to have their
+    /// whole allowance back, which is where a new key starts anyway
+    fn sweep(&mut self, now: Instant) {
+        let (rate, items) = (self.rate(), self.items);
+        self.keys.retain(|_, key_rate| {
+            Self::refill(rate, items, key_rate, now);
+            key_rate.tokens < f64::from(items)
+        });
+        self.swept = self.keys.len();
+    }
+}
+
+/// A control handle for a split that isolates noisy keys, e.g. tenants, by
+/// sending the items of any key that goes over its rate to a slow lane.
+/// `new` returns the handle along with a predicate to split with, which
+/// sends items to the first stream while their key is within `items` per
+/// `per` and to the second once it's over. Each key's allowance refills
+/// evenly over time, so a key that quiets down moves back to the first
+/// stream, and keys are only tracked while they're using their allowance
+///
+///```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use split_stream_by::{SlowLane, SplitStreamByExt};
+///
+/// let (slow_lane, predicate) = SlowLane::new(2, Duration::from_secs(60), |&(tenant, _): &(char, i32)| tenant);
+/// let incoming_stream = futures::stream::iter([('a', 0), ('a', 1), ('b', 2), ('a', 3)]);
+/// let (normal, slow) = incoming_stream.split_by(predicate);
+/// futures::executor::block_on(async {
+///     let (normal, slow) = futures::join!(normal.collect::<Vec<_>>(), slow.collect::<Vec<_>>());
+///     assert_eq!(vec![('a', 0), ('a', 1), ('b', 2)], normal);
+///     assert_eq!(vec![('a', 3)], slow);
+/// });
+/// assert!(slow_lane.is_slow(&'a'));
+/// ```
+pub struct SlowLane<K> {
+    lanes: Arc<Mutex<Lanes<K>>>,
+}
+
+impl<K> SlowLane<K>
+where
+    K: Hash + Eq + Send + 'static,
+{
+    /// Creates a handle allowing each key `items` per `per`, and the
+    /// predicate to pass to `split_by` that routes by it, finding each item's
+    /// key with `key`. Panics if either is zero
+    pub fn new<I>(
+        items: u32,
+        per: Duration,
+        key: impl Fn(&I) -> K + Send + Sync,
+    ) -> (Self, impl Fn(&I) -> bool + Send + Sync) {
+        assert!(
+            items > 0,
+            "a slow lane has to allow each key at least one item"
+        );
+        assert!(!per.is_zero(), "a slow lane needs a non-zero period");
+        let handle = Self {
+            lanes: Arc::new(Mutex::new(Lanes {
+                items,
+                per,
+                keys: HashMap::new(),
+                swept: 0,
+            })),
+        };
+        let lanes = Arc::clone(&handle.lanes);
+        let predicate = move |item: &I| {
+            let key = key(item);
+            lanes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(key)
+        };
+        (handle, predicate)
+    }
+
+    /// Whether `key` is over its rate, so that its next item would go to the
+    /// slow lane
+    pub fn is_slow(&self, key: &K) -> bool {
+        let mut lanes = self.lanes.lock().unwrap_or_else(PoisonError::into_inner);
+        let (rate, items) = (lanes.rate(), lanes.items);
+        match lanes.keys.get_mut(key) {
+            Some(key_rate) => {
+                Lanes::<K>::refill(rate, items, key_rate, Instant::now());
+                key_rate.tokens < 1.0
+            }
+            None => false,
+        }
+    }
+
+    /// The keys that are over their rate, whose next items would go to the
+    /// slow lane
+    pub fn slow_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let mut lanes = self.lanes.lock().unwrap_or_else(PoisonError::into_inner);
+        let (rate, items, now) = (lanes.rate(), lanes.items, Instant::now());
+        lanes
+            .keys
+            .iter_mut()
+            .filter_map(|(key, key_rate)| {
+                Lanes::<K>::refill(rate, items, key_rate, now);
+                (key_rate.tokens < 1.0).then(|| key.clone())
+            })
+            .collect()
+    }
+
+    /// Gives `key` its whole allowance back, e.g. once a noisy tenant has been
+    /// dealt with
+    pub fn reset(&self, key: &K) {
+        self.lanes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys
+            .remove(key);
+    }
+}
+
+impl<K> Clone for SlowLane<K> {
+    fn clone(&self) -> Self {
+        Self {
+            lanes: Arc::clone(&self.lanes),
+        }
+    }
+}
+
+impl<K> fmt::Debug for SlowLane<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lanes = self.lanes.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("SlowLane")
+            .field("items", &lanes.items)
+            .field("per", &lanes.per)
+            .field("tracked_keys", &lanes.keys.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::SlowLane;
+
+    #[test]
+    fn test_slow_lane_decay() {
+        let (slow_lane, predicate) = SlowLane::new(2, Duration::from_millis(50), |&n: &u32| n);
+        assert!(predicate(&1));
+        assert!(predicate(&1));
+        assert!(!predicate(&1));
+        assert!(predicate(&2));
+        assert_eq!(vec![1], slow_lane.slow_keys());
+        thread::sleep(Duration::from_millis(60));
+        assert!(!slow_lane.is_slow(&1));
+        assert!(predicate(&1));
+        slow_lane.reset(&1);
+        assert!(predicate(&1));
+    }
+}
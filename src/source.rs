@@ -0,0 +1,152 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// The underlying stream of a split, held inline in the split's state and
+    /// pinned along with it. It's the only field of the state that's pinned, and
+    /// it's polled in place, only moved out by `take`, which needs the stream to
+    /// be `Unpin`
+    pub(crate) struct Source<S> {
+        #[pin]
+        stream: Option<S>,
+    }
+}
+
+impl<S> Source<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream: Some(stream),
+        }
+    }
+
+    /// Whether the stream is still held, i.e. hasn't been taken
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn is_some(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub(crate) fn as_ref(&self) -> Option<&S> {
+        self.stream.as_ref()
+    }
+
+    /// Takes the stream out, which is only possible once nothing relies on it
+    /// staying where it is
+    pub(crate) fn take(&mut self) -> Option<S>
+    where
+        S: Unpin,
+    {
+        self.stream.take()
+    }
+
+    /// Polls the stream, or returns `Ready(None)` once it has been taken
+    pub(crate) fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>>
+    where
+        S: Stream,
+    {
+        match self.project().stream.as_pin_mut() {
+            Some(stream) => stream.poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        marker::PhantomPinned,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{Stream, StreamExt};
+
+    use crate::{SplitBy, SplitByBuffered, SplitStreamByExt};
+
+    /// A stream that can't be unpinned and checks it hasn't moved since it
+    /// was first polled
+    struct Unmoved {
+        next: Cell<u32>,
+        end: u32,
+        address: Cell<Option<usize>>,
+        _pinned: PhantomPinned,
+    }
+
+    impl Unmoved {
+        fn new(end: u32) -> Self {
+            Self {
+                next: Cell::new(0),
+                end,
+                address: Cell::new(None),
+                _pinned: PhantomPinned,
+            }
+        }
+    }
+
+    impl Stream for Unmoved {
+        type Item = u32;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<u32>> {
+            let this = self.as_ref().get_ref();
+            let address = this as *const Self as usize;
+            let first = this.address.get().unwrap_or(address);
+            assert_eq!(first, address);
+            this.address.set(Some(first));
+            let next = this.next.get();
+            if next == this.end {
+                return Poll::Ready(None);
+            }
+            this.next.set(next + 1);
+            Poll::Ready(Some(next))
+        }
+    }
+
+    #[test]
+    fn test_split_by_pins_in_place() {
+        futures::executor::block_on(async {
+            let split = SplitBy::new(Unmoved::new(4), |&n: &u32| n % 2 == 0);
+            futures::pin_mut!(split);
+            let next_even = futures::future::poll_fn(|cx| split.as_mut().poll_next_true(cx));
+            assert_eq!(Some(0), next_even.await);
+            let next_odd = futures::future::poll_fn(|cx| split.as_mut().poll_next_false(cx));
+            assert_eq!(Some(1), next_odd.await);
+            let next_even = futures::future::poll_fn(|cx| split.as_mut().poll_next_true(cx));
+            assert_eq!(Some(2), next_even.await);
+        })
+    }
+
+    #[test]
+    fn test_split_by_buffered_pins_in_place() {
+        let split = SplitByBuffered::<_, _, _, 2>::new(Unmoved::new(6), |&n: &u32| n % 2 == 0);
+        futures::pin_mut!(split);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        // Polling the two sides in turn, as the halves would be
+        for n in (0..6).step_by(2) {
+            assert_eq!(Poll::Ready(Some(n)), split.as_mut().poll_next_true(&mut cx));
+            assert_eq!(
+                Poll::Ready(Some(n + 1)),
+                split.as_mut().poll_next_false(&mut cx)
+            );
+        }
+        assert_eq!(Poll::Ready(None), split.as_mut().poll_next_true(&mut cx));
+    }
+
+    #[test]
+    fn test_drive_pins_in_place() {
+        let (even_stream, odd_stream, drive) = Unmoved::new(6).split_by_driven(|&n| n % 2 == 0);
+        futures::executor::block_on(async {
+            let (evens, odds, ()) = futures::join!(
+                even_stream.collect::<Vec<_>>(),
+                odd_stream.collect::<Vec<_>>(),
+                drive
+            );
+            assert_eq!(vec![0, 2, 4], evens);
+            assert_eq!(vec![1, 3, 5], odds);
+        });
+    }
+}
@@ -1,21 +1,95 @@
+#[cfg(feature = "time")]
+use std::time::Duration;
 use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+    sync::Arc,
+    task::Poll,
 };
 
-use futures::Stream;
-use pin_project::pin_project;
+use futures::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+use pin_project_lite::pin_project;
+
+#[cfg(feature = "time")]
+use crate::deadline::{DeadLetters, DeadlinePolicy, Deadlines};
+#[cfg(feature = "debug-invariants")]
+use crate::invariants::{self, SideState};
+#[cfg(not(feature = "time"))]
+use crate::no_timer::{Deadlines, RateLimits};
+#[cfg(feature = "time")]
+use crate::rate_limit::{RateLimit, RateLimits};
+use crate::{
+    abort::{AbortHandle, Abortable},
+    bias::Streaks,
+    builder::DrainSource,
+    consumer_check::ConsumerCheck,
+    degrade::{AutoDegrade, Degrader},
+    hooks::RouteHooks,
+    inject::{InjectHandle, Injectable},
+    pause::{Pausable, PauseHandle},
+    peek::{self, Peeked},
+    quota::{Quota, Quotas},
+    side_waker::{next_consumer_id, SideWaker},
+    source::Source,
+    split_handle::SplitHandle,
+    stats::{StatsHandle, StatsSlot},
+    sync::{self, LockFamily, Mutex, StdMutex},
+    trace::{trace_event, trace_span, Span},
+    ClosePolicy, PausePolicy, ReuniteError, Side,
+};
 
-#[pin_project]
-pub(crate) struct SplitBy<I, S, P> {
-    buf_true: Option<I>,
-    buf_false: Option<I>,
-    waker_true: Option<Waker>,
-    waker_false: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
+pin_project! {
+    /// The state shared between the two halves returned by `split_by`. This is
+    /// public so that custom combinators can own it directly and drive each side
+    /// through `poll_next_true` and `poll_next_false` instead of going through
+    /// `TrueSplitBy` and `FalseSplitBy`
+    ///
+    ///```rust
+    /// use split_stream_by::SplitBy;
+    ///
+    /// futures::executor::block_on(async {
+    ///     let incoming_stream = futures::stream::iter([0,1,2,3]);
+    ///     let mut split = Box::pin(SplitBy::new(incoming_stream, |&n: &i32| n % 2 == 0));
+    ///     let next_even = futures::future::poll_fn(|cx| split.as_mut().poll_next_true(cx));
+    ///     assert_eq!(Some(0), next_even.await);
+    ///     let next_odd = futures::future::poll_fn(|cx| split.as_mut().poll_next_false(cx));
+    ///     assert_eq!(Some(1), next_odd.await);
+    /// })
+    /// ```
+    pub struct SplitBy<I, S, P> {
+        buf_true: Option<I>,
+        buf_false: Option<I>,
+        waker_true: SideWaker,
+        waker_false: SideWaker,
+        closed_true: Option<ClosePolicy>,
+        closed_false: Option<ClosePolicy>,
+        paused_true: Option<PausePolicy>,
+        paused_false: Option<PausePolicy>,
+        handles_true: usize,
+        handles_false: usize,
+        terminated: bool,
+        streaks: Streaks,
+        quotas: Quotas,
+        // Set by `SplitBuilder::drain_on_drop`, and taken once it has been used
+        drain_on_drop: Option<DrainSource<S>>,
+        degrader: Degrader,
+        // Set by `swap_outputs`, inverting the predicate's results
+        swapped: bool,
+        stats: StatsSlot,
+        hooks: RouteHooks<I>,
+        rate_limits: RateLimits,
+        deadlines: Deadlines<I>,
+        span: Span,
+        panic: Option<Box<dyn Any + Send>>,
+        #[pin]
+        stream: Source<S>,
+        predicate: P,
+    }
 }
 
 impl<I, S, P> SplitBy<I, S, P>
@@ -23,125 +97,1435 @@ where
     S: Stream<Item = I>,
     P: Fn(&I) -> bool,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
+    /// Creates the shared state without wrapping it for sharing between two
+    /// halves
+    pub fn new(stream: S, predicate: P) -> Self {
+        Self::with_hooks(stream, predicate, RouteHooks::new())
+    }
+
+    /// Creates the shared state with `hooks` that are called as items are
+    /// routed and buffered
+    pub fn with_hooks(stream: S, predicate: P, hooks: RouteHooks<I>) -> Self {
+        Self {
             buf_false: None,
             buf_true: None,
-            waker_false: None,
-            waker_true: None,
-            stream,
+            waker_false: SideWaker::new("false"),
+            waker_true: SideWaker::new("true"),
+            closed_false: None,
+            closed_true: None,
+            paused_true: None,
+            paused_false: None,
+            handles_true: 0,
+            handles_false: 0,
+            terminated: false,
+            streaks: Streaks::default(),
+            quotas: Quotas::default(),
+            drain_on_drop: None,
+            degrader: Degrader::default(),
+            swapped: false,
+            stats: StatsSlot::default(),
+            hooks,
+            rate_limits: RateLimits::default(),
+            deadlines: Deadlines::default(),
+            span: trace_span!("split_by"),
+            panic: None,
+            stream: Source::new(stream),
             predicate,
-        }))
+        }
+    }
+
+    pub(crate) fn new_shared<M: LockFamily>(
+        stream: S,
+        predicate: P,
+        hooks: RouteHooks<I>,
+    ) -> Arc<Mutex<Self, M>> {
+        Arc::new(Mutex::new(Self::with_hooks(stream, predicate, hooks)))
+    }
+
+    pub(crate) fn size_hint_true(&self) -> (usize, Option<usize>) {
+        let buffered = usize::from(self.buf_true.is_some());
+        if self.closed_true.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
     }
 
-    fn poll_next_true(
+    pub(crate) fn size_hint_false(&self) -> (usize, Option<usize>) {
+        let buffered = usize::from(self.buf_false.is_some());
+        if self.closed_false.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
+    }
+
+    /// Polls for the next item where the predicate returns `true`. Items meant
+    /// for the other side are buffered and its waker is notified, and
+    /// `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_true(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<I>> {
+        self.poll_next_true_as(0, cx)
+    }
+
+    /// The same as `poll_next_true`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_true_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        self.as_mut().project().degrader.polled(Side::Left);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Left, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Left) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `false` stream run before carrying on
+            let this = self.project();
+            this.waker_false.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_true(consumer, cx);
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Left, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Left);
+            this.streaks.yielded(Side::Left);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Left);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `true` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_true(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Left);
+        if self.buf_true.is_some() {
+            return Poll::Ready(true);
+        }
+        let item = match Pin::new(&mut *self).route_next_true(consumer, cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        self.buf_true = Some(item);
+        self.stats.buffered(Side::Left, 1);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, 1);
+        self.hooks.buffered(Side::Left, 1);
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_true_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_true(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_true(consumer, cx),
+            |this| this.end_after_panic(Side::Left),
+        )
+    }
+
+    /// Ends both streams after something panicked while `side` was being
+    /// polled, be it the underlying stream, a hook or an item being dropped,
+    /// as a panicking predicate does. The state may have been left half
+    /// updated, so rather than have the other stream carry on with it, it
+    /// yields what's already buffered for it and ends. The panic itself
+    /// carries on to whoever was polling `side`
+    fn end_after_panic(self: std::pin::Pin<&mut Self>, side: Side) {
         let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_true.is_none() {
-            *this.waker_true = Some(cx.waker().clone());
+        if !*this.terminated {
+            *this.terminated = true;
+            this.stats.terminated();
+        }
+        match side {
+            Side::Left => this.waker_false.wake(),
+            Side::Right => this.waker_true.wake(),
+        }
+    }
+
+    /// `route_next_true` without ending both streams if it panics
+    fn try_route_next_true(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_true.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_true.register(consumer, cx.waker());
+        this.stats.polled(Side::Left);
+        if this.paused_true.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
+        }
+        #[cfg(feature = "time")]
+        {
+            // Anything that has waited too long for the `false` stream is either rerouted
+            // here or discarded, as is anything that has waited too long for this one unless
+            // it would be rerouted, since this stream is getting to it now
+            while let Some(policy) = this.deadlines.expired(Side::Right) {
+                let item = this
+                    .buf_false
+                    .take()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Right);
+                this.stats
+                    .unbuffered(Side::Right, usize::from(this.buf_false.is_some()));
+                this.hooks
+                    .buffered(Side::Right, usize::from(this.buf_false.is_some()));
+                trace_event!(side = "false", ?policy, "item expired");
+                if policy == DeadlinePolicy::Reroute {
+                    return Poll::Ready(Some(item));
+                }
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                }
+            }
+            while this
+                .deadlines
+                .expired(Side::Left)
+                .is_some_and(|policy| policy != DeadlinePolicy::Reroute)
+            {
+                let item = this
+                    .buf_true
+                    .take()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Left);
+                this.stats
+                    .unbuffered(Side::Left, usize::from(this.buf_true.is_some()));
+                this.hooks
+                    .buffered(Side::Left, usize::from(this.buf_true.is_some()));
+                trace_event!(side = "true", "item expired");
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                }
+                // The `false` stream may be waiting on space in the buffer
+                this.waker_false.wake();
+            }
+            // Whichever of the two comes first, this stream is woken when the oldest item
+            // buffered for the `false` stream expires
+            this.deadlines.poll_expiry(Side::Right, cx);
         }
         if let Some(item) = this.buf_true.take() {
+            this.stats
+                .unbuffered(Side::Left, usize::from(this.buf_true.is_some()));
+            #[cfg(feature = "time")]
+            this.deadlines.unbuffered(Side::Left);
+            this.hooks
+                .buffered(Side::Left, usize::from(this.buf_true.is_some()));
             // There was already a value in the buffer. Return that value
             return Poll::Ready(Some(item));
         }
-        if this.buf_false.is_some() {
+        if this.quotas.ended(Side::Left) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        let degraded = this
+            .degrader
+            .check(Side::Right, usize::from(this.buf_false.is_some()));
+        if !degraded
+            && this.buf_false.is_some()
+            && !matches!(
+                this.paused_false,
+                Some(PausePolicy::Drop) | Some(PausePolicy::Divert)
+            )
+        {
             // There is a value available for the other stream. Wake that stream if possible
             // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_false {
-                waker.wake_by_ref();
-            }
+            trace_event!(side = "false", "buffer full");
+            this.stats.buffer_full(Side::Right);
+            this.waker_false.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    Poll::Ready(Some(item))
-                } else {
-                    // This value is not what we wanted. Store it and notify other partition task if
-                    // it exists
-                    let _ = this.buf_false.replace(item);
-                    if let Some(waker) = this.waker_false {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `false` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_false.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let is_true = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(&item))) {
+                Ok(is_true) => is_true != *this.swapped,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_false.wake();
+                    return Poll::Ready(None);
                 }
+            };
+            let side = if is_true { Side::Left } else { Side::Right };
+            let is_true = match this.quotas.route(side, true) {
+                Some(side) => side == Side::Left,
+                None => {
+                    // The side the item is for has received its quota and drops what's
+                    // over it
+                    trace_event!(?side, "item over quota");
+                    this.stats.discarded(side, 1);
+                    this.hooks.discarded(side, Some(item));
+                    continue;
+                }
+            };
+            if is_true {
+                trace_event!(side = "true", "item routed");
+                this.hooks.routed_left(&item);
+                this.stats.routed(Side::Left);
+                return Poll::Ready(Some(item));
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `false` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_false {
-                    waker.wake_by_ref();
+            trace_event!(side = "false", "item routed");
+            this.hooks.routed_right(&item);
+            this.stats.routed(Side::Right);
+            match this.closed_false {
+                Some(ClosePolicy::Discard) => {
+                    // The `false` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "false", "item discarded");
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                    continue;
+                }
+                Some(ClosePolicy::EndSibling) => {
+                    // The `false` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_true = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Left);
+                    return Poll::Ready(None);
                 }
-                Poll::Ready(None)
+                None => {}
             }
-            Poll::Pending => Poll::Pending,
+            match this.paused_false {
+                Some(PausePolicy::Drop) => {
+                    // The `false` stream is paused and its items are being dropped until it's
+                    // resumed, so keep polling
+                    trace_event!(side = "false", "item discarded");
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                    continue;
+                }
+                Some(PausePolicy::Divert) => {
+                    // The `false` stream is paused and its items are being diverted to this one
+                    trace_event!(side = "true", "item diverted");
+                    return Poll::Ready(Some(item));
+                }
+                _ => {}
+            }
+            if degraded {
+                // The `false` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "false", "item discarded");
+                this.stats.discarded(Side::Right, 1);
+                this.hooks.discarded_right(Some(item));
+                continue;
+            }
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. Nothing is displaced since we checked above that the
+            // buffer was empty, so the item is never lost between here and being yielded
+            let displaced = this.buf_false.replace(item);
+            debug_assert!(displaced.is_none());
+            this.stats
+                .buffered(Side::Right, usize::from(this.buf_false.is_some()));
+            #[cfg(feature = "time")]
+            this.deadlines
+                .buffered(Side::Right, usize::from(this.buf_false.is_some()));
+            this.hooks
+                .buffered(Side::Right, usize::from(this.buf_false.is_some()));
+            trace_event!(side = "false", "item buffered");
+            #[cfg(feature = "time")]
+            this.deadlines.poll_expiry(Side::Right, cx);
+            this.waker_false.wake();
+            return Poll::Pending;
         }
     }
 
-    fn poll_next_false(
+    /// Polls for the next item where the predicate returns `false`. Items meant
+    /// for the other side are buffered and its waker is notified, and
+    /// `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_false(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_false.is_none() {
-            *this.waker_false = Some(cx.waker().clone());
+        self.poll_next_false_as(0, cx)
+    }
+
+    /// The same as `poll_next_false`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_false_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        self.as_mut().project().degrader.polled(Side::Right);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Right, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Right) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `true` stream run before carrying on
+            let this = self.project();
+            this.waker_true.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_false(consumer, cx);
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Right, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Right);
+            this.streaks.yielded(Side::Right);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Right);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `false` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_false(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Right);
+        if self.buf_false.is_some() {
+            return Poll::Ready(true);
+        }
+        let item = match Pin::new(&mut *self).route_next_false(consumer, cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        self.buf_false = Some(item);
+        self.stats.buffered(Side::Right, 1);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, 1);
+        self.hooks.buffered(Side::Right, 1);
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_false_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_false(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_false(consumer, cx),
+            |this| this.end_after_panic(Side::Right),
+        )
+    }
+
+    /// `route_next_false` without ending both streams if it panics
+    fn try_route_next_false(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_false.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_false.register(consumer, cx.waker());
+        this.stats.polled(Side::Right);
+        if this.paused_false.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
+        }
+        #[cfg(feature = "time")]
+        {
+            // Anything that has waited too long for the `true` stream is either rerouted
+            // here or discarded, as is anything that has waited too long for this one unless
+            // it would be rerouted, since this stream is getting to it now
+            while let Some(policy) = this.deadlines.expired(Side::Left) {
+                let item = this
+                    .buf_true
+                    .take()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Left);
+                this.stats
+                    .unbuffered(Side::Left, usize::from(this.buf_true.is_some()));
+                this.hooks
+                    .buffered(Side::Left, usize::from(this.buf_true.is_some()));
+                trace_event!(side = "true", ?policy, "item expired");
+                if policy == DeadlinePolicy::Reroute {
+                    return Poll::Ready(Some(item));
+                }
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                }
+            }
+            while this
+                .deadlines
+                .expired(Side::Right)
+                .is_some_and(|policy| policy != DeadlinePolicy::Reroute)
+            {
+                let item = this
+                    .buf_false
+                    .take()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Right);
+                this.stats
+                    .unbuffered(Side::Right, usize::from(this.buf_false.is_some()));
+                this.hooks
+                    .buffered(Side::Right, usize::from(this.buf_false.is_some()));
+                trace_event!(side = "false", "item expired");
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                }
+                // The `true` stream may be waiting on space in the buffer
+                this.waker_true.wake();
+            }
+            // Whichever of the two comes first, this stream is woken when the oldest item
+            // buffered for the `true` stream expires
+            this.deadlines.poll_expiry(Side::Left, cx);
         }
         if let Some(item) = this.buf_false.take() {
+            this.stats
+                .unbuffered(Side::Right, usize::from(this.buf_false.is_some()));
+            #[cfg(feature = "time")]
+            this.deadlines.unbuffered(Side::Right);
+            this.hooks
+                .buffered(Side::Right, usize::from(this.buf_false.is_some()));
             // There was already a value in the buffer. Return that value
             return Poll::Ready(Some(item));
         }
-        if this.buf_true.is_some() {
+        if this.quotas.ended(Side::Right) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        let degraded = this
+            .degrader
+            .check(Side::Left, usize::from(this.buf_true.is_some()));
+        if !degraded
+            && this.buf_true.is_some()
+            && !matches!(
+                this.paused_true,
+                Some(PausePolicy::Drop) | Some(PausePolicy::Divert)
+            )
+        {
             // There is a value available for the other stream. Wake that stream if possible
             // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_true {
-                waker.wake_by_ref();
-            }
+            trace_event!(side = "true", "buffer full");
+            this.stats.buffer_full(Side::Left);
+            this.waker_true.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    // This value is not what we wanted. Store it and notify other stream if waker
-                    // exists
-                    let _ = this.buf_true.replace(item);
-                    if let Some(waker) = this.waker_true {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Some(item))
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `true` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_true.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let is_true = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(&item))) {
+                Ok(is_true) => is_true != *this.swapped,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_true.wake();
+                    return Poll::Ready(None);
                 }
+            };
+            let side = if is_true { Side::Left } else { Side::Right };
+            let is_true = match this.quotas.route(side, true) {
+                Some(side) => side == Side::Left,
+                None => {
+                    // The side the item is for has received its quota and drops what's
+                    // over it
+                    trace_event!(?side, "item over quota");
+                    this.stats.discarded(side, 1);
+                    this.hooks.discarded(side, Some(item));
+                    continue;
+                }
+            };
+            if !is_true {
+                trace_event!(side = "false", "item routed");
+                this.hooks.routed_right(&item);
+                this.stats.routed(Side::Right);
+                return Poll::Ready(Some(item));
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `true` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_true {
-                    waker.wake_by_ref();
+            trace_event!(side = "true", "item routed");
+            this.hooks.routed_left(&item);
+            this.stats.routed(Side::Left);
+            match this.closed_true {
+                Some(ClosePolicy::Discard) => {
+                    // The `true` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "true", "item discarded");
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                    continue;
+                }
+                Some(ClosePolicy::EndSibling) => {
+                    // The `true` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_false = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Right);
+                    return Poll::Ready(None);
                 }
-                Poll::Ready(None)
+                None => {}
             }
-            Poll::Pending => Poll::Pending,
+            match this.paused_true {
+                Some(PausePolicy::Drop) => {
+                    // The `true` stream is paused and its items are being dropped until it's
+                    // resumed, so keep polling
+                    trace_event!(side = "true", "item discarded");
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                    continue;
+                }
+                Some(PausePolicy::Divert) => {
+                    // The `true` stream is paused and its items are being diverted to this one
+                    trace_event!(side = "false", "item diverted");
+                    return Poll::Ready(Some(item));
+                }
+                _ => {}
+            }
+            if degraded {
+                // The `true` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "true", "item discarded");
+                this.stats.discarded(Side::Left, 1);
+                this.hooks.discarded_left(Some(item));
+                continue;
+            }
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. Nothing is displaced since we checked above that the
+            // buffer was empty, so the item is never lost between here and being yielded
+            let displaced = this.buf_true.replace(item);
+            debug_assert!(displaced.is_none());
+            this.stats
+                .buffered(Side::Left, usize::from(this.buf_true.is_some()));
+            #[cfg(feature = "time")]
+            this.deadlines
+                .buffered(Side::Left, usize::from(this.buf_true.is_some()));
+            this.hooks
+                .buffered(Side::Left, usize::from(this.buf_true.is_some()));
+            trace_event!(side = "true", "item buffered");
+            #[cfg(feature = "time")]
+            this.deadlines.poll_expiry(Side::Left, cx);
+            this.waker_true.wake();
+            return Poll::Pending;
         }
     }
 }
 
+impl<I, S, P> SplitBy<I, S, P> {
+    /// Panics if the shared state is in a state it should never reach, given
+    /// that polling `side` just returned `poll`
+    #[cfg(feature = "debug-invariants")]
+    fn check_invariants<T>(&self, side: Side, poll: &Poll<Option<T>>) {
+        invariants::check(
+            side,
+            poll,
+            [
+                SideState {
+                    buffered: usize::from(self.buf_true.is_some()),
+                    closed: self.closed_true.is_some(),
+                    waker: &self.waker_true,
+                },
+                SideState {
+                    buffered: usize::from(self.buf_false.is_some()),
+                    closed: self.closed_false.is_some(),
+                    waker: &self.waker_false,
+                },
+            ],
+            self.terminated,
+            self.stream.is_some(),
+            &self.stats,
+        );
+    }
+
+    pub(crate) fn is_terminated_true(&self) -> bool {
+        self.closed_true.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Left)) && self.buf_true.is_none())
+    }
+
+    pub(crate) fn is_terminated_false(&self) -> bool {
+        self.closed_false.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Right)) && self.buf_false.is_none())
+    }
+
+    pub(crate) fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.panic.take()
+    }
+
+    /// Returns a handle to the counters, which are only kept from the first
+    /// time this is called
+    pub(crate) fn stats_handle(&mut self) -> StatsHandle {
+        if let Some(counters) = self.stats.enable() {
+            self.waker_true.count_wakes(Arc::clone(&counters));
+            self.waker_false.count_wakes(counters);
+        }
+        self.stats.handle()
+    }
+
+    /// Adds a summary of the `true` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_true(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Left)
+            .field("buffered", &usize::from(self.buf_true.is_some()))
+            .field(
+                "sibling_waker_registered",
+                &self.waker_false.is_registered(),
+            )
+            .field("closed", &self.closed_true)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Adds a summary of the `false` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_false(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Right)
+            .field("buffered", &usize::from(self.buf_false.is_some()))
+            .field("sibling_waker_registered", &self.waker_true.is_registered())
+            .field("closed", &self.closed_false)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Pauses the `true` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `false` side is woken since it may no
+    /// longer have to wait on the `true` buffer
+    pub(crate) fn pause_true(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", ?policy, "stream paused");
+        self.paused_true = Some(policy);
+        self.waker_false.wake();
+    }
+
+    /// Resumes the `true` side, waking it if it was paused
+    pub(crate) fn resume_true(&mut self) {
+        if self.paused_true.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "true", "stream resumed");
+            self.waker_true.wake();
+        }
+    }
+
+    /// Pauses the `false` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `true` side is woken since it may no
+    /// longer have to wait on the `false` buffer
+    pub(crate) fn pause_false(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", ?policy, "stream paused");
+        self.paused_false = Some(policy);
+        self.waker_true.wake();
+    }
+
+    /// Resumes the `false` side, waking it if it was paused
+    pub(crate) fn resume_false(&mut self) {
+        if self.paused_false.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "false", "stream resumed");
+            self.waker_false.wake();
+        }
+    }
+
+    /// Sets how long an item may wait in either buffer before it's handled
+    /// according to `policy`, waking both sides so that their timers are set
+    #[cfg(feature = "time")]
+    pub(crate) fn set_deadline(&mut self, deadline: Duration, policy: DeadlinePolicy) {
+        self.deadlines.set(deadline, policy);
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Swaps which stream the predicate's results go to, moving whatever is
+    /// already buffered along with them. Anything that ends up buffered for a
+    /// closed side is dropped, and both sides are woken
+    pub(crate) fn swap_outputs(&mut self) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        self.swapped = !self.swapped;
+        trace_event!(swapped = self.swapped, "outputs swapped");
+        std::mem::swap(&mut self.buf_true, &mut self.buf_false);
+        if self.closed_true.is_some() {
+            self.stats
+                .discarded(Side::Left, usize::from(self.buf_true.is_some()));
+            self.hooks.discarded_left(self.buf_true.take());
+        }
+        if self.closed_false.is_some() {
+            self.stats
+                .discarded(Side::Right, usize::from(self.buf_false.is_some()));
+            self.hooks.discarded_right(self.buf_false.take());
+        }
+        #[cfg(feature = "time")]
+        self.deadlines.swap();
+        for (side, len) in [
+            (Side::Left, usize::from(self.buf_true.is_some())),
+            (Side::Right, usize::from(self.buf_false.is_some())),
+        ] {
+            self.stats.buffered(side, len);
+            #[cfg(feature = "time")]
+            self.deadlines.buffered(side, len);
+            self.hooks.buffered(side, len);
+        }
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Sets when a side that isn't keeping up starts having its items dropped,
+    /// or turns that off, recovering both sides
+    pub(crate) fn set_auto_degrade(&mut self, policy: Option<AutoDegrade>) {
+        self.degrader.set(policy);
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Caps how many items in a row either side may yield before giving way to
+    /// the other, or removes the cap
+    pub(crate) fn set_max_consecutive(&mut self, max: Option<u32>) {
+        self.streaks.set(max);
+    }
+
+    /// Caps how many items `side` receives, or removes its cap, waking it in
+    /// case that ends it
+    pub(crate) fn set_quota(&mut self, side: Side, quota: Option<Quota>) {
+        self.quotas.set(side, quota);
+        match side {
+            Side::Left => self.waker_true.wake(),
+            Side::Right => self.waker_false.wake(),
+        }
+    }
+
+    pub(crate) fn quota_remaining(&self, side: Side) -> Option<u64> {
+        self.quotas.remaining(side)
+    }
+
+    /// Limits how fast `side` yields items, or removes its limit, waking it in
+    /// case it was waiting on the previous limit
+    #[cfg(feature = "time")]
+    pub(crate) fn set_rate_limit(&mut self, side: Side, limit: Option<RateLimit>) {
+        self.rate_limits.set(side, limit);
+        match side {
+            Side::Left => self.waker_true.wake(),
+            Side::Right => self.waker_false.wake(),
+        }
+    }
+
+    /// Buffers an item from outside the split for the `true` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_true(&mut self, item: I) -> Result<(), I> {
+        if self.closed_true.is_some() || self.terminated || self.quotas.ended(Side::Left) {
+            return Err(item);
+        }
+        if self.buf_true.is_some() {
+            return Err(item);
+        }
+        self.buf_true = Some(item);
+        let len = 1;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", buffered = len, "item injected");
+        self.stats.buffered(Side::Left, len);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, len);
+        self.hooks.buffered(Side::Left, len);
+        self.waker_true.wake();
+        Ok(())
+    }
+
+    /// Buffers an item from outside the split for the `false` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_false(&mut self, item: I) -> Result<(), I> {
+        if self.closed_false.is_some() || self.terminated || self.quotas.ended(Side::Right) {
+            return Err(item);
+        }
+        if self.buf_false.is_some() {
+            return Err(item);
+        }
+        self.buf_false = Some(item);
+        let len = 1;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", buffered = len, "item injected");
+        self.stats.buffered(Side::Right, len);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, len);
+        self.hooks.buffered(Side::Right, len);
+        self.waker_false.wake();
+        Ok(())
+    }
+
+    /// Ends both sides and takes the underlying stream along with anything
+    /// buffered for either side, unless this was already done
+    pub(crate) fn abort(&mut self) -> Option<SplitByParts<I, S>>
+    where
+        S: Unpin,
+    {
+        let stream = self.stream.take()?;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!("splitter aborted");
+        self.terminated = true;
+        self.stats.terminated();
+        let parts = SplitByParts {
+            stream,
+            buffered_true: self.buf_true.take(),
+            buffered_false: self.buf_false.take(),
+        };
+        self.stats.buffered(Side::Left, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.stats.buffered(Side::Right, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_true.wake();
+        self.waker_false.wake();
+        Some(parts)
+    }
+
+    /// Counts another output stream handle for the `true` side
+    pub(crate) fn attach_true(&mut self) {
+        self.handles_true += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `true` side once no handles are left
+    pub(crate) fn detach_true(&mut self, consumer: usize) {
+        self.waker_true.unregister(consumer);
+        self.handles_true -= 1;
+        if self.handles_true == 0 {
+            self.close_true(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    /// Counts another output stream handle for the `false` side
+    pub(crate) fn attach_false(&mut self) {
+        self.handles_false += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `false` side once no handles are left
+    pub(crate) fn detach_false(&mut self, consumer: usize) {
+        self.waker_false.unregister(consumer);
+        self.handles_false -= 1;
+        if self.handles_false == 0 {
+            self.close_false(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    pub(crate) fn set_drain_on_drop(&mut self, drain: DrainSource<S>) {
+        self.drain_on_drop = Some(drain);
+    }
+
+    /// Hands the underlying stream over to be read to the end once both sides
+    /// have been dropped, if the split was built with `drain_on_drop` and the
+    /// stream hasn't ended already
+    fn drain_if_detached(&mut self) {
+        if self.handles_true > 0 || self.handles_false > 0 || self.terminated {
+            return;
+        }
+        if let Some(drain) = self.drain_on_drop.take() {
+            trace_event!("draining the stream");
+            drain.drain(&mut self.stream);
+        }
+    }
+
+    /// Marks the `true` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_true(&mut self, policy: ClosePolicy) {
+        if self.closed_true.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", ?policy, "stream closed");
+        self.closed_true = Some(policy);
+        self.stats.closed(Side::Left);
+        self.stats
+            .discarded(Side::Left, usize::from(self.buf_true.is_some()));
+        let discarded = self.buf_true.take();
+        self.stats.buffered(Side::Left, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.waker_false.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_left(discarded);
+    }
+
+    /// Marks the `false` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_false(&mut self, policy: ClosePolicy) {
+        if self.closed_false.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", ?policy, "stream closed");
+        self.closed_false = Some(policy);
+        self.stats.closed(Side::Right);
+        self.stats
+            .discarded(Side::Right, usize::from(self.buf_false.is_some()));
+        let discarded = self.buf_false.take();
+        self.stats.buffered(Side::Right, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_true.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_right(discarded);
+    }
+}
+
+impl<I, S, P> Pausable for SplitBy<I, S, P> {
+    fn pause(&mut self, side: Side, policy: PausePolicy) {
+        match side {
+            Side::Left => self.pause_true(policy),
+            Side::Right => self.pause_false(policy),
+        }
+    }
+
+    fn resume(&mut self, side: Side) {
+        match side {
+            Side::Left => self.resume_true(),
+            Side::Right => self.resume_false(),
+        }
+    }
+}
+
+impl<I, S, P> Injectable for SplitBy<I, S, P> {
+    type Left = I;
+    type Right = I;
+
+    fn inject_left(&mut self, item: I) -> Result<(), I> {
+        self.inject_true(item)
+    }
+
+    fn inject_right(&mut self, item: I) -> Result<(), I> {
+        self.inject_false(item)
+    }
+}
+
+impl<I, S: Unpin, P> Abortable for SplitBy<I, S, P> {
+    type Parts = SplitByParts<I, S>;
+
+    fn abort(&mut self) -> Option<Self::Parts> {
+        SplitBy::abort(self)
+    }
+}
+
+/// The pieces of a `split_by` that has been taken apart with `into_parts`
+pub struct SplitByParts<I, S> {
+    /// The underlying stream, positioned after the last item that was read
+    pub stream: S,
+    /// The item that was buffered for the `true` stream, if any
+    pub buffered_true: Option<I>,
+    /// The item that was buffered for the `false` stream, if any
+    pub buffered_false: Option<I>,
+}
+
 /// A struct that implements `Stream` which returns the items where the
 /// predicate returns `true`
-pub struct TrueSplitBy<I, S, P> {
-    stream: Arc<Mutex<SplitBy<I, S, P>>>,
+pub struct TrueSplitBy<I, S, P, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitBy<I, S, P>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
+}
+
+/// `TrueSplitBy` without the item type of the underlying stream, which is taken
+/// from `S`, so that the `true` stream can be stored in a struct naming only
+/// the stream and the predicate
+pub type True<S, P> = TrueSplitBy<<S as Stream>::Item, S, P>;
+
+impl<I, S, P, M: LockFamily> TrueSplitBy<I, S, P, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>, M>>) -> Self {
+        stream.lock().attach_true();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_true(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.take_panic()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitBy<I, S, P>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitBy<I, S, P>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitBy<I, S, P>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitBy<I, S, P>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Left)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Left)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_false > 0 && guard.closed_false.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.buf_true.is_some()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Left, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Left)
+    }
+
+    pub(crate) fn set_drain_on_drop(&self, drain: DrainSource<S>) {
+        let mut guard = self.stream.lock();
+        guard.set_drain_on_drop(drain);
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Left, limit);
+    }
+
+    /// Swaps which of the two streams the predicate's results go to, so that
+    /// items it returns `true` for are yielded by the `false` stream and vice
+    /// versa. Items that are already buffered move over as well, so each one
+    /// is yielded as if it had been routed after the swap
+    pub fn swap_outputs(&self) {
+        let mut guard = self.stream.lock();
+        guard.swap_outputs();
+    }
+
+    /// Sets how long an item may wait in either stream's buffer. An item that
+    /// has waited for longer than `deadline` is handled according to `policy`
+    /// the next time either stream is polled, and the stream that didn't get
+    /// to it is woken when that's due. Setting it again replaces the previous
+    /// deadline, including for items that are already buffered
+    #[cfg(feature = "time")]
+    pub fn set_deadline(&self, deadline: Duration, policy: DeadlinePolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_deadline(deadline, policy);
+    }
+
+    /// Returns a stream of the items that expired with
+    /// `DeadlinePolicy::DeadLetter`. Only one dead letter stream is fed at a
+    /// time, so creating another one ends this one
+    #[cfg(feature = "time")]
+    pub fn dead_letters(&self) -> DeadLetters<I> {
+        let mut guard = self.stream.lock();
+        guard.deadlines.dead_letters()
+    }
+
+    /// Takes apart the split, returning the underlying stream along with any
+    /// items that were read from it but not yet yielded. `other` must be the
+    /// stream that was created alongside this one, and neither may have been
+    /// cloned, otherwise both are handed back in the error. They're also
+    /// handed back if the underlying stream was already taken with
+    /// `AbortHandle::abort`
+    pub fn into_parts(
+        self,
+        other: FalseSplitBy<I, S, P, M>,
+    ) -> Result<SplitByParts<I, S>, ReuniteError<Self, FalseSplitBy<I, S, P, M>>>
+    where
+        S: Unpin,
+    {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        let parts = {
+            let mut guard = self.stream.lock();
+            if guard.handles_true == 1 && guard.handles_false == 1 {
+                guard.stream.take().map(|stream| SplitByParts {
+                    stream,
+                    buffered_true: guard.buf_true.take(),
+                    buffered_false: guard.buf_false.take(),
+                })
+            } else {
+                None
+            }
+        };
+        // Dropping the halves afterwards closes both sides, which is harmless now that
+        // their buffers are empty
+        parts.ok_or(ReuniteError(self, other))
+    }
 }
 
-impl<I, S, P> TrueSplitBy<I, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>) -> Self {
-        Self { stream }
+impl<I, S, P, M: LockFamily> TrueSplitBy<I, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitBy<I, S, P>, I, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "TrueSplitBy",
+            cx,
+            |state, cx| state.poll_peek_true(id, cx),
+            |state| state.buf_true.as_ref(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitBy<I, S, P>, I, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "TrueSplitBy",
+            |state, cx| state.poll_peek_true(id, cx),
+            |state| state.buf_true.as_ref(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
     }
 }
 
-impl<I, S, P> Stream for TrueSplitBy<I, S, P>
+impl<I, S, P, M: LockFamily> Stream for TrueSplitBy<I, S, P, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
@@ -151,29 +1535,314 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitBy::poll_next_true(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("TrueSplitBy", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
         };
-        response
+        let poll = SplitBy::poll_next_true_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, S, P, M: LockFamily> FusedStream for TrueSplitBy<I, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_true(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, P, M: LockFamily> fmt::Debug for TrueSplitBy<I, S, P, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TrueSplitBy");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_true(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, S, P, M: LockFamily> Clone for TrueSplitBy<I, S, P, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, S, P, M: LockFamily> Drop for TrueSplitBy<I, S, P, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_true(id), self.id);
     }
 }
 
 /// A struct that implements `Stream` which returns the items where the
 /// predicate returns `false`
-pub struct FalseSplitBy<I, S, P> {
-    stream: Arc<Mutex<SplitBy<I, S, P>>>,
+pub struct FalseSplitBy<I, S, P, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitBy<I, S, P>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
+}
+
+/// `FalseSplitBy` without the item type of the underlying stream, which is
+/// taken from `S`, so that the `false` stream can be stored in a struct naming
+/// only the stream and the predicate
+pub type False<S, P> = FalseSplitBy<<S as Stream>::Item, S, P>;
+
+impl<I, S, P, M: LockFamily> FalseSplitBy<I, S, P, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>, M>>) -> Self {
+        stream.lock().attach_false();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_false(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.take_panic()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitBy<I, S, P>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitBy<I, S, P>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitBy<I, S, P>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitBy<I, S, P>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Right)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Right)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_true > 0 && guard.closed_true.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.buf_false.is_some()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Right, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Right)
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Right, limit);
+    }
+
+    /// Swaps which of the two streams the predicate's results go to, so that
+    /// items it returns `true` for are yielded by the `false` stream and vice
+    /// versa. Items that are already buffered move over as well, so each one
+    /// is yielded as if it had been routed after the swap
+    pub fn swap_outputs(&self) {
+        let mut guard = self.stream.lock();
+        guard.swap_outputs();
+    }
+
+    /// Sets how long an item may wait in either stream's buffer. An item that
+    /// has waited for longer than `deadline` is handled according to `policy`
+    /// the next time either stream is polled, and the stream that didn't get
+    /// to it is woken when that's due. Setting it again replaces the previous
+    /// deadline, including for items that are already buffered
+    #[cfg(feature = "time")]
+    pub fn set_deadline(&self, deadline: Duration, policy: DeadlinePolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_deadline(deadline, policy);
+    }
+
+    /// Returns a stream of the items that expired with
+    /// `DeadlinePolicy::DeadLetter`. Only one dead letter stream is fed at a
+    /// time, so creating another one ends this one
+    #[cfg(feature = "time")]
+    pub fn dead_letters(&self) -> DeadLetters<I> {
+        let mut guard = self.stream.lock();
+        guard.deadlines.dead_letters()
+    }
 }
 
-impl<I, S, P> FalseSplitBy<I, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>) -> Self {
-        Self { stream }
+impl<I, S, P, M: LockFamily> FalseSplitBy<I, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitBy<I, S, P>, I, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "FalseSplitBy",
+            cx,
+            |state, cx| state.poll_peek_false(id, cx),
+            |state| state.buf_false.as_ref(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitBy<I, S, P>, I, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "FalseSplitBy",
+            |state, cx| state.poll_peek_false(id, cx),
+            |state| state.buf_false.as_ref(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
     }
 }
 
-impl<I, S, P> Stream for FalseSplitBy<I, S, P>
+impl<I, S, P, M: LockFamily> Stream for FalseSplitBy<I, S, P, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
@@ -183,12 +1852,514 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitBy::poll_next_false(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("FalseSplitBy", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        };
+        let poll = SplitBy::poll_next_false_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, S, P, M: LockFamily> FusedStream for FalseSplitBy<I, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_false(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, P, M: LockFamily> fmt::Debug for FalseSplitBy<I, S, P, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FalseSplitBy");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_false(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, S, P, M: LockFamily> Clone for FalseSplitBy<I, S, P, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, S, P, M: LockFamily> Drop for FalseSplitBy<I, S, P, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_false(id), self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+
+    use crate::{ClosePolicy, ReuniteError, RouteHooks, Side, SplitEvent, SplitStreamByExt};
+
+    #[test]
+    fn test_dropped_half_with_buffered_item() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert_eq!(Some(0), even_stream.next().await);
+            // Leave 1 sitting in the odd buffer before dropping the odd stream
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            drop(odd_stream);
+            assert_eq!(vec![2, 4], even_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_dropped_half_before_polling() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+            let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            drop(even_stream);
+            assert_eq!(vec![1, 3, 5], odd_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_close_discard() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+            let (even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            odd_stream.close(ClosePolicy::Discard);
+            assert_eq!(None, odd_stream.next().await);
+            assert_eq!(vec![0, 2, 4], even_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_close_end_sibling() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 2, 3, 4, 5]);
+            let (even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            odd_stream.close(ClosePolicy::EndSibling);
+            assert_eq!(vec![0, 2], even_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_predicate_panic() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| {
+                assert!(n < 2, "predicate panicked");
+                n % 2 == 0
+            });
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(None, even_stream.next().await);
+            assert_eq!(None, odd_stream.next().await);
+            let payload = odd_stream.take_panic().unwrap();
+            assert_eq!(Some(&"predicate panicked"), payload.downcast_ref::<&str>());
+            assert!(even_stream.take_panic().is_none());
+        })
+    }
+
+    #[test]
+    fn test_poisoned_lock() {
+        struct PanicOnDrop(i32);
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                if self.0 == 1 && !std::thread::panicking() {
+                    panic!("dropped 1")
+                }
+            }
+        }
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 4]).map(PanicOnDrop);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by(|item| item.0 % 2 == 0);
+            assert_eq!(Some(0), even_stream.next().await.map(|item| item.0));
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            // Dropping the buffered 1 panics while the lock is held, poisoning it
+            let closed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                odd_stream.close(ClosePolicy::Discard)
+            }));
+            assert!(closed.is_err());
+            let rest = even_stream.map(|item| item.0).collect::<Vec<_>>().await;
+            assert_eq!(vec![2, 4], rest);
+        })
+    }
+
+    #[test]
+    fn test_source_panic_ends_both_sides() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
         };
-        response
+
+        use futures::{FutureExt, Stream};
+
+        use crate::test_util::CountingWaker;
+
+        let incoming_stream = futures::stream::iter([1, 2, 5]).map(|n| {
+            assert!(n != 5, "source failed");
+            n
+        });
+        let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let odd_task = CountingWaker::new();
+        let odd_waker = odd_task.waker();
+        let mut poll_odd =
+            || Pin::new(&mut odd_stream).poll_next(&mut Context::from_waker(&odd_waker));
+        assert_eq!(Poll::Ready(Some(1)), poll_odd());
+        assert_eq!(Poll::Pending, poll_odd());
+        assert_eq!(Some(2), even_stream.next().now_or_never().flatten());
+        let woken = odd_task.wakes();
+        // The panic carries on to the task polling the even stream, and the odd stream is
+        // woken to find the split has ended rather than being left waiting
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            even_stream.next().now_or_never()
+        }))
+        .unwrap_err();
+        assert_eq!(Some(&"source failed"), payload.downcast_ref::<&str>());
+        assert!(odd_task.wakes() > woken);
+        assert_eq!(Poll::Ready(None), poll_odd());
+        // Nor is the failed source polled again
+        assert_eq!(Some(None), even_stream.next().now_or_never());
+    }
+
+    #[test]
+    fn test_into_parts() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            let parts = even_stream.into_parts(odd_stream).unwrap();
+            assert_eq!(None, parts.buffered_true);
+            assert_eq!(Some(1), parts.buffered_false);
+            assert_eq!(vec![2, 3], parts.stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_into_parts_mismatched() {
+        fn is_even(n: &i32) -> bool {
+            n % 2 == 0
+        }
+        let (even_stream, _) = futures::stream::iter([0]).split_by(is_even);
+        let (_, odd_stream) = futures::stream::iter([0]).split_by(is_even);
+        assert!(even_stream.into_parts(odd_stream).is_err());
+    }
+
+    #[test]
+    fn test_cloned_consumers() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter(0..20);
+            let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            let other_even_stream = even_stream.clone();
+            let (mut evens, mut other_evens, odds) = futures::join!(
+                even_stream.collect::<Vec<_>>(),
+                other_even_stream.collect::<Vec<_>>(),
+                odd_stream.collect::<Vec<_>>()
+            );
+            evens.append(&mut other_evens);
+            evens.sort_unstable();
+            assert_eq!((0..20).step_by(2).collect::<Vec<_>>(), evens);
+            assert_eq!((1..20).step_by(2).collect::<Vec<_>>(), odds);
+        })
+    }
+
+    #[test]
+    fn test_into_parts_with_clone() {
+        let incoming_stream = futures::stream::iter([0]);
+        let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let other_even_stream = even_stream.clone();
+        let (even_stream, odd_stream) = match even_stream.into_parts(odd_stream) {
+            Ok(_) => panic!("a clone of the `true` stream still exists"),
+            Err(ReuniteError(even_stream, odd_stream)) => (even_stream, odd_stream),
+        };
+        drop(other_even_stream);
+        assert!(even_stream.into_parts(odd_stream).is_ok());
+    }
+
+    #[test]
+    fn test_into_parts_after_abort() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 2]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            let parts = even_stream.abort_handle().abort().unwrap();
+            assert_eq!(Some(1), parts.buffered_false);
+            // The stream has already been handed back, so there's nothing to
+            // take apart and the halves come back as they were
+            let (mut even_stream, mut odd_stream) = match even_stream.into_parts(odd_stream) {
+                Ok(_) => panic!("the stream was taken by the abort"),
+                Err(ReuniteError(even_stream, odd_stream)) => (even_stream, odd_stream),
+            };
+            assert_eq!(None, even_stream.next().await);
+            assert_eq!(None, odd_stream.next().await);
+        })
+    }
+
+    #[test]
+    fn test_handed_off_between_tasks() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use futures::Stream;
+
+        use crate::test_util::CountingWaker;
+
+        let task_a = CountingWaker::new();
+        let task_b = CountingWaker::new();
+        let waker_a = task_a.waker();
+        let waker_b = task_b.waker();
+        let incoming_stream = futures::stream::iter([1, 2]);
+        let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let mut poll_even =
+            |waker| Pin::new(&mut even_stream).poll_next(&mut Context::from_waker(waker));
+        // The even stream is left waiting by one task and then moved to another, which is
+        // the one woken once the odd stream has read an item for it
+        assert!(poll_even(&waker_a).is_pending());
+        assert!(poll_even(&waker_b).is_pending());
+        let noop = futures::task::noop_waker();
+        let mut poll_odd = || Pin::new(&mut odd_stream).poll_next(&mut Context::from_waker(&noop));
+        assert_eq!(Poll::Ready(Some(1)), poll_odd());
+        assert!(poll_odd().is_pending());
+        assert_eq!(0, task_a.wakes());
+        assert!(task_b.wakes() > 0);
+        assert_eq!(Poll::Ready(Some(2)), poll_even(&waker_b));
+        // Debug builds notice the second task taking over
+        #[cfg(debug_assertions)]
+        assert_eq!(1, even_stream.consumer.contended());
+    }
+
+    #[test]
+    fn test_stats() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            let stats = even_stream.stats_handle();
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            let snapshot = stats.snapshot();
+            assert_eq!(1, snapshot.routed_left);
+            assert_eq!(1, snapshot.routed_right);
+            assert_eq!(0, snapshot.buffered_left);
+            assert_eq!(1, snapshot.buffered_right);
+            assert_eq!(2, snapshot.source_polls);
+            assert!(!snapshot.terminated);
+            drop(odd_stream);
+            assert_eq!(vec![2], even_stream.collect::<Vec<_>>().await);
+            let snapshot = stats.snapshot();
+            assert_eq!(2, snapshot.routed_left);
+            assert_eq!(0, snapshot.buffered_right);
+            assert!(snapshot.terminated);
+        })
+    }
+
+    #[test]
+    fn test_stats_taken_late() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            // Nothing was kept until now, but the counts and gauges pick up from where the
+            // split is
+            let stats = odd_stream.stats_handle();
+            let snapshot = stats.snapshot();
+            assert_eq!(1, snapshot.routed_left);
+            assert_eq!(1, snapshot.routed_right);
+            assert_eq!(1, snapshot.yielded_left);
+            assert_eq!(1, snapshot.buffered_right);
+            assert_eq!(2, snapshot.source_polls);
+            assert_eq!(0, snapshot.wakes);
+            drop(odd_stream);
+            assert_eq!(vec![2], even_stream.collect::<Vec<_>>().await);
+            let snapshot = stats.snapshot();
+            assert_eq!(1, snapshot.dropped_right);
+            assert!(snapshot.terminated);
+        })
+    }
+
+    #[test]
+    fn test_route_hooks() {
+        futures::executor::block_on(async {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let hooks = RouteHooks::new()
+                .on_route({
+                    let events = Arc::clone(&events);
+                    move |side, &n| events.lock().unwrap().push((side, n))
+                })
+                .on_buffer({
+                    let events = Arc::clone(&events);
+                    move |side, len| events.lock().unwrap().push((side, len))
+                });
+            let incoming_stream = futures::stream::iter([0_usize, 1]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_with_hooks(|&n| n % 2 == 0, hooks);
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(
+                vec![
+                    (Side::Left, 0),
+                    (Side::Right, 1),
+                    (Side::Right, 1),
+                    (Side::Right, 0)
+                ],
+                *events.lock().unwrap()
+            );
+        })
+    }
+
+    #[test]
+    fn test_events() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 3]);
+            let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            let events = even_stream.stats_handle().events();
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            odd_stream.close(ClosePolicy::Discard);
+            assert_eq!(None, even_stream.next().await);
+            assert_eq!(
+                vec![
+                    SplitEvent::Routed { side: Side::Left },
+                    SplitEvent::Routed { side: Side::Right },
+                    SplitEvent::BufferFull { side: Side::Right },
+                    SplitEvent::Dropped { side: Side::Right },
+                    SplitEvent::Routed { side: Side::Right },
+                    SplitEvent::Dropped { side: Side::Right },
+                    SplitEvent::Terminated,
+                ],
+                events.collect::<Vec<_>>().await
+            );
+        })
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_watch_stalls() {
+        use std::time::Duration;
+
+        // Polled within a tokio runtime so that the test also runs with `tokio-timer`
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let incoming_stream = futures::stream::iter([1, 0]);
+            let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            let mut stalls = Vec::new();
+            let watch = even_stream
+                .stats_handle()
+                .watch_stalls(Duration::from_millis(10), |stall| stalls.push(stall));
+            futures::join!(watch, async {
+                <crate::Timer as crate::Delay>::new(Duration::from_millis(50)).await;
+                drop(even_stream);
+                drop(odd_stream);
+            });
+            assert_eq!(1, stalls.len());
+            assert_eq!(Side::Right, stalls[0].side);
+            assert_eq!(1, stalls[0].buffered);
+        })
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_rate_limit() {
+        // Polled within a tokio runtime so that the test also runs with `tokio-timer`
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            use std::time::{Duration, Instant};
+
+            let incoming_stream = futures::stream::iter([0, 2, 4]);
+            let (even_stream, _odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            even_stream.set_rate_limit(Some(crate::RateLimit::new(1, Duration::from_millis(50))));
+            let start = Instant::now();
+            assert_eq!(vec![0, 2, 4], even_stream.collect::<Vec<_>>().await);
+            // The first item is allowed straight away, and each of the others waits for the
+            // allowance to refill
+            assert!(start.elapsed() >= Duration::from_millis(90));
+        })
+    }
+
+    #[test]
+    fn test_max_consecutive() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 2, 4, 6]);
+            let (mut even_stream, _odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            even_stream.set_max_consecutive(Some(2));
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(Some(2), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(4), even_stream.next().await);
+            assert_eq!(Some(6), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+        })
+    }
+
+    #[test]
+    fn test_aliases_and_boxed() {
+        use futures::stream::{BoxStream, Iter};
+
+        use crate::{False, True};
+
+        type Source = Iter<std::array::IntoIter<i32, 4>>;
+        type IsEven = fn(&i32) -> bool;
+
+        struct Evens {
+            evens: True<Source, IsEven>,
+            odds: BoxStream<'static, i32>,
+        }
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let (evens, odds): (_, False<Source, IsEven>) =
+                incoming_stream.split_by(|&n| n % 2 == 0);
+            let mut split = Evens {
+                evens,
+                odds: odds.boxed(),
+            };
+            assert_eq!(Some(0), split.evens.next().await);
+            assert_eq!(Some(1), split.odds.next().await);
+            let mut evens = split.evens.boxed_local();
+            assert_eq!(Some(2), evens.next().await);
+            assert_eq!(vec![3], split.odds.collect::<Vec<_>>().await);
+        })
     }
 }
@@ -1,148 +1,1705 @@
+#[cfg(feature = "time")]
+use std::time::Duration;
 use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+    sync::Arc,
+    task::Poll,
 };
 
-use crate::ring_buf::RingBuf;
-use futures::Stream;
-use pin_project::pin_project;
+#[cfg(feature = "time")]
+use crate::deadline::{DeadLetters, DeadlinePolicy, Deadlines};
+#[cfg(feature = "debug-invariants")]
+use crate::invariants::{self, SideState};
+#[cfg(not(feature = "time"))]
+use crate::no_timer::{Deadlines, RateLimits};
+#[cfg(feature = "time")]
+use crate::rate_limit::{RateLimit, RateLimits};
+use crate::{
+    abort::{AbortHandle, Abortable},
+    bias::{Scheduler, Streaks},
+    buffer::Buffer,
+    builder::DrainSource,
+    consumer_check::ConsumerCheck,
+    degrade::{AutoDegrade, Degrader},
+    hooks::RouteHooks,
+    inject::{InjectHandle, Injectable},
+    overflow::OverflowPolicy,
+    pause::{Pausable, PauseHandle},
+    peek::{self, Peeked},
+    quota::{Quota, Quotas},
+    ring_buf::RingBuf,
+    side_waker::{next_consumer_id, SideWaker},
+    source::Source,
+    split_handle::SplitHandle,
+    stats::{Counters, StatsHandle, StatsSlot},
+    sync::{self, LockFamily, Mutex, StdMutex},
+    trace::{trace_event, trace_span, Span},
+    ClosePolicy, PausePolicy, PollBias, ReuniteError, Side, WakeStrategy,
+};
+use futures::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+use pin_project_lite::pin_project;
 
-#[pin_project]
-pub(crate) struct SplitByBuffered<I, S, P, const N: usize> {
-    buf_true: RingBuf<I, N>,
-    buf_false: RingBuf<I, N>,
-    waker_true: Option<Waker>,
-    waker_false: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
+pin_project! {
+    /// The state shared between the two halves returned by `split_by_buffered`.
+    /// This is public so that custom combinators can own it directly and drive each
+    /// side through `poll_next_true` and `poll_next_false` instead of going through
+    /// `TrueSplitByBuffered` and `FalseSplitByBuffered`
+    ///
+    /// Once the underlying stream ends (or the predicate panics) it isn't polled
+    /// again. Each side drains whatever is already buffered for it and then
+    /// yields `None`, regardless of how full the other side's buffer is. The
+    /// items waiting for each side are kept in a `B`, see `Buffer`
+    pub struct SplitByBufferedWith<I, S, P, B> {
+        buf_true: B,
+        buf_false: B,
+        waker_true: SideWaker,
+        waker_false: SideWaker,
+        closed_true: Option<ClosePolicy>,
+        closed_false: Option<ClosePolicy>,
+        paused_true: Option<PausePolicy>,
+        paused_false: Option<PausePolicy>,
+        handles_true: usize,
+        handles_false: usize,
+        scheduler: Scheduler,
+        terminated: bool,
+        streaks: Streaks,
+        quotas: Quotas,
+        // What happens to an item routed to each side while its buffer is full
+        overflow: [OverflowPolicy; 2],
+        // Set by `SplitBuilder::drain_on_drop`, and taken once it has been used
+        drain_on_drop: Option<DrainSource<S>>,
+        degrader: Degrader,
+        // Set by `swap_outputs`, inverting the predicate's results
+        swapped: bool,
+        stats: StatsSlot,
+        hooks: RouteHooks<I>,
+        rate_limits: RateLimits,
+        deadlines: Deadlines<I>,
+        span: Span,
+        panic: Option<Box<dyn Any + Send>>,
+        #[pin]
+        stream: Source<S>,
+        predicate: P,
+    }
 }
 
+/// The state of a split made by `split_by_buffered`, which buffers up to `N`
+/// items for each side in a `RingBuf`
+pub type SplitByBuffered<I, S, P, const N: usize> = SplitByBufferedWith<I, S, P, RingBuf<I, N>>;
+
 impl<I, S, P, const N: usize> SplitByBuffered<I, S, P, N>
 where
     S: Stream<Item = I>,
     P: Fn(&I) -> bool,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            buf_false: RingBuf::new(),
-            buf_true: RingBuf::new(),
-            waker_false: None,
-            waker_true: None,
-            stream,
+    /// Creates the shared state without wrapping it for sharing between two
+    /// halves
+    pub fn new(stream: S, predicate: P) -> Self {
+        Self::with_hooks(stream, predicate, RouteHooks::new())
+    }
+
+    /// Creates the shared state with `hooks` that are called as items are
+    /// routed and buffered
+    pub fn with_hooks(stream: S, predicate: P, hooks: RouteHooks<I>) -> Self {
+        Self::with_buffers(stream, predicate, hooks, RingBuf::new(), RingBuf::new())
+    }
+
+    /// Creates the shared state keeping its counters in `stats`, which lets
+    /// the predicate see them while it routes an item
+    pub(crate) fn with_stats(
+        stream: S,
+        predicate: P,
+        hooks: RouteHooks<I>,
+        stats: Arc<Counters>,
+    ) -> Self {
+        let mut state = Self::with_hooks(stream, predicate, hooks);
+        state.waker_true.count_wakes(Arc::clone(&stats));
+        state.waker_false.count_wakes(Arc::clone(&stats));
+        state.stats = StatsSlot::enabled(stats);
+        state
+    }
+
+    pub(crate) fn new_shared<M: LockFamily>(
+        stream: S,
+        predicate: P,
+        hooks: RouteHooks<I>,
+    ) -> Arc<Mutex<Self, M>> {
+        Arc::new(Mutex::new(Self::with_hooks(stream, predicate, hooks)))
+    }
+}
+
+impl<I, S, P, B: Buffer<I>> SplitByBufferedWith<I, S, P, B>
+where
+    S: Stream<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    /// Creates the shared state keeping the items waiting for each side in
+    /// `buf_true` and `buf_false`, which should both be empty
+    pub fn with_buffers(
+        stream: S,
+        predicate: P,
+        hooks: RouteHooks<I>,
+        buf_true: B,
+        buf_false: B,
+    ) -> Self {
+        Self {
+            buf_false,
+            buf_true,
+            waker_false: SideWaker::new("false"),
+            waker_true: SideWaker::new("true"),
+            closed_false: None,
+            closed_true: None,
+            paused_true: None,
+            paused_false: None,
+            handles_true: 0,
+            handles_false: 0,
+            scheduler: Scheduler::default(),
+            terminated: false,
+            streaks: Streaks::default(),
+            quotas: Quotas::default(),
+            overflow: [OverflowPolicy::Block; 2],
+            drain_on_drop: None,
+            degrader: Degrader::default(),
+            swapped: false,
+            stats: StatsSlot::default(),
+            hooks,
+            rate_limits: RateLimits::default(),
+            deadlines: Deadlines::default(),
+            span: trace_span!("split_by_buffered"),
+            panic: None,
+            stream: Source::new(stream),
             predicate,
-        }))
+        }
+    }
+
+    pub(crate) fn size_hint_true(&self) -> (usize, Option<usize>) {
+        let buffered = self.buf_true.len();
+        if self.closed_true.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
     }
 
-    fn poll_next_true(
+    pub(crate) fn size_hint_false(&self) -> (usize, Option<usize>) {
+        let buffered = self.buf_false.len();
+        if self.closed_false.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
+    }
+
+    /// Polls for the next item where the predicate returns `true`. Items meant
+    /// for the other side are buffered and its waker is notified, and
+    /// `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_true(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<I>> {
+        self.poll_next_true_as(0, cx)
+    }
+
+    /// The same as `poll_next_true`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_true_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        self.as_mut().project().degrader.polled(Side::Left);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Left, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Left) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `false` stream run before carrying on
+            let this = self.project();
+            this.waker_false.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_true(consumer, cx);
+        self.as_mut().project().waker_false.flush();
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Left, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Left);
+            this.streaks.yielded(Side::Left);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Left);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `true` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_true(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Left);
+        if !self.buf_true.is_empty() {
+            return Poll::Ready(true);
+        }
+        let poll = Pin::new(&mut *self).route_next_true(consumer, cx);
+        self.waker_false.flush();
+        let item = match poll {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        let rejected = self.buf_true.push_back(item);
+        debug_assert!(rejected.is_none());
+        self.stats.buffered(Side::Left, self.buf_true.len());
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, self.buf_true.len());
+        self.hooks.buffered(Side::Left, self.buf_true.len());
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_true_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_true(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_true(consumer, cx),
+            |this| this.end_after_panic(Side::Left),
+        )
+    }
+
+    /// Ends both streams after something panicked while `side` was being
+    /// polled, be it the underlying stream, a hook or an item being dropped,
+    /// as a panicking predicate does. The state may have been left half
+    /// updated, so rather than have the other stream carry on with it, it
+    /// yields what's already buffered for it and ends. The panic itself
+    /// carries on to whoever was polling `side`
+    fn end_after_panic(self: std::pin::Pin<&mut Self>, side: Side) {
         let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_true.is_none() {
-            *this.waker_true = Some(cx.waker().clone());
+        if !*this.terminated {
+            *this.terminated = true;
+            this.stats.terminated();
+        }
+        match side {
+            Side::Left => this.waker_false.wake(),
+            Side::Right => this.waker_true.wake(),
+        }
+    }
+
+    /// `route_next_true` without ending both streams if it panics
+    fn try_route_next_true(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_true.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_true.register(consumer, cx.waker());
+        this.stats.polled(Side::Left);
+        if this.paused_true.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
+        }
+        #[cfg(feature = "time")]
+        {
+            // Anything that has waited too long for the `false` stream is either rerouted
+            // here or discarded, as is anything that has waited too long for this one unless
+            // it would be rerouted, since this stream is getting to it now
+            while let Some(policy) = this.deadlines.expired(Side::Right) {
+                let item = this
+                    .buf_false
+                    .pop_front()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Right);
+                this.stats.unbuffered(Side::Right, this.buf_false.len());
+                this.hooks.buffered(Side::Right, this.buf_false.len());
+                trace_event!(side = "false", ?policy, "item expired");
+                if policy == DeadlinePolicy::Reroute {
+                    return Poll::Ready(Some(item));
+                }
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                }
+            }
+            while this
+                .deadlines
+                .expired(Side::Left)
+                .is_some_and(|policy| policy != DeadlinePolicy::Reroute)
+            {
+                let item = this
+                    .buf_true
+                    .pop_front()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Left);
+                this.stats.unbuffered(Side::Left, this.buf_true.len());
+                this.hooks.buffered(Side::Left, this.buf_true.len());
+                trace_event!(side = "true", "item expired");
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                }
+                // The `false` stream may be waiting on space in the buffer
+                if this.buf_true.remaining() == 1 {
+                    this.waker_false.wake();
+                }
+            }
+            // Whichever of the two comes first, this stream is woken when the oldest item
+            // buffered for the `false` stream expires
+            this.deadlines.poll_expiry(Side::Right, cx);
         }
         if let Some(item) = this.buf_true.pop_front() {
-            // There was already a value in the buffer. Return that value
+            this.stats.unbuffered(Side::Left, this.buf_true.len());
+            #[cfg(feature = "time")]
+            this.deadlines.unbuffered(Side::Left);
+            this.hooks.buffered(Side::Left, this.buf_true.len());
+            // There was already a value in the buffer. Return that value, waking the `false`
+            // stream if the buffer was full since it may be waiting on space in it, or if it's
+            // waiting for this stream to take everything buffered for it or its turn
+            let new_round = this.scheduler.yielded(Side::Left);
+            let deferring =
+                new_round || this.buf_true.is_empty() && this.scheduler.defers(Side::Right);
+            if (this.buf_true.remaining() == 1 || deferring) && !*this.terminated {
+                this.waker_false.wake();
+            }
             return Poll::Ready(Some(item));
         }
-        if this.buf_false.remaining() == 0 {
-            // The other buffer is full, so notify that stream and return pending
-            if let Some(waker) = this.waker_false {
-                waker.wake_by_ref();
-            }
+        if this.quotas.ended(Side::Left) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        if !this.buf_false.is_empty()
+            && this.paused_false.is_none()
+            && !this.degrader.is_degraded(Side::Right)
+            && this.scheduler.defers(Side::Left)
+        {
+            // The `false` stream is served first, so leave the underlying stream alone until
+            // it has taken everything buffered for it
+            trace_event!(side = "true", "deferred to other side");
+            this.waker_false.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    Poll::Ready(Some(item))
-                } else {
-                    // This value is not what we wanted. Store it and notify other partition task if
-                    // it exists. This can't fail because we checked above that the buffer isn't
-                    // full
-                    let _ = this.buf_false.push_back(item);
-                    if let Some(waker) = this.waker_false {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
+        let degraded = this.degrader.check(Side::Right, this.buf_false.len());
+        if !degraded
+            && this.buf_false.remaining() == 0
+            && this.overflow[Side::Right as usize] == OverflowPolicy::Block
+            && !matches!(
+                this.paused_false,
+                Some(PausePolicy::Drop) | Some(PausePolicy::Divert)
+            )
+        {
+            // The `false` buffer is full and nothing can be read from the stream until the
+            // `false` stream makes room, so notify it and return pending
+            trace_event!(side = "false", "buffer full");
+            this.stats.buffer_full(Side::Right);
+            this.waker_false.wake();
+            return Poll::Pending;
+        }
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `false` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_false.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let is_true = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(&item))) {
+                Ok(is_true) => is_true != *this.swapped,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_false.wake();
+                    return Poll::Ready(None);
+                }
+            };
+            let side = if is_true { Side::Left } else { Side::Right };
+            let is_true = match this.quotas.route(side, true) {
+                Some(side) => side == Side::Left,
+                None => {
+                    // The side the item is for has received its quota and drops what's
+                    // over it
+                    trace_event!(?side, "item over quota");
+                    this.stats.discarded(side, 1);
+                    this.hooks.discarded(side, Some(item));
+                    continue;
                 }
+            };
+            if is_true {
+                trace_event!(side = "true", "item routed");
+                this.hooks.routed_left(&item);
+                this.stats.routed(Side::Left);
+                this.scheduler.yielded(Side::Left);
+                return Poll::Ready(Some(item));
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `false` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_false {
-                    waker.wake_by_ref();
+            trace_event!(side = "false", "item routed");
+            this.hooks.routed_right(&item);
+            this.stats.routed(Side::Right);
+            match this.closed_false {
+                Some(ClosePolicy::Discard) => {
+                    // The `false` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "false", "item discarded");
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                    continue;
                 }
-                Poll::Ready(None)
+                Some(ClosePolicy::EndSibling) => {
+                    // The `false` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_true = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Left);
+                    return Poll::Ready(None);
+                }
+                None => {}
+            }
+            match this.paused_false {
+                Some(PausePolicy::Drop) => {
+                    // The `false` stream is paused and its items are being dropped until it's
+                    // resumed, so keep polling
+                    trace_event!(side = "false", "item discarded");
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                    continue;
+                }
+                Some(PausePolicy::Divert) => {
+                    // The `false` stream is paused and its items are being diverted to this one
+                    trace_event!(side = "true", "item diverted");
+                    return Poll::Ready(Some(item));
+                }
+                _ => {}
             }
-            Poll::Pending => Poll::Pending,
+            if degraded {
+                // The `false` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "false", "item discarded");
+                this.stats.discarded(Side::Right, 1);
+                this.hooks.discarded_right(Some(item));
+                continue;
+            }
+            if this.buf_false.remaining() == 0 {
+                // The `false` buffer is full, which only gets this far when its overflow
+                // policy drops an item rather than holding up this stream
+                trace_event!(side = "false", "buffer overflowed");
+                this.stats.buffer_full(Side::Right);
+                this.stats.discarded(Side::Right, 1);
+                match this.overflow[Side::Right as usize] {
+                    OverflowPolicy::DropOldest => {
+                        let oldest = this.buf_false.pop_front().expect("the buffer is full");
+                        this.stats.unbuffered(Side::Right, this.buf_false.len());
+                        #[cfg(feature = "time")]
+                        this.deadlines.unbuffered(Side::Right);
+                        this.hooks.discarded_right(Some(oldest));
+                    }
+                    OverflowPolicy::Block | OverflowPolicy::DropNewest => {
+                        this.hooks.discarded_right(Some(item));
+                        continue;
+                    }
+                }
+            }
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. There's room for it, since the buffer wasn't full
+            // when it was checked above or has just had room made in it, so the item is
+            // never lost between here and being yielded
+            let rejected = this.buf_false.push_back(item);
+            debug_assert!(rejected.is_none());
+            this.stats.buffered(Side::Right, this.buf_false.len());
+            #[cfg(feature = "time")]
+            this.deadlines.buffered(Side::Right, this.buf_false.len());
+            this.hooks.buffered(Side::Right, this.buf_false.len());
+            trace_event!(
+                side = "false",
+                buffered = this.buf_false.len(),
+                "item buffered"
+            );
+            #[cfg(feature = "time")]
+            this.deadlines.poll_expiry(Side::Right, cx);
+            if !this.waker_false.wake_buffered(this.buf_false.len())
+                && this.buf_false.remaining() > 0
+            {
+                // The wake strategy is holding the wake back, so keep reading. The `false`
+                // stream is woken once this stops, by whoever called this
+                continue;
+            }
+            return Poll::Pending;
         }
     }
 
-    fn poll_next_false(
+    /// Polls for the next item where the predicate returns `false`. Items meant
+    /// for the other side are buffered and its waker is notified, and
+    /// `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_false(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_false.is_none() {
-            *this.waker_false = Some(cx.waker().clone());
+        self.poll_next_false_as(0, cx)
+    }
+
+    /// The same as `poll_next_false`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_false_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        self.as_mut().project().degrader.polled(Side::Right);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Right, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Right) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `true` stream run before carrying on
+            let this = self.project();
+            this.waker_true.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_false(consumer, cx);
+        self.as_mut().project().waker_true.flush();
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Right, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Right);
+            this.streaks.yielded(Side::Right);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Right);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `false` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_false(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Right);
+        if !self.buf_false.is_empty() {
+            return Poll::Ready(true);
+        }
+        let poll = Pin::new(&mut *self).route_next_false(consumer, cx);
+        self.waker_true.flush();
+        let item = match poll {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        let rejected = self.buf_false.push_back(item);
+        debug_assert!(rejected.is_none());
+        self.stats.buffered(Side::Right, self.buf_false.len());
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, self.buf_false.len());
+        self.hooks.buffered(Side::Right, self.buf_false.len());
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_false_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_false(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_false(consumer, cx),
+            |this| this.end_after_panic(Side::Right),
+        )
+    }
+
+    /// `route_next_false` without ending both streams if it panics
+    fn try_route_next_false(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<I>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_false.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_false.register(consumer, cx.waker());
+        this.stats.polled(Side::Right);
+        if this.paused_false.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
+        }
+        #[cfg(feature = "time")]
+        {
+            // Anything that has waited too long for the `true` stream is either rerouted
+            // here or discarded, as is anything that has waited too long for this one unless
+            // it would be rerouted, since this stream is getting to it now
+            while let Some(policy) = this.deadlines.expired(Side::Left) {
+                let item = this
+                    .buf_true
+                    .pop_front()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Left);
+                this.stats.unbuffered(Side::Left, this.buf_true.len());
+                this.hooks.buffered(Side::Left, this.buf_true.len());
+                trace_event!(side = "true", ?policy, "item expired");
+                if policy == DeadlinePolicy::Reroute {
+                    return Poll::Ready(Some(item));
+                }
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                }
+            }
+            while this
+                .deadlines
+                .expired(Side::Right)
+                .is_some_and(|policy| policy != DeadlinePolicy::Reroute)
+            {
+                let item = this
+                    .buf_false
+                    .pop_front()
+                    .expect("expired items are still buffered");
+                this.deadlines.unbuffered(Side::Right);
+                this.stats.unbuffered(Side::Right, this.buf_false.len());
+                this.hooks.buffered(Side::Right, this.buf_false.len());
+                trace_event!(side = "false", "item expired");
+                if let Some(item) = this.deadlines.discard(item) {
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                }
+                // The `true` stream may be waiting on space in the buffer
+                if this.buf_false.remaining() == 1 {
+                    this.waker_true.wake();
+                }
+            }
+            // Whichever of the two comes first, this stream is woken when the oldest item
+            // buffered for the `true` stream expires
+            this.deadlines.poll_expiry(Side::Left, cx);
         }
         if let Some(item) = this.buf_false.pop_front() {
-            // There was already a value in the buffer. Return that value
+            this.stats.unbuffered(Side::Right, this.buf_false.len());
+            #[cfg(feature = "time")]
+            this.deadlines.unbuffered(Side::Right);
+            this.hooks.buffered(Side::Right, this.buf_false.len());
+            // There was already a value in the buffer. Return that value, waking the `true`
+            // stream if the buffer was full since it may be waiting on space in it, or if it's
+            // waiting for this stream to take everything buffered for it or its turn
+            let new_round = this.scheduler.yielded(Side::Right);
+            let deferring =
+                new_round || this.buf_false.is_empty() && this.scheduler.defers(Side::Left);
+            if (this.buf_false.remaining() == 1 || deferring) && !*this.terminated {
+                this.waker_true.wake();
+            }
             return Poll::Ready(Some(item));
         }
-        if this.buf_true.remaining() == 0 {
-            // The other buffer is full, so notify that stream and return pending
-            if let Some(waker) = this.waker_true {
-                waker.wake_by_ref();
-            }
+        if this.quotas.ended(Side::Right) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        if !this.buf_true.is_empty()
+            && this.paused_true.is_none()
+            && !this.degrader.is_degraded(Side::Left)
+            && this.scheduler.defers(Side::Right)
+        {
+            // The `true` stream is served first, so leave the underlying stream alone until
+            // it has taken everything buffered for it
+            trace_event!(side = "false", "deferred to other side");
+            this.waker_true.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    // This value is not what we wanted. Store it and notify other stream if waker
-                    // it exists. This can't fail because we checked above that the buffer isn't
-                    // full
-                    let _ = this.buf_true.push_back(item);
-                    if let Some(waker) = this.waker_true {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Some(item))
+        let degraded = this.degrader.check(Side::Left, this.buf_true.len());
+        if !degraded
+            && this.buf_true.remaining() == 0
+            && this.overflow[Side::Left as usize] == OverflowPolicy::Block
+            && !matches!(
+                this.paused_true,
+                Some(PausePolicy::Drop) | Some(PausePolicy::Divert)
+            )
+        {
+            // The `true` buffer is full and nothing can be read from the stream until the
+            // `true` stream makes room, so notify it and return pending
+            trace_event!(side = "true", "buffer full");
+            this.stats.buffer_full(Side::Left);
+            this.waker_true.wake();
+            return Poll::Pending;
+        }
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `true` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_true.wake();
+                    return Poll::Ready(None);
                 }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let is_true = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(&item))) {
+                Ok(is_true) => is_true != *this.swapped,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_true.wake();
+                    return Poll::Ready(None);
+                }
+            };
+            let side = if is_true { Side::Left } else { Side::Right };
+            let is_true = match this.quotas.route(side, true) {
+                Some(side) => side == Side::Left,
+                None => {
+                    // The side the item is for has received its quota and drops what's
+                    // over it
+                    trace_event!(?side, "item over quota");
+                    this.stats.discarded(side, 1);
+                    this.hooks.discarded(side, Some(item));
+                    continue;
+                }
+            };
+            if !is_true {
+                trace_event!(side = "false", "item routed");
+                this.hooks.routed_right(&item);
+                this.stats.routed(Side::Right);
+                this.scheduler.yielded(Side::Right);
+                return Poll::Ready(Some(item));
+            }
+            trace_event!(side = "true", "item routed");
+            this.hooks.routed_left(&item);
+            this.stats.routed(Side::Left);
+            match this.closed_true {
+                Some(ClosePolicy::Discard) => {
+                    // The `true` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "true", "item discarded");
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                    continue;
+                }
+                Some(ClosePolicy::EndSibling) => {
+                    // The `true` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_false = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Right);
+                    return Poll::Ready(None);
+                }
+                None => {}
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `true` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_true {
-                    waker.wake_by_ref();
+            match this.paused_true {
+                Some(PausePolicy::Drop) => {
+                    // The `true` stream is paused and its items are being dropped until it's
+                    // resumed, so keep polling
+                    trace_event!(side = "true", "item discarded");
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                    continue;
                 }
-                Poll::Ready(None)
+                Some(PausePolicy::Divert) => {
+                    // The `true` stream is paused and its items are being diverted to this one
+                    trace_event!(side = "false", "item diverted");
+                    return Poll::Ready(Some(item));
+                }
+                _ => {}
+            }
+            if degraded {
+                // The `true` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "true", "item discarded");
+                this.stats.discarded(Side::Left, 1);
+                this.hooks.discarded_left(Some(item));
+                continue;
             }
-            Poll::Pending => Poll::Pending,
+            if this.buf_true.remaining() == 0 {
+                // The `true` buffer is full, which only gets this far when its overflow
+                // policy drops an item rather than holding up this stream
+                trace_event!(side = "true", "buffer overflowed");
+                this.stats.buffer_full(Side::Left);
+                this.stats.discarded(Side::Left, 1);
+                match this.overflow[Side::Left as usize] {
+                    OverflowPolicy::DropOldest => {
+                        let oldest = this.buf_true.pop_front().expect("the buffer is full");
+                        this.stats.unbuffered(Side::Left, this.buf_true.len());
+                        #[cfg(feature = "time")]
+                        this.deadlines.unbuffered(Side::Left);
+                        this.hooks.discarded_left(Some(oldest));
+                    }
+                    OverflowPolicy::Block | OverflowPolicy::DropNewest => {
+                        this.hooks.discarded_left(Some(item));
+                        continue;
+                    }
+                }
+            }
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. There's room for it, since the buffer wasn't full
+            // when it was checked above or has just had room made in it, so the item is
+            // never lost between here and being yielded
+            let rejected = this.buf_true.push_back(item);
+            debug_assert!(rejected.is_none());
+            this.stats.buffered(Side::Left, this.buf_true.len());
+            #[cfg(feature = "time")]
+            this.deadlines.buffered(Side::Left, this.buf_true.len());
+            this.hooks.buffered(Side::Left, this.buf_true.len());
+            trace_event!(
+                side = "true",
+                buffered = this.buf_true.len(),
+                "item buffered"
+            );
+            #[cfg(feature = "time")]
+            this.deadlines.poll_expiry(Side::Left, cx);
+            if !this.waker_true.wake_buffered(this.buf_true.len()) && this.buf_true.remaining() > 0
+            {
+                // The wake strategy is holding the wake back, so keep reading. The `true`
+                // stream is woken once this stops, by whoever called this
+                continue;
+            }
+            return Poll::Pending;
         }
     }
 }
 
+impl<I, S, P, B: Buffer<I>> SplitByBufferedWith<I, S, P, B> {
+    /// Panics if the shared state is in a state it should never reach, given
+    /// that polling `side` just returned `poll`
+    #[cfg(feature = "debug-invariants")]
+    fn check_invariants<T>(&self, side: Side, poll: &Poll<Option<T>>) {
+        invariants::check(
+            side,
+            poll,
+            [
+                SideState {
+                    buffered: self.buf_true.len(),
+                    closed: self.closed_true.is_some(),
+                    waker: &self.waker_true,
+                },
+                SideState {
+                    buffered: self.buf_false.len(),
+                    closed: self.closed_false.is_some(),
+                    waker: &self.waker_false,
+                },
+            ],
+            self.terminated,
+            self.stream.is_some(),
+            &self.stats,
+        );
+    }
+
+    /// Returns a handle to the counters, which are only kept from the first
+    /// time this is called
+    pub(crate) fn stats_handle(&mut self) -> StatsHandle {
+        if let Some(counters) = self.stats.enable() {
+            self.waker_true.count_wakes(Arc::clone(&counters));
+            self.waker_false.count_wakes(counters);
+        }
+        self.stats.handle()
+    }
+
+    pub(crate) fn is_terminated_true(&self) -> bool {
+        self.closed_true.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Left)) && self.buf_true.is_empty())
+    }
+
+    pub(crate) fn is_terminated_false(&self) -> bool {
+        self.closed_false.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Right)) && self.buf_false.is_empty())
+    }
+
+    /// Adds a summary of the `true` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_true(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Left)
+            .field("buffered", &self.buf_true.len())
+            .field(
+                "sibling_waker_registered",
+                &self.waker_false.is_registered(),
+            )
+            .field("closed", &self.closed_true)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Adds a summary of the `false` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_false(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Right)
+            .field("buffered", &self.buf_false.len())
+            .field("sibling_waker_registered", &self.waker_true.is_registered())
+            .field("closed", &self.closed_false)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Pauses the `true` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `false` side is woken since it may no
+    /// longer have to wait on the `true` buffer
+    pub(crate) fn pause_true(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", ?policy, "stream paused");
+        self.paused_true = Some(policy);
+        self.waker_false.wake();
+    }
+
+    /// Resumes the `true` side, waking it if it was paused
+    pub(crate) fn resume_true(&mut self) {
+        if self.paused_true.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "true", "stream resumed");
+            self.waker_true.wake();
+        }
+    }
+
+    /// Pauses the `false` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `true` side is woken since it may no
+    /// longer have to wait on the `false` buffer
+    pub(crate) fn pause_false(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", ?policy, "stream paused");
+        self.paused_false = Some(policy);
+        self.waker_true.wake();
+    }
+
+    /// Resumes the `false` side, waking it if it was paused
+    pub(crate) fn resume_false(&mut self) {
+        if self.paused_false.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "false", "stream resumed");
+            self.waker_false.wake();
+        }
+    }
+
+    /// Sets how long an item may wait in either buffer before it's handled
+    /// according to `policy`, waking both sides so that their timers are set
+    #[cfg(feature = "time")]
+    pub(crate) fn set_deadline(&mut self, deadline: Duration, policy: DeadlinePolicy) {
+        self.deadlines.set(deadline, policy);
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Sets which side, if either, reads from the underlying stream only once
+    /// everything buffered for the other side has been taken, waking both
+    /// sides since either may have been waiting on the other
+    pub(crate) fn set_bias(&mut self, bias: Option<PollBias>) {
+        self.scheduler.set(bias);
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Swaps which stream the predicate's results go to, moving whatever is
+    /// already buffered along with them. Anything that ends up buffered for a
+    /// closed side is dropped, and both sides are woken
+    pub(crate) fn swap_outputs(&mut self) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        self.swapped = !self.swapped;
+        trace_event!(swapped = self.swapped, "outputs swapped");
+        std::mem::swap(&mut self.buf_true, &mut self.buf_false);
+        if self.closed_true.is_some() {
+            self.stats.discarded(Side::Left, self.buf_true.len());
+            self.hooks.discarded_left(self.buf_true.drain());
+        }
+        if self.closed_false.is_some() {
+            self.stats.discarded(Side::Right, self.buf_false.len());
+            self.hooks.discarded_right(self.buf_false.drain());
+        }
+        #[cfg(feature = "time")]
+        self.deadlines.swap();
+        for (side, len) in [
+            (Side::Left, self.buf_true.len()),
+            (Side::Right, self.buf_false.len()),
+        ] {
+            self.stats.buffered(side, len);
+            #[cfg(feature = "time")]
+            self.deadlines.buffered(side, len);
+            self.hooks.buffered(side, len);
+        }
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Sets when a side that isn't keeping up starts having its items dropped,
+    /// or turns that off, recovering both sides
+    pub(crate) fn set_auto_degrade(&mut self, policy: Option<AutoDegrade>) {
+        self.degrader.set(policy);
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Caps how many items in a row either side may yield before giving way to
+    /// the other, or removes the cap
+    pub(crate) fn set_max_consecutive(&mut self, max: Option<u32>) {
+        self.streaks.set(max);
+    }
+
+    /// Sets what happens to items routed to `side` while its buffer is full,
+    /// waking the other side in case it was waiting on room in that buffer
+    pub(crate) fn set_overflow_policy(&mut self, side: Side, policy: OverflowPolicy) {
+        self.overflow[side as usize] = policy;
+        match side {
+            Side::Left => self.waker_false.wake(),
+            Side::Right => self.waker_true.wake(),
+        }
+    }
+
+    /// Caps how many items `side` receives, or removes its cap, waking it in
+    /// case that ends it
+    pub(crate) fn set_quota(&mut self, side: Side, quota: Option<Quota>) {
+        self.quotas.set(side, quota);
+        match side {
+            Side::Left => self.waker_true.wake(),
+            Side::Right => self.waker_false.wake(),
+        }
+    }
+
+    pub(crate) fn quota_remaining(&self, side: Side) -> Option<u64> {
+        self.quotas.remaining(side)
+    }
+
+    /// Limits how fast `side` yields items, or removes its limit, waking it in
+    /// case it was waiting on the previous limit
+    #[cfg(feature = "time")]
+    pub(crate) fn set_rate_limit(&mut self, side: Side, limit: Option<RateLimit>) {
+        self.rate_limits.set(side, limit);
+        match side {
+            Side::Left => self.waker_true.wake(),
+            Side::Right => self.waker_false.wake(),
+        }
+    }
+
+    /// Buffers an item from outside the split for the `true` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_true(&mut self, item: I) -> Result<(), I> {
+        if self.closed_true.is_some() || self.terminated || self.quotas.ended(Side::Left) {
+            return Err(item);
+        }
+        if let Some(item) = self.buf_true.push_back(item) {
+            return Err(item);
+        }
+        let len = self.buf_true.len();
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", buffered = len, "item injected");
+        self.stats.buffered(Side::Left, len);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, len);
+        self.hooks.buffered(Side::Left, len);
+        self.waker_true.wake();
+        Ok(())
+    }
+
+    /// Buffers an item from outside the split for the `false` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_false(&mut self, item: I) -> Result<(), I> {
+        if self.closed_false.is_some() || self.terminated || self.quotas.ended(Side::Right) {
+            return Err(item);
+        }
+        if let Some(item) = self.buf_false.push_back(item) {
+            return Err(item);
+        }
+        let len = self.buf_false.len();
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", buffered = len, "item injected");
+        self.stats.buffered(Side::Right, len);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, len);
+        self.hooks.buffered(Side::Right, len);
+        self.waker_false.wake();
+        Ok(())
+    }
+
+    /// Ends both sides and takes the underlying stream along with anything
+    /// buffered for either side, unless this was already done
+    pub(crate) fn abort(&mut self) -> Option<SplitByBufferedParts<I, S>>
+    where
+        S: Unpin,
+    {
+        let stream = self.stream.take()?;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!("splitter aborted");
+        self.terminated = true;
+        self.stats.terminated();
+        // The closures would otherwise capture all of `self`, which `_entered` borrows
+        let (buf_true, buf_false) = (&mut self.buf_true, &mut self.buf_false);
+        let parts = SplitByBufferedParts {
+            stream,
+            buffered_true: buf_true.drain().collect(),
+            buffered_false: buf_false.drain().collect(),
+        };
+        self.stats.buffered(Side::Left, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.stats.buffered(Side::Right, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_true.wake();
+        self.waker_false.wake();
+        Some(parts)
+    }
+
+    /// Counts another output stream handle for the `true` side
+    pub(crate) fn attach_true(&mut self) {
+        self.handles_true += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `true` side once no handles are left
+    pub(crate) fn detach_true(&mut self, consumer: usize) {
+        self.waker_true.unregister(consumer);
+        self.handles_true -= 1;
+        if self.handles_true == 0 {
+            self.close_true(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    /// Counts another output stream handle for the `false` side
+    pub(crate) fn attach_false(&mut self) {
+        self.handles_false += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `false` side once no handles are left
+    pub(crate) fn detach_false(&mut self, consumer: usize) {
+        self.waker_false.unregister(consumer);
+        self.handles_false -= 1;
+        if self.handles_false == 0 {
+            self.close_false(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    pub(crate) fn set_drain_on_drop(&mut self, drain: DrainSource<S>) {
+        self.drain_on_drop = Some(drain);
+    }
+
+    /// Sets the wake strategies for the first and second side
+    pub(crate) fn set_wake_strategy(&mut self, [first, second]: [Box<dyn WakeStrategy>; 2]) {
+        self.waker_true.set_strategy(first);
+        self.waker_false.set_strategy(second);
+    }
+
+    /// Hands the underlying stream over to be read to the end once both sides
+    /// have been dropped, if the split was built with `drain_on_drop` and the
+    /// stream hasn't ended already
+    fn drain_if_detached(&mut self) {
+        if self.handles_true > 0 || self.handles_false > 0 || self.terminated {
+            return;
+        }
+        if let Some(drain) = self.drain_on_drop.take() {
+            trace_event!("draining the stream");
+            drain.drain(&mut self.stream);
+        }
+    }
+
+    /// Marks the `true` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_true(&mut self, policy: ClosePolicy) {
+        if self.closed_true.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "true", ?policy, "stream closed");
+        self.closed_true = Some(policy);
+        self.stats.closed(Side::Left);
+        self.stats.discarded(Side::Left, self.buf_true.len());
+        let mut discarded = self.buf_true.take();
+        self.stats.buffered(Side::Left, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.waker_false.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_left(discarded.drain());
+    }
+
+    /// Marks the `false` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_false(&mut self, policy: ClosePolicy) {
+        if self.closed_false.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "false", ?policy, "stream closed");
+        self.closed_false = Some(policy);
+        self.stats.closed(Side::Right);
+        self.stats.discarded(Side::Right, self.buf_false.len());
+        let mut discarded = self.buf_false.take();
+        self.stats.buffered(Side::Right, 0);
+        #[cfg(feature = "time")]
+        self.deadlines.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_true.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_right(discarded.drain());
+    }
+}
+
+impl<I, S, P, B: Buffer<I>> Pausable for SplitByBufferedWith<I, S, P, B> {
+    fn pause(&mut self, side: Side, policy: PausePolicy) {
+        match side {
+            Side::Left => self.pause_true(policy),
+            Side::Right => self.pause_false(policy),
+        }
+    }
+
+    fn resume(&mut self, side: Side) {
+        match side {
+            Side::Left => self.resume_true(),
+            Side::Right => self.resume_false(),
+        }
+    }
+}
+
+impl<I, S, P, B: Buffer<I>> Injectable for SplitByBufferedWith<I, S, P, B> {
+    type Left = I;
+    type Right = I;
+
+    fn inject_left(&mut self, item: I) -> Result<(), I> {
+        self.inject_true(item)
+    }
+
+    fn inject_right(&mut self, item: I) -> Result<(), I> {
+        self.inject_false(item)
+    }
+}
+
+impl<I, S: Unpin, P, B: Buffer<I>> Abortable for SplitByBufferedWith<I, S, P, B> {
+    type Parts = SplitByBufferedParts<I, S>;
+
+    fn abort(&mut self) -> Option<Self::Parts> {
+        SplitByBufferedWith::abort(self)
+    }
+}
+
+/// The pieces of a `split_by_buffered` that has been taken apart with `into_parts`
+pub struct SplitByBufferedParts<I, S> {
+    /// The underlying stream, positioned after the last item that was read
+    pub stream: S,
+    /// The items that were buffered for the `true` stream, oldest first
+    pub buffered_true: Vec<I>,
+    /// The items that were buffered for the `false` stream, oldest first
+    pub buffered_false: Vec<I>,
+}
+
 /// A struct that implements `Stream` which returns the items where the
 /// predicate returns `true`
-pub struct TrueSplitByBuffered<I, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>,
+pub struct TrueSplitByBufferedWith<I, S, P, B: Buffer<I>, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitByBufferedWith<I, S, P, B>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
 }
 
-impl<I, S, P, const N: usize> TrueSplitByBuffered<I, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>) -> Self {
-        Self { stream }
+/// `TrueSplitByBufferedWith` buffering up to `N` items for each side in a
+/// `RingBuf`, which is what `split_by_buffered` returns
+pub type TrueSplitByBuffered<I, S, P, const N: usize, M = StdMutex> =
+    TrueSplitByBufferedWith<I, S, P, RingBuf<I, N>, M>;
+
+/// `TrueSplitByBuffered` without the item type of the underlying stream, which
+/// is taken from `S`, so that the `true` stream can be stored in a struct
+/// naming only the stream and the predicate
+pub type TrueBuffered<S, P, const N: usize> = TrueSplitByBuffered<<S as Stream>::Item, S, P, N>;
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> TrueSplitByBufferedWith<I, S, P, B, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitByBufferedWith<I, S, P, B>, M>>) -> Self {
+        stream.lock().attach_true();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_true(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.panic.take()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Left)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Left)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_false > 0 && guard.closed_false.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        !guard.buf_true.is_empty()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Sets what happens to items routed to this stream while its buffer is
+    /// full. By default the other stream waits for this one to make room, see
+    /// `OverflowPolicy` for the alternatives
+    pub fn set_overflow_policy(&self, policy: OverflowPolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_overflow_policy(Side::Left, policy);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Left, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Left)
+    }
+
+    pub(crate) fn set_drain_on_drop(&self, drain: DrainSource<S>) {
+        let mut guard = self.stream.lock();
+        guard.set_drain_on_drop(drain);
+    }
+
+    pub(crate) fn set_wake_strategy(&self, strategy: [Box<dyn WakeStrategy>; 2]) {
+        let mut guard = self.stream.lock();
+        guard.set_wake_strategy(strategy);
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Left, limit);
+    }
+
+    /// Sets which stream is served first when both are being polled, or
+    /// removes the bias with `None`. See `PollBias` for how it works
+    pub fn set_bias(&self, bias: Option<PollBias>) {
+        let mut guard = self.stream.lock();
+        guard.set_bias(bias);
+    }
+
+    /// Swaps which of the two streams the predicate's results go to, so that
+    /// items it returns `true` for are yielded by the `false` stream and vice
+    /// versa. Items that are already buffered move over as well, so each one
+    /// is yielded as if it had been routed after the swap
+    pub fn swap_outputs(&self) {
+        let mut guard = self.stream.lock();
+        guard.swap_outputs();
+    }
+
+    /// Sets how long an item may wait in either stream's buffer. An item that
+    /// has waited for longer than `deadline` is handled according to `policy`
+    /// the next time either stream is polled, and the stream that didn't get
+    /// to it is woken when that's due. Setting it again replaces the previous
+    /// deadline, including for items that are already buffered
+    #[cfg(feature = "time")]
+    pub fn set_deadline(&self, deadline: Duration, policy: DeadlinePolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_deadline(deadline, policy);
+    }
+
+    /// Returns a stream of the items that expired with
+    /// `DeadlinePolicy::DeadLetter`. Only one dead letter stream is fed at a
+    /// time, so creating another one ends this one
+    #[cfg(feature = "time")]
+    pub fn dead_letters(&self) -> DeadLetters<I> {
+        let mut guard = self.stream.lock();
+        guard.deadlines.dead_letters()
+    }
+
+    /// Takes apart the split, returning the underlying stream along with any
+    /// items that were read from it but not yet yielded. `other` must be the
+    /// stream that was created alongside this one, and neither may have been
+    /// cloned, otherwise both are handed back in the error. They're also
+    /// handed back if the underlying stream was already taken with
+    /// `AbortHandle::abort`
+    pub fn into_parts(
+        self,
+        other: FalseSplitByBufferedWith<I, S, P, B, M>,
+    ) -> Result<
+        SplitByBufferedParts<I, S>,
+        ReuniteError<Self, FalseSplitByBufferedWith<I, S, P, B, M>>,
+    >
+    where
+        S: Unpin,
+    {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        let parts = {
+            let mut guard = self.stream.lock();
+            if guard.handles_true == 1 && guard.handles_false == 1 {
+                guard.stream.take().map(|stream| SplitByBufferedParts {
+                    stream,
+                    buffered_true: guard.buf_true.drain().collect(),
+                    buffered_false: guard.buf_false.drain().collect(),
+                })
+            } else {
+                None
+            }
+        };
+        // Dropping the halves afterwards closes both sides, which is harmless now that
+        // their buffers are empty
+        parts.ok_or(ReuniteError(self, other))
     }
 }
 
-impl<I, S, P, const N: usize> Stream for TrueSplitByBuffered<I, S, P, N>
+impl<I, S, P, B: Buffer<I>, M: LockFamily> TrueSplitByBufferedWith<I, S, P, B, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitByBufferedWith<I, S, P, B>, I, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "TrueSplitByBuffered",
+            cx,
+            |state, cx| state.poll_peek_true(id, cx),
+            |state| state.buf_true.peek_front(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitByBufferedWith<I, S, P, B>, I, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "TrueSplitByBuffered",
+            |state, cx| state.poll_peek_true(id, cx),
+            |state| state.buf_true.peek_front(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Stream for TrueSplitByBufferedWith<I, S, P, B, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
@@ -152,29 +1709,334 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByBuffered::poll_next_true(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("TrueSplitByBuffered", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
         };
-        response
+        let poll = SplitByBufferedWith::poll_next_true_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> FusedStream for TrueSplitByBufferedWith<I, S, P, B, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_true(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> fmt::Debug for TrueSplitByBufferedWith<I, S, P, B, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TrueSplitByBuffered");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_true(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Clone for TrueSplitByBufferedWith<I, S, P, B, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Drop for TrueSplitByBufferedWith<I, S, P, B, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_true(id), self.id);
     }
 }
 
 /// A struct that implements `Stream` which returns the items where the
 /// predicate returns `false`
-pub struct FalseSplitByBuffered<I, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>,
+pub struct FalseSplitByBufferedWith<I, S, P, B: Buffer<I>, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitByBufferedWith<I, S, P, B>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
 }
 
-impl<I, S, P, const N: usize> FalseSplitByBuffered<I, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>) -> Self {
-        Self { stream }
+/// `FalseSplitByBufferedWith` buffering up to `N` items for each side in a
+/// `RingBuf`, which is what `split_by_buffered` returns
+pub type FalseSplitByBuffered<I, S, P, const N: usize, M = StdMutex> =
+    FalseSplitByBufferedWith<I, S, P, RingBuf<I, N>, M>;
+
+/// `FalseSplitByBuffered` without the item type of the underlying stream, which
+/// is taken from `S`, so that the `false` stream can be stored in a struct
+/// naming only the stream and the predicate
+pub type FalseBuffered<S, P, const N: usize> = FalseSplitByBuffered<<S as Stream>::Item, S, P, N>;
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> FalseSplitByBufferedWith<I, S, P, B, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitByBufferedWith<I, S, P, B>, M>>) -> Self {
+        stream.lock().attach_false();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_false(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.panic.take()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitByBufferedWith<I, S, P, B>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Right)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Right)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_true > 0 && guard.closed_true.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        !guard.buf_false.is_empty()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Sets what happens to items routed to this stream while its buffer is
+    /// full. By default the other stream waits for this one to make room, see
+    /// `OverflowPolicy` for the alternatives
+    pub fn set_overflow_policy(&self, policy: OverflowPolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_overflow_policy(Side::Right, policy);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Right, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Right)
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Right, limit);
+    }
+
+    /// Sets which stream is served first when both are being polled, or
+    /// removes the bias with `None`. See `PollBias` for how it works
+    pub fn set_bias(&self, bias: Option<PollBias>) {
+        let mut guard = self.stream.lock();
+        guard.set_bias(bias);
+    }
+
+    /// Swaps which of the two streams the predicate's results go to, so that
+    /// items it returns `true` for are yielded by the `false` stream and vice
+    /// versa. Items that are already buffered move over as well, so each one
+    /// is yielded as if it had been routed after the swap
+    pub fn swap_outputs(&self) {
+        let mut guard = self.stream.lock();
+        guard.swap_outputs();
+    }
+
+    /// Sets how long an item may wait in either stream's buffer. An item that
+    /// has waited for longer than `deadline` is handled according to `policy`
+    /// the next time either stream is polled, and the stream that didn't get
+    /// to it is woken when that's due. Setting it again replaces the previous
+    /// deadline, including for items that are already buffered
+    #[cfg(feature = "time")]
+    pub fn set_deadline(&self, deadline: Duration, policy: DeadlinePolicy) {
+        let mut guard = self.stream.lock();
+        guard.set_deadline(deadline, policy);
+    }
+
+    /// Returns a stream of the items that expired with
+    /// `DeadlinePolicy::DeadLetter`. Only one dead letter stream is fed at a
+    /// time, so creating another one ends this one
+    #[cfg(feature = "time")]
+    pub fn dead_letters(&self) -> DeadLetters<I> {
+        let mut guard = self.stream.lock();
+        guard.deadlines.dead_letters()
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> FalseSplitByBufferedWith<I, S, P, B, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitByBufferedWith<I, S, P, B>, I, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "FalseSplitByBuffered",
+            cx,
+            |state, cx| state.poll_peek_false(id, cx),
+            |state| state.buf_false.peek_front(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitByBufferedWith<I, S, P, B>, I, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "FalseSplitByBuffered",
+            |state, cx| state.poll_peek_false(id, cx),
+            |state| state.buf_false.peek_front(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
     }
 }
 
-impl<I, S, P, const N: usize> Stream for FalseSplitByBuffered<I, S, P, N>
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Stream for FalseSplitByBufferedWith<I, S, P, B, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
@@ -184,12 +2046,413 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByBuffered::poll_next_false(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("FalseSplitByBuffered", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        };
+        let poll = SplitByBufferedWith::poll_next_false_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> FusedStream for FalseSplitByBufferedWith<I, S, P, B, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_false(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> fmt::Debug for FalseSplitByBufferedWith<I, S, P, B, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FalseSplitByBuffered");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_false(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Clone for FalseSplitByBufferedWith<I, S, P, B, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, S, P, B: Buffer<I>, M: LockFamily> Drop for FalseSplitByBufferedWith<I, S, P, B, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_false(id), self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{FutureExt, Stream, StreamExt};
+
+    use crate::{OverflowPolicy, RouteHooks, SplitBuilder, SplitStreamByExt};
+
+    #[test]
+    fn test_dropped_half_with_full_buffer() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 3, 5, 0, 2, 7, 4]);
+            let (mut even_stream, odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            // Fill the odd buffer so the even stream can't make progress on its own
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            drop(odd_stream);
+            assert_eq!(vec![0, 2, 4], even_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_hook_panic_ends_both_sides() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (mut even_stream, mut odd_stream) = SplitBuilder::new(incoming_stream)
+            .buffer::<2>()
+            .hooks(RouteHooks::new().on_route(|_, &n: &i32| assert!(n != 2, "hook failed")))
+            .build_by(|&n| n % 2 == 0);
+        assert_eq!(Some(Some(0)), even_stream.next().now_or_never());
+        // The even stream may wait once for the odd one to take the 1, and
+        // then reads on to the 2
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            for _ in 0..2 {
+                assert_eq!(None, even_stream.next().now_or_never());
+            }
+        }))
+        .unwrap_err();
+        assert_eq!(Some(&"hook failed"), payload.downcast_ref::<&str>());
+        // The odd stream gets what was buffered for it before the split ends
+        futures::executor::block_on(async {
+            assert_eq!(vec![1], (&mut odd_stream).collect::<Vec<_>>().await);
+            assert_eq!(None, even_stream.next().await);
+        });
+    }
+
+    #[test]
+    fn test_size_hint() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 3, 0, 2]);
+            let (mut even_stream, odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            assert_eq!((0, Some(4)), even_stream.size_hint());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!((2, Some(4)), odd_stream.size_hint());
+            assert_eq!((0, Some(2)), even_stream.size_hint());
+            drop(odd_stream);
+            assert_eq!(vec![0, 2], even_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_drain_after_end() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 3, 0]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(None, even_stream.next().await);
+            assert_eq!(Some(3), odd_stream.next().await);
+            assert_eq!(None, odd_stream.next().await);
+        })
+    }
+
+    #[cfg(feature = "latency")]
+    #[test]
+    fn test_buffer_latency() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 0]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            let stats = even_stream.stats_handle();
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(Some(1), odd_stream.next().await);
+            drop(odd_stream);
+            assert_eq!(1, stats.buffer_latency(crate::Side::Right).len());
+            assert_eq!(0, stats.buffer_latency(crate::Side::Left).len());
+        })
+    }
+
+    #[test]
+    fn test_debug() {
+        struct NotDebug(u8);
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([NotDebug(1), NotDebug(3)]);
+            let (mut even_stream, odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|n| n.0 % 2 == 0);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(
+                "FalseSplitByBuffered { side: Right, buffered: 1, sibling_waker_registered: true, closed: None, terminated: false }",
+                format!("{:?}", odd_stream)
+            );
+        })
+    }
+
+    #[test]
+    fn test_pause_divert() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 3, 0, 5]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            let pause = odd_stream.pause_handle();
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            pause.pause(crate::Side::Right, crate::PausePolicy::Divert);
+            assert!(futures::poll!(odd_stream.next()).is_pending());
+            assert_eq!(Some(3), even_stream.next().await);
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(Some(5), even_stream.next().await);
+            assert_eq!(None, even_stream.next().await);
+            pause.resume(crate::Side::Right);
+            assert_eq!(vec![1], odd_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_deadline_dead_letter() {
+        // Polled within a tokio runtime so that the test also runs with `tokio-timer`
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            use std::time::Duration;
+
+            let incoming_stream = futures::stream::iter([1, 3, 0, 5, 2]);
+            let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            even_stream.set_deadline(Duration::from_millis(10), crate::DeadlinePolicy::DeadLetter);
+            let dead_letters = even_stream.dead_letters();
+            // The odd stream is never polled, so the even stream only gets past each odd item
+            // once it has expired
+            assert_eq!(vec![0, 2], even_stream.collect::<Vec<_>>().await);
+            drop(odd_stream);
+            assert_eq!(vec![1, 3, 5], dead_letters.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_bias() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 0, 3]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            even_stream.set_bias(Some(crate::PollBias::LeftFirst));
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert!(futures::poll!(odd_stream.next()).is_pending());
+            // There's room for more in the even buffer, but the odd stream waits for the
+            // even stream to take the 0 first
+            assert!(futures::poll!(odd_stream.next()).is_pending());
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(Some(3), odd_stream.next().await);
+        })
+    }
+
+    #[test]
+    fn test_weighted_bias() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 4, 3]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            even_stream.set_bias(Some(crate::PollBias::Weighted { left: 2, right: 1 }));
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(2), even_stream.next().await);
+            // The even stream has had its share of the round while the 1 is waiting
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(Some(4), even_stream.next().await);
+        })
+    }
+
+    #[test]
+    fn test_on_complete() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            let mut complete = even_stream.stats_handle().on_complete();
+            assert_eq!(Some(0), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(None, even_stream.next().await);
+            // The 1 is still buffered for the odd stream
+            assert!(futures::poll!(&mut complete).is_pending());
+            assert_eq!(Some(1), odd_stream.next().await);
+            complete.await;
+        })
+    }
+
+    #[test]
+    fn test_backpressure() {
+        use crate::{BackpressureEvent, Side};
+
+        futures::executor::block_on(async {
+            let (sender, receiver) = futures::channel::mpsc::unbounded();
+            let (mut even_stream, mut odd_stream) =
+                receiver.split_by_buffered::<2>(|&n: &i32| n % 2 == 0);
+            let mut backpressure = even_stream.stats_handle().backpressure();
+            sender.unbounded_send(1).unwrap();
+            sender.unbounded_send(3).unwrap();
+            // Each poll buffers one item for the odd stream, and the third finds it full
+            for _ in 0..3 {
+                assert!(futures::poll!(even_stream.next()).is_pending());
+            }
+            assert_eq!(Some(1), odd_stream.next().await);
+            assert_eq!(Some(3), odd_stream.next().await);
+            assert!(futures::poll!(odd_stream.next()).is_pending());
+            drop(sender);
+            assert_eq!(None, odd_stream.next().await);
+            assert_eq!(
+                vec![
+                    BackpressureEvent::SideFull { side: Side::Right },
+                    BackpressureEvent::SideDrained { side: Side::Right },
+                    BackpressureEvent::SourceStalled,
+                ],
+                backpressure.by_ref().collect::<Vec<_>>().await
+            );
+        })
+    }
+
+    #[test]
+    fn test_swap_outputs() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            assert_eq!(Some(0), even_stream.next().await);
+            // Buffers the 1 for the odd stream, which moves over with the swap
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            odd_stream.swap_outputs();
+            assert_eq!(Some(1), even_stream.next().await);
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert_eq!(Some(3), even_stream.next().await);
+            assert_eq!(Some(2), odd_stream.next().await);
+        })
+    }
+
+    #[test]
+    fn test_auto_degrade() {
+        use crate::AutoDegrade;
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 3, 5, 0]);
+            let (mut even_stream, odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            even_stream.set_auto_degrade(Some(AutoDegrade::Buffered(2)));
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            assert!(futures::poll!(even_stream.next()).is_pending());
+            // The odd stream now has two items waiting, so the 5 is dropped rather than
+            // holding up the even stream
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(1, even_stream.stats_handle().snapshot().dropped_right);
+            assert_eq!(vec![1, 3], odd_stream.collect::<Vec<_>>().await);
+        })
+    }
+
+    #[test]
+    fn test_peek() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([1, 0, 3]);
+            let (mut even_stream, mut odd_stream) =
+                incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            assert_eq!(Some(1), odd_stream.peek().await.map(|n| *n));
+            // Peeking again sees the same item rather than reading another
+            assert_eq!(Some(1), odd_stream.peek().await.map(|n| *n));
+            assert_eq!((1, Some(3)), odd_stream.size_hint());
+            assert_eq!(Some(0), even_stream.peek().await.map(|n| *n));
+            assert_eq!(Some(0), even_stream.next().await);
+            assert_eq!(vec![1, 3], odd_stream.collect::<Vec<_>>().await);
+            assert_eq!(None, even_stream.peek().await.map(|n| *n));
+        })
+    }
+
+    #[test]
+    fn test_quota_divert() {
+        use crate::{Quota, QuotaPolicy};
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4]);
+            let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<2>(|&n| n % 2 == 0);
+            even_stream.set_quota(Some(Quota::new(1, QuotaPolicy::Divert)));
+            let (evens, odds) = futures::join!(
+                even_stream.collect::<Vec<_>>(),
+                odd_stream.collect::<Vec<_>>()
+            );
+            assert_eq!(vec![0], evens);
+            assert_eq!(vec![1, 2, 3, 4], odds);
+        })
+    }
+
+    #[test]
+    fn test_overflow_drop_oldest() {
+        let incoming_stream = futures::stream::iter(0..6);
+        let (mut large_stream, small_stream) = incoming_stream.split_by_buffered::<2>(|&n| n >= 5);
+        small_stream.set_overflow_policy(OverflowPolicy::DropOldest);
+        // The large stream reads past the full buffer for the small one, which keeps the
+        // two most recent small items
+        let large = loop {
+            if let Some(item) = large_stream.next().now_or_never() {
+                break item;
+            }
         };
-        response
+        assert_eq!(Some(5), large);
+        futures::executor::block_on(async {
+            assert_eq!(vec![3, 4], small_stream.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_coalesced_wake() {
+        use crate::{Coalesced, SplitBuilder};
+
+        let incoming_stream = futures::stream::iter(0..6);
+        let (mut large_stream, small_stream) = SplitBuilder::new(incoming_stream)
+            .buffer::<8>()
+            .wake_strategy(Coalesced)
+            .build_by(|&n| n >= 5);
+        let stats = small_stream.stats_handle();
+        // Reading through the small items wakes the small stream once, when the large
+        // item turns up, rather than returning after each of them
+        assert_eq!(Some(Some(5)), large_stream.next().now_or_never());
+        assert_eq!(1, stats.snapshot().wakes);
+        futures::executor::block_on(async {
+            assert_eq!(vec![0, 1, 2, 3, 4], small_stream.collect::<Vec<_>>().await);
+        });
     }
 }
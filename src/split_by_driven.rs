@@ -0,0 +1,217 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    future::Either,
+    ready,
+    stream::FusedStream,
+    Stream,
+};
+
+use pin_project_lite::pin_project;
+
+use crate::{
+    source::Source,
+    trace::{trace_span, Span},
+};
+
+/// How many items `Drive` routes in one poll before yielding, so that a
+/// source that's always ready can't starve the task polling it
+const ITEMS_PER_POLL: usize = 64;
+
+pub(crate) fn new<S, P, L, R>(
+    stream: S,
+    predicate: P,
+    route: fn(&P, S::Item) -> Either<L, R>,
+) -> (SplitByDriven<L>, SplitByDriven<R>, Drive<S, P, L, R>)
+where
+    S: Stream,
+{
+    let (left_sender, left_receiver) = mpsc::unbounded();
+    let (right_sender, right_receiver) = mpsc::unbounded();
+    (
+        SplitByDriven {
+            receiver: left_receiver,
+        },
+        SplitByDriven {
+            receiver: right_receiver,
+        },
+        Drive {
+            stream: Source::new(stream),
+            predicate,
+            route,
+            left: left_sender,
+            right: right_sender,
+            span: trace_span!("split_by_driven"),
+        },
+    )
+}
+
+pin_project! {
+    /// A future that reads the underlying stream of `split_by_driven` or
+    /// `split_by_map_driven` and routes its items to the two output streams. It
+    /// completes once the underlying stream ends, or once both output streams have
+    /// been dropped, and dropping it ends both output streams once they've been
+    /// drained
+    #[must_use = "nothing is routed unless the `Drive` future is polled"]
+    pub struct Drive<S, P, L, R>
+    where
+        S: Stream,
+    {
+        #[pin]
+        stream: Source<S>,
+        predicate: P,
+        route: fn(&P, S::Item) -> Either<L, R>,
+        left: UnboundedSender<L>,
+        right: UnboundedSender<R>,
+        // A child of the span current when the split was made, entered while
+        // routing, so that the routing shows up under the caller's span wherever
+        // the future ends up being polled
+        span: Span,
+    }
+}
+
+impl<S, P, L, R> Future for Drive<S, P, L, R>
+where
+    S: Stream,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        for _ in 0..ITEMS_PER_POLL {
+            if this.left.is_closed() && this.right.is_closed() {
+                // Nobody is left to send items to
+                return Poll::Ready(());
+            }
+            let item = match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => item,
+                None => {
+                    // The output streams end once drained, even while this future is
+                    // kept around after completing
+                    this.left.close_channel();
+                    this.right.close_channel();
+                    return Poll::Ready(());
+                }
+            };
+            // A send only fails once that side's stream has been dropped, in which case
+            // the item is discarded like it would be by the other kinds of split
+            match (this.route)(this.predicate, item) {
+                Either::Left(item) => {
+                    let _ = this.left.unbounded_send(item);
+                }
+                Either::Right(item) => {
+                    let _ = this.right.unbounded_send(item);
+                }
+            }
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// A struct that implements `Stream` which returns the items routed to one
+/// side by `split_by_driven` or `split_by_map_driven`. Items are queued without
+/// bound until they're read, so each stream can be consumed at its own pace or
+/// not at all
+pub struct SplitByDriven<T> {
+    receiver: UnboundedReceiver<T>,
+}
+
+impl<T> Stream for SplitByDriven<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.receiver.size_hint()
+    }
+}
+
+impl<T> FusedStream for SplitByDriven<T> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+impl<T> fmt::Debug for SplitByDriven<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitByDriven")
+            .field("terminated", &self.is_terminated())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{FutureExt, StreamExt};
+
+    use super::ITEMS_PER_POLL;
+    use crate::SplitStreamByExt;
+
+    #[test]
+    fn test_ends_with_drive_kept() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (mut even_stream, mut odd_stream, mut drive) =
+            incoming_stream.split_by_driven(|&n| n % 2 == 0);
+        futures::executor::block_on(async {
+            (&mut drive).await;
+            assert_eq!(vec![0, 2], (&mut even_stream).collect::<Vec<_>>().await);
+            assert_eq!(vec![1, 3], (&mut odd_stream).collect::<Vec<_>>().await);
+        });
+        drop(drive);
+    }
+
+    #[test]
+    fn test_dropped_side() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, odd_stream, drive) = incoming_stream.split_by_driven(|&n| n % 2 == 0);
+        drop(even_stream);
+        futures::executor::block_on(async {
+            drive.await;
+            assert_eq!(vec![1, 3], odd_stream.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_both_sides_dropped() {
+        // The underlying stream never ends, so the drive only completes because nobody is
+        // left to route to
+        let incoming_stream = futures::stream::repeat(1);
+        let (even_stream, odd_stream, drive) = incoming_stream.split_by_driven(|&n| n % 2 == 0);
+        drop((even_stream, odd_stream));
+        assert_eq!(Some(()), drive.now_or_never());
+    }
+
+    #[test]
+    fn test_yields_to_executor() {
+        // A source that's always ready gets a bounded number of items routed per poll,
+        // and the drive asks to be polled again rather than hogging the task
+        let incoming_stream = futures::stream::iter(0..);
+        let (mut even_stream, mut odd_stream, mut drive) =
+            incoming_stream.split_by_driven(|&n| n % 2 == 0);
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, drive.poll_unpin(&mut cx));
+        let mut routed = Vec::new();
+        while let Some(Some(n)) = even_stream.next().now_or_never() {
+            routed.push(n);
+        }
+        while let Some(Some(n)) = odd_stream.next().now_or_never() {
+            routed.push(n);
+        }
+        routed.sort_unstable();
+        assert_eq!((0..ITEMS_PER_POLL).collect::<Vec<_>>(), routed);
+    }
+}
@@ -1,23 +1,78 @@
 use std::{
+    any::Any,
+    fmt,
     marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+    sync::Arc,
+    task::Poll,
 };
 
-use futures::{future::Either, Stream};
-use pin_project::pin_project;
+use futures::{
+    future::Either,
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+use pin_project_lite::pin_project;
+
+#[cfg(feature = "debug-invariants")]
+use crate::invariants::{self, SideState};
+#[cfg(not(feature = "time"))]
+use crate::no_timer::RateLimits;
+#[cfg(feature = "time")]
+use crate::rate_limit::{RateLimit, RateLimits};
+use crate::{
+    abort::{AbortHandle, Abortable},
+    bias::Streaks,
+    builder::DrainSource,
+    consumer_check::ConsumerCheck,
+    degrade::{AutoDegrade, Degrader},
+    hooks::RouteHooks,
+    inject::{InjectHandle, Injectable},
+    pause::{Pausable, PauseHandle},
+    peek::{self, Peeked},
+    quota::{Quota, Quotas},
+    side_waker::{next_consumer_id, SideWaker},
+    source::Source,
+    split_handle::SplitHandle,
+    stats::{StatsHandle, StatsSlot},
+    sync::{self, LockFamily, Mutex, StdMutex},
+    trace::{trace_event, trace_span, Span},
+    ClosePolicy, PausePolicy, ReuniteError, Side,
+};
 
-#[pin_project]
-pub(crate) struct SplitByMap<I, L, R, S, P> {
-    buf_left: Option<L>,
-    buf_right: Option<R>,
-    waker_left: Option<Waker>,
-    waker_right: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
-    item: PhantomData<I>,
+pin_project! {
+    /// The state shared between the two halves returned by `split_by_map`. This is
+    /// public so that custom combinators can own it directly and drive each side
+    /// through `poll_next_left` and `poll_next_right` instead of going through
+    /// `LeftSplitByMap` and `RightSplitByMap`
+    pub struct SplitByMap<I, L, R, S, P> {
+        buf_left: Option<L>,
+        buf_right: Option<R>,
+        waker_left: SideWaker,
+        waker_right: SideWaker,
+        closed_left: Option<ClosePolicy>,
+        closed_right: Option<ClosePolicy>,
+        paused_left: Option<PausePolicy>,
+        paused_right: Option<PausePolicy>,
+        handles_left: usize,
+        handles_right: usize,
+        terminated: bool,
+        streaks: Streaks,
+        quotas: Quotas,
+        // Set by `SplitBuilder::drain_on_drop`, and taken once it has been used
+        drain_on_drop: Option<DrainSource<S>>,
+        degrader: Degrader,
+        stats: StatsSlot,
+        hooks: RouteHooks<L, R>,
+        rate_limits: RateLimits,
+        span: Span,
+        panic: Option<Box<dyn Any + Send>>,
+        #[pin]
+        stream: Source<S>,
+        predicate: P,
+        item: PhantomData<I>,
+    }
 }
 
 impl<I, L, R, S, P> SplitByMap<I, L, R, S, P>
@@ -25,128 +80,1212 @@ where
     S: Stream<Item = I>,
     P: Fn(I) -> Either<L, R>,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
+    /// Creates the shared state without wrapping it for sharing between two
+    /// halves
+    pub fn new(stream: S, predicate: P) -> Self {
+        Self::with_hooks(stream, predicate, RouteHooks::new())
+    }
+
+    /// Creates the shared state with `hooks` that are called as items are
+    /// routed and buffered
+    pub fn with_hooks(stream: S, predicate: P, hooks: RouteHooks<L, R>) -> Self {
+        Self {
             buf_right: None,
             buf_left: None,
-            waker_right: None,
-            waker_left: None,
-            stream,
+            waker_right: SideWaker::new("right"),
+            waker_left: SideWaker::new("left"),
+            closed_right: None,
+            closed_left: None,
+            paused_left: None,
+            paused_right: None,
+            handles_left: 0,
+            handles_right: 0,
+            terminated: false,
+            streaks: Streaks::default(),
+            quotas: Quotas::default(),
+            drain_on_drop: None,
+            degrader: Degrader::default(),
+            stats: StatsSlot::default(),
+            hooks,
+            rate_limits: RateLimits::default(),
+            span: trace_span!("split_by_map"),
+            panic: None,
+            stream: Source::new(stream),
             predicate,
             item: PhantomData,
-        }))
+        }
+    }
+
+    pub(crate) fn new_shared<M: LockFamily>(
+        stream: S,
+        predicate: P,
+        hooks: RouteHooks<L, R>,
+    ) -> Arc<Mutex<Self, M>> {
+        Arc::new(Mutex::new(Self::with_hooks(stream, predicate, hooks)))
+    }
+
+    pub(crate) fn size_hint_left(&self) -> (usize, Option<usize>) {
+        let buffered = usize::from(self.buf_left.is_some());
+        if self.closed_left.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
+    }
+
+    pub(crate) fn size_hint_right(&self) -> (usize, Option<usize>) {
+        let buffered = usize::from(self.buf_right.is_some());
+        if self.closed_right.is_some() {
+            (0, Some(0))
+        } else if self.terminated {
+            (buffered, Some(buffered))
+        } else {
+            let upper = self.stream.as_ref().and_then(|stream| stream.size_hint().1);
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
+    }
+
+    /// Polls for the next item where the predicate returns `Either::Left(..)`.
+    /// Items meant for the other side are buffered and its waker is notified,
+    /// and `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_left(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<L>> {
+        self.poll_next_left_as(0, cx)
     }
 
-    fn poll_next_left(
+    /// The same as `poll_next_left`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_left_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<L>> {
+        self.as_mut().project().degrader.polled(Side::Left);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Left, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Left) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `right` stream run before carrying on
+            let this = self.project();
+            this.waker_right.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_left(consumer, cx);
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Left, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Left);
+            this.streaks.yielded(Side::Left);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Left);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `left` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_left(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Left);
+        if self.buf_left.is_some() {
+            return Poll::Ready(true);
+        }
+        let item = match Pin::new(&mut *self).route_next_left(consumer, cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        self.buf_left = Some(item);
+        self.stats.buffered(Side::Left, 1);
+        self.hooks.buffered(Side::Left, 1);
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_left_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_left(
         self: std::pin::Pin<&mut Self>,
+        consumer: usize,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<L>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_left(consumer, cx),
+            |this| this.end_after_panic(Side::Left),
+        )
+    }
+
+    /// Ends both streams after something panicked while `side` was being
+    /// polled, be it the underlying stream, a hook or an item being dropped,
+    /// as a panicking predicate does. The state may have been left half
+    /// updated, so rather than have the other stream carry on with it, it
+    /// yields what's already buffered for it and ends. The panic itself
+    /// carries on to whoever was polling `side`
+    fn end_after_panic(self: std::pin::Pin<&mut Self>, side: Side) {
         let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_left.is_none() {
-            *this.waker_left = Some(cx.waker().clone());
+        if !*this.terminated {
+            *this.terminated = true;
+            this.stats.terminated();
+        }
+        match side {
+            Side::Left => this.waker_right.wake(),
+            Side::Right => this.waker_left.wake(),
+        }
+    }
+
+    /// `route_next_left` without ending both streams if it panics
+    fn try_route_next_left(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<L>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_left.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_left.register(consumer, cx.waker());
+        this.stats.polled(Side::Left);
+        if this.paused_left.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
         }
         if let Some(item) = this.buf_left.take() {
+            this.stats
+                .unbuffered(Side::Left, usize::from(this.buf_left.is_some()));
+            this.hooks
+                .buffered(Side::Left, usize::from(this.buf_left.is_some()));
             // There was already a value in the buffer. Return that value
             return Poll::Ready(Some(item));
         }
-        if this.buf_right.is_some() {
+        if this.quotas.ended(Side::Left) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        let degraded = this
+            .degrader
+            .check(Side::Right, usize::from(this.buf_right.is_some()));
+        if !degraded && this.buf_right.is_some() && *this.paused_right != Some(PausePolicy::Drop) {
             // There is a value available for the other stream. Wake that stream if possible
             // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_right {
-                waker.wake_by_ref();
-            }
+            trace_event!(side = "right", "buffer full");
+            this.stats.buffer_full(Side::Right);
+            this.waker_right.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => Poll::Ready(Some(left_item)),
-                    Either::Right(right_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_right.replace(right_item);
-                        if let Some(waker) = this.waker_right {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `right` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_right.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let item = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(item))) {
+                Ok(Either::Left(left_item)) => {
+                    if this.quotas.route(Side::Left, false).is_none() {
+                        // This stream has received its quota and drops what's over it
+                        trace_event!(side = "left", "item over quota");
+                        this.stats.discarded(Side::Left, 1);
+                        this.hooks.discarded_left(Some(left_item));
+                        continue;
                     }
+                    trace_event!(side = "left", "item routed");
+                    this.hooks.routed_left(&left_item);
+                    this.stats.routed(Side::Left);
+                    return Poll::Ready(Some(left_item));
+                }
+                Ok(Either::Right(right_item)) => right_item,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_right.wake();
+                    return Poll::Ready(None);
                 }
+            };
+            if this.quotas.route(Side::Right, false).is_none() {
+                // The `right` stream has received its quota and drops what's over it
+                trace_event!(side = "right", "item over quota");
+                this.stats.discarded(Side::Right, 1);
+                this.hooks.discarded_right(Some(item));
+                continue;
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `right` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_right {
-                    waker.wake_by_ref();
+            trace_event!(side = "right", "item routed");
+            this.hooks.routed_right(&item);
+            this.stats.routed(Side::Right);
+            match this.closed_right {
+                Some(ClosePolicy::Discard) => {
+                    // The `right` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "right", "item discarded");
+                    this.stats.discarded(Side::Right, 1);
+                    this.hooks.discarded_right(Some(item));
+                    continue;
                 }
-                Poll::Ready(None)
+                Some(ClosePolicy::EndSibling) => {
+                    // The `right` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_left = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Left);
+                    return Poll::Ready(None);
+                }
+                None => {}
+            }
+            if *this.paused_right == Some(PausePolicy::Drop) {
+                // The `right` stream is paused and its items are being dropped until it's
+                // resumed, so keep polling
+                trace_event!(side = "right", "item discarded");
+                this.stats.discarded(Side::Right, 1);
+                this.hooks.discarded_right(Some(item));
+                continue;
+            }
+            if degraded {
+                // The `right` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "right", "item discarded");
+                this.stats.discarded(Side::Right, 1);
+                this.hooks.discarded_right(Some(item));
+                continue;
             }
-            Poll::Pending => Poll::Pending,
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. Nothing is displaced since we checked above that the
+            // buffer was empty, so the item is never lost between here and being yielded
+            let displaced = this.buf_right.replace(item);
+            debug_assert!(displaced.is_none());
+            this.stats
+                .buffered(Side::Right, usize::from(this.buf_right.is_some()));
+            this.hooks
+                .buffered(Side::Right, usize::from(this.buf_right.is_some()));
+            trace_event!(side = "right", "item buffered");
+            this.waker_right.wake();
+            return Poll::Pending;
         }
     }
 
-    fn poll_next_right(
+    /// Polls for the next item where the predicate returns `Either::Right(..)`.
+    /// Items meant for the other side are buffered and its waker is notified,
+    /// and `Poll::Pending` is returned while the other side's buffer is full
+    pub fn poll_next_right(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<R>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_right.is_none() {
-            *this.waker_right = Some(cx.waker().clone());
+        self.poll_next_right_as(0, cx)
+    }
+
+    /// The same as `poll_next_right`, but registers the waker on behalf of
+    /// `consumer` so that clones of an output stream don't replace each
+    /// other's wakers
+    pub(crate) fn poll_next_right_as(
+        mut self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<R>> {
+        self.as_mut().project().degrader.polled(Side::Right);
+        #[cfg(feature = "time")]
+        if self
+            .as_mut()
+            .project()
+            .rate_limits
+            .poll_ready(Side::Right, cx)
+            .is_pending()
+        {
+            // This stream is being held back by its rate limit, and is woken once it may
+            // yield again
+            return Poll::Pending;
+        }
+        if self.as_mut().project().streaks.give_way(Side::Right) {
+            // This stream has yielded as many items in a row as it's allowed to, so let the
+            // `left` stream run before carrying on
+            let this = self.project();
+            this.waker_left.wake();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let poll = self.as_mut().route_next_right(consumer, cx);
+        #[cfg(feature = "debug-invariants")]
+        self.check_invariants(Side::Right, &poll);
+        if let Poll::Ready(Some(_)) = &poll {
+            let this = self.project();
+            this.stats.yielded(Side::Right);
+            this.streaks.yielded(Side::Right);
+            #[cfg(feature = "time")]
+            this.rate_limits.take(Side::Right);
+        }
+        poll
+    }
+
+    /// Makes sure an item for the `right` stream is buffered so that it can be
+    /// peeked at, reading one from the underlying stream if there isn't one
+    /// already. Returns whether there is one
+    pub(crate) fn poll_peek_right(
+        &mut self,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool>
+    where
+        S: Unpin,
+    {
+        self.degrader.polled(Side::Right);
+        if self.buf_right.is_some() {
+            return Poll::Ready(true);
+        }
+        let item = match Pin::new(&mut *self).route_next_right(consumer, cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(false),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The item goes back to the front of this stream's buffer, which is empty, to be
+        // yielded by the next poll
+        self.buf_right = Some(item);
+        self.stats.buffered(Side::Right, 1);
+        self.hooks.buffered(Side::Right, 1);
+        Poll::Ready(true)
+    }
+
+    /// Everything `poll_next_right_as` does apart from applying the rate limit
+    /// and counting what's yielded
+    fn route_next_right(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<R>> {
+        sync::end_on_panic(
+            self,
+            |this| this.try_route_next_right(consumer, cx),
+            |this| this.end_after_panic(Side::Right),
+        )
+    }
+
+    /// `route_next_right` without ending both streams if it panics
+    fn try_route_next_right(
+        self: std::pin::Pin<&mut Self>,
+        consumer: usize,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<R>> {
+        let mut this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+        if this.closed_right.is_some() {
+            // This stream has been closed so it won't yield anything else
+            return Poll::Ready(None);
+        }
+        // Each handle to this side should only be polled by one task at a time, but it
+        // may not be the same task that polled it last
+        this.waker_right.register(consumer, cx.waker());
+        this.stats.polled(Side::Right);
+        if this.paused_right.is_some() {
+            // This stream has been paused. Anything buffered for it stays there until it's
+            // resumed, which wakes it
+            return Poll::Pending;
         }
         if let Some(item) = this.buf_right.take() {
+            this.stats
+                .unbuffered(Side::Right, usize::from(this.buf_right.is_some()));
+            this.hooks
+                .buffered(Side::Right, usize::from(this.buf_right.is_some()));
             // There was already a value in the buffer. Return that value
             return Poll::Ready(Some(item));
         }
-        if this.buf_left.is_some() {
+        if this.quotas.ended(Side::Right) {
+            // This stream has received its quota and yielded what was buffered for it, so
+            // it ends rather than reading on
+            return Poll::Ready(None);
+        }
+        if *this.terminated {
+            // The underlying stream has ended or the predicate panicked, so nothing else
+            // will be read from it
+            return Poll::Ready(None);
+        }
+        let degraded = this
+            .degrader
+            .check(Side::Left, usize::from(this.buf_left.is_some()));
+        if !degraded && this.buf_left.is_some() && *this.paused_left != Some(PausePolicy::Drop) {
             // There is a value available for the other stream. Wake that stream if possible
             // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_left {
-                waker.wake_by_ref();
-            }
+            trace_event!(side = "left", "buffer full");
+            this.stats.buffer_full(Side::Left);
+            this.waker_left.wake();
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_left.replace(left_item);
-                        if let Some(waker) = this.waker_left {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
+        loop {
+            this.stats.source_polled();
+            // The stream is only missing once it's been handed back by `AbortHandle`, which
+            // also ends both sides
+            let item = match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) => {
+                    trace_event!("stream terminated");
+                    // If the underlying stream is finished, the `left` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    this.waker_left.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    this.stats.source_pending();
+                    return Poll::Pending;
+                }
+            };
+            let item = match panic::catch_unwind(AssertUnwindSafe(|| (this.predicate)(item))) {
+                Ok(Either::Right(right_item)) => {
+                    if this.quotas.route(Side::Right, false).is_none() {
+                        // This stream has received its quota and drops what's over it
+                        trace_event!(side = "right", "item over quota");
+                        this.stats.discarded(Side::Right, 1);
+                        this.hooks.discarded_right(Some(right_item));
+                        continue;
                     }
-                    Either::Right(right_item) => Poll::Ready(Some(right_item)),
+                    trace_event!(side = "right", "item routed");
+                    this.hooks.routed_right(&right_item);
+                    this.stats.routed(Side::Right);
+                    return Poll::Ready(Some(right_item));
+                }
+                Ok(Either::Left(left_item)) => left_item,
+                Err(payload) => {
+                    trace_event!("predicate panicked");
+                    // The predicate panicked. End both streams instead of leaving the shared
+                    // state half updated, and hold on to the payload for `take_panic`
+                    *this.terminated = true;
+                    this.stats.terminated();
+                    *this.panic = Some(payload);
+                    this.waker_left.wake();
+                    return Poll::Ready(None);
                 }
+            };
+            if this.quotas.route(Side::Left, false).is_none() {
+                // The `left` stream has received its quota and drops what's over it
+                trace_event!(side = "left", "item over quota");
+                this.stats.discarded(Side::Left, 1);
+                this.hooks.discarded_left(Some(item));
+                continue;
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `left` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_left {
-                    waker.wake_by_ref();
+            trace_event!(side = "left", "item routed");
+            this.hooks.routed_left(&item);
+            this.stats.routed(Side::Left);
+            match this.closed_left {
+                Some(ClosePolicy::Discard) => {
+                    // The `left` stream has been closed so nothing will consume this value.
+                    // Discard it and keep polling
+                    trace_event!(side = "left", "item discarded");
+                    this.stats.discarded(Side::Left, 1);
+                    this.hooks.discarded_left(Some(item));
+                    continue;
+                }
+                Some(ClosePolicy::EndSibling) => {
+                    // The `left` stream has been closed and asked for this stream to end
+                    // rather than skip over its values
+                    *this.closed_right = Some(ClosePolicy::Discard);
+                    this.stats.closed(Side::Right);
+                    return Poll::Ready(None);
                 }
-                Poll::Ready(None)
+                None => {}
             }
-            Poll::Pending => Poll::Pending,
+            if *this.paused_left == Some(PausePolicy::Drop) {
+                // The `left` stream is paused and its items are being dropped until it's
+                // resumed, so keep polling
+                trace_event!(side = "left", "item discarded");
+                this.stats.discarded(Side::Left, 1);
+                this.hooks.discarded_left(Some(item));
+                continue;
+            }
+            if degraded {
+                // The `left` stream isn't keeping up, so its items are dropped until it's
+                // polled again rather than holding up this stream
+                trace_event!(side = "left", "item discarded");
+                this.stats.discarded(Side::Left, 1);
+                this.hooks.discarded_left(Some(item));
+                continue;
+            }
+            // This value is not what we wanted. Store it and notify the other stream if it
+            // has registered a waker. Nothing is displaced since we checked above that the
+            // buffer was empty, so the item is never lost between here and being yielded
+            let displaced = this.buf_left.replace(item);
+            debug_assert!(displaced.is_none());
+            this.stats
+                .buffered(Side::Left, usize::from(this.buf_left.is_some()));
+            this.hooks
+                .buffered(Side::Left, usize::from(this.buf_left.is_some()));
+            trace_event!(side = "left", "item buffered");
+            this.waker_left.wake();
+            return Poll::Pending;
+        }
+    }
+}
+
+impl<I, L, R, S, P> SplitByMap<I, L, R, S, P> {
+    /// Panics if the shared state is in a state it should never reach, given
+    /// that polling `side` just returned `poll`
+    #[cfg(feature = "debug-invariants")]
+    fn check_invariants<T>(&self, side: Side, poll: &Poll<Option<T>>) {
+        invariants::check(
+            side,
+            poll,
+            [
+                SideState {
+                    buffered: usize::from(self.buf_left.is_some()),
+                    closed: self.closed_left.is_some(),
+                    waker: &self.waker_left,
+                },
+                SideState {
+                    buffered: usize::from(self.buf_right.is_some()),
+                    closed: self.closed_right.is_some(),
+                    waker: &self.waker_right,
+                },
+            ],
+            self.terminated,
+            self.stream.is_some(),
+            &self.stats,
+        );
+    }
+
+    /// Returns a handle to the counters, which are only kept from the first
+    /// time this is called
+    pub(crate) fn stats_handle(&mut self) -> StatsHandle {
+        if let Some(counters) = self.stats.enable() {
+            self.waker_left.count_wakes(Arc::clone(&counters));
+            self.waker_right.count_wakes(counters);
+        }
+        self.stats.handle()
+    }
+
+    pub(crate) fn is_terminated_left(&self) -> bool {
+        self.closed_left.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Left)) && self.buf_left.is_none())
+    }
+
+    pub(crate) fn is_terminated_right(&self) -> bool {
+        self.closed_right.is_some()
+            || ((self.terminated || self.quotas.ended(Side::Right)) && self.buf_right.is_none())
+    }
+
+    /// Adds a summary of the `left` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_left(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Left)
+            .field("buffered", &usize::from(self.buf_left.is_some()))
+            .field(
+                "sibling_waker_registered",
+                &self.waker_right.is_registered(),
+            )
+            .field("closed", &self.closed_left)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Adds a summary of the `right` side to a `Debug` implementation, without
+    /// needing the items to implement `Debug`
+    pub(crate) fn debug_right(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("side", &Side::Right)
+            .field("buffered", &usize::from(self.buf_right.is_some()))
+            .field("sibling_waker_registered", &self.waker_left.is_registered())
+            .field("closed", &self.closed_right)
+            .field("terminated", &self.terminated);
+    }
+
+    /// Pauses the `left` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `right` side is woken since it may no
+    /// longer have to wait on the `left` buffer
+    pub(crate) fn pause_left(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "left", ?policy, "stream paused");
+        self.paused_left = Some(match policy {
+            // Items can only be diverted when both sides have the same type
+            PausePolicy::Divert => PausePolicy::Buffer,
+            policy => policy,
+        });
+        self.waker_right.wake();
+    }
+
+    /// Resumes the `left` side, waking it if it was paused
+    pub(crate) fn resume_left(&mut self) {
+        if self.paused_left.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "left", "stream resumed");
+            self.waker_left.wake();
+        }
+    }
+
+    /// Pauses the `right` side, handling items routed to it according to
+    /// `policy` until it's resumed. The `left` side is woken since it may no
+    /// longer have to wait on the `right` buffer
+    pub(crate) fn pause_right(&mut self, policy: PausePolicy) {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "right", ?policy, "stream paused");
+        self.paused_right = Some(match policy {
+            // Items can only be diverted when both sides have the same type
+            PausePolicy::Divert => PausePolicy::Buffer,
+            policy => policy,
+        });
+        self.waker_left.wake();
+    }
+
+    /// Resumes the `right` side, waking it if it was paused
+    pub(crate) fn resume_right(&mut self) {
+        if self.paused_right.take().is_some() {
+            #[cfg(feature = "tracing")]
+            let _entered = self.span.enter();
+            trace_event!(side = "right", "stream resumed");
+            self.waker_right.wake();
+        }
+    }
+
+    /// Sets when a side that isn't keeping up starts having its items dropped,
+    /// or turns that off, recovering both sides
+    pub(crate) fn set_auto_degrade(&mut self, policy: Option<AutoDegrade>) {
+        self.degrader.set(policy);
+        self.waker_left.wake();
+        self.waker_right.wake();
+    }
+
+    /// Caps how many items in a row either side may yield before giving way to
+    /// the other, or removes the cap
+    pub(crate) fn set_max_consecutive(&mut self, max: Option<u32>) {
+        self.streaks.set(max);
+    }
+
+    /// Caps how many items `side` receives, or removes its cap, waking it in
+    /// case that ends it
+    pub(crate) fn set_quota(&mut self, side: Side, quota: Option<Quota>) {
+        self.quotas.set(side, quota);
+        match side {
+            Side::Left => self.waker_left.wake(),
+            Side::Right => self.waker_right.wake(),
+        }
+    }
+
+    pub(crate) fn quota_remaining(&self, side: Side) -> Option<u64> {
+        self.quotas.remaining(side)
+    }
+
+    /// Limits how fast `side` yields items, or removes its limit, waking it in
+    /// case it was waiting on the previous limit
+    #[cfg(feature = "time")]
+    pub(crate) fn set_rate_limit(&mut self, side: Side, limit: Option<RateLimit>) {
+        self.rate_limits.set(side, limit);
+        match side {
+            Side::Left => self.waker_left.wake(),
+            Side::Right => self.waker_right.wake(),
+        }
+    }
+
+    /// Buffers an item from outside the split for the `left` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_left(&mut self, item: L) -> Result<(), L> {
+        if self.closed_left.is_some() || self.terminated || self.quotas.ended(Side::Left) {
+            return Err(item);
+        }
+        if self.buf_left.is_some() {
+            return Err(item);
         }
+        self.buf_left = Some(item);
+        let len = 1;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "left", buffered = len, "item injected");
+        self.stats.buffered(Side::Left, len);
+        self.hooks.buffered(Side::Left, len);
+        self.waker_left.wake();
+        Ok(())
+    }
+
+    /// Buffers an item from outside the split for the `right` side, handing it
+    /// back if there's no room for it or the side won't yield anything else
+    pub(crate) fn inject_right(&mut self, item: R) -> Result<(), R> {
+        if self.closed_right.is_some() || self.terminated || self.quotas.ended(Side::Right) {
+            return Err(item);
+        }
+        if self.buf_right.is_some() {
+            return Err(item);
+        }
+        self.buf_right = Some(item);
+        let len = 1;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "right", buffered = len, "item injected");
+        self.stats.buffered(Side::Right, len);
+        self.hooks.buffered(Side::Right, len);
+        self.waker_right.wake();
+        Ok(())
+    }
+
+    /// Ends both sides and takes the underlying stream along with anything
+    /// buffered for either side, unless this was already done
+    pub(crate) fn abort(&mut self) -> Option<SplitByMapParts<L, R, S>>
+    where
+        S: Unpin,
+    {
+        let stream = self.stream.take()?;
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!("splitter aborted");
+        self.terminated = true;
+        self.stats.terminated();
+        let parts = SplitByMapParts {
+            stream,
+            buffered_left: self.buf_left.take(),
+            buffered_right: self.buf_right.take(),
+        };
+        self.stats.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.stats.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_left.wake();
+        self.waker_right.wake();
+        Some(parts)
+    }
+
+    /// Counts another output stream handle for the `left` side
+    pub(crate) fn attach_left(&mut self) {
+        self.handles_left += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `left` side once no handles are left
+    pub(crate) fn detach_left(&mut self, consumer: usize) {
+        self.waker_left.unregister(consumer);
+        self.handles_left -= 1;
+        if self.handles_left == 0 {
+            self.close_left(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    /// Counts another output stream handle for the `right` side
+    pub(crate) fn attach_right(&mut self) {
+        self.handles_right += 1;
+    }
+
+    /// Stops counting the output stream handle that was polling as `consumer`,
+    /// closing the `right` side once no handles are left
+    pub(crate) fn detach_right(&mut self, consumer: usize) {
+        self.waker_right.unregister(consumer);
+        self.handles_right -= 1;
+        if self.handles_right == 0 {
+            self.close_right(ClosePolicy::Discard);
+            self.drain_if_detached();
+        }
+    }
+
+    pub(crate) fn set_drain_on_drop(&mut self, drain: DrainSource<S>) {
+        self.drain_on_drop = Some(drain);
+    }
+
+    /// Hands the underlying stream over to be read to the end once both sides
+    /// have been dropped, if the split was built with `drain_on_drop` and the
+    /// stream hasn't ended already
+    fn drain_if_detached(&mut self) {
+        if self.handles_left > 0 || self.handles_right > 0 || self.terminated {
+            return;
+        }
+        if let Some(drain) = self.drain_on_drop.take() {
+            trace_event!("draining the stream");
+            drain.drain(&mut self.stream);
+        }
+    }
+
+    /// Marks the `left` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_left(&mut self, policy: ClosePolicy) {
+        if self.closed_left.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "left", ?policy, "stream closed");
+        self.closed_left = Some(policy);
+        self.stats.closed(Side::Left);
+        self.stats
+            .discarded(Side::Left, usize::from(self.buf_left.is_some()));
+        let discarded = self.buf_left.take();
+        self.stats.buffered(Side::Left, 0);
+        self.hooks.buffered(Side::Left, 0);
+        self.waker_right.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_left(discarded);
+    }
+
+    /// Marks the `right` side as closed, dropping anything buffered for it. Items
+    /// routed to it afterwards are handled according to `policy`. Closing it again
+    /// has no effect
+    pub(crate) fn close_right(&mut self, policy: ClosePolicy) {
+        if self.closed_right.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        trace_event!(side = "right", ?policy, "stream closed");
+        self.closed_right = Some(policy);
+        self.stats.closed(Side::Right);
+        self.stats
+            .discarded(Side::Right, usize::from(self.buf_right.is_some()));
+        let discarded = self.buf_right.take();
+        self.stats.buffered(Side::Right, 0);
+        self.hooks.buffered(Side::Right, 0);
+        self.waker_left.wake();
+        // Handed over last so that an item panicking as it's dropped, or the hook
+        // panicking, leaves the state consistent for whoever takes the poisoned lock next
+        self.hooks.discarded_right(discarded);
+    }
+}
+
+impl<I, L, R, S, P> Pausable for SplitByMap<I, L, R, S, P> {
+    fn pause(&mut self, side: Side, policy: PausePolicy) {
+        match side {
+            Side::Left => self.pause_left(policy),
+            Side::Right => self.pause_right(policy),
+        }
+    }
+
+    fn resume(&mut self, side: Side) {
+        match side {
+            Side::Left => self.resume_left(),
+            Side::Right => self.resume_right(),
+        }
+    }
+}
+
+impl<I, L, R, S, P> Injectable for SplitByMap<I, L, R, S, P> {
+    type Left = L;
+    type Right = R;
+
+    fn inject_left(&mut self, item: L) -> Result<(), L> {
+        self.inject_left(item)
+    }
+
+    fn inject_right(&mut self, item: R) -> Result<(), R> {
+        self.inject_right(item)
+    }
+}
+
+impl<I, L, R, S: Unpin, P> Abortable for SplitByMap<I, L, R, S, P> {
+    type Parts = SplitByMapParts<L, R, S>;
+
+    fn abort(&mut self) -> Option<Self::Parts> {
+        SplitByMap::abort(self)
     }
 }
 
+/// The pieces of a `split_by_map` that has been taken apart with `into_parts`
+pub struct SplitByMapParts<L, R, S> {
+    /// The underlying stream, positioned after the last item that was read
+    pub stream: S,
+    /// The item that was buffered for the `left` stream, if any
+    pub buffered_left: Option<L>,
+    /// The item that was buffered for the `right` stream, if any
+    pub buffered_right: Option<R>,
+}
+
 /// A struct that implements `Stream` which returns the inner values where
 /// the predicate returns `Either::Left(..)` when using `split_by_map`
-pub struct LeftSplitByMap<I, L, R, S, P> {
-    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>,
+pub struct LeftSplitByMap<I, L, R, S, P, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
 }
 
-impl<I, L, R, S, P> LeftSplitByMap<I, L, R, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>) -> Self {
-        Self { stream }
+/// `LeftSplitByMap` without the item type of the underlying stream, which is
+/// taken from `S`, so that the `left` stream can be stored in a struct naming
+/// only the stream, the output types and the predicate
+pub type Left<S, L, R, P> = LeftSplitByMap<<S as Stream>::Item, L, R, S, P>;
+
+impl<I, L, R, S, P, M: LockFamily> LeftSplitByMap<I, L, R, S, P, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>, M>>) -> Self {
+        stream.lock().attach_left();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_left(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.panic.take()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitByMap<I, L, R, S, P>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitByMap<I, L, R, S, P>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitByMap<I, L, R, S, P>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitByMap<I, L, R, S, P>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Left)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Left)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_right > 0 && guard.closed_right.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.buf_left.is_some()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Left, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Left)
+    }
+
+    pub(crate) fn set_drain_on_drop(&self, drain: DrainSource<S>) {
+        let mut guard = self.stream.lock();
+        guard.set_drain_on_drop(drain);
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Left, limit);
+    }
+
+    /// Takes apart the split, returning the underlying stream along with any
+    /// items that were read from it but not yet yielded. `other` must be the
+    /// stream that was created alongside this one, and neither may have been
+    /// cloned, otherwise both are handed back in the error. They're also
+    /// handed back if the underlying stream was already taken with
+    /// `AbortHandle::abort`
+    pub fn into_parts(
+        self,
+        other: RightSplitByMap<I, L, R, S, P, M>,
+    ) -> Result<SplitByMapParts<L, R, S>, ReuniteError<Self, RightSplitByMap<I, L, R, S, P, M>>>
+    where
+        S: Unpin,
+    {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        let parts = {
+            let mut guard = self.stream.lock();
+            if guard.handles_left == 1 && guard.handles_right == 1 {
+                guard.stream.take().map(|stream| SplitByMapParts {
+                    stream,
+                    buffered_left: guard.buf_left.take(),
+                    buffered_right: guard.buf_right.take(),
+                })
+            } else {
+                None
+            }
+        };
+        // Dropping the halves afterwards closes both sides, which is harmless now that
+        // their buffers are empty
+        parts.ok_or(ReuniteError(self, other))
     }
 }
 
-impl<I, L, R, S, P> Stream for LeftSplitByMap<I, L, R, S, P>
+impl<I, L, R, S, P, M: LockFamily> LeftSplitByMap<I, L, R, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitByMap<I, L, R, S, P>, L, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "LeftSplitByMap",
+            cx,
+            |state, cx| state.poll_peek_left(id, cx),
+            |state| state.buf_left.as_ref(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitByMap<I, L, R, S, P>, L, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "LeftSplitByMap",
+            |state, cx| state.poll_peek_left(id, cx),
+            |state| state.buf_left.as_ref(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, L>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, L>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> Stream for LeftSplitByMap<I, L, R, S, P, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(I) -> Either<L, R>,
@@ -156,29 +1295,285 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMap::poll_next_left(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("LeftSplitByMap", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
         };
-        response
+        let poll = SplitByMap::poll_next_left_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_left(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> FusedStream for LeftSplitByMap<I, L, R, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_left(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> fmt::Debug for LeftSplitByMap<I, L, R, S, P, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("LeftSplitByMap");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_left(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> Clone for LeftSplitByMap<I, L, R, S, P, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> Drop for LeftSplitByMap<I, L, R, S, P, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_left(id), self.id);
     }
 }
 
 /// A struct that implements `Stream` which returns the inner values where
 /// the predicate returns `Either::Right(..)` when using `split_by_map`
-pub struct RightSplitByMap<I, L, R, S, P> {
-    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>,
+pub struct RightSplitByMap<I, L, R, S, P, M: LockFamily = StdMutex> {
+    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>, M>>,
+    consumer: ConsumerCheck,
+    id: usize,
+}
+
+/// `RightSplitByMap` without the item type of the underlying stream, which is
+/// taken from `S`, so that the `right` stream can be stored in a struct naming
+/// only the stream, the output types and the predicate
+pub type Right<S, L, R, P> = RightSplitByMap<<S as Stream>::Item, L, R, S, P>;
+
+impl<I, L, R, S, P, M: LockFamily> RightSplitByMap<I, L, R, S, P, M> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>, M>>) -> Self {
+        stream.lock().attach_right();
+        Self {
+            stream,
+            consumer: ConsumerCheck::default(),
+            id: next_consumer_id(),
+        }
+    }
+
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        let mut guard = self.stream.lock();
+        guard.close_right(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut guard = self.stream.lock();
+        guard.panic.take()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+
+    /// Returns a handle for pausing and resuming either output stream
+    pub fn pause_handle(&self) -> PauseHandle<SplitByMap<I, L, R, S, P>, M> {
+        PauseHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for pushing items from outside the split into either
+    /// output stream
+    pub fn inject_handle(&self) -> InjectHandle<SplitByMap<I, L, R, S, P>, M> {
+        InjectHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for stopping the split and taking back the underlying
+    /// stream
+    pub fn abort_handle(&self) -> AbortHandle<SplitByMap<I, L, R, S, P>, M> {
+        AbortHandle::new(Arc::downgrade(&self.stream))
+    }
+
+    /// Returns a handle for controlling the split and reading its stats
+    /// without owning either output stream
+    pub fn split_handle(&self) -> SplitHandle<SplitByMap<I, L, R, S, P>, M> {
+        SplitHandle::new(Arc::downgrade(&self.stream), self.stats_handle())
+    }
+
+    /// Returns how many items the predicate has routed to this stream so far,
+    /// including any that were dropped without being yielded
+    pub fn items_routed(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.routed_total(Side::Right)
+    }
+
+    /// Returns how many items this stream, or any clone of it, has yielded so
+    /// far
+    pub fn items_yielded(&self) -> u64 {
+        let guard = self.stream.lock();
+        guard.stats.yielded_total(Side::Right)
+    }
+
+    /// Whether the other output stream, or any clone of it, still exists and
+    /// hasn't been closed. This doesn't poll anything
+    pub fn sibling_active(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.handles_left > 0 && guard.closed_left.is_none()
+    }
+
+    /// Whether items are buffered for this stream, so that polling it would
+    /// yield one straight away unless it's paused. This doesn't poll anything
+    pub fn has_buffered(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.buf_right.is_some()
+    }
+
+    /// Whether the underlying stream has ended (or the predicate panicked),
+    /// so nothing else will be routed. This doesn't poll anything
+    pub fn source_ended(&self) -> bool {
+        let guard = self.stream.lock();
+        guard.terminated
+    }
+
+    /// Sets when either stream is switched to dropping its items because it
+    /// isn't keeping up, or turns that off with `None`. See `AutoDegrade` for
+    /// how it works
+    pub fn set_auto_degrade(&self, policy: Option<AutoDegrade>) {
+        let mut guard = self.stream.lock();
+        guard.set_auto_degrade(policy);
+    }
+
+    /// Caps how many items in a row either stream may yield before it returns
+    /// `Poll::Pending` once, waking itself and the other stream, or removes
+    /// the cap with `None`. This keeps a long run of items for one side from
+    /// starving the other side's task when both are polled from the same
+    /// task. A cap of 0 is treated as 1
+    pub fn set_max_consecutive(&self, max: Option<u32>) {
+        let mut guard = self.stream.lock();
+        guard.set_max_consecutive(max);
+    }
+
+    /// Caps how many items this stream receives in total, or removes its cap
+    /// with `None`. Once it has received its quota, the items routed to it
+    /// are handled according to the quota's `QuotaPolicy`. Setting a quota
+    /// starts its count afresh
+    pub fn set_quota(&self, quota: Option<Quota>) {
+        let mut guard = self.stream.lock();
+        guard.set_quota(Side::Right, quota);
+    }
+
+    /// How many more items this stream may receive before its quota runs out,
+    /// or `None` if it has no quota
+    pub fn quota_remaining(&self) -> Option<u64> {
+        let guard = self.stream.lock();
+        guard.quota_remaining(Side::Right)
+    }
+
+    /// Limits how fast this stream yields items, or removes its limit with
+    /// `None`. See `RateLimit` for how it works
+    #[cfg(feature = "time")]
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut guard = self.stream.lock();
+        guard.set_rate_limit(Side::Right, limit);
+    }
 }
 
-impl<I, L, R, S, P> RightSplitByMap<I, L, R, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>) -> Self {
-        Self { stream }
+impl<I, L, R, S, P, M: LockFamily> RightSplitByMap<I, L, R, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    /// Polls for the next item for this stream without taking it, reading and
+    /// buffering one from the underlying stream if there isn't one buffered
+    /// already. The item stays next in line to be yielded, and neither stream
+    /// can make progress while the returned `Peeked` is held
+    pub fn poll_peek(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Peeked<'_, SplitByMap<I, L, R, S, P>, R, M>>> {
+        let id = self.id;
+        peek::poll_peek(
+            &self.stream,
+            &mut self.consumer,
+            "RightSplitByMap",
+            cx,
+            |state, cx| state.poll_peek_right(id, cx),
+            |state| state.buf_right.as_ref(),
+        )
+    }
+
+    /// Waits for the next item for this stream without taking it, as with
+    /// `poll_peek`
+    pub async fn peek(&mut self) -> Option<Peeked<'_, SplitByMap<I, L, R, S, P>, R, M>> {
+        let id = self.id;
+        peek::peek(
+            &self.stream,
+            &mut self.consumer,
+            "RightSplitByMap",
+            |state, cx| state.poll_peek_right(id, cx),
+            |state| state.buf_right.as_ref(),
+        )
+        .await
+    }
+
+    /// Boxes this stream up as a `BoxStream`, which has no type parameters
+    /// besides the item type
+    pub fn boxed<'a>(self) -> BoxStream<'a, R>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes this stream up as a `LocalBoxStream`, as with `boxed` but for
+    /// streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, R>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
     }
 }
 
-impl<I, L, R, S, P> Stream for RightSplitByMap<I, L, R, S, P>
+impl<I, L, R, S, P, M: LockFamily> Stream for RightSplitByMap<I, L, R, S, P, M>
 where
     S: Stream<Item = I> + Unpin,
     P: Fn(I) -> Either<L, R>,
@@ -188,12 +1583,152 @@ where
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMap::poll_next_right(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        let this = self.get_mut();
+        this.consumer.check("RightSplitByMap", cx);
+        #[cfg(feature = "test-fault")]
+        if crate::fault::force_pending(cx) {
+            return Poll::Pending;
+        }
+        let mut guard = match this.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
         };
-        response
+        let poll = SplitByMap::poll_next_right_as(Pin::new(&mut guard), this.id, cx);
+        this.consumer.record(cx, &poll);
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_right(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => (0, None),
+        }
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> FusedStream for RightSplitByMap<I, L, R, S, P, M>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.is_terminated_right(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> fmt::Debug for RightSplitByMap<I, L, R, S, P, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("RightSplitByMap");
+        match self.stream.try_lock() {
+            Some(guard) => guard.debug_right(&mut debug),
+            None => {
+                // The other side is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"));
+            }
+        }
+        debug.finish()
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> Clone for RightSplitByMap<I, L, R, S, P, M> {
+    /// Creates another handle to this output stream. Each item is yielded by
+    /// only one of the handles, so they can be consumed by separate tasks
+    /// competing for items. The side is closed once every handle to it has
+    /// been dropped
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.stream))
+    }
+}
+
+impl<I, L, R, S, P, M: LockFamily> Drop for RightSplitByMap<I, L, R, S, P, M> {
+    fn drop(&mut self) {
+        self.stream
+            .lock_or_defer(|state, id| state.detach_right(id), self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{stream::FusedStream, StreamExt};
+
+    use crate::{Either, SplitStreamByMapExt};
+
+    #[test]
+    fn test_select_until_terminated() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+            let (mut left_stream, mut right_stream) = incoming_stream.split_by_map(|n| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n.to_string())
+                }
+            });
+            let mut lefts = Vec::new();
+            let mut rights = Vec::new();
+            loop {
+                futures::select! {
+                    n = left_stream.next() => lefts.extend(n),
+                    s = right_stream.next() => rights.extend(s),
+                    complete => break,
+                }
+            }
+            assert!(left_stream.is_terminated());
+            assert!(right_stream.is_terminated());
+            assert_eq!(vec![0, 2, 4], lefts);
+            assert_eq!(vec!["1", "3", "5"], rights);
+        })
+    }
+
+    #[test]
+    fn test_item_counts() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2]);
+            let (mut left_stream, right_stream) = incoming_stream.split_by_map(|n| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n)
+                }
+            });
+            assert_eq!(Some(0), left_stream.next().await);
+            assert!(futures::poll!(left_stream.next()).is_pending());
+            assert_eq!(1, left_stream.items_routed());
+            assert_eq!(1, left_stream.items_yielded());
+            assert_eq!(1, right_stream.items_routed());
+            assert_eq!(0, right_stream.items_yielded());
+        })
+    }
+
+    #[test]
+    fn test_quota_end() {
+        use crate::{Quota, QuotaPolicy};
+
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4]);
+            let (mut left_stream, right_stream) = incoming_stream.split_by_map(|n| {
+                if n % 2 == 0 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n)
+                }
+            });
+            right_stream.set_quota(Some(Quota::new(1, QuotaPolicy::End)));
+            assert_eq!(Some(1), right_stream.quota_remaining());
+            assert_eq!(Some(0), left_stream.next().await);
+            // The 1 is buffered for the right stream, which ends once it's taken it
+            assert!(futures::poll!(left_stream.next()).is_pending());
+            assert_eq!(vec![1], right_stream.collect::<Vec<_>>().await);
+            assert_eq!(vec![2, 4], left_stream.collect::<Vec<_>>().await);
+        })
     }
 }
@@ -0,0 +1,282 @@
+use std::{any::Any, cell::RefCell, fmt, pin::Pin, task::Poll};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{ClosePolicy, SplitBy, StatsHandle};
+
+/// Splits a borrowed stream in two for the duration of `f`. The shared state
+/// lives inside the returned future rather than behind an `Arc<Mutex<..>>`,
+/// so the halves only borrow it and there's no lock to take. The state still
+/// allocates what it needs as any split does, such as the wakers of each
+/// side. Since the halves can't outlive `f`, both have to be consumed within
+/// it (e.g. with `join!`)
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_scoped;
+///
+/// futures::executor::block_on(async {
+///     let mut incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+///     let (evens, odds) = split_by_scoped(
+///         &mut incoming_stream,
+///         |&n| n % 2 == 0,
+///         async |even_stream, odd_stream| {
+///             futures::join!(
+///                 even_stream.collect::<Vec<_>>(),
+///                 odd_stream.collect::<Vec<_>>()
+///             )
+///         },
+///     )
+///     .await;
+///     assert_eq!(vec![0,2,4], evens);
+///     assert_eq!(vec![1,3,5], odds);
+/// })
+/// ```
+pub async fn split_by_scoped<S, P, F, T>(stream: &mut S, predicate: P, f: F) -> T
+where
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+    F: for<'a> AsyncFnOnce(
+        TrueSplitByScoped<'a, S::Item, &'a mut S, P>,
+        FalseSplitByScoped<'a, S::Item, &'a mut S, P>,
+    ) -> T,
+{
+    let state = RefCell::new(SplitBy::new(stream, predicate));
+    f(
+        TrueSplitByScoped { stream: &state },
+        FalseSplitByScoped { stream: &state },
+    )
+    .await
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true` when using `split_by_scoped`
+pub struct TrueSplitByScoped<'a, I, S, P> {
+    stream: &'a RefCell<SplitBy<I, S, P>>,
+}
+
+impl<'a, I, S, P> TrueSplitByScoped<'a, I, S, P> {
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        self.stream.borrow_mut().close_true(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.stream.borrow_mut().take_panic()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stream.borrow_mut().stats_handle()
+    }
+}
+
+impl<'a, I, S, P> Stream for TrueSplitByScoped<'a, I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        if let Ok(mut guard) = self.stream.try_borrow_mut() {
+            SplitBy::poll_next_true(Pin::new(&mut guard), cx)
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.borrow().size_hint_true()
+    }
+}
+
+impl<'a, I, S, P> FusedStream for TrueSplitByScoped<'a, I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.borrow().is_terminated_true()
+    }
+}
+
+impl<'a, I, S, P> fmt::Debug for TrueSplitByScoped<'a, I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TrueSplitByScoped");
+        if let Ok(state) = self.stream.try_borrow() {
+            state.debug_true(&mut debug);
+        } else {
+            // The other side is in the middle of being polled
+            debug.field("state", &format_args!("<borrowed>"));
+        }
+        debug.finish()
+    }
+}
+
+impl<'a, I, S, P> Drop for TrueSplitByScoped<'a, I, S, P> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.stream.try_borrow_mut() {
+            state.close_true(ClosePolicy::Discard);
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false` when using `split_by_scoped`
+pub struct FalseSplitByScoped<'a, I, S, P> {
+    stream: &'a RefCell<SplitBy<I, S, P>>,
+}
+
+impl<'a, I, S, P> FalseSplitByScoped<'a, I, S, P> {
+    /// Closes this stream without dropping it. It will yield `None` from now
+    /// on, anything already buffered for it is dropped, and items routed to it
+    /// afterwards are handled according to `policy`. Closing an already closed
+    /// stream has no effect
+    pub fn close(&mut self, policy: ClosePolicy) {
+        self.stream.borrow_mut().close_false(policy);
+    }
+
+    /// Takes the payload of a panic raised by the predicate, if there was one.
+    /// Both streams end when the predicate panics, and the payload can only be
+    /// taken once from either of them
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.stream.borrow_mut().take_panic()
+    }
+
+    /// Returns a handle for reading the splitter's counters, which stays
+    /// usable independently of either output stream
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stream.borrow_mut().stats_handle()
+    }
+}
+
+impl<'a, I, S, P> Stream for FalseSplitByScoped<'a, I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        if let Ok(mut guard) = self.stream.try_borrow_mut() {
+            SplitBy::poll_next_false(Pin::new(&mut guard), cx)
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.borrow().size_hint_false()
+    }
+}
+
+impl<'a, I, S, P> FusedStream for FalseSplitByScoped<'a, I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.borrow().is_terminated_false()
+    }
+}
+
+impl<'a, I, S, P> fmt::Debug for FalseSplitByScoped<'a, I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FalseSplitByScoped");
+        if let Ok(state) = self.stream.try_borrow() {
+            state.debug_false(&mut debug);
+        } else {
+            // The other side is in the middle of being polled
+            debug.field("state", &format_args!("<borrowed>"));
+        }
+        debug.finish()
+    }
+}
+
+impl<'a, I, S, P> Drop for FalseSplitByScoped<'a, I, S, P> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.stream.try_borrow_mut() {
+            state.close_false(ClosePolicy::Discard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::split_by_scoped;
+
+    #[test]
+    fn test_stream_usable_after_scope() {
+        futures::executor::block_on(async {
+            let mut incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+            let first_even = split_by_scoped(
+                &mut incoming_stream,
+                |&n| n % 2 == 0,
+                async |mut even_stream, _odd_stream| even_stream.next().await,
+            )
+            .await;
+            assert_eq!(Some(0), first_even);
+            // Only the 0 was read, so the stream carries on from the 1
+            assert_eq!(vec![1, 2, 3], incoming_stream.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_dropped_side_is_discarded() {
+        futures::executor::block_on(async {
+            let mut incoming_stream = futures::stream::iter(0..6);
+            let evens = split_by_scoped(
+                &mut incoming_stream,
+                |&n| n % 2 == 0,
+                async |even_stream, odd_stream| {
+                    drop(odd_stream);
+                    even_stream.collect::<Vec<_>>().await
+                },
+            )
+            .await;
+            assert_eq!(vec![0, 2, 4], evens);
+        });
+    }
+
+    #[test]
+    fn test_predicate_panic() {
+        futures::executor::block_on(async {
+            let mut incoming_stream = futures::stream::iter(0..6);
+            let (evens, odds, panic) = split_by_scoped(
+                &mut incoming_stream,
+                |&n| {
+                    assert_ne!(3, n, "no threes");
+                    n % 2 == 0
+                },
+                async |mut even_stream, odd_stream| {
+                    let (evens, odds) = futures::join!(
+                        (&mut even_stream).collect::<Vec<_>>(),
+                        odd_stream.collect::<Vec<_>>()
+                    );
+                    (evens, odds, even_stream.take_panic())
+                },
+            )
+            .await;
+            // Both streams end at the panic
+            assert_eq!(vec![0, 2], evens);
+            assert_eq!(vec![1], odds);
+            assert!(panic.is_some());
+        });
+    }
+}
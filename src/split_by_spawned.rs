@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future::{self, Either},
+    stream::FusedStream,
+    Stream, StreamExt,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Spawns a task onto the current tokio runtime that reads `stream` to the
+/// end, sending each item to one of the two returned streams according to
+/// `route`. The task stops early once both streams have been dropped. It's
+/// named after `name`, if given, for tools such as tokio-console
+pub(crate) fn spawn<S, F, L, R>(
+    stream: S,
+    name: Option<&str>,
+    route: F,
+) -> (SplitBySpawned<L>, SplitBySpawned<R>)
+where
+    S: Stream + Send + 'static,
+    F: Fn(S::Item) -> Either<L, R> + Send + 'static,
+    L: Send + 'static,
+    R: Send + 'static,
+{
+    let (left_sender, left_receiver) = mpsc::unbounded_channel();
+    let (right_sender, right_receiver) = mpsc::unbounded_channel();
+    let driver = async move {
+        let both_closed = future::join(left_sender.closed(), right_sender.closed());
+        futures::pin_mut!(stream, both_closed);
+        loop {
+            let item = match future::select(stream.next(), both_closed.as_mut()).await {
+                Either::Left((Some(item), _)) => item,
+                // Either the stream ended or nobody is left to send its items to
+                Either::Left((None, _)) | Either::Right(_) => return,
+            };
+            // A send only fails once that side's stream has been dropped, in which case
+            // the item is discarded like it would be by the other kinds of split
+            match route(item) {
+                Either::Left(item) => {
+                    let _ = left_sender.send(item);
+                }
+                Either::Right(item) => {
+                    let _ = right_sender.send(item);
+                }
+            }
+        }
+    };
+    let name = match name {
+        Some(name) => format!("split-stream-by driver ({})", name),
+        None => String::from("split-stream-by driver"),
+    };
+    // The span is created here rather than in the task, so that it's a child of
+    // the caller's current span
+    #[cfg(feature = "tracing")]
+    let driver = tracing::Instrument::instrument(
+        driver,
+        tracing::trace_span!("split_by_spawned", task = %name),
+    );
+    // Task names are only kept by tokio when it's built with `--cfg tokio_unstable`
+    // and its `tracing` feature, which tokio-console needs anyway
+    #[cfg(all(tokio_unstable, feature = "tracing"))]
+    tokio::task::Builder::new()
+        .name(&name)
+        .spawn(driver)
+        .expect("failed to spawn the split's driver task");
+    #[cfg(not(all(tokio_unstable, feature = "tracing")))]
+    {
+        let _ = name;
+        tokio::spawn(driver);
+    }
+    (
+        SplitBySpawned {
+            receiver: left_receiver,
+        },
+        SplitBySpawned {
+            receiver: right_receiver,
+        },
+    )
+}
+
+/// A struct that implements `Stream` which returns the items routed to one
+/// side by `split_by_spawned` or `split_by_map_spawned`. Items are queued
+/// without bound until they're read, so each stream can be consumed at its own
+/// pace or not at all
+pub struct SplitBySpawned<T> {
+    receiver: UnboundedReceiver<T>,
+}
+
+impl<T> Stream for SplitBySpawned<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.receiver.len(), None)
+    }
+}
+
+impl<T> FusedStream for SplitBySpawned<T> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_closed() && self.receiver.is_empty()
+    }
+}
+
+impl<T> fmt::Debug for SplitBySpawned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitBySpawned")
+            .field("buffered", &self.receiver.len())
+            .field("terminated", &self.is_terminated())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+    use tokio::sync::oneshot;
+
+    use crate::SplitStreamByExt;
+
+    #[tokio::test]
+    async fn test_completes() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, odd_stream) = incoming_stream.split_by_spawned(|&n| n % 2 == 0);
+        let (evens, odds) = futures::join!(
+            even_stream.collect::<Vec<_>>(),
+            odd_stream.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 2], evens);
+        assert_eq!(vec![1, 3], odds);
+    }
+
+    #[tokio::test]
+    async fn test_named() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, odd_stream) =
+            incoming_stream.split_by_spawned_named("numbers", |&n| n % 2 == 0);
+        drop(odd_stream);
+        assert_eq!(vec![0, 2], even_stream.collect::<Vec<_>>().await);
+    }
+
+    #[tokio::test]
+    async fn test_one_side_closed() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, mut odd_stream) = incoming_stream.split_by_spawned(|&n| n % 2 == 0);
+        drop(even_stream);
+        assert_eq!(Some(1), odd_stream.next().await);
+        assert_eq!(Some(3), odd_stream.next().await);
+        assert_eq!(None, odd_stream.next().await);
+    }
+
+    #[tokio::test]
+    async fn test_task_exits() {
+        // The underlying stream never ends, so it's only dropped once the task has seen
+        // both streams go away
+        let (guard, dropped) = oneshot::channel::<()>();
+        let incoming_stream = futures::stream::poll_fn(move |_| {
+            let _ = &guard;
+            Poll::<Option<u32>>::Pending
+        });
+        let (even_stream, odd_stream) = incoming_stream.split_by_spawned(|&n| n % 2 == 0);
+        drop(even_stream);
+        drop(odd_stream);
+        assert!(dropped.await.is_err());
+    }
+}
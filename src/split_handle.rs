@@ -0,0 +1,202 @@
+use std::sync::Weak;
+
+use crate::sync::{LockFamily, Mutex, StdMutex};
+
+use crate::{
+    abort::Abortable, pause::Pausable, Buffer, PausePolicy, Side, SplitBy, SplitByBufferedWith,
+    Stats, StatsHandle,
+};
+
+mod sealed {
+    /// Implemented by the shared state of the splits whose outputs can be
+    /// swapped, which are the ones where both sides have the same item type
+    pub trait Swappable {
+        fn swap_outputs(&mut self);
+    }
+}
+
+pub(crate) use sealed::Swappable;
+
+/// A cheap, cloneable handle for controlling a split without owning either
+/// of its output streams, returned by `split_handle` on either of them. It
+/// gathers the operations of the other handles in one place for a task that
+/// supervises splits it doesn't consume from. Like the other handles it
+/// doesn't keep the split alive, and the controls do nothing once both
+/// streams have been dropped, although the stats stay readable
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{PausePolicy, Side, SplitStreamByExt};
+///
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let (mut even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+/// let handle = even_stream.split_handle();
+/// handle.pause(Side::Right, PausePolicy::Drop);
+/// futures::executor::block_on(async {
+///     assert_eq!(Some(0), even_stream.next().await);
+///     assert_eq!(Some(2), even_stream.next().await);
+///     assert_eq!(3, handle.stats().routed_left + handle.stats().routed_right);
+///     assert!(handle.abort().is_some());
+/// });
+/// ```
+pub struct SplitHandle<T, M: LockFamily = StdMutex> {
+    state: Weak<Mutex<T, M>>,
+    stats: StatsHandle,
+}
+
+impl<T, M: LockFamily> SplitHandle<T, M> {
+    pub(crate) fn new(state: Weak<Mutex<T, M>>, stats: StatsHandle) -> Self {
+        Self { state, stats }
+    }
+
+    /// Returns the current values of the split's counters
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Returns a handle for reading the split's counters and subscribing to
+    /// its events
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
+    /// Whether either output stream is still around, so that the controls
+    /// still have an effect
+    pub fn is_alive(&self) -> bool {
+        self.state.strong_count() > 0
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let state = self.state.upgrade()?;
+        let mut guard = state.lock();
+        Some(f(&mut guard))
+    }
+}
+
+impl<T: Pausable, M: LockFamily> SplitHandle<T, M> {
+    /// Pauses the `side` stream, as with `PauseHandle::pause`
+    pub fn pause(&self, side: Side, policy: PausePolicy) {
+        self.with_state(|state| state.pause(side, policy));
+    }
+
+    /// Resumes the `side` stream, as with `PauseHandle::resume`
+    pub fn resume(&self, side: Side) {
+        self.with_state(|state| state.resume(side));
+    }
+}
+
+impl<T: Abortable, M: LockFamily> SplitHandle<T, M> {
+    /// Shuts the split down, ending both output streams and handing back the
+    /// underlying stream as with `AbortHandle::abort`
+    pub fn abort(&self) -> Option<T::Parts> {
+        self.with_state(|state| state.abort()).flatten()
+    }
+}
+
+impl<T: Swappable, M: LockFamily> SplitHandle<T, M> {
+    /// Swaps which stream the predicate's results go to, as with
+    /// `swap_outputs` on either output stream
+    pub fn swap_outputs(&self) {
+        self.with_state(|state| state.swap_outputs());
+    }
+}
+
+impl<T, M: LockFamily> Clone for SplitHandle<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Weak::clone(&self.state),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<I, S, P> Swappable for SplitBy<I, S, P> {
+    fn swap_outputs(&mut self) {
+        SplitBy::swap_outputs(self)
+    }
+}
+
+impl<I, S, P, B: Buffer<I>> Swappable for SplitByBufferedWith<I, S, P, B> {
+    fn swap_outputs(&mut self) {
+        SplitByBufferedWith::swap_outputs(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use futures::StreamExt;
+
+    use crate::{PausePolicy, Side, SplitStreamByExt};
+
+    #[test]
+    fn test_outlives_split() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<4>(|&n| n % 2 == 0);
+        let handle = even_stream.split_handle();
+        assert!(handle.is_alive());
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(
+                even_stream.take(1).collect::<Vec<_>>(),
+                odd_stream.take(1).collect::<Vec<_>>()
+            );
+            assert_eq!((vec![0], vec![1]), (evens, odds));
+        });
+        // Both streams were dropped by `take`, so the controls do nothing
+        assert!(!handle.is_alive());
+        handle.pause(Side::Left, PausePolicy::Drop);
+        handle.resume(Side::Left);
+        handle.swap_outputs();
+        assert!(handle.abort().is_none());
+        // But the counters are still there to read
+        let stats = handle.clone().stats();
+        assert_eq!(1, stats.yielded_left);
+        assert_eq!(1, stats.yielded_right);
+    }
+
+    #[test]
+    fn test_concurrent_use() {
+        let incoming_stream = futures::stream::iter(0..2000);
+        let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<4>(|&n| n % 2 == 0);
+        let handle = even_stream.split_handle();
+        let (mut evens, mut odds) = thread::scope(|scope| {
+            let pausing = handle.clone();
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    pausing.pause(Side::Right, PausePolicy::Buffer);
+                    thread::yield_now();
+                    pausing.resume(Side::Right);
+                }
+            });
+            let swapping = handle.clone();
+            scope.spawn(move || {
+                // An even number of swaps, so the predicate ends up as it started
+                for _ in 0..200 {
+                    swapping.swap_outputs();
+                    thread::yield_now();
+                }
+            });
+            let reading = handle.clone();
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    let stats = reading.stats();
+                    assert!(stats.yielded_left <= stats.routed_left);
+                    assert!(stats.yielded_right <= stats.routed_right);
+                }
+            });
+            futures::executor::block_on(async {
+                futures::join!(
+                    even_stream.collect::<Vec<_>>(),
+                    odd_stream.collect::<Vec<_>>()
+                )
+            })
+        });
+        // The swaps move items between the streams, but each one is yielded once
+        let stats = handle.stats();
+        assert_eq!(2000, stats.yielded_left + stats.yielded_right);
+        evens.append(&mut odds);
+        evens.sort_unstable();
+        assert_eq!((0..2000).collect::<Vec<_>>(), evens);
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future,
+    stream::{self, BoxStream, FusedStream, Map, PollNext, SelectWithStrategy},
+    Stream, StreamExt,
+};
+
+use crate::{
+    sync::LockFamily, Buffer, FalseSplitBy, FalseSplitByBufferedWith, LeftSplitByMap,
+    LeftSplitByMapBufferedWith, ReuniteError, RightSplitByMap, RightSplitByMapBufferedWith,
+    SplitByBufferedParts, SplitByMapBufferedParts, SplitByMapParts, SplitByParts, TrueSplitBy,
+    TrueSplitByBufferedWith,
+};
+
+mod sealed {
+    use crate::ReuniteError;
+
+    /// Implemented by the first stream of each kind of split, taking it apart
+    /// together with the second
+    pub trait Reunite<F>: Sized {
+        type Parts;
+        fn reunite(self, other: F) -> Result<Self::Parts, ReuniteError<Self, F>>;
+    }
+}
+
+use sealed::Reunite;
+
+/// The two output streams of a split kept together, for hanging operations
+/// on the pair and for naming it in a single field. The `_pair` variants of
+/// `split_by`, `split_by_buffered`, `split_by_map` and
+/// `split_by_map_buffered` return one, the streams returned by any of the
+/// other split methods can be turned into one with `SplitPair::from`, and
+/// `into_tuple` takes it apart again
+///
+///```rust
+/// use split_stream_by::SplitStreamByExt;
+///
+/// let incoming_stream = futures::stream::iter([0,1,2,3]);
+/// let pair = incoming_stream.split_by_pair(|&n| n % 2 == 0);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = pair.map_false(|n| n * 10).collect_both().await;
+///     assert_eq!(vec![0, 2], evens);
+///     assert_eq!(vec![10, 30], odds);
+/// });
+/// ```
+pub struct SplitPair<T, F>(pub T, pub F);
+
+impl<T, F> SplitPair<T, F> {
+    /// Takes the pair apart into its first and second stream
+    pub fn into_tuple(self) -> (T, F) {
+        (self.0, self.1)
+    }
+
+    /// Maps the items of the first stream with `f`
+    pub fn map_true<U, G>(self, f: G) -> SplitPair<Map<T, G>, F>
+    where
+        T: Stream,
+        G: FnMut(T::Item) -> U,
+    {
+        SplitPair(self.0.map(f), self.1)
+    }
+
+    /// Maps the items of the second stream with `f`
+    pub fn map_false<U, G>(self, f: G) -> SplitPair<T, Map<F, G>>
+    where
+        F: Stream,
+        G: FnMut(F::Item) -> U,
+    {
+        SplitPair(self.0, self.1.map(f))
+    }
+
+    /// Boxes both streams, so that the pair's type no longer depends on the
+    /// predicate or the underlying stream
+    pub fn boxed<'a>(self) -> SplitPair<BoxStream<'a, T::Item>, BoxStream<'a, F::Item>>
+    where
+        T: Stream + Send + 'a,
+        F: Stream + Send + 'a,
+    {
+        SplitPair(self.0.boxed(), self.1.boxed())
+    }
+
+    /// Collects both streams at the same time, so that neither is held up
+    /// waiting for the other to be read
+    pub fn collect_both(self) -> impl Future<Output = (Vec<T::Item>, Vec<F::Item>)>
+    where
+        T: Stream,
+        F: Stream,
+    {
+        future::join(self.0.collect(), self.1.collect())
+    }
+
+    /// Merges the two streams back into one that always yields from the first
+    /// when both have an item ready, and only from the second while the first
+    /// has nothing ready. This recombines the sides by priority rather than
+    /// in the order the items were read, with the first as the high priority
+    /// side, so a busy first stream can hold the second off indefinitely. The
+    /// merged stream ends once both have ended.
+    ///
+    /// A split's first stream is left pending when it reads an item for the
+    /// second, so the priority only decides between items that are already
+    /// waiting, e.g. once per-side processing has made each stream slower than
+    /// the underlying one
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitPair;
+    ///
+    /// let pair = SplitPair(futures::stream::iter([1, 2]), futures::stream::iter([10, 20]));
+    /// futures::executor::block_on(async {
+    ///     assert_eq!(vec![1, 2, 10, 20], pair.reunite_biased().collect::<Vec<_>>().await);
+    /// });
+    /// ```
+    pub fn reunite_biased(self) -> SelectWithStrategy<T, F, fn(&mut ()) -> PollNext, ()>
+    where
+        T: Stream,
+        F: Stream<Item = T::Item>,
+    {
+        stream::select_with_strategy(self.0, self.1, |_: &mut ()| PollNext::Left)
+    }
+
+    /// Merges the two streams back into one ordered by `key`, given that each
+    /// stream is ordered by it on its own, e.g. by an event timestamp that
+    /// per-side processing has kept in order. An item is only yielded once
+    /// the other stream has an item ready to compare it with or has ended, so
+    /// a stream with nothing ready holds the merge up. Items with equal keys
+    /// are taken from the first stream first. The merged stream ends once
+    /// both have ended.
+    ///
+    /// Both streams are read as far as they have items ready, queueing the
+    /// items that can't be yielded yet, so that a split isn't left blocked on
+    /// an item for the side the merge is waiting on. The queues grow with how
+    /// far apart the two streams get in key order
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{SplitPair, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([(1, 'a'), (2, 'B'), (3, 'C'), (5, 'd')]);
+    /// let pair = SplitPair::from(incoming_stream.split_by(|(_, c)| c.is_lowercase()));
+    /// let merged = pair
+    ///     .map_true(|(time, c)| (time, c.to_ascii_uppercase()))
+    ///     .reunite_by_key(|&(time, _)| time);
+    /// futures::executor::block_on(async {
+    ///     assert_eq!(
+    ///         vec![(1, 'A'), (2, 'B'), (3, 'C'), (5, 'D')],
+    ///         merged.collect::<Vec<_>>().await
+    ///     );
+    /// });
+    /// ```
+    pub fn reunite_by_key<K, G>(self, key: G) -> ReuniteByKey<T, F, G>
+    where
+        T: Stream,
+        F: Stream<Item = T::Item>,
+        K: Ord,
+        G: FnMut(&T::Item) -> K,
+    {
+        ReuniteByKey {
+            first: Box::pin(self.0),
+            second: Box::pin(self.1),
+            first_queue: VecDeque::new(),
+            second_queue: VecDeque::new(),
+            first_ended: false,
+            second_ended: false,
+            key,
+        }
+    }
+
+    /// Takes apart the split the two streams came from, as with `into_parts`
+    /// on the first of them. This fails if either stream was cloned or if the
+    /// pair was put together from streams of different splits
+    pub fn reunite(self) -> Result<T::Parts, ReuniteError<T, F>>
+    where
+        T: Reunite<F>,
+    {
+        self.0.reunite(self.1)
+    }
+}
+
+/// The stream returned by `SplitPair::reunite_by_key`
+pub struct ReuniteByKey<T: Stream, F: Stream, G> {
+    first: Pin<Box<T>>,
+    second: Pin<Box<F>>,
+    /// Items read from each stream that haven't been yielded yet
+    first_queue: VecDeque<T::Item>,
+    second_queue: VecDeque<F::Item>,
+    first_ended: bool,
+    second_ended: bool,
+    key: G,
+}
+
+// The streams are boxed so that nothing is pinned in place
+impl<T: Stream, F: Stream, G> Unpin for ReuniteByKey<T, F, G> {}
+
+/// Reads one item from `stream` into `queue` unless it has ended, returning
+/// whether that got anywhere
+fn read_into<S: Stream>(
+    stream: Pin<&mut S>,
+    queue: &mut VecDeque<S::Item>,
+    ended: &mut bool,
+    cx: &mut Context<'_>,
+) -> bool {
+    if *ended {
+        return false;
+    }
+    match stream.poll_next(cx) {
+        Poll::Ready(Some(item)) => queue.push_back(item),
+        Poll::Ready(None) => *ended = true,
+        Poll::Pending => return false,
+    }
+    true
+}
+
+impl<T, F, K, G> Stream for ReuniteByKey<T, F, G>
+where
+    T: Stream,
+    F: Stream<Item = T::Item>,
+    K: Ord,
+    G: FnMut(&T::Item) -> K,
+{
+    type Item = T::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T::Item>> {
+        let this = &mut *self;
+        let first_read = read_into(
+            this.first.as_mut(),
+            &mut this.first_queue,
+            &mut this.first_ended,
+            cx,
+        );
+        let second_read = read_into(
+            this.second.as_mut(),
+            &mut this.second_queue,
+            &mut this.second_ended,
+            cx,
+        );
+        let take_first = match (this.first_queue.front(), this.second_queue.front()) {
+            (Some(first), Some(second)) => (this.key)(first) <= (this.key)(second),
+            (Some(_), None) if this.second_ended => true,
+            (None, Some(_)) if this.first_ended => false,
+            (None, None) if this.first_ended && this.second_ended => return Poll::Ready(None),
+            _ => {
+                // A stream that yielded an item hasn't registered to be woken, and the
+                // other one may be waiting on it being read further, e.g. when one side
+                // of a split has read an item for the other
+                if first_read || second_read {
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+        };
+        if take_first {
+            Poll::Ready(this.first_queue.pop_front())
+        } else {
+            Poll::Ready(this.second_queue.pop_front())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let queued = self.first_queue.len() + self.second_queue.len();
+        let (first_lower, first_upper) = if self.first_ended {
+            (0, Some(0))
+        } else {
+            self.first.size_hint()
+        };
+        let (second_lower, second_upper) = if self.second_ended {
+            (0, Some(0))
+        } else {
+            self.second.size_hint()
+        };
+        let lower = first_lower
+            .saturating_add(second_lower)
+            .saturating_add(queued);
+        let upper = first_upper
+            .zip(second_upper)
+            .and_then(|(first, second)| first.checked_add(second)?.checked_add(queued));
+        (lower, upper)
+    }
+}
+
+impl<T, F, K, G> FusedStream for ReuniteByKey<T, F, G>
+where
+    T: Stream,
+    F: Stream<Item = T::Item>,
+    K: Ord,
+    G: FnMut(&T::Item) -> K,
+{
+    fn is_terminated(&self) -> bool {
+        self.first_ended
+            && self.second_ended
+            && self.first_queue.is_empty()
+            && self.second_queue.is_empty()
+    }
+}
+
+impl<T: Stream, F: Stream, G> fmt::Debug for ReuniteByKey<T, F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReuniteByKey")
+            .field("first_queued", &self.first_queue.len())
+            .field("second_queued", &self.second_queue.len())
+            .field("first_ended", &self.first_ended)
+            .field("second_ended", &self.second_ended)
+            .finish()
+    }
+}
+
+impl<T, F> From<(T, F)> for SplitPair<T, F> {
+    fn from((true_stream, false_stream): (T, F)) -> Self {
+        Self(true_stream, false_stream)
+    }
+}
+
+impl<T, F> From<SplitPair<T, F>> for (T, F) {
+    fn from(pair: SplitPair<T, F>) -> Self {
+        pair.into_tuple()
+    }
+}
+
+impl<I, S: Unpin, P, M: LockFamily> Reunite<FalseSplitBy<I, S, P, M>> for TrueSplitBy<I, S, P, M> {
+    type Parts = SplitByParts<I, S>;
+
+    fn reunite(
+        self,
+        other: FalseSplitBy<I, S, P, M>,
+    ) -> Result<Self::Parts, ReuniteError<Self, FalseSplitBy<I, S, P, M>>> {
+        self.into_parts(other)
+    }
+}
+
+impl<I, S: Unpin, P, B: Buffer<I>, M: LockFamily> Reunite<FalseSplitByBufferedWith<I, S, P, B, M>>
+    for TrueSplitByBufferedWith<I, S, P, B, M>
+{
+    type Parts = SplitByBufferedParts<I, S>;
+
+    fn reunite(
+        self,
+        other: FalseSplitByBufferedWith<I, S, P, B, M>,
+    ) -> Result<Self::Parts, ReuniteError<Self, FalseSplitByBufferedWith<I, S, P, B, M>>> {
+        self.into_parts(other)
+    }
+}
+
+impl<I, L, R, S: Unpin, P, M: LockFamily> Reunite<RightSplitByMap<I, L, R, S, P, M>>
+    for LeftSplitByMap<I, L, R, S, P, M>
+{
+    type Parts = SplitByMapParts<L, R, S>;
+
+    fn reunite(
+        self,
+        other: RightSplitByMap<I, L, R, S, P, M>,
+    ) -> Result<Self::Parts, ReuniteError<Self, RightSplitByMap<I, L, R, S, P, M>>> {
+        self.into_parts(other)
+    }
+}
+
+impl<I, L, R, S: Unpin, P, BL: Buffer<L>, BR: Buffer<R>, M: LockFamily>
+    Reunite<RightSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>>
+    for LeftSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>
+{
+    type Parts = SplitByMapBufferedParts<L, R, S>;
+
+    fn reunite(
+        self,
+        other: RightSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>,
+    ) -> Result<
+        Self::Parts,
+        ReuniteError<Self, RightSplitByMapBufferedWith<I, L, R, S, P, BL, BR, M>>,
+    > {
+        self.into_parts(other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, FutureExt, StreamExt};
+
+    use super::SplitPair;
+
+    use crate::{Either, SplitStreamByExt, SplitStreamByMapExt};
+
+    #[test]
+    fn test_reunite_by_key_buffered() {
+        // Runs of items for one side fill up the other side's buffer, which has to be
+        // taken from before the merge can go on
+        let incoming_stream = futures::stream::iter([0, 2, 4, 6, 1, 3, 8, 5, 5, 7]);
+        let pair = incoming_stream.split_by_buffered_pair::<2>(|&n| n % 2 == 0);
+        let merged = pair.reunite_by_key(|&n| n);
+        futures::executor::block_on(async {
+            assert_eq!(
+                vec![0, 1, 2, 3, 4, 5, 5, 6, 7, 8],
+                merged.collect::<Vec<_>>().await
+            );
+        });
+    }
+
+    #[test]
+    fn test_map_pair_reunites() {
+        let incoming_stream = futures::stream::iter([1, -2, 3, 4]);
+        let pair = incoming_stream.split_by_map_buffered_pair::<2>(|n: i32| {
+            if n > 0 {
+                Either::Left(n)
+            } else {
+                Either::Right(n.to_string())
+            }
+        });
+        let (mut positives, negatives) = pair.into_tuple();
+        assert_eq!(Some(Some(1)), positives.next().now_or_never());
+        // The left side reads -2 and then defers to the right, leaving it buffered
+        assert_eq!(None, positives.next().now_or_never());
+        let parts = SplitPair(positives, negatives).reunite().unwrap();
+        assert!(parts.buffered_left.is_empty());
+        assert_eq!(vec!["-2".to_string()], parts.buffered_right);
+        futures::executor::block_on(async {
+            assert_eq!(vec![3, 4], parts.stream.collect::<Vec<_>>().await);
+        });
+    }
+
+    #[test]
+    fn test_reunite_biased_prefers_first_once_ready() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (high_sender, high) = futures::channel::mpsc::unbounded();
+        let low = futures::stream::iter([10, 20, 30]);
+        let mut merged = SplitPair(high, low).reunite_biased();
+        // The first has nothing ready, so the second goes ahead
+        assert_eq!(Poll::Ready(Some(10)), merged.poll_next_unpin(&mut cx));
+        high_sender.unbounded_send(1).unwrap();
+        assert_eq!(Poll::Ready(Some(1)), merged.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(20)), merged.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(Some(30)), merged.poll_next_unpin(&mut cx));
+        // Only the second has ended, so the merge waits on the first
+        assert_eq!(Poll::Pending, merged.poll_next_unpin(&mut cx));
+        high_sender.unbounded_send(2).unwrap();
+        drop(high_sender);
+        assert_eq!(Poll::Ready(Some(2)), merged.poll_next_unpin(&mut cx));
+        assert_eq!(Poll::Ready(None), merged.poll_next_unpin(&mut cx));
+    }
+
+    #[test]
+    fn test_reunite_biased_split() {
+        // The source ends with odds still buffered, which the merge still yields
+        let incoming_stream = futures::stream::iter([1, 3, 5, 0]);
+        let merged = incoming_stream
+            .split_by_buffered_pair::<4>(|&n| n % 2 == 0)
+            .reunite_biased();
+        futures::executor::block_on(async {
+            let mut merged = merged.collect::<Vec<_>>().await;
+            merged.sort();
+            assert_eq!(vec![0, 1, 3, 5], merged);
+        });
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    convert::TryFrom,
+    sync::{OnceLock, Weak},
+    time::Duration,
+};
+
+use crate::{
+    delay::{Delay, Instant, Timer},
+    stats::Counters,
+    Side,
+};
+
+/// Reported by `StatsHandle::watch_stalls` when a side has items waiting for
+/// it but its stream hasn't been polled for longer than the threshold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stall {
+    /// The side whose stream isn't being polled
+    pub side: Side,
+    /// How many items are waiting in its buffer
+    pub buffered: usize,
+    /// How long it has been since the stream was last polled, or since the
+    /// watch started if it has never been polled
+    pub idle: Duration,
+}
+
+/// Nanoseconds since the first time this was called. Last poll times are
+/// stored this way so that they fit in an atomic
+pub(crate) fn now_nanos() -> u64 {
+    static BASE: OnceLock<Instant> = OnceLock::new();
+    // Never returns 0 since that means a side hasn't been polled
+    let base = *BASE.get_or_init(Timer::now);
+    let nanos = Timer::now().saturating_duration_since(base).as_nanos();
+    u64::try_from(nanos).unwrap_or(u64::MAX).max(1)
+}
+
+pub(crate) async fn watch(
+    counters: Weak<Counters>,
+    threshold: Duration,
+    mut on_stall: impl FnMut(Stall),
+) {
+    let started = now_nanos();
+    let mut reported = [false; 2];
+    loop {
+        <Timer as Delay>::new(threshold / 2).await;
+        let counters = match counters.upgrade() {
+            Some(counters) => counters,
+            None => return,
+        };
+        let stats = counters.snapshot();
+        if stats.terminated && stats.buffered_left == 0 && stats.buffered_right == 0 {
+            return;
+        }
+        let now = now_nanos();
+        for (side, buffered) in [
+            (Side::Left, stats.buffered_left),
+            (Side::Right, stats.buffered_right),
+        ] {
+            let last_poll = match counters.last_poll(side) {
+                0 => started,
+                last_poll => last_poll.max(started),
+            };
+            let idle = Duration::from_nanos(now.saturating_sub(last_poll));
+            if buffered == 0 || idle < threshold {
+                reported[side as usize] = false;
+            } else if !reported[side as usize] {
+                reported[side as usize] = true;
+                on_stall(Stall {
+                    side,
+                    buffered,
+                    idle,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,553 @@
+#[cfg(feature = "metrics")]
+use std::sync::OnceLock;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+};
+
+#[cfg(feature = "time")]
+use std::time::Duration;
+
+#[cfg(feature = "latency")]
+use crate::latency::Latency;
+#[cfg(feature = "metrics")]
+use crate::metrics_export::MetricsHandles;
+#[cfg(feature = "time")]
+use crate::stall::{self, Stall};
+use crate::{
+    events::{BackpressureEvent, BackpressureEvents, Monitor, SplitEvent, SplitEvents},
+    Side,
+};
+
+/// Counters kept up to date by a splitter while its lock is held. They're
+/// atomics only so that a `StatsHandle` can read them without taking the lock
+#[derive(Default)]
+pub(crate) struct Counters {
+    routed: [AtomicU64; 2],
+    yielded: [AtomicU64; 2],
+    dropped: [AtomicU64; 2],
+    buffered: [AtomicUsize; 2],
+    wakes: AtomicU64,
+    source_polls: AtomicU64,
+    terminated: AtomicBool,
+    closed: [AtomicBool; 2],
+    /// The tasks waiting on `OnComplete`, woken once nothing else will be
+    /// delivered
+    completion: Mutex<Vec<Waker>>,
+    #[cfg(feature = "metrics")]
+    metrics: OnceLock<MetricsHandles>,
+    #[cfg(feature = "latency")]
+    latency: Mutex<Latency>,
+    #[cfg(feature = "time")]
+    last_poll: [AtomicU64; 2],
+    monitor: Monitor<SplitEvent>,
+    backpressure: Monitor<BackpressureEvent>,
+    /// Whether each side has filled up since it last drained
+    full: [AtomicBool; 2],
+    /// Whether the underlying stream has been pending since it last yielded
+    stalled: AtomicBool,
+}
+
+impl Counters {
+    /// Counters carrying on from `totals`
+    fn resume(totals: &Totals) -> Self {
+        let counters = Self::default();
+        for side in [Side::Left, Side::Right] {
+            let index = side as usize;
+            counters.routed[index].store(totals.routed[index], Ordering::Relaxed);
+            counters.yielded[index].store(totals.yielded[index], Ordering::Relaxed);
+            counters.dropped[index].store(totals.dropped[index], Ordering::Relaxed);
+            counters.buffered[index].store(totals.buffered[index], Ordering::Relaxed);
+            counters.closed[index].store(totals.closed[index], Ordering::Relaxed);
+        }
+        counters
+            .source_polls
+            .store(totals.source_polls, Ordering::Relaxed);
+        if totals.terminated {
+            counters.terminated.store(true, Ordering::Relaxed);
+            counters.monitor.close();
+            counters.backpressure.close();
+        }
+        counters
+    }
+
+    pub(crate) fn routed(&self, side: Side) {
+        self.routed[side as usize].fetch_add(1, Ordering::Relaxed);
+        self.stalled.store(false, Ordering::Relaxed);
+        self.monitor.emit(SplitEvent::Routed { side });
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.get() {
+            metrics.routed(side);
+        }
+    }
+
+    pub(crate) fn yielded(&self, side: Side) {
+        self.yielded[side as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn buffered(&self, side: Side, len: usize) {
+        self.buffered[side as usize].store(len, Ordering::Relaxed);
+        #[cfg(feature = "latency")]
+        self.latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .buffered(side, len);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.get() {
+            metrics.buffered(side, len);
+        }
+        if len == 0 {
+            if self.full[side as usize].swap(false, Ordering::Relaxed) {
+                self.backpressure
+                    .emit(BackpressureEvent::SideDrained { side });
+            }
+            self.check_complete();
+        }
+    }
+
+    /// How many items are buffered for `side`, as last reported to `buffered`
+    pub(crate) fn buffered_len(&self, side: Side) -> usize {
+        self.buffered[side as usize].load(Ordering::Relaxed)
+    }
+
+    /// Like `buffered`, but for when the buffer for `side` shrank because its
+    /// oldest item was yielded
+    pub(crate) fn unbuffered(&self, side: Side, len: usize) {
+        #[cfg(feature = "latency")]
+        self.latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unbuffered(side);
+        self.buffered(side, len);
+    }
+
+    pub(crate) fn buffer_full(&self, side: Side) {
+        self.monitor.emit(SplitEvent::BufferFull { side });
+        if !self.full[side as usize].swap(true, Ordering::Relaxed) {
+            self.backpressure.emit(BackpressureEvent::SideFull { side });
+        }
+    }
+
+    /// Records that `count` items meant for `side` were dropped without being
+    /// yielded
+    pub(crate) fn discarded(&self, side: Side, count: usize) {
+        self.dropped[side as usize].fetch_add(count as u64, Ordering::Relaxed);
+        for _ in 0..count {
+            self.monitor.emit(SplitEvent::Dropped { side });
+        }
+    }
+
+    #[cfg_attr(not(feature = "time"), allow(unused_variables))]
+    pub(crate) fn polled(&self, side: Side) {
+        #[cfg(feature = "time")]
+        self.last_poll[side as usize].store(stall::now_nanos(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn woke(&self) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.get() {
+            metrics.woke();
+        }
+    }
+
+    pub(crate) fn source_polled(&self) {
+        self.source_polls.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.get() {
+            metrics.source_polled();
+        }
+    }
+
+    /// Records that the underlying stream had nothing ready when polled
+    pub(crate) fn source_pending(&self) {
+        if !self.stalled.swap(true, Ordering::Relaxed) {
+            self.backpressure.emit(BackpressureEvent::SourceStalled);
+        }
+    }
+
+    pub(crate) fn terminated(&self) {
+        self.terminated.store(true, Ordering::Relaxed);
+        self.monitor.emit(SplitEvent::Terminated);
+        // Nothing else can happen, so let the event streams end
+        self.monitor.close();
+        self.backpressure.close();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.get() {
+            metrics.terminated();
+        }
+        self.check_complete();
+    }
+
+    /// Records that `side` was closed, so nothing else will be delivered to it
+    pub(crate) fn closed(&self, side: Side) {
+        self.closed[side as usize].store(true, Ordering::Relaxed);
+        self.check_complete();
+    }
+
+    /// Whether each side has either been closed or has nothing buffered after
+    /// the underlying stream ended, so nothing else will be delivered
+    fn is_complete(&self) -> bool {
+        let terminated = self.terminated.load(Ordering::Relaxed);
+        [Side::Left, Side::Right].iter().all(|&side| {
+            self.closed[side as usize].load(Ordering::Relaxed)
+                || terminated && self.buffered[side as usize].load(Ordering::Relaxed) == 0
+        })
+    }
+
+    /// Wakes anything waiting on `OnComplete` if the split is complete. The
+    /// waiting tasks are only locked once it is
+    fn check_complete(&self) {
+        if !self.is_complete() {
+            return;
+        }
+        let waiting = std::mem::take(
+            &mut *self
+                .completion
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+
+    /// When `side` was last polled, as returned by `stall::now_nanos`, or 0 if
+    /// it hasn't been polled yet
+    #[cfg(feature = "time")]
+    pub(crate) fn last_poll(&self, side: Side) -> u64 {
+        self.last_poll[side as usize].load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            routed_left: self.routed[Side::Left as usize].load(Ordering::Relaxed),
+            routed_right: self.routed[Side::Right as usize].load(Ordering::Relaxed),
+            yielded_left: self.yielded[Side::Left as usize].load(Ordering::Relaxed),
+            yielded_right: self.yielded[Side::Right as usize].load(Ordering::Relaxed),
+            dropped_left: self.dropped[Side::Left as usize].load(Ordering::Relaxed),
+            dropped_right: self.dropped[Side::Right as usize].load(Ordering::Relaxed),
+            buffered_left: self.buffered[Side::Left as usize].load(Ordering::Relaxed),
+            buffered_right: self.buffered[Side::Right as usize].load(Ordering::Relaxed),
+            wakes: self.wakes.load(Ordering::Relaxed),
+            source_polls: self.source_polls.load(Ordering::Relaxed),
+            terminated: self.terminated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The values of a splitter's counters and gauges that are cheap to keep
+/// under its lock, so that `Counters` can start from them whenever stats are
+/// first asked for
+#[derive(Default)]
+struct Totals {
+    routed: [u64; 2],
+    yielded: [u64; 2],
+    dropped: [u64; 2],
+    buffered: [usize; 2],
+    source_polls: u64,
+    terminated: bool,
+    closed: [bool; 2],
+}
+
+/// Where a splitter records its stats. Nothing is allocated and no atomics or
+/// events are touched until something asks for a `StatsHandle`, from then on
+/// everything is also recorded in `Counters`. Whatever was counted before
+/// that carries over, but events, wakes, latencies and poll times only count
+/// from then on
+#[derive(Default)]
+pub(crate) struct StatsSlot {
+    totals: Totals,
+    counters: Option<Arc<Counters>>,
+}
+
+impl StatsSlot {
+    /// A slot that records in `counters` from the start, for a splitter that
+    /// reads them itself
+    pub(crate) fn enabled(counters: Arc<Counters>) -> Self {
+        Self {
+            totals: Totals::default(),
+            counters: Some(counters),
+        }
+    }
+
+    /// Starts recording in `Counters` if that hasn't happened yet, returning
+    /// them only if it hasn't, so that they can be handed to the side wakers
+    pub(crate) fn enable(&mut self) -> Option<Arc<Counters>> {
+        if self.counters.is_some() {
+            return None;
+        }
+        let counters = Arc::new(Counters::resume(&self.totals));
+        self.counters = Some(Arc::clone(&counters));
+        Some(counters)
+    }
+
+    /// Returns a handle to the counters, which have to be enabled
+    pub(crate) fn handle(&self) -> StatsHandle {
+        let counters = self.counters.as_ref().expect("stats are enabled first");
+        StatsHandle::new(Arc::clone(counters))
+    }
+
+    pub(crate) fn routed(&mut self, side: Side) {
+        self.totals.routed[side as usize] += 1;
+        if let Some(counters) = &self.counters {
+            counters.routed(side);
+        }
+    }
+
+    pub(crate) fn yielded(&mut self, side: Side) {
+        self.totals.yielded[side as usize] += 1;
+        if let Some(counters) = &self.counters {
+            counters.yielded(side);
+        }
+    }
+
+    pub(crate) fn routed_total(&self, side: Side) -> u64 {
+        self.totals.routed[side as usize]
+    }
+
+    pub(crate) fn yielded_total(&self, side: Side) -> u64 {
+        self.totals.yielded[side as usize]
+    }
+
+    pub(crate) fn buffered(&mut self, side: Side, len: usize) {
+        self.totals.buffered[side as usize] = len;
+        if let Some(counters) = &self.counters {
+            counters.buffered(side, len);
+        }
+    }
+
+    /// How many items are buffered for `side`, as last reported to `buffered`
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn buffered_len(&self, side: Side) -> usize {
+        self.totals.buffered[side as usize]
+    }
+
+    pub(crate) fn unbuffered(&mut self, side: Side, len: usize) {
+        self.totals.buffered[side as usize] = len;
+        if let Some(counters) = &self.counters {
+            counters.unbuffered(side, len);
+        }
+    }
+
+    pub(crate) fn buffer_full(&self, side: Side) {
+        if let Some(counters) = &self.counters {
+            counters.buffer_full(side);
+        }
+    }
+
+    pub(crate) fn discarded(&mut self, side: Side, count: usize) {
+        self.totals.dropped[side as usize] += count as u64;
+        if let Some(counters) = &self.counters {
+            counters.discarded(side, count);
+        }
+    }
+
+    pub(crate) fn polled(&self, side: Side) {
+        if let Some(counters) = &self.counters {
+            counters.polled(side);
+        }
+    }
+
+    pub(crate) fn source_polled(&mut self) {
+        self.totals.source_polls += 1;
+        if let Some(counters) = &self.counters {
+            counters.source_polled();
+        }
+    }
+
+    pub(crate) fn source_pending(&self) {
+        if let Some(counters) = &self.counters {
+            counters.source_pending();
+        }
+    }
+
+    pub(crate) fn terminated(&mut self) {
+        self.totals.terminated = true;
+        if let Some(counters) = &self.counters {
+            counters.terminated();
+        }
+    }
+
+    pub(crate) fn closed(&mut self, side: Side) {
+        self.totals.closed[side as usize] = true;
+        if let Some(counters) = &self.counters {
+            counters.closed(side);
+        }
+    }
+}
+
+/// A point in time snapshot of a splitter's counters. `left` refers to the
+/// first of the pair of streams (the `true` stream for `split_by`) and `right`
+/// to the second
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Items the predicate has routed to the left stream, including any that
+    /// were discarded because it was closed
+    pub routed_left: u64,
+    /// Items the predicate has routed to the right stream, including any that
+    /// were discarded because it was closed
+    pub routed_right: u64,
+    /// Items the left stream has yielded
+    pub yielded_left: u64,
+    /// Items the right stream has yielded
+    pub yielded_right: u64,
+    /// Items meant for the left stream that were dropped without being
+    /// yielded, e.g. because it was closed or degraded
+    pub dropped_left: u64,
+    /// Items meant for the right stream that were dropped without being
+    /// yielded
+    pub dropped_right: u64,
+    /// Items currently buffered for the left stream
+    pub buffered_left: usize,
+    /// Items currently buffered for the right stream
+    pub buffered_right: usize,
+    /// Number of times either stream has been woken by the splitter
+    pub wakes: u64,
+    /// Number of times the underlying stream has been polled
+    pub source_polls: u64,
+    /// Whether the underlying stream has ended (or the predicate panicked)
+    pub terminated: bool,
+}
+
+/// A cheap, cloneable handle for reading a splitter's `Stats` independently
+/// of either output stream. It remains usable after both streams are dropped.
+///
+/// A splitter only starts keeping stats when the first handle to them is
+/// taken (a `SplitHandle` takes one too), so one that's never inspected pays
+/// nothing for them. The item counts and buffer gauges cover everything from
+/// the start either way, but events, wakes, poll times and latencies are only
+/// recorded from then on, so take the handle before polling to see them all
+#[derive(Clone)]
+pub struct StatsHandle {
+    counters: Arc<Counters>,
+}
+
+impl StatsHandle {
+    pub(crate) fn new(counters: Arc<Counters>) -> Self {
+        Self { counters }
+    }
+
+    /// Returns the current values of the splitter's counters
+    pub fn snapshot(&self) -> Stats {
+        self.counters.snapshot()
+    }
+
+    /// Returns a stream of the events happening inside the splitter from now
+    /// on. Only one event stream is fed at a time, so creating another one
+    /// ends this one. If the underlying stream has already terminated, the
+    /// returned stream is empty
+    pub fn events(&self) -> SplitEvents {
+        SplitEvents::new(self.counters.monitor.subscribe(&self.counters.terminated))
+    }
+
+    /// Returns a stream of `BackpressureEvent`s from now on, for a producer
+    /// that wants to adapt how fast it feeds the underlying stream. Like
+    /// `events`, only one backpressure stream is fed at a time and the
+    /// returned stream is empty if the underlying stream has already
+    /// terminated
+    pub fn backpressure(&self) -> BackpressureEvents {
+        BackpressureEvents::new(
+            self.counters
+                .backpressure
+                .subscribe(&self.counters.terminated),
+        )
+    }
+
+    /// Returns a future that resolves once the underlying stream has ended
+    /// and everything buffered for either side has been delivered, or once
+    /// the sides that are left have been closed. This is a way to wait for
+    /// the split to finish without awaiting both consumers
+    pub fn on_complete(&self) -> OnComplete {
+        OnComplete {
+            counters: Arc::clone(&self.counters),
+        }
+    }
+
+    /// Returns a future that checks every `threshold / 2` for a side that has
+    /// items waiting in its buffer but hasn't been polled for at least
+    /// `threshold`, calling `on_stall` once each time that happens. This is
+    /// the usual symptom of forgetting to poll one of the two streams, which
+    /// otherwise shows up as a silent hang.
+    ///
+    /// The future completes once the underlying stream has ended and both
+    /// buffers are empty, or once the splitter and every other handle to it
+    /// have been dropped. It needs to be spawned or polled alongside the
+    /// streams
+    #[cfg(feature = "time")]
+    pub fn watch_stalls(
+        self,
+        threshold: Duration,
+        on_stall: impl FnMut(Stall),
+    ) -> impl Future<Output = ()> {
+        stall::watch(Arc::downgrade(&self.counters), threshold, on_stall)
+    }
+
+    /// Returns a histogram of how long items yielded by the `side` stream
+    /// waited in its buffer, in nanoseconds. Items that were yielded as soon
+    /// as they were read aren't recorded, nor are items that were dropped
+    /// while buffered. This is the number to look at when picking `N` for the
+    /// buffered variants
+    #[cfg(feature = "latency")]
+    pub fn buffer_latency(&self, side: Side) -> hdrhistogram::Histogram<u64> {
+        self.counters
+            .latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .histogram(side)
+    }
+
+    /// Registers the splitter's counters and gauges with the `metrics` facade,
+    /// with `labels` (e.g. the splitter's name) attached to each of them. From
+    /// then on they're updated as the splitter runs. The metrics are
+    ///
+    /// - `split_stream_by_routed_total` (counter, with a `side` label)
+    /// - `split_stream_by_buffered` (gauge, with a `side` label)
+    /// - `split_stream_by_wakes_total` (counter)
+    /// - `split_stream_by_source_polls_total` (counter)
+    /// - `split_stream_by_terminated` (gauge, 1 once the stream has ended)
+    ///
+    /// Returns `false` without doing anything if the splitter was already
+    /// registered
+    #[cfg(feature = "metrics")]
+    pub fn register_metrics(&self, labels: impl metrics::IntoLabels) -> bool {
+        let mut registered = false;
+        self.counters.metrics.get_or_init(|| {
+            registered = true;
+            MetricsHandles::register(labels, &self.counters.snapshot())
+        });
+        registered
+    }
+}
+
+/// A future that resolves once a split has delivered everything it will,
+/// created with `StatsHandle::on_complete`
+pub struct OnComplete {
+    counters: Arc<Counters>,
+}
+
+impl Future for OnComplete {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // The check is made with the lock held so that it can't miss the splitter
+        // completing in between
+        let mut completion = self
+            .counters
+            .completion
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.counters.is_complete() {
+            return Poll::Ready(());
+        }
+        if !completion.iter().any(|waker| waker.will_wake(cx.waker())) {
+            completion.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+};
+
+/// Builds a predicate for `split_by` and its variants that sends every item
+/// with the same correlation key to the same side, e.g. so that a request and
+/// its response land on the same consumer. The side for a key is picked by
+/// `predicate` on the first item with that key, and items with a key that's
+/// remembered follow it without `predicate` being called.
+///
+/// Only the `capacity` most recently seen keys are remembered, and the side
+/// for a key that has been forgotten is picked again by `predicate`
+///
+/// Panics if `capacity` is zero
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{sticky_predicate, SplitStreamByExt};
+///
+/// // Requests are routed by id, and responses follow their request
+/// let incoming_stream = futures::stream::iter([
+///     ("request", 1),
+///     ("request", 2),
+///     ("response", 2),
+///     ("response", 1),
+/// ]);
+/// let (odd_stream, even_stream) = incoming_stream.split_by(sticky_predicate(
+///     64,
+///     |&(_, id): &(&str, u32)| id,
+///     |&(kind, id)| kind == "request" && id % 2 == 1,
+/// ));
+/// futures::executor::block_on(async {
+///     let (odd, even) = futures::join!(
+///         odd_stream.collect::<Vec<_>>(),
+///         even_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![("request", 1), ("response", 1)], odd);
+///     assert_eq!(vec![("request", 2), ("response", 2)], even);
+/// });
+/// ```
+pub fn sticky_predicate<I, K>(
+    capacity: usize,
+    key: impl Fn(&I) -> K,
+    predicate: impl Fn(&I) -> bool,
+) -> impl Fn(&I) -> bool
+where
+    K: Hash + Eq + Clone,
+{
+    assert!(capacity > 0, "at least one key has to be remembered");
+    let sides = Mutex::new(StickySides::new(capacity));
+    move |item| {
+        let key = key(item);
+        let mut sides = sides.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(side) = sides.get(&key) {
+            return side;
+        }
+        let side = predicate(item);
+        sides.insert(key, side);
+        side
+    }
+}
+
+/// The side picked for each key, bounded to the most recently used keys
+struct StickySides<K> {
+    capacity: usize,
+    /// The side for each key, and when the key was last used
+    sides: HashMap<K, (bool, u64)>,
+    /// The keys by when they were last used, oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone> StickySides<K> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            sides: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<bool> {
+        let (side, used) = self.sides.get_mut(key)?;
+        let key = self.recency.remove(used).expect("every key has a use");
+        self.clock += 1;
+        *used = self.clock;
+        self.recency.insert(self.clock, key);
+        Some(*side)
+    }
+
+    fn insert(&mut self, key: K, side: bool) {
+        if self.sides.len() == self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.sides.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.sides.insert(key, (side, self.clock));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::sticky_predicate;
+
+    #[test]
+    fn test_least_recently_used_key_forgotten() {
+        let calls = Cell::new(0);
+        let predicate = sticky_predicate(
+            2,
+            |&(key, _): &(char, bool)| key,
+            |&(_, side)| {
+                calls.set(calls.get() + 1);
+                side
+            },
+        );
+        assert!(predicate(&('a', true)));
+        assert!(!predicate(&('b', false)));
+        // Using `a` again makes `b` the one forgotten for `c`
+        assert!(predicate(&('a', false)));
+        assert!(predicate(&('c', true)));
+        assert!(predicate(&('a', false)));
+        assert!(predicate(&('b', true)));
+        assert_eq!(4, calls.get());
+    }
+}
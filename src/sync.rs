@@ -0,0 +1,516 @@
+use std::{
+    cell::{RefCell, RefMut},
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        PoisonError, TryLockError,
+    },
+};
+
+/// A lock that can guard the shared state of a split. The splits in this
+/// crate are generic over a `LockFamily` naming one of these, which is
+/// `std::sync::Mutex` unless another is picked with `SplitBuilder::lock`.
+///
+/// Poisoning is ignored. A panic while either output stream is being polled
+/// ends both of them before it carries on, so the other one doesn't go on
+/// with state that may have been left half updated. Elsewhere, e.g. when a
+/// side is closed and its buffered items are dropped, the state is updated
+/// before any of the user's code runs
+pub trait SharedLock<T> {
+    /// The guard holding the lock, giving access to the state
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    /// Creates an unlocked lock guarding `value`
+    fn new(value: T) -> Self;
+
+    /// Waits for the lock and takes it
+    fn lock(&self) -> Self::Guard<'_>;
+
+    /// Takes the lock if it's free, without waiting
+    fn try_lock(&self) -> Option<Self::Guard<'_>>;
+}
+
+impl<T> SharedLock<T> for std::sync::Mutex<T> {
+    type Guard<'a>
+        = std::sync::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        std::sync::Mutex::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        std::sync::Mutex::lock(self).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        match std::sync::Mutex::try_lock(self) {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+/// Panics in `lock` if the state is already borrowed, which on one thread
+/// can only mean it's being locked again while it's held
+impl<T> SharedLock<T> for RefCell<T> {
+    type Guard<'a>
+        = RefMut<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        RefCell::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.borrow_mut()
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.try_borrow_mut().ok()
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+impl<T> SharedLock<T> for parking_lot::Mutex<T> {
+    type Guard<'a>
+        = parking_lot::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        parking_lot::Mutex::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        parking_lot::Mutex::lock(self)
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        parking_lot::Mutex::try_lock(self)
+    }
+}
+
+#[cfg(feature = "spin")]
+impl<T> SharedLock<T> for spin::Mutex<T> {
+    type Guard<'a>
+        = spin::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        spin::Mutex::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        spin::Mutex::lock(self)
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        spin::Mutex::try_lock(self)
+    }
+}
+
+#[cfg(loom)]
+impl<T> SharedLock<T> for loom::sync::Mutex<T> {
+    type Guard<'a>
+        = loom::sync::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        loom::sync::Mutex::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        loom::sync::Mutex::lock(self).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        match loom::sync::Mutex::try_lock(self) {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+/// Picks the lock guarding the shared state of a split, for any type of
+/// state. The output streams of a split take it as their last type
+/// parameter, `StdMutex` by default
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{Local, SplitBuilder};
+///
+/// // The state never leaves this thread, so a `RefCell` is enough
+/// let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+/// let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+///     .lock::<Local>()
+///     .build_by(|&n| n % 2 == 0);
+/// futures::executor::block_on(async {
+///     let (evens, odds) = futures::join!(
+///         even_stream.collect::<Vec<_>>(),
+///         odd_stream.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0, 2], evens);
+///     assert_eq!(vec![1, 3], odds);
+/// });
+/// ```
+pub trait LockFamily: 'static {
+    /// The lock guarding a state of type `T`
+    type Lock<T>: SharedLock<T>;
+}
+
+/// `std::sync::Mutex`, the default. Building with `--cfg loom` swaps it for
+/// loom's model of a mutex so that the tests in `tests/loom.rs` can explore
+/// every interleaving of two tasks polling the two sides
+pub struct StdMutex;
+
+impl LockFamily for StdMutex {
+    #[cfg(not(loom))]
+    type Lock<T> = std::sync::Mutex<T>;
+    #[cfg(loom)]
+    type Lock<T> = loom::sync::Mutex<T>;
+}
+
+/// `parking_lot::Mutex`, which is smaller and doesn't poison
+#[cfg(feature = "parking_lot")]
+pub struct ParkingLot;
+
+#[cfg(feature = "parking_lot")]
+impl LockFamily for ParkingLot {
+    type Lock<T> = parking_lot::Mutex<T>;
+}
+
+/// `spin::Mutex`, for splits whose sides are only ever polled briefly, as
+/// there's nothing to park a waiting thread on
+#[cfg(feature = "spin")]
+pub struct Spin;
+
+#[cfg(feature = "spin")]
+impl LockFamily for Spin {
+    type Lock<T> = spin::Mutex<T>;
+}
+
+/// `RefCell`, for a split whose output streams stay on one thread. They
+/// aren't `Send`, so the compiler holds them to that
+pub struct Local;
+
+impl LockFamily for Local {
+    type Lock<T> = RefCell<T>;
+}
+
+/// The lock picked by `M`, with the splits' own fault injection on top.
+///
+/// Dropping an output stream has to update the state, but it may be dropped
+/// while the lock is held on the same thread, e.g. while its sibling's item
+/// is `Peeked` or from inside a hook, where waiting for the lock would never
+/// end (or panic, with `Local`). So that work is queued up with
+/// `lock_or_defer` instead, and whoever holds the lock does it before they
+/// let go
+pub(crate) struct Mutex<T, M: LockFamily = StdMutex> {
+    lock: M::Lock<T>,
+    deferred: Deferred<T>,
+}
+
+/// Work queued up by `Mutex::lock_or_defer`, as a function and its argument
+/// rather than a closure so that it doesn't tie the state to `'static`
+struct Deferred<T> {
+    pending: AtomicBool,
+    queue: std::sync::Mutex<Vec<(fn(&mut T, usize), usize)>>,
+}
+
+impl<T> Deferred<T> {
+    /// `pending` is only set once the work is queued up, and cleared before
+    /// the queue is taken, so that work is never missed, only looked for once
+    /// too often. It's a plain atomic even under loom, which would otherwise
+    /// have every lock of the state to explore
+    fn push(&self, work: fn(&mut T, usize), arg: usize) {
+        self.queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((work, arg));
+        self.pending.store(true, Ordering::SeqCst);
+    }
+
+    fn run(&self, state: &mut T) {
+        if !self.pending.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let queue = std::mem::take(&mut *self.queue.lock().unwrap_or_else(PoisonError::into_inner));
+        for (work, arg) in queue {
+            work(state, arg);
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::SeqCst)
+    }
+}
+
+impl<T, M: LockFamily> Mutex<T, M> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            lock: SharedLock::new(value),
+            deferred: Deferred {
+                pending: AtomicBool::new(false),
+                queue: std::sync::Mutex::new(Vec::new()),
+            },
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T, M> {
+        self.guard(SharedLock::lock(&self.lock))
+    }
+
+    pub(crate) fn try_lock(&self) -> Option<MutexGuard<'_, T, M>> {
+        #[cfg(feature = "test-fault")]
+        if crate::fault::contend() {
+            return None;
+        }
+        SharedLock::try_lock(&self.lock).map(|guard| self.guard(guard))
+    }
+
+    /// Calls `work` with the state and `arg` if the lock is free, and
+    /// otherwise leaves it to whoever holds the lock
+    pub(crate) fn lock_or_defer(&self, work: fn(&mut T, usize), arg: usize) {
+        if let Some(mut guard) = SharedLock::try_lock(&self.lock).map(|guard| self.guard(guard)) {
+            work(&mut guard, arg);
+            return;
+        }
+        self.deferred.push(work, arg);
+        // The lock may have been let go of since, in which case nobody else
+        // would see the work until the state is next locked
+        if let Some(guard) = SharedLock::try_lock(&self.lock) {
+            drop(self.guard(guard));
+        }
+    }
+
+    fn guard<'a>(&'a self, mut guard: LockGuard<'a, T, M>) -> MutexGuard<'a, T, M> {
+        self.deferred.run(&mut guard);
+        MutexGuard {
+            guard: Some(guard),
+            mutex: self,
+        }
+    }
+}
+
+type LockGuard<'a, T, M> = <<M as LockFamily>::Lock<T> as SharedLock<T>>::Guard<'a>;
+
+/// Holds the lock on a `Mutex`, doing any work queued up with `lock_or_defer`
+/// while it was held before letting go
+pub(crate) struct MutexGuard<'a, T, M: LockFamily = StdMutex> {
+    guard: Option<LockGuard<'a, T, M>>,
+    mutex: &'a Mutex<T, M>,
+}
+
+impl<T, M: LockFamily> Deref for MutexGuard<'_, T, M> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard
+            .as_ref()
+            .expect("the lock is held until the guard is dropped")
+    }
+}
+
+impl<T, M: LockFamily> DerefMut for MutexGuard<'_, T, M> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard
+            .as_mut()
+            .expect("the lock is held until the guard is dropped")
+    }
+}
+
+impl<T, M: LockFamily> Drop for MutexGuard<'_, T, M> {
+    fn drop(&mut self) {
+        while let Some(mut guard) = self.guard.take() {
+            self.mutex.deferred.run(&mut guard);
+            drop(guard);
+            // Work queued up after it was run but before the lock was let go
+            // would otherwise wait for the next time the state is locked
+            if self.mutex.deferred.is_pending() {
+                self.guard = SharedLock::try_lock(&self.mutex.lock);
+            }
+        }
+    }
+}
+
+/// Called by an output stream that found the lock taken, before it asks to be
+/// polled again. Under loom this yields to the task holding the lock, since
+/// otherwise the model would explore spinning on it forever
+pub(crate) fn lock_contended() {
+    #[cfg(loom)]
+    loom::thread::yield_now();
+}
+
+/// Runs `poll` on the locked state of a split, and if it panics, runs `end` on
+/// the state before the panic carries on unwinding
+pub(crate) fn end_on_panic<T: ?Sized, R>(
+    mut state: Pin<&mut T>,
+    poll: impl FnOnce(Pin<&mut T>) -> R,
+    end: impl FnOnce(Pin<&mut T>),
+) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(|| poll(state.as_mut()))) {
+        Ok(result) => result,
+        Err(payload) => {
+            end(state);
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{stream::FusedStream, FutureExt, Stream, StreamExt};
+
+    use super::{Local, LockFamily, Mutex, SharedLock, StdMutex};
+    use crate::SplitBuilder;
+
+    fn check_try_lock<M: LockFamily>() {
+        let lock = Mutex::<u32, M>::new(1);
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+            assert!(lock.try_lock().is_none());
+        }
+        assert_eq!(Some(2), lock.try_lock().map(|guard| *guard));
+    }
+
+    fn check_split<M: LockFamily>() {
+        let incoming_stream = futures::stream::iter(0..10);
+        let (even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+            .buffer::<2>()
+            .lock::<M>()
+            .build_by(|&n| n % 2 == 0);
+        futures::executor::block_on(async {
+            let (evens, odds) = futures::join!(
+                even_stream.collect::<Vec<_>>(),
+                odd_stream.collect::<Vec<_>>()
+            );
+            assert_eq!(vec![0, 2, 4, 6, 8], evens);
+            assert_eq!(vec![1, 3, 5, 7, 9], odds);
+        });
+        let [zeros, ones, twos] = SplitBuilder::new(futures::stream::iter(0..9))
+            .buffer::<2>()
+            .lock::<M>()
+            .build_fan_out(|&n| n % 3);
+        futures::executor::block_on(async {
+            let (zeros, ones, twos) = futures::join!(
+                zeros.collect::<Vec<_>>(),
+                ones.collect::<Vec<_>>(),
+                twos.collect::<Vec<_>>()
+            );
+            assert_eq!(vec![0, 3, 6], zeros);
+            assert_eq!(vec![1, 4, 7], ones);
+            assert_eq!(vec![2, 5, 8], twos);
+        });
+    }
+
+    fn check_deferred<M: LockFamily>() {
+        let lock = Mutex::<Vec<usize>, M>::new(Vec::new());
+        lock.lock_or_defer(|state, n| state.push(n), 1);
+        {
+            let guard = lock.lock();
+            lock.lock_or_defer(|state, n| state.push(n), 2);
+            assert_eq!(vec![1], *guard);
+        }
+        assert_eq!(vec![1, 2], *lock.lock());
+    }
+
+    /// Drops one side while the other's item is peeked at, which holds the
+    /// lock on the same thread
+    fn check_drop_while_peeked<M: LockFamily>() {
+        let incoming_stream = futures::stream::iter([0, 1, 3, 5, 2, 4]);
+        let (mut even_stream, odd_stream) = SplitBuilder::new(incoming_stream)
+            .buffer::<2>()
+            .lock::<M>()
+            .build_by(|&n| n % 2 == 0);
+        let peeked = even_stream.peek().now_or_never().flatten();
+        assert_eq!(Some(0), peeked.as_deref().copied());
+        assert_eq!((0, None), odd_stream.size_hint());
+        assert!(!odd_stream.is_terminated());
+        drop(odd_stream);
+        drop(peeked);
+        // The odd stream was only let go of once the peeked item was, and its
+        // items no longer hold up the even stream
+        let evens = (0..8)
+            .filter_map(|_| even_stream.next().now_or_never())
+            .take_while(Option::is_some)
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 2, 4], evens);
+    }
+
+    #[test]
+    fn test_try_lock_when_held() {
+        check_try_lock::<StdMutex>();
+        check_try_lock::<Local>();
+        #[cfg(feature = "parking_lot")]
+        check_try_lock::<super::ParkingLot>();
+        #[cfg(feature = "spin")]
+        check_try_lock::<super::Spin>();
+    }
+
+    #[test]
+    fn test_split_with_each_lock() {
+        check_split::<StdMutex>();
+        check_split::<Local>();
+        #[cfg(feature = "parking_lot")]
+        check_split::<super::ParkingLot>();
+        #[cfg(feature = "spin")]
+        check_split::<super::Spin>();
+    }
+
+    #[test]
+    fn test_deferred_with_each_lock() {
+        check_deferred::<StdMutex>();
+        check_deferred::<Local>();
+        #[cfg(feature = "parking_lot")]
+        check_deferred::<super::ParkingLot>();
+        #[cfg(feature = "spin")]
+        check_deferred::<super::Spin>();
+    }
+
+    #[test]
+    fn test_drop_while_peeked_with_each_lock() {
+        check_drop_while_peeked::<StdMutex>();
+        check_drop_while_peeked::<Local>();
+        #[cfg(feature = "parking_lot")]
+        check_drop_while_peeked::<super::ParkingLot>();
+        #[cfg(feature = "spin")]
+        check_drop_while_peeked::<super::Spin>();
+    }
+
+    #[test]
+    fn test_std_mutex_ignores_poison() {
+        let lock = std::sync::Arc::new(std::sync::Mutex::new(1));
+        let poisoner = std::sync::Arc::clone(&lock);
+        let panicked = std::thread::spawn(move || {
+            let _guard = poisoner.lock();
+            panic!("poisoning the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(lock.is_poisoned());
+        assert_eq!(1, *SharedLock::lock(&*lock));
+        assert_eq!(Some(1), SharedLock::try_lock(&*lock).map(|guard| *guard));
+    }
+
+    #[test]
+    fn test_local_try_lock_when_borrowed() {
+        let lock: std::cell::RefCell<u32> = SharedLock::new(1);
+        let borrowed = lock.borrow();
+        assert!(SharedLock::try_lock(&lock).is_none());
+        drop(borrowed);
+        assert!(SharedLock::try_lock(&lock).is_some());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+use crate::{
+    ring_buf::RingBuf,
+    side_waker::SideWaker,
+    stats::{StatsHandle, StatsSlot},
+    sync::{self, Mutex},
+    trace::trace_event,
+    Side,
+};
+
+/// Splits off a lossy monitoring tap from a stream. The first of the pair of
+/// streams returned yields every item, while the second yields copies of a
+/// sample of them, roughly `rate` of the items (e.g. 0.01 for one in every
+/// hundred). The sample is deterministic rather than random, so with a rate
+/// of 0.5 every second item is copied.
+///
+/// The tap can never hold up the first stream: it buffers up to N copies and
+/// drops any more while its buffer is full. It also never reads from the
+/// underlying stream itself, so it only sees items as the first stream is
+/// consumed, and it ends once it has drained its buffer after the first
+/// stream has ended or been dropped
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::tap_sampled;
+///
+/// let incoming_stream = futures::stream::iter(0..10);
+/// let (all, sampled) = tap_sampled::<_, 8>(incoming_stream, 0.5);
+/// futures::executor::block_on(async {
+///     assert_eq!((0..10).collect::<Vec<_>>(), all.collect::<Vec<_>>().await);
+///     assert_eq!(vec![1, 3, 5, 7, 9], sampled.collect::<Vec<_>>().await);
+/// });
+/// ```
+///
+/// Panics if `rate` isn't between 0 and 1
+pub fn tap_sampled<S, const N: usize>(
+    stream: S,
+    rate: f64,
+) -> (TapSampledSource<S::Item, S, N>, TapSampled<S::Item, S, N>)
+where
+    S: Stream,
+    S::Item: Clone,
+{
+    assert!(
+        (0.0..=1.0).contains(&rate),
+        "the sample rate must be between 0 and 1"
+    );
+    let state = Arc::new(Mutex::new(TapState {
+        stream,
+        rate,
+        credit: 0.0,
+        buf: RingBuf::new(),
+        waker: SideWaker::new("tap"),
+        ended: false,
+        tap_closed: false,
+        stats: StatsSlot::default(),
+    }));
+    (
+        TapSampledSource {
+            stream: Arc::clone(&state),
+        },
+        TapSampled { stream: state },
+    )
+}
+
+struct TapState<I, S, const N: usize> {
+    stream: S,
+    rate: f64,
+    credit: f64,
+    buf: RingBuf<I, N>,
+    waker: SideWaker,
+    /// The underlying stream has ended or the first stream has been dropped,
+    /// so nothing else will be sampled
+    ended: bool,
+    tap_closed: bool,
+    stats: StatsSlot,
+}
+
+impl<I, S, const N: usize> TapState<I, S, N>
+where
+    S: Stream<Item = I> + Unpin,
+    I: Clone,
+{
+    fn poll_next_source(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.ended {
+            return Poll::Ready(None);
+        }
+        self.stats.source_polled();
+        let item = match Pin::new(&mut self.stream).poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => {
+                trace_event!("stream terminated");
+                self.stats.terminated();
+                self.end();
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {
+                self.stats.source_pending();
+                return Poll::Pending;
+            }
+        };
+        self.stats.routed(Side::Left);
+        self.credit += self.rate;
+        if self.credit >= 1.0 && !self.tap_closed {
+            self.credit -= 1.0;
+            self.stats.routed(Side::Right);
+            if self.buf.push_back(item.clone()).is_some() {
+                // The tap is behind, so rather than hold up this stream the copy is dropped
+                trace_event!(side = "tap", "item discarded");
+                self.stats.discarded(Side::Right, 1);
+            } else {
+                self.stats.buffered(Side::Right, self.buf.len());
+                self.waker.wake();
+            }
+        }
+        self.stats.yielded(Side::Left);
+        Poll::Ready(Some(item))
+    }
+}
+
+impl<I, S, const N: usize> TapState<I, S, N> {
+    fn stats_handle(&mut self) -> StatsHandle {
+        if let Some(counters) = self.stats.enable() {
+            self.waker.count_wakes(counters);
+        }
+        self.stats.handle()
+    }
+
+    fn poll_next_tap(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.waker.register(0, cx.waker());
+        if let Some(item) = self.buf.pop_front() {
+            self.stats.unbuffered(Side::Right, self.buf.len());
+            self.stats.yielded(Side::Right);
+            Poll::Ready(Some(item))
+        } else if self.ended {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Stops sampling, waking the tap so that it can end once it has drained
+    /// its buffer
+    fn end(&mut self) {
+        if !self.ended {
+            self.ended = true;
+            self.waker.wake();
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns every item of the
+/// underlying stream when using `tap_sampled`
+pub struct TapSampledSource<I, S, const N: usize> {
+    stream: Arc<Mutex<TapState<I, S, N>>>,
+}
+
+impl<I, S, const N: usize> TapSampledSource<I, S, N> {
+    /// Returns a handle for reading the tap's counters, where the left side is
+    /// this stream and the right side is the tap
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+}
+
+impl<I, S, const N: usize> Stream for TapSampledSource<I, S, N>
+where
+    S: Stream<Item = I> + Unpin,
+    I: Clone,
+{
+    type Item = I;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut guard = match self.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                // The tap is in the middle of taking an item out of its buffer
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        };
+        guard.poll_next_source(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let guard = match self.stream.try_lock() {
+            Some(guard) => guard,
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => return (0, None),
+        };
+        if guard.ended {
+            (0, Some(0))
+        } else {
+            guard.stream.size_hint()
+        }
+    }
+}
+
+impl<I, S, const N: usize> FusedStream for TapSampledSource<I, S, N>
+where
+    S: Stream<Item = I> + Unpin,
+    I: Clone,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.ended,
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, const N: usize> fmt::Debug for TapSampledSource<I, S, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TapSampledSource").finish_non_exhaustive()
+    }
+}
+
+impl<I, S, const N: usize> Drop for TapSampledSource<I, S, N> {
+    fn drop(&mut self) {
+        self.stream.lock_or_defer(|state, _| state.end(), 0);
+    }
+}
+
+/// A struct that implements `Stream` which returns copies of the sampled
+/// items when using `tap_sampled`
+pub struct TapSampled<I, S, const N: usize> {
+    stream: Arc<Mutex<TapState<I, S, N>>>,
+}
+
+impl<I, S, const N: usize> TapSampled<I, S, N> {
+    /// Returns a handle for reading the tap's counters, where the left side is
+    /// the stream of every item and the right side is this one
+    pub fn stats_handle(&self) -> StatsHandle {
+        let mut guard = self.stream.lock();
+        guard.stats_handle()
+    }
+}
+
+impl<I, S, const N: usize> Stream for TapSampled<I, S, N>
+where
+    S: Stream<Item = I>,
+{
+    type Item = I;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut guard = match self.stream.try_lock() {
+            Some(guard) => guard,
+            None => {
+                // The other stream is in the middle of being polled
+                sync::lock_contended();
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        };
+        guard.poll_next_tap(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let guard = match self.stream.try_lock() {
+            Some(guard) => guard,
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => return (0, None),
+        };
+        let buffered = guard.buf.len();
+        if guard.ended {
+            (buffered, Some(buffered))
+        } else {
+            let upper = guard.stream.size_hint().1;
+            (
+                buffered,
+                upper.and_then(|upper| upper.checked_add(buffered)),
+            )
+        }
+    }
+}
+
+impl<I, S, const N: usize> FusedStream for TapSampled<I, S, N>
+where
+    S: Stream<Item = I>,
+{
+    fn is_terminated(&self) -> bool {
+        match self.stream.try_lock() {
+            Some(guard) => guard.ended && guard.buf.is_empty(),
+            // Either side is in the middle of being polled, maybe by whoever is asking
+            None => false,
+        }
+    }
+}
+
+impl<I, S, const N: usize> fmt::Debug for TapSampled<I, S, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TapSampled");
+        match self.stream.try_lock() {
+            Some(guard) => debug.field("buffered", &guard.buf.len()),
+            None => {
+                // The other stream is in the middle of being polled
+                debug.field("state", &format_args!("<locked>"))
+            }
+        };
+        debug.finish()
+    }
+}
+
+impl<I, S, const N: usize> Drop for TapSampled<I, S, N> {
+    fn drop(&mut self) {
+        self.stream.lock_or_defer(
+            |state, _| {
+                // Stop making copies now that nothing will read them
+                state.tap_closed = true;
+                state.buf.clear();
+                state.stats.buffered(Side::Right, 0);
+            },
+            0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::tap_sampled;
+
+    #[test]
+    fn test_full_tap_drops() {
+        futures::executor::block_on(async {
+            let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4]);
+            let (all, sampled) = tap_sampled::<_, 2>(incoming_stream, 1.0);
+            let stats = all.stats_handle();
+            // The tap isn't polled until the end, so only the copies that fit are kept
+            assert_eq!(vec![0, 1, 2, 3, 4], all.collect::<Vec<_>>().await);
+            assert_eq!(vec![0, 1], sampled.collect::<Vec<_>>().await);
+            assert_eq!(5, stats.snapshot().routed_right);
+        })
+    }
+}
@@ -0,0 +1,285 @@
+//! Helpers for testing code built on the splits, enabled with the `test-util`
+//! feature: a source stream that yields, pends and ends when told to, a
+//! waker counting its wakes, and assertions on what each side of a split
+//! received
+//!
+//!```rust
+//! use futures::StreamExt;
+//! use split_stream_by::{test_util, SplitStreamByExt};
+//!
+//! let (script, incoming_stream) = test_util::scripted();
+//! let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n: &i32| n % 2 == 0);
+//! script.yield_items([2, 1]);
+//! script.pend();
+//! futures::executor::block_on(async {
+//!     assert_eq!(Some(2), even_stream.next().await);
+//!     assert_eq!(Some(1), odd_stream.next().await);
+//!     assert!(futures::poll!(odd_stream.next()).is_pending());
+//!     script.end();
+//!     assert_eq!(None, even_stream.next().await);
+//!     assert_eq!(None, odd_stream.next().await);
+//!     assert_eq!(4, script.polls());
+//! });
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use futures::{stream::FusedStream, Stream};
+
+enum Step<T> {
+    Yield(T),
+    Pend,
+    End,
+}
+
+struct ScriptState<T> {
+    steps: VecDeque<Step<T>>,
+    ended: bool,
+    polls: usize,
+    waker: Option<Waker>,
+}
+
+/// Creates a source stream along with the `Script` controlling it. The stream
+/// works through the steps queued on the script in order, and returns
+/// `Poll::Pending` once it runs out until more are queued
+pub fn scripted<T>() -> (Script<T>, ScriptedStream<T>) {
+    let state = Arc::new(Mutex::new(ScriptState {
+        steps: VecDeque::new(),
+        ended: false,
+        polls: 0,
+        waker: None,
+    }));
+    (
+        Script {
+            state: Arc::clone(&state),
+        },
+        ScriptedStream { state },
+    )
+}
+
+/// Queues up what a `ScriptedStream` does each time it's polled. Cloning it
+/// gives another handle on the same queue
+pub struct Script<T> {
+    state: Arc<Mutex<ScriptState<T>>>,
+}
+
+impl<T> Script<T> {
+    fn push(&self, step: Step<T>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.steps.push_back(step);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Has the stream yield `item`
+    pub fn yield_item(&self, item: T) {
+        self.push(Step::Yield(item));
+    }
+
+    /// Has the stream yield each of `items` in turn
+    pub fn yield_items(&self, items: impl IntoIterator<Item = T>) {
+        for item in items {
+            self.yield_item(item);
+        }
+    }
+
+    /// Has the stream return `Poll::Pending` once. It's woken as soon as
+    /// something else is queued
+    pub fn pend(&self) {
+        self.push(Step::Pend);
+    }
+
+    /// Has the stream end. Nothing queued after this is ever reached
+    pub fn end(&self) {
+        self.push(Step::End);
+    }
+
+    /// How many times the stream has been polled
+    pub fn polls(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .polls
+    }
+
+    /// How many queued steps the stream hasn't got to yet
+    pub fn pending_steps(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .steps
+            .len()
+    }
+}
+
+impl<T> Clone for Script<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Script<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("pending_steps", &self.pending_steps())
+            .field("polls", &self.polls())
+            .finish()
+    }
+}
+
+/// A stream that does whatever its `Script` says, returned by `scripted`
+pub struct ScriptedStream<T> {
+    state: Arc<Mutex<ScriptState<T>>>,
+}
+
+impl<T> Stream for ScriptedStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.polls += 1;
+        if state.ended {
+            return Poll::Ready(None);
+        }
+        match state.steps.pop_front() {
+            Some(Step::Yield(item)) => Poll::Ready(Some(item)),
+            Some(Step::End) => {
+                state.ended = true;
+                Poll::Ready(None)
+            }
+            Some(Step::Pend) | None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> FusedStream for ScriptedStream<T> {
+    fn is_terminated(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .ended
+    }
+}
+
+impl<T> fmt::Debug for ScriptedStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedStream").finish_non_exhaustive()
+    }
+}
+
+/// Counts the wakes of the wakers made by a `CountingWaker`
+#[derive(Default)]
+struct WakeCount(AtomicUsize);
+
+impl Wake for WakeCount {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Hands out wakers that count how many times they've been woken, for
+/// checking that a side of a split is woken when it should be, and only then.
+/// Clones share the count
+///
+///```rust
+/// use std::task::{Context, Poll};
+/// use futures::StreamExt;
+/// use split_stream_by::{test_util::{self, CountingWaker}, SplitStreamByExt};
+///
+/// let (script, incoming_stream) = test_util::scripted();
+/// let (mut even_stream, _odd_stream) = incoming_stream.split_by(|&n: &i32| n % 2 == 0);
+/// let counter = CountingWaker::new();
+/// let waker = counter.waker();
+/// let mut cx = Context::from_waker(&waker);
+/// assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+/// script.yield_item(2);
+/// assert_eq!(1, counter.wakes());
+/// ```
+#[derive(Clone, Default)]
+pub struct CountingWaker {
+    count: Arc<WakeCount>,
+}
+
+impl CountingWaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A waker adding to the count each time it's woken
+    pub fn waker(&self) -> Waker {
+        Waker::from(Arc::clone(&self.count))
+    }
+
+    /// How many times the wakers have been woken so far
+    pub fn wakes(&self) -> usize {
+        self.count.0.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for CountingWaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountingWaker")
+            .field("wakes", &self.wakes())
+            .finish()
+    }
+}
+
+/// Asserts that `received` holds items from `sent` in the order they were
+/// sent, possibly with some missing, as for a side of a split that drops
+/// items
+#[track_caller]
+pub fn assert_in_order<T: PartialEq + fmt::Debug>(sent: &[T], received: &[T]) {
+    let mut remaining = sent.iter();
+    for (index, item) in received.iter().enumerate() {
+        assert!(
+            remaining.any(|sent| sent == item),
+            "item {} ({:?}) of {:?} is out of order or wasn't sent, sent {:?}",
+            index,
+            item,
+            received,
+            sent
+        );
+    }
+}
+
+/// Asserts that the two sides of a split received exactly the items of
+/// `sent` that `predicate` sends their way, in order
+#[track_caller]
+pub fn assert_split<T: PartialEq + fmt::Debug>(
+    sent: &[T],
+    received_true: &[T],
+    received_false: &[T],
+    predicate: impl Fn(&T) -> bool,
+) {
+    let (expected_true, expected_false): (Vec<&T>, Vec<&T>) =
+        sent.iter().partition(|item| predicate(item));
+    assert_eq!(
+        expected_true,
+        received_true.iter().collect::<Vec<_>>(),
+        "the `true` side received the wrong items"
+    );
+    assert_eq!(
+        expected_false,
+        received_false.iter().collect::<Vec<_>>(),
+        "the `false` side received the wrong items"
+    );
+}
@@ -0,0 +1,174 @@
+/// Emits a trace level `tracing` event when the `tracing` feature is enabled
+/// and expands to nothing otherwise, so call sites don't need their own `cfg`
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($arg:tt)+) => {
+        tracing::trace!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($arg:tt)+) => {};
+}
+
+pub(crate) use trace_event;
+
+/// Makes the trace level span a split enters while routing, or a `()` in its
+/// place without the `tracing` feature, so the split's state can hold a
+/// `Span` either way
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($name:literal) => {
+        tracing::trace_span!($name)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($name:literal) => {
+        ()
+    };
+}
+
+pub(crate) use trace_span;
+
+#[cfg(feature = "tracing")]
+pub(crate) type Span = tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) type Span = ();
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::{
+        fmt,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, StreamExt};
+    use tracing::{
+        field::{Field, Visit},
+        span::Id,
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{self, SubscriberExt},
+        registry::{LookupSpan, Scope},
+        Layer, Registry,
+    };
+
+    use crate::SplitStreamByExt;
+
+    /// Records each event as the spans it was emitted in, outermost first, its
+    /// `side` field and its message, along with each span entered
+    #[derive(Clone, Default)]
+    struct Recorded {
+        events: Arc<Mutex<Vec<(String, Option<String>, String)>>>,
+        entered: Arc<Mutex<Vec<String>>>,
+    }
+
+    fn path<'a, R: LookupSpan<'a>>(scope: Scope<'a, R>) -> String {
+        scope
+            .from_root()
+            .map(|span| span.name())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    #[derive(Default)]
+    struct Fields {
+        side: Option<String>,
+        message: String,
+    }
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            match field.name() {
+                "side" => self.side = Some(value.to_string()),
+                _ => self.record_debug(field, &value),
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            match field.name() {
+                "message" => self.message = format!("{:?}", value),
+                "side" => self.side = Some(format!("{:?}", value)),
+                _ => {}
+            }
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorded {
+        fn on_event(&self, event: &Event<'_>, cx: layer::Context<'_, S>) {
+            let span = cx.event_scope(event).map_or_else(String::new, path);
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            self.events
+                .lock()
+                .unwrap()
+                .push((span, fields.side, fields.message));
+        }
+
+        fn on_enter(&self, id: &Id, cx: layer::Context<'_, S>) {
+            if let Some(span) = cx.span(id) {
+                self.entered.lock().unwrap().push(path(span.scope()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_by_events() {
+        let recorded = Recorded::default();
+        let subscriber = Registry::default().with(recorded.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let mut cx = Context::from_waker(noop_waker_ref());
+            let incoming_stream = futures::stream::iter([0, 1]);
+            let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+            assert_eq!(Poll::Ready(Some(0)), even_stream.poll_next_unpin(&mut cx));
+            assert_eq!(Poll::Pending, even_stream.poll_next_unpin(&mut cx));
+            assert_eq!(Poll::Ready(Some(1)), odd_stream.poll_next_unpin(&mut cx));
+            assert_eq!(Poll::Ready(None), odd_stream.poll_next_unpin(&mut cx));
+        });
+        let events = recorded.events.lock().unwrap();
+        assert!(events.iter().all(|(span, _, _)| span == "split_by"));
+        let routing = events
+            .iter()
+            .map(|(_, side, message)| (side.as_deref(), message.as_str()))
+            .filter(|(_, message)| !message.starts_with("side woken"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Some("true"), "item routed"),
+                (Some("false"), "item routed"),
+                (Some("false"), "item buffered"),
+                (None, "stream terminated"),
+                (Some("false"), "stream closed"),
+                (Some("true"), "stream closed"),
+            ],
+            routing
+        );
+    }
+
+    #[test]
+    fn test_driven_span_within_caller() {
+        let recorded = Recorded::default();
+        let subscriber = Registry::default().with(recorded.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let (even_stream, odd_stream, drive) = tracing::info_span!("caller")
+                .in_scope(|| futures::stream::iter([0, 1]).split_by_driven(|&n| n % 2 == 0));
+            futures::executor::block_on(async {
+                let (evens, odds, ()) = futures::join!(
+                    even_stream.collect::<Vec<_>>(),
+                    odd_stream.collect::<Vec<_>>(),
+                    drive
+                );
+                assert_eq!((vec![0], vec![1]), (evens, odds));
+            });
+        });
+        // Routing happens in a span of its own, under the one the split was made in
+        let entered = recorded.entered.lock().unwrap();
+        assert!(entered.contains(&"caller/split_by_driven".to_string()));
+    }
+}
@@ -0,0 +1,159 @@
+use std::convert::TryFrom;
+
+use futures::{future::Either, Stream};
+
+use crate::{Left, Right, SplitStreamByMapExt};
+
+type RouteFirst<I, L, R> = fn(I) -> Either<L, Either<R, I>>;
+
+type RouteRest<R, I> = fn(Either<R, I>) -> Either<R, I>;
+
+/// The items that didn't convert into `L`, which `split_by_try_from` splits
+/// again into the second and third streams
+type Rest<S, L, R> =
+    Right<S, L, Either<R, <S as Stream>::Item>, RouteFirst<<S as Stream>::Item, L, R>>;
+
+/// The first stream returned by `split_by_try_from`, of the items that
+/// converted into `L`
+pub type TryFromLeft<S, L, R> =
+    Left<S, L, Either<R, <S as Stream>::Item>, RouteFirst<<S as Stream>::Item, L, R>>;
+
+/// The second stream returned by `split_by_try_from`, of the items that
+/// converted into `R`
+pub type TryFromRight<S, L, R> =
+    Left<Rest<S, L, R>, R, <S as Stream>::Item, RouteRest<R, <S as Stream>::Item>>;
+
+/// The third stream returned by `split_by_try_from`, of the items that
+/// converted into neither type
+pub type TryFromErrors<S, L, R> =
+    Right<Rest<S, L, R>, R, <S as Stream>::Item, RouteRest<R, <S as Stream>::Item>>;
+
+fn route_first<I, L, R>(item: I) -> Either<L, Either<R, I>>
+where
+    I: Clone,
+    L: TryFrom<I>,
+    R: TryFrom<I>,
+{
+    if let Ok(left) = L::try_from(item.clone()) {
+        return Either::Left(left);
+    }
+    match R::try_from(item.clone()) {
+        Ok(right) => Either::Right(Either::Left(right)),
+        Err(_) => Either::Right(Either::Right(item)),
+    }
+}
+
+fn route_rest<R, I>(item: Either<R, I>) -> Either<R, I> {
+    item
+}
+
+/// This extension trait provides the functionality for splitting a stream by
+/// the `TryFrom` conversions its items already have, without a predicate
+pub trait SplitStreamByTryFromExt: Stream {
+    /// Splits the stream into the items that convert into `L`, those that
+    /// don't but convert into `R`, and the rest, which are yielded unchanged
+    /// by the third stream. Each item is tried as `L` first, so an item that
+    /// converts into both goes to the first stream.
+    ///
+    /// Since `TryFrom` takes the item by value, it's cloned for each
+    /// conversion tried, so wrap items that are expensive to clone in an
+    /// `Arc`. As with the other splits, the three streams are read in step,
+    /// so one that isn't consumed holds up the others unless it's dropped. A
+    /// stream that isn't `Unpin` has to be pinned with `Box::pin` first
+    ///
+    ///```rust
+    /// use std::convert::TryFrom;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByTryFromExt;
+    ///
+    /// #[derive(Clone)]
+    /// enum Message {
+    ///     Request(u32),
+    ///     Response(String),
+    ///     Ping,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Request(u32);
+    ///
+    /// impl TryFrom<Message> for Request {
+    ///     type Error = Message;
+    ///     fn try_from(message: Message) -> Result<Self, Message> {
+    ///         match message {
+    ///             Message::Request(id) => Ok(Request(id)),
+    ///             other => Err(other),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Response(String);
+    ///
+    /// impl TryFrom<Message> for Response {
+    ///     type Error = Message;
+    ///     fn try_from(message: Message) -> Result<Self, Message> {
+    ///         match message {
+    ///             Message::Response(body) => Ok(Response(body)),
+    ///             other => Err(other),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let incoming_stream = futures::stream::iter([
+    ///     Message::Request(1),
+    ///     Message::Ping,
+    ///     Message::Response("ok".to_string()),
+    /// ]);
+    /// let (requests, responses, others) = incoming_stream.split_by_try_from::<Request, Response>();
+    /// futures::executor::block_on(async {
+    ///     let (requests, responses, others) = futures::join!(
+    ///         requests.collect::<Vec<_>>(),
+    ///         responses.collect::<Vec<_>>(),
+    ///         others.collect::<Vec<_>>(),
+    ///     );
+    ///     assert_eq!(vec![Request(1)], requests);
+    ///     assert_eq!(vec![Response("ok".to_string())], responses);
+    ///     assert_eq!(1, others.len());
+    /// });
+    /// ```
+    fn split_by_try_from<L, R>(
+        self,
+    ) -> (
+        TryFromLeft<Self, L, R>,
+        TryFromRight<Self, L, R>,
+        TryFromErrors<Self, L, R>,
+    )
+    where
+        Self: Sized + Unpin,
+        Self::Item: Clone,
+        L: TryFrom<Self::Item>,
+        R: TryFrom<Self::Item>,
+    {
+        let (left, rest) = self.split_by_map(route_first as RouteFirst<Self::Item, L, R>);
+        let (right, errors) = rest.split_by_map(route_rest as RouteRest<R, Self::Item>);
+        (left, right, errors)
+    }
+}
+
+impl<T> SplitStreamByTryFromExt for T where T: Stream + ?Sized {}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::SplitStreamByTryFromExt;
+
+    #[test]
+    fn test_try_from_prefers_left() {
+        // Small values convert into both, so only the first conversion counts
+        let incoming_stream = futures::stream::iter([1i32, 300, -1, 70_000]);
+        let (bytes, shorts, others) = incoming_stream.split_by_try_from::<u8, i16>();
+        drop(others);
+        futures::executor::block_on(async {
+            let (bytes, shorts) =
+                futures::join!(bytes.collect::<Vec<_>>(), shorts.collect::<Vec<_>>());
+            assert_eq!(vec![1], bytes);
+            assert_eq!(vec![300, -1], shorts);
+        });
+    }
+}
@@ -0,0 +1,88 @@
+/// Splits a stream of enum values into two streams by match arms, expanding
+/// to a `split_by_map` call whose predicate matches each item against the
+/// arms. What the first arm evaluates to goes to the first stream, and what
+/// any of the remaining arms evaluate to goes to the second, so those arms
+/// have to evaluate to the same type. It saves writing out the `Either` for
+/// enums that don't warrant a `TryFrom` impl or that belong to another crate.
+///
+/// The arms are checked like those of any `match`, so they have to cover
+/// every variant, and a catch-all last arm such as `other => other` sends
+/// whatever the first arm doesn't match to the second stream unchanged. An
+/// arm can list several patterns separated by `|`. Prefixing the arms with
+/// `buffered = N;` expands to `split_by_map_buffered::<N>` instead
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_by_variants;
+///
+/// enum Message {
+///     Request(u32),
+///     Response(String),
+///     Ping,
+/// }
+///
+/// let incoming_stream = futures::stream::iter([
+///     Message::Request(1),
+///     Message::Ping,
+///     Message::Response("ok".to_string()),
+/// ]);
+/// let (requests, responses) = split_by_variants!(incoming_stream,
+///     Message::Request(id) => id,
+///     Message::Response(body) => Some(body),
+///     Message::Ping => None,
+/// );
+/// futures::executor::block_on(async {
+///     let (requests, responses) =
+///         futures::join!(requests.collect::<Vec<_>>(), responses.collect::<Vec<_>>());
+///     assert_eq!(vec![1], requests);
+///     assert_eq!(vec![None, Some("ok".to_string())], responses);
+/// });
+/// ```
+#[macro_export]
+macro_rules! split_by_variants {
+    ($stream:expr, buffered = $n:expr; $($left:pat)|+ => $left_value:expr, $($($right:pat)|+ => $right_value:expr),+ $(,)?) => {
+        $crate::SplitStreamByMapExt::split_by_map_buffered::<{ $n }>($stream, |item| match item {
+            $($left)|+ => $crate::Either::Left($left_value),
+            $($($right)|+ => $crate::Either::Right($right_value),)+
+        })
+    };
+    ($stream:expr, $($left:pat)|+ => $left_value:expr, $($($right:pat)|+ => $right_value:expr),+ $(,)?) => {
+        $crate::SplitStreamByMapExt::split_by_map($stream, |item| match item {
+            $($left)|+ => $crate::Either::Left($left_value),
+            $($($right)|+ => $crate::Either::Right($right_value),)+
+        })
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    enum Shape {
+        Circle(f64),
+        Square(f64),
+        Rect(f64, f64),
+        Point,
+    }
+
+    #[test]
+    fn test_split_by_variants_buffered() {
+        let incoming_stream = futures::stream::iter([
+            Shape::Square(2.0),
+            Shape::Circle(1.0),
+            Shape::Rect(1.0, 3.0),
+            Shape::Point,
+        ]);
+        let (regular, others) = split_by_variants!(incoming_stream, buffered = 4;
+            Shape::Circle(size) | Shape::Square(size) => size,
+            Shape::Rect(width, height) => width * height,
+            Shape::Point => 0.0,
+        );
+        futures::executor::block_on(async {
+            let (regular, others) =
+                futures::join!(regular.collect::<Vec<_>>(), others.collect::<Vec<_>>());
+            assert_eq!(vec![2.0, 1.0], regular);
+            assert_eq!(vec![3.0, 0.0], others);
+        });
+    }
+}
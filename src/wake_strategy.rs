@@ -0,0 +1,112 @@
+/// Decides when the output stream of a buffered split is woken for items
+/// buffered for it, set with `SplitBuilder::wake_strategy`. Each time the
+/// split buffers an item for a stream it asks the stream's strategy whether
+/// to wake it right away. If not, the split carries on reading the
+/// underlying stream, and the wake is only held back until the split stops
+/// reading, which is when it has an item for the stream being polled, the
+/// underlying stream has nothing ready, or the buffer is full. So a strategy
+/// trades how soon a stream hears about its items for fewer wakes, but can't
+/// leave items stranded in a buffer.
+///
+/// Wakes for anything other than a buffered item, such as the split ending
+/// or a buffer having room again, are never held back
+pub trait WakeStrategy: Send {
+    /// Whether to wake the stream now that `buffered` items are waiting for
+    /// it
+    fn wake_now(&mut self, buffered: usize) -> bool;
+}
+
+/// Wakes a stream for every item buffered for it, as the splits do by
+/// default. Every item gets to its stream as soon as possible, at the cost of
+/// a wake per item and of the split going back to the executor after each
+/// item it buffers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Immediate;
+
+impl WakeStrategy for Immediate {
+    fn wake_now(&mut self, _buffered: usize) -> bool {
+        true
+    }
+}
+
+/// Wakes a stream once for each run of items buffered for it, when the split
+/// stops reading, rather than once per item
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Coalesced;
+
+impl WakeStrategy for Coalesced {
+    fn wake_now(&mut self, _buffered: usize) -> bool {
+        false
+    }
+}
+
+/// Wakes a stream as soon as this many items are waiting for it, and
+/// otherwise once the split stops reading, like `Coalesced`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Threshold(pub usize);
+
+impl WakeStrategy for Threshold {
+    fn wake_now(&mut self, buffered: usize) -> bool {
+        buffered >= self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{Coalesced, Immediate, Threshold, WakeStrategy};
+    use crate::{test_util, test_util::CountingWaker, SplitBuilder};
+
+    /// How many times the small stream is woken while the large stream reads
+    /// through five small items to get to a large one
+    fn wakes(strategy: impl WakeStrategy + Clone + 'static) -> usize {
+        let (script, incoming_stream) = test_util::scripted();
+        let (mut large_stream, mut small_stream) = SplitBuilder::new(incoming_stream)
+            .buffer::<8>()
+            .wake_strategy(strategy)
+            .build_by(|&n: &i32| n >= 5);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        assert_eq!(
+            Poll::Pending,
+            small_stream.poll_next_unpin(&mut Context::from_waker(&waker))
+        );
+        script.yield_items(0..6);
+        // Queuing the items wakes the small stream, which was left waiting on the
+        // source stream, so only count from here
+        let before = counter.wakes();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let large = loop {
+            if let Poll::Ready(item) = large_stream.poll_next_unpin(&mut cx) {
+                break item;
+            }
+        };
+        assert_eq!(Some(5), large);
+        counter.wakes() - before
+    }
+
+    #[test]
+    fn test_immediate_wakes_per_item() {
+        assert_eq!(5, wakes(Immediate));
+    }
+
+    #[test]
+    fn test_coalesced_wakes_once() {
+        assert_eq!(1, wakes(Coalesced));
+    }
+
+    #[test]
+    fn test_threshold_wakes_from_threshold() {
+        // Held back for the first two items, then woken for each one after
+        assert_eq!(3, wakes(Threshold(3)));
+    }
+
+    #[test]
+    fn test_threshold_above_buffered_wakes_once() {
+        // Never reached, so it's the same as `Coalesced`
+        assert_eq!(1, wakes(Threshold(8)));
+    }
+}
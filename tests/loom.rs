@@ -0,0 +1,67 @@
+//! Runs two tasks polling the two sides of a split under loom, which checks
+//! every interleaving of them for lost wakeups. Only built with
+//! `RUSTFLAGS="--cfg loom"`, e.g.
+//! `RUSTFLAGS="--cfg loom" cargo test --release --test loom`
+#![cfg(loom)]
+
+use futures::StreamExt;
+use split_stream_by::{SplitStreamByExt, SplitStreamByMapExt};
+
+#[test]
+fn test_split_by() {
+    loom::model(|| {
+        let incoming_stream = futures::stream::iter([0, 1, 2]);
+        let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let odd =
+            loom::thread::spawn(move || loom::future::block_on(odd_stream.collect::<Vec<_>>()));
+        let even = loom::future::block_on(even_stream.collect::<Vec<_>>());
+        assert_eq!(vec![0, 2], even);
+        assert_eq!(vec![1], odd.join().unwrap());
+    });
+}
+
+#[test]
+fn test_drop_while_other_side_polled() {
+    loom::model(|| {
+        let incoming_stream = futures::stream::iter([1, 0, 3, 2]);
+        let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+        let even =
+            loom::thread::spawn(move || loom::future::block_on(even_stream.collect::<Vec<_>>()));
+        // Whether or not the even stream holds the lock at the time, it's let go
+        // of the odd items once the odd stream is gone
+        drop(odd_stream);
+        assert_eq!(vec![0, 2], even.join().unwrap());
+    });
+}
+
+#[test]
+fn test_split_by_buffered() {
+    loom::model(|| {
+        let incoming_stream = futures::stream::iter([1, 3, 0]);
+        let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<1>(|&n| n % 2 == 0);
+        let odd =
+            loom::thread::spawn(move || loom::future::block_on(odd_stream.collect::<Vec<_>>()));
+        let even = loom::future::block_on(even_stream.collect::<Vec<_>>());
+        assert_eq!(vec![0], even);
+        assert_eq!(vec![1, 3], odd.join().unwrap());
+    });
+}
+
+#[test]
+fn test_split_by_map() {
+    loom::model(|| {
+        let incoming_stream = futures::stream::iter([0, 1, 2]);
+        let (even_stream, odd_stream) = incoming_stream.split_by_map(|n| {
+            if n % 2 == 0 {
+                futures::future::Either::Left(n)
+            } else {
+                futures::future::Either::Right(n.to_string())
+            }
+        });
+        let odd =
+            loom::thread::spawn(move || loom::future::block_on(odd_stream.collect::<Vec<_>>()));
+        let even = loom::future::block_on(even_stream.collect::<Vec<_>>());
+        assert_eq!(vec![0, 2], even);
+        assert_eq!(vec!["1"], odd.join().unwrap());
+    });
+}